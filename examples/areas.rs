@@ -6,7 +6,7 @@ fn main() -> Result<(), Error> {
     for area in maps {
         let area = area?;
 
-        println!("{:x}-{:x} {}{}{}{}{}",
+        println!("{:x}-{:x} {}{}{}{} {:x} {}",
             area.start(),
             area.end(),
             if area.protection().contains(Protection::READ) {
@@ -29,10 +29,8 @@ fn main() -> Result<(), Error> {
             } else {
                 "p"
             },
-            format!(" {:x} {}",
-                area.file_offset().unwrap_or(0),
-                area.path().map(|path| path.display().to_string()).unwrap_or(String::new()),
-            ),
+            area.file_offset().unwrap_or(0),
+            area.path().map(|path| path.display().to_string()).unwrap_or_default(),
         );
     }
 
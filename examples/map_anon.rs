@@ -1,8 +1,8 @@
-use mmap_rs::{Error, MmapFlags, MmapOptions};
+use mmap_rs::{Error, MmapFlags, MmapOptions, page_size};
 
 fn main() -> Result<(), Error> {
     // Allocate a single page of anonymous memory that is private and mutable.
-    let mut mapping = MmapOptions::new(MmapOptions::page_size().0)
+    let mut mapping = MmapOptions::new(page_size())
         .with_flags(MmapFlags::COPY_ON_WRITE)
         .map_mut()?;
 
@@ -0,0 +1,24 @@
+use mmap_rs::{Error, MmapOptions};
+use std::fs::File;
+use yoke::Yoke;
+
+fn main() -> Result<(), Error> {
+    let file = File::open("Cargo.toml")?;
+    let size = file.metadata()?.len() as usize;
+
+    let mapping = unsafe {
+        MmapOptions::new(size)
+            .with_file(file, 0)
+    }.map()?;
+
+    // Parse the first line of the file and keep it alive together with the mapping.
+    let first_line: Yoke<&'static str, _> = mapping.try_attach(|bytes| {
+        std::str::from_utf8(bytes)
+            .map(|text| text.lines().next().unwrap_or(""))
+            .map_err(Error::Utf8)
+    })?;
+
+    println!("first line: {}", first_line.get());
+
+    Ok(())
+}
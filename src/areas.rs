@@ -1,5 +1,5 @@
-use bitflags::bitflags;
 use crate::error::Error;
+use crate::protection::Protection;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::ops::Range;
@@ -14,21 +14,15 @@ use crate::os_impl::linux as platform;
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 use crate::os_impl::macos as platform;
 
+#[cfg(target_os = "netbsd")]
+use crate::os_impl::netbsd as platform;
+
+#[cfg(target_os = "openbsd")]
+use crate::os_impl::openbsd as platform;
+
 #[cfg(target_os = "windows")]
 use crate::os_impl::windows as platform;
 
-bitflags! {
-    /// The protection of the memory area.
-    pub struct Protection: u32 {
-        /// The memory area is mapped with read permissions.
-        const READ          = 1 << 0;
-        /// The memory area is mapped with write permissions.
-        const WRITE         = 1 << 1;
-        /// The memory area is mapped with execute permissions.
-        const EXECUTE       = 1 << 3;
-    }
-}
-
 /// The share mode of the memory area.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ShareMode {
@@ -97,6 +91,61 @@ impl MemoryArea {
     }
 }
 
+/// A glob pattern that is matched against the paths of memory areas, where `*` matches any
+/// sequence of characters, including `/`, and `?` matches any single character.
+#[derive(Clone, Debug)]
+pub(crate) struct PathPattern {
+    pattern: Vec<char>,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+        }
+    }
+
+    /// Matches the whole path against the pattern, backtracking to the last `*` on a mismatch.
+    pub fn matches(&self, path: &str) -> bool {
+        let mut index = 0;
+        let mut rest = path;
+        let mut star: Option<(usize, &str)> = None;
+
+        loop {
+            let mut chars = rest.chars();
+
+            match (self.pattern.get(index), chars.next()) {
+                (Some('*'), _) => {
+                    index += 1;
+                    star = Some((index, rest));
+                }
+                (Some(&expected), Some(c)) if expected == '?' || expected == c => {
+                    index += 1;
+                    rest = chars.as_str();
+                }
+                (None, None) => return true,
+                _ => {
+                    // Let the last `*` match one more character and try again from there.
+                    let (star_index, star_rest) = match star {
+                        Some(star) => star,
+                        _ => return false,
+                    };
+
+                    let mut chars = star_rest.chars();
+
+                    if chars.next().is_none() {
+                        return false;
+                    }
+
+                    index = star_index;
+                    rest = chars.as_str();
+                    star = Some((star_index, rest));
+                }
+            }
+        }
+    }
+}
+
 /// The memory areas of the process.
 pub struct MemoryAreas<B> {
     inner: platform::MemoryAreas<B>,
@@ -112,6 +161,36 @@ impl MemoryAreas<BufReader<File>> {
             inner,
         })
     }
+
+    /// Looks up the memory area that contains the given address in the specified process, or in
+    /// the current process if no process ID is given. Returns `None` if the address is not
+    /// mapped.
+    ///
+    /// This is cheaper than iterating over all memory areas: on Linux, only the address ranges
+    /// of the lines of `/proc/[pid]/maps` up to the one containing the address are parsed, while
+    /// Microsoft Windows and macOS query the region containing the address directly using
+    /// `VirtualQueryEx()` and `mach_vm_region()` respectively. As the region is queried
+    /// directly on Microsoft Windows, it is not coalesced with adjacent regions with identical
+    /// attributes.
+    pub fn query(pid: Option<u32>, address: usize) -> Result<Option<MemoryArea>, Error> {
+        platform::MemoryAreas::query(pid, address)
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    /// Only yields the memory areas that are backed by a path that matches the given glob
+    /// pattern, where `*` matches any sequence of characters, including `/`, and `?` matches any
+    /// single character. The pattern has to match the whole path, e.g. `*/libc.so*` finds the
+    /// memory areas of the C library.
+    ///
+    /// On Linux, the lines of `/proc/[pid]/maps` with paths that do not match are skipped
+    /// without parsing them.
+    /// On OpenBSD, the paths of the memory areas are not exposed, so no memory areas are
+    /// yielded.
+    pub fn filter_path(mut self, pattern: &str) -> Self {
+        self.inner.filter_path(PathPattern::new(pattern));
+        self
+    }
 }
 
 impl<B: BufRead> Iterator for MemoryAreas<B> {
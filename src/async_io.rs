@@ -0,0 +1,254 @@
+//! This module implements adapters to use memory mappings with the I/O traits of Tokio.
+
+use crate::error::Error;
+use crate::MmapMut;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use tokio::task::JoinHandle;
+
+/// Calculates the new position after seeking within a mapping of the given size.
+fn seek_position(position: usize, size: usize, seek: SeekFrom) -> io::Result<usize> {
+    let new_position = match seek {
+        SeekFrom::Start(offset) => offset as i128,
+        SeekFrom::Current(offset) => position as i128 + offset as i128,
+        SeekFrom::End(offset) => size as i128 + offset as i128,
+    };
+
+    match new_position {
+        new_position if new_position >= 0 && new_position <= usize::MAX as i128 => {
+            Ok(new_position as usize)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )),
+    }
+}
+
+/// An adapter that exposes a memory mapping through [`AsyncRead`], [`AsyncBufRead`] and
+/// [`AsyncSeek`], such that mapped file contents can be served through the I/O traits of Tokio.
+///
+/// As the contents are already mapped into memory, reading never blocks on the runtime. However,
+/// reading the pages may still cause page faults if they are not resident in memory.
+pub struct AsyncMmapReader<M> {
+    inner: M,
+    position: usize,
+}
+
+impl<M: AsRef<[u8]>> AsyncMmapReader<M> {
+    /// Constructs the reader from a memory mapping, starting at the beginning of the mapping.
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            position: 0,
+        }
+    }
+
+    /// Yields the current position of the reader within the mapping.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Yields a reference to the underlying memory mapping.
+    #[inline]
+    pub fn get_ref(&self) -> &M {
+        &self.inner
+    }
+
+    /// Consumes the reader, returning the underlying memory mapping.
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+
+    /// Yields the bytes that have not been read yet.
+    fn remaining(&self) -> &[u8] {
+        let bytes = self.inner.as_ref();
+
+        &bytes[self.position.min(bytes.len())..]
+    }
+}
+
+impl<M: AsRef<[u8]> + Unpin> AsyncRead for AsyncMmapReader<M> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let remaining = this.remaining();
+        let size = remaining.len().min(buf.remaining());
+
+        buf.put_slice(&remaining[..size]);
+        this.position += size;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<M: AsRef<[u8]> + Unpin> AsyncBufRead for AsyncMmapReader<M> {
+    fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Poll::Ready(Ok(self.get_mut().remaining()))
+    }
+
+    fn consume(self: Pin<&mut Self>, amount: usize) {
+        let this = self.get_mut();
+
+        this.position = this.position.saturating_add(amount);
+    }
+}
+
+impl<M: AsRef<[u8]> + Unpin> AsyncSeek for AsyncMmapReader<M> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+
+        this.position = seek_position(this.position, this.inner.as_ref().len(), position)?;
+
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.position as u64))
+    }
+}
+
+/// An adapter that exposes a mutable memory mapping through [`AsyncWrite`] and [`AsyncSeek`].
+///
+/// Writes are copied into the mapping directly. Flushing the writer synchronously flushes a
+/// file-backed mapping on the blocking thread pool of Tokio using
+/// [`tokio::task::spawn_blocking`], such that the runtime does not stall while the pages are
+/// being written back. Flushing an anonymous mapping completes immediately.
+///
+/// Writing past the end of the mapping does not grow the mapping, but writes zero bytes instead.
+pub struct AsyncMmapWriter {
+    inner: Option<MmapMut>,
+    position: usize,
+    flush: Option<JoinHandle<(MmapMut, Result<(), Error>)>>,
+}
+
+impl AsyncMmapWriter {
+    /// Constructs the writer from a mutable memory mapping, starting at the beginning of the
+    /// mapping.
+    pub fn new(inner: MmapMut) -> Self {
+        Self {
+            inner: Some(inner),
+            position: 0,
+            flush: None,
+        }
+    }
+
+    /// Yields the current position of the writer within the mapping.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Consumes the writer, returning the underlying memory mapping. Returns `None` if the writer
+    /// is still in the middle of flushing the mapping.
+    pub fn into_inner(self) -> Option<MmapMut> {
+        self.inner
+    }
+
+    /// Polls the pending flush, if any, such that the mapping is available again.
+    fn poll_pending_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let handle = match self.flush.as_mut() {
+            Some(handle) => handle,
+            _ => return Poll::Ready(Ok(())),
+        };
+
+        let result = match Pin::new(handle).poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(result) => result,
+        };
+
+        self.flush = None;
+
+        match result {
+            Ok((inner, result)) => {
+                self.inner = Some(inner);
+                Poll::Ready(result.map_err(io::Error::other))
+            }
+            Err(e) => Poll::Ready(Err(io::Error::other(e))),
+        }
+    }
+
+    /// Yields the mapping, or an error if the mapping has been lost due to a panic while flushing.
+    fn mapping(&mut self) -> io::Result<&mut MmapMut> {
+        self.inner.as_mut().ok_or_else(|| io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            "the memory mapping was lost while flushing",
+        ))
+    }
+}
+
+impl AsyncWrite for AsyncMmapWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // Wait for any pending flush to hand back the mapping.
+        match this.poll_pending_flush(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Ready(Ok(())) => (),
+        }
+
+        let position = this.position;
+        let mapping = this.mapping()?;
+        let start = position.min(mapping.size());
+        let size = buf.len().min(mapping.size() - start);
+
+        mapping[start..start + size].copy_from_slice(&buf[..size]);
+        this.position = start + size;
+
+        Poll::Ready(Ok(size))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.flush.is_none() {
+            let mapping = this.mapping()?;
+
+            // There is nothing to write back for anonymous mappings.
+            if mapping.file().is_none() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mapping = this.inner.take().unwrap();
+
+            this.flush = Some(tokio::task::spawn_blocking(move || {
+                let result = mapping.flush(0..mapping.size());
+
+                (mapping, result)
+            }));
+        }
+
+        this.poll_pending_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl AsyncSeek for AsyncMmapWriter {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let size = this.mapping()?.size();
+
+        this.position = seek_position(this.position, size, position)?;
+
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.position as u64))
+    }
+}
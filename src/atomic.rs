@@ -0,0 +1,60 @@
+//! This module implements views of memory mappings as slices of atomics, such that
+//! synchronization primitives can be built on mappings that are shared with other processes.
+
+use crate::error::Error;
+use crate::MmapMut;
+use std::mem::{align_of, size_of};
+use std::sync::atomic::{AtomicI16, AtomicI32, AtomicI8, AtomicIsize, AtomicU16, AtomicU32, AtomicU8, AtomicUsize};
+#[cfg(target_has_atomic = "64")]
+use std::sync::atomic::{AtomicI64, AtomicU64};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// The atomic types that a memory mapping can be viewed as, which have the same in-memory
+/// representation as their underlying integer types. This trait is sealed and cannot be
+/// implemented outside of this crate.
+pub trait AtomicValue: Sync + sealed::Sealed {}
+
+macro_rules! atomic_value_impl {
+    ($($t:ty),+) => {
+        $(
+            impl sealed::Sealed for $t {}
+            impl AtomicValue for $t {}
+        )+
+    }
+}
+
+atomic_value_impl!(AtomicU8, AtomicU16, AtomicU32, AtomicUsize, AtomicI8, AtomicI16, AtomicI32, AtomicIsize);
+#[cfg(target_has_atomic = "64")]
+atomic_value_impl!(AtomicU64, AtomicI64);
+
+impl MmapMut {
+    /// Views the memory mapping as a slice of atomics of type `A`, e.g. [`AtomicU32`]. This allows
+    /// the mapping to be used for synchronization with other processes that share the mapping,
+    /// provided that they also access the memory atomically.
+    ///
+    /// This borrows the mapping mutably, such that the contents cannot be accessed through
+    /// non-atomic references while the slice is alive. The slice itself may be shared between
+    /// threads.
+    ///
+    /// Returns [`Error::Unaligned`] if the address of the mapping is not aligned to the alignment
+    /// of `A`, or if the size of the mapping is not a multiple of the size of `A`.
+    pub fn as_atomic_slice<A: AtomicValue>(&mut self) -> Result<&[A], Error> {
+        let address = self.as_ptr() as usize;
+        let size = self.size();
+
+        if !address.is_multiple_of(align_of::<A>()) {
+            return Err(Error::Unaligned(address, align_of::<A>()));
+        }
+
+        if !size.is_multiple_of(size_of::<A>()) {
+            return Err(Error::Unaligned(size, size_of::<A>()));
+        }
+
+        Ok(unsafe {
+            std::slice::from_raw_parts(self.as_mut_ptr() as *const A, size / size_of::<A>())
+        })
+    }
+}
@@ -0,0 +1,52 @@
+//! This module implements a report of the memory mapping features that the current platform
+//! supports, such that portable applications can pick a strategy up front rather than probing
+//! for support through failing system calls.
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// The memory mapping features that the operating system and the kernel support. See
+/// [`capabilities()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Capabilities {
+    /// The size of the pages in bytes. See [`crate::page_size()`].
+    pub page_size: usize,
+    /// The granularity at which mappings can be placed in bytes. See
+    /// [`crate::allocation_granularity()`].
+    pub allocation_granularity: usize,
+    /// The sizes of the huge pages in bytes, in ascending order. Whether huge pages of a size are
+    /// actually available may depend on the configuration of the system, see
+    /// [`crate::MmapOptions::check_huge_pages()`].
+    pub huge_page_sizes: Vec<usize>,
+    /// Whether memory can be mapped at a fixed address without replacing the pages that have
+    /// been mapped there already, i.e. `MAP_FIXED_NOREPLACE` on Linux 4.17 or newer,
+    /// `MAP_FIXED | MAP_EXCL` on FreeBSD and always on Microsoft Windows.
+    pub fixed_noreplace: bool,
+    /// Whether anonymous memory can be backed by a file descriptor using `memfd_create()`.
+    pub memfd: bool,
+    /// Whether files created using `memfd_create()` can be sealed against further changes.
+    pub sealing: bool,
+    /// Whether memory has to be mapped with `MAP_JIT` to be both writable and executable, as is
+    /// the case with the hardened runtime on macOS and iOS.
+    pub jit: bool,
+    /// Whether the operating system can track the pages that have been written to, i.e.
+    /// `MEM_WRITE_WATCH` on Microsoft Windows.
+    pub write_watch: bool,
+    /// The number of NUMA nodes, which is one on systems without NUMA or without the APIs to
+    /// query the nodes.
+    pub numa_nodes: usize,
+}
+
+/// Reports the memory mapping features that the current platform supports. As this queries the
+/// kernel and may probe some of the features, the result should be cached by the caller if it is
+/// needed repeatedly. With the `fake` feature, this reports the in-memory backend, which supports
+/// none of the features.
+pub fn capabilities() -> Capabilities {
+    platform::capabilities()
+}
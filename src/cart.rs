@@ -0,0 +1,44 @@
+//! This module implements the traits needed to use memory mappings as the cart of a [`Yoke`],
+//! such that zero-copy structures parsed from the mapping can be stored alongside the mapping
+//! that owns their bytes.
+//!
+//! To share the parsed structure between multiple owners, wrap the mapping in an [`Arc`] first,
+//! as [`Arc`] is a cloneable cart.
+//!
+//! [`Arc`]: std::sync::Arc
+
+use crate::{Mmap, MmapMut};
+use stable_deref_trait::StableDeref;
+use yoke::{Yoke, Yokeable};
+
+// The pages of the mapping do not move when the mapping itself is moved, such that the slice
+// returned by `Deref` stays valid for as long as the mapping is alive.
+unsafe impl StableDeref for Mmap {}
+unsafe impl StableDeref for MmapMut {}
+
+impl Mmap {
+    /// Parses a zero-copy structure from the bytes of this mapping, and attaches the structure to
+    /// the mapping, such that the mapping is kept alive for as long as the structure is.
+    ///
+    /// This is equivalent to [`Yoke::attach_to_cart()`].
+    pub fn attach<Y, F>(self, f: F) -> Yoke<Y, Mmap>
+    where
+        Y: for<'a> Yokeable<'a>,
+        F: for<'de> FnOnce(&'de [u8]) -> <Y as Yokeable<'de>>::Output,
+    {
+        Yoke::attach_to_cart(self, f)
+    }
+
+    /// Parses a zero-copy structure from the bytes of this mapping, and attaches the structure to
+    /// the mapping, such that the mapping is kept alive for as long as the structure is. In case
+    /// parsing fails, the error returned by the parser is returned instead.
+    ///
+    /// This is equivalent to [`Yoke::try_attach_to_cart()`].
+    pub fn try_attach<Y, E, F>(self, f: F) -> Result<Yoke<Y, Mmap>, E>
+    where
+        Y: for<'a> Yokeable<'a>,
+        F: for<'de> FnOnce(&'de [u8]) -> Result<<Y as Yokeable<'de>>::Output, E>,
+    {
+        Yoke::try_attach_to_cart(self, f)
+    }
+}
@@ -0,0 +1,123 @@
+//! This module implements sharing a mapping with a child process, by letting the child inherit
+//! the file backing the mapping and describing the mapping through environment variables, such
+//! that multi-process applications do not have to write that boilerplate themselves.
+
+use crate::error::Error;
+use crate::mmap::allocation_granularity;
+use crate::MmapOptions;
+use std::fs::File;
+use std::process::{Child, Command};
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// The environment variable that holds the inherited file descriptor on Unix, or the inherited
+/// handle on Microsoft Windows, in decimal.
+pub const SHARED_HANDLE_VAR: &str = "MMAP_RS_SHARED_HANDLE";
+
+/// The environment variable that holds the offset within the file at which the mapping starts.
+pub const SHARED_OFFSET_VAR: &str = "MMAP_RS_SHARED_OFFSET";
+
+/// The environment variable that holds the size of the mapping in bytes.
+pub const SHARED_SIZE_VAR: &str = "MMAP_RS_SHARED_SIZE";
+
+/// The environment variable that holds the address at which the child has to map the memory, if
+/// the mapping has to be at the same address as in the parent.
+pub const SHARED_ADDRESS_VAR: &str = "MMAP_RS_SHARED_ADDRESS";
+
+/// Describes the mapping through the environment of the command, and spawns it as a child process
+/// that inherits the file.
+pub(crate) fn spawn(
+    command: &mut Command,
+    file: Option<&File>,
+    offset: u64,
+    size: usize,
+    address: Option<usize>,
+) -> Result<Child, Error> {
+    let file = file.ok_or_else(|| Error::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "only mappings backed by a file can be shared with a child process",
+    )))?;
+
+    command
+        .env(SHARED_OFFSET_VAR, offset.to_string())
+        .env(SHARED_SIZE_VAR, size.to_string());
+
+    match address {
+        Some(address) => command.env(SHARED_ADDRESS_VAR, address.to_string()),
+        _ => command.env_remove(SHARED_ADDRESS_VAR),
+    };
+
+    platform::spawn_inheriting(command, file, SHARED_HANDLE_VAR)
+}
+
+/// Parses the value of the environment variable, if it has been set.
+fn var<T: std::str::FromStr>(name: &str) -> Result<Option<T>, Error> {
+    let value = match std::env::var(name) {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(e) => return Err(Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))),
+    };
+
+    value.parse().map(Some).map_err(|_| Error::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("the environment variable {} is invalid: {:?}", name, value),
+    )))
+}
+
+impl MmapOptions {
+    /// Returns the options to map the memory that the parent process shared with this process
+    /// through [`Mmap::spawn_with()`](crate::Mmap::spawn_with), or `None` if this process has not
+    /// been spawned that way. The options are configured with the inherited file, and with the
+    /// offset and size of the mapping of the parent, such that the memory can be mapped using
+    /// [`MmapOptions::map()`] or [`MmapOptions::map_mut()`], depending on the access that the
+    /// parent has opened the file with.
+    ///
+    /// If the parent requires the memory to be mapped at the same address, e.g. as the memory
+    /// contains pointers, mapping fails with an error of the kind
+    /// [`std::io::ErrorKind::AddrNotAvailable`] if the address is in use in this process.
+    ///
+    /// # Safety
+    ///
+    /// This takes ownership of the inherited file descriptor or handle, so this must be called at
+    /// most once, and the environment variables must not have been tampered with. See
+    /// [`MmapOptions::with_file()`] for the safety requirements of mapping the file, which the
+    /// parent shares with this process.
+    pub unsafe fn from_parent() -> Result<Option<Self>, Error> {
+        let handle = match var::<u64>(SHARED_HANDLE_VAR)? {
+            Some(handle) => handle,
+            _ => return Ok(None),
+        };
+
+        let missing = |name: &str| Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("the environment variable {} is missing", name),
+        ));
+
+        let offset = var::<u64>(SHARED_OFFSET_VAR)?.ok_or_else(|| missing(SHARED_OFFSET_VAR))?;
+        let size = var::<usize>(SHARED_SIZE_VAR)?.ok_or_else(|| missing(SHARED_SIZE_VAR))?;
+        let file = platform::inherited_file(handle)?;
+
+        let mut options = MmapOptions::new(size).with_file(file, offset);
+
+        // The address is merely a hint on Unix, so confine the mapping to the exact range.
+        if let Some(address) = var::<usize>(SHARED_ADDRESS_VAR)? {
+            let granularity = allocation_granularity();
+            let end = address
+                .checked_add(size.div_ceil(granularity) * granularity)
+                .ok_or(Error::OutOfBounds(address..usize::MAX))?;
+
+            options = options
+                .with_address(address)
+                .with_address_range(address..end);
+        }
+
+        Ok(Some(options))
+    }
+}
@@ -0,0 +1,62 @@
+//! This module implements mapping a large file one window at a time, such that streaming
+//! processors can handle files of any size with a bounded amount of address space.
+
+use crate::error::Error;
+use crate::{Mmap, MmapOptions};
+
+/// An iterator over consecutive read-only mappings of a file. See [`MmapOptions::map_chunks()`].
+pub struct MmapChunks {
+    options: MmapOptions,
+    chunk_size: usize,
+    /// The start of the next chunk, relative to the offset of the options.
+    position: usize,
+}
+
+impl MmapChunks {
+    pub(crate) fn new(options: MmapOptions, chunk_size: usize) -> Self {
+        Self {
+            options,
+            chunk_size,
+            position: 0,
+        }
+    }
+
+    /// Returns the start of the next chunk, relative to the offset the file is mapped from.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl Iterator for MmapChunks {
+    type Item = Result<Mmap, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let size = self.options.inner.size();
+
+        if self.position >= size {
+            return None;
+        }
+
+        let len = self.chunk_size.min(size - self.position);
+        let result = self.options.inner
+            .window(self.position, len)
+            .and_then(|inner| MmapOptions { inner }.map());
+
+        // Stop after the first failure, rather than failing for every remaining chunk.
+        self.position = match result {
+            Ok(_) => self.position + len,
+            Err(_) => size,
+        };
+
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.options.inner.size() - self.position).div_ceil(self.chunk_size);
+
+        (0, Some(remaining))
+    }
+}
+
+impl std::iter::FusedIterator for MmapChunks {}
@@ -0,0 +1,437 @@
+//! This module implements a compatibility layer that mirrors the API of the [memmap2] crate on top
+//! of this crate, such that projects can migrate by changing their imports.
+//!
+//! Contrary to the rest of this crate, the types in this module return [`std::io::Error`], just
+//! like [memmap2] does. The main difference is that [`Mmap::lock()`] and [`MmapMut::lock()`]
+//! require mutable access to the mapping.
+//!
+//! [memmap2]: https://docs.rs/memmap2
+
+use crate::{MmapFlags, PageSize};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+/// A memory map builder, providing advanced options and flags for specifying memory map behavior.
+#[derive(Clone, Debug)]
+pub struct MmapOptions {
+    offset: u64,
+    len: Option<usize>,
+    flags: MmapFlags,
+    page_bits: Option<u8>,
+}
+
+impl Default for MmapOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmapOptions {
+    /// Creates a new set of options for configuring and creating a memory map.
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            len: None,
+            flags: MmapFlags::empty(),
+            page_bits: None,
+        }
+    }
+
+    /// Configures the memory map to start at byte `offset` from the beginning of the file.
+    ///
+    /// This option has no effect on anonymous memory maps.
+    pub fn offset(&mut self, offset: u64) -> &mut Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Configures the created memory mapped buffer to be `len` bytes long.
+    ///
+    /// This option is mandatory for anonymous memory maps. For file-backed memory maps, the
+    /// length will default to the file length minus the offset.
+    pub fn len(&mut self, len: usize) -> &mut Self {
+        self.len = Some(len);
+        self
+    }
+
+    /// Configures the anonymous memory map to be suitable for a process or thread stack.
+    pub fn stack(&mut self) -> &mut Self {
+        self.flags |= MmapFlags::STACK;
+        self
+    }
+
+    /// Configures the anonymous memory map to be allocated using huge pages. The page size can
+    /// optionally be specified in log2 notation.
+    pub fn huge(&mut self, page_bits: Option<u8>) -> &mut Self {
+        self.flags |= MmapFlags::HUGE_PAGES;
+        self.page_bits = page_bits;
+        self
+    }
+
+    /// Populates (prefaults) the page tables for the mapping.
+    pub fn populate(&mut self) -> &mut Self {
+        self.flags |= MmapFlags::POPULATE;
+        self
+    }
+
+    /// Sets up the options of this crate. Returns `None` if the mapping would be empty.
+    fn options(
+        &self,
+        file: Option<&File>,
+        flags: MmapFlags,
+    ) -> io::Result<Option<crate::MmapOptions>> {
+        let len = match (self.len, file) {
+            (Some(len), Some(file)) => {
+                let file_len = file.metadata()?.len();
+
+                // Accessing the pages past the end of the file raises `SIGBUS`.
+                match self.offset.checked_add(len as u64) {
+                    Some(end) if end <= file_len => len,
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "memory map length is larger than the file",
+                        ))
+                    }
+                }
+            }
+            (Some(len), None) => len,
+            (None, Some(file)) => {
+                let file_len = file.metadata()?.len();
+
+                if self.offset > file_len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "memory map offset is larger than length",
+                    ));
+                }
+
+                (file_len - self.offset) as usize
+            }
+            (None, None) => 0,
+        };
+
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let mut options = crate::MmapOptions::new(len).with_flags(self.flags | flags);
+
+        if let Some(page_bits) = self.page_bits {
+            options = options.with_page_size(PageSize(page_bits as usize));
+        }
+
+        if let Some(file) = file {
+            options = unsafe { options.with_file(file.try_clone()?, self.offset) };
+        }
+
+        Ok(Some(options))
+    }
+
+    /// Creates a read-only memory map backed by a file.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapOptions::with_file()`].
+    pub unsafe fn map(&self, file: &File) -> io::Result<Mmap> {
+        let options = match self.options(Some(file), MmapFlags::empty())? {
+            Some(options) => options,
+            _ => return Ok(Mmap { inner: None }),
+        };
+
+        Ok(Mmap {
+            inner: Some(options.map()?),
+        })
+    }
+
+    /// Creates a readable and executable memory map backed by a file.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapOptions::with_file()`].
+    #[cfg(feature = "exec")]
+    pub unsafe fn map_exec(&self, file: &File) -> io::Result<Mmap> {
+        let options = match self.options(Some(file), MmapFlags::empty())? {
+            Some(options) => options,
+            _ => return Ok(Mmap { inner: None }),
+        };
+
+        Ok(Mmap {
+            inner: Some(options.map_exec()?),
+        })
+    }
+
+    /// Creates a writeable memory map backed by a file.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapOptions::with_file()`].
+    pub unsafe fn map_mut(&self, file: &File) -> io::Result<MmapMut> {
+        let options = match self.options(Some(file), MmapFlags::empty())? {
+            Some(options) => options,
+            _ => return Ok(MmapMut { inner: None }),
+        };
+
+        Ok(MmapMut {
+            inner: Some(options.map_mut()?),
+        })
+    }
+
+    /// Creates a copy-on-write memory map backed by a file. Data written to the memory map will
+    /// not be visible by other processes, and will not be carried through to the underlying file.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapOptions::with_file()`].
+    pub unsafe fn map_copy(&self, file: &File) -> io::Result<MmapMut> {
+        let options = match self.options(Some(file), MmapFlags::COPY_ON_WRITE)? {
+            Some(options) => options,
+            _ => return Ok(MmapMut { inner: None }),
+        };
+
+        Ok(MmapMut {
+            inner: Some(options.map_mut()?),
+        })
+    }
+
+    /// Creates a copy-on-write read-only memory map backed by a file.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapOptions::with_file()`].
+    pub unsafe fn map_copy_read_only(&self, file: &File) -> io::Result<Mmap> {
+        let options = match self.options(Some(file), MmapFlags::COPY_ON_WRITE)? {
+            Some(options) => options,
+            _ => return Ok(Mmap { inner: None }),
+        };
+
+        Ok(Mmap {
+            inner: Some(options.map()?),
+        })
+    }
+
+    /// Creates an anonymous memory map. The memory map length should be configured using
+    /// [`MmapOptions::len()`] before creating an anonymous memory map.
+    pub fn map_anon(&self) -> io::Result<MmapMut> {
+        let options = match self.options(None, MmapFlags::COPY_ON_WRITE)? {
+            Some(options) => options,
+            _ => return Ok(MmapMut { inner: None }),
+        };
+
+        Ok(MmapMut {
+            inner: Some(options.map_mut()?),
+        })
+    }
+}
+
+/// A handle to an immutable memory mapped buffer.
+pub struct Mmap {
+    inner: Option<crate::Mmap>,
+}
+
+impl Mmap {
+    /// Creates a read-only memory map backed by a file.
+    ///
+    /// This is equivalent to calling `MmapOptions::new().map(file)`.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapOptions::with_file()`].
+    pub unsafe fn map(file: &File) -> io::Result<Mmap> {
+        MmapOptions::new().map(file)
+    }
+
+    /// Transition the memory map to be writable.
+    pub fn make_mut(self) -> io::Result<MmapMut> {
+        let inner = match self.inner {
+            Some(inner) => Some(inner.make_mut().map_err(|(_, e)| e)?),
+            _ => None,
+        };
+
+        Ok(MmapMut { inner })
+    }
+
+    /// Transition the memory map to be readable and executable.
+    #[cfg(feature = "exec")]
+    pub fn make_exec(self) -> io::Result<Mmap> {
+        let inner = match self.inner {
+            Some(inner) => Some(inner.make_exec().map_err(|(_, e)| e)?),
+            _ => None,
+        };
+
+        Ok(Mmap { inner })
+    }
+
+    /// Locks the whole memory map into RAM.
+    pub fn lock(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => Ok(inner.lock()?),
+            _ => Ok(()),
+        }
+    }
+
+    /// Unlocks the whole memory map.
+    pub fn unlock(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => Ok(inner.unlock()?),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self.inner.as_ref() {
+            Some(inner) => inner,
+            _ => &[],
+        }
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl fmt::Debug for Mmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mmap")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A handle to a mutable memory mapped buffer.
+pub struct MmapMut {
+    inner: Option<crate::MmapMut>,
+}
+
+impl MmapMut {
+    /// Creates a writeable memory map backed by a file.
+    ///
+    /// This is equivalent to calling `MmapOptions::new().map_mut(file)`.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapOptions::with_file()`].
+    pub unsafe fn map_mut(file: &File) -> io::Result<MmapMut> {
+        MmapOptions::new().map_mut(file)
+    }
+
+    /// Creates an anonymous memory map.
+    ///
+    /// This is equivalent to calling `MmapOptions::new().len(length).map_anon()`.
+    pub fn map_anon(length: usize) -> io::Result<MmapMut> {
+        MmapOptions::new().len(length).map_anon()
+    }
+
+    /// Flushes outstanding memory map modifications to disk.
+    pub fn flush(&self) -> io::Result<()> {
+        self.flush_range(0, self.len())
+    }
+
+    /// Asynchronously flushes outstanding memory map modifications to disk.
+    pub fn flush_async(&self) -> io::Result<()> {
+        self.flush_async_range(0, self.len())
+    }
+
+    /// Flushes outstanding memory map modifications in the range to disk.
+    pub fn flush_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        match self.inner.as_ref() {
+            Some(inner) => Ok(inner.flush(offset..offset + len)?),
+            _ => Ok(()),
+        }
+    }
+
+    /// Asynchronously flushes outstanding memory map modifications in the range to disk.
+    pub fn flush_async_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        match self.inner.as_ref() {
+            Some(inner) => Ok(inner.flush_async(offset..offset + len)?),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an immutable version of this memory mapped buffer.
+    pub fn make_read_only(self) -> io::Result<Mmap> {
+        let inner = match self.inner {
+            Some(inner) => Some(inner.make_read_only().map_err(|(_, e)| e)?),
+            _ => None,
+        };
+
+        Ok(Mmap { inner })
+    }
+
+    /// Transition the memory map to be readable and executable.
+    #[cfg(feature = "exec")]
+    pub fn make_exec(self) -> io::Result<Mmap> {
+        let inner = match self.inner {
+            Some(inner) => Some(inner.make_exec().map_err(|(_, e)| e)?),
+            _ => None,
+        };
+
+        Ok(Mmap { inner })
+    }
+
+    /// Locks the whole memory map into RAM.
+    pub fn lock(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => Ok(inner.lock()?),
+            _ => Ok(()),
+        }
+    }
+
+    /// Unlocks the whole memory map.
+    pub fn unlock(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => Ok(inner.unlock()?),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Deref for MmapMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self.inner.as_ref() {
+            Some(inner) => inner,
+            _ => &[],
+        }
+    }
+}
+
+impl DerefMut for MmapMut {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self.inner.as_mut() {
+            Some(inner) => inner,
+            _ => &mut [],
+        }
+    }
+}
+
+impl AsRef<[u8]> for MmapMut {
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl AsMut<[u8]> for MmapMut {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.deref_mut()
+    }
+}
+
+impl fmt::Debug for MmapMut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapMut")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,104 @@
+//! This module implements bulk copies within and between memory mappings with bounds checking,
+//! such that data can be moved between mapped regions without resorting to raw pointers.
+
+use crate::error::Error;
+use crate::mmap::check_range;
+use crate::volatile::check_bytes;
+use crate::MmapMut;
+use std::ops::Range;
+
+/// The size from which non-overlapping copies use non-temporal stores on x86-64, such that
+/// copying a large amount of data does not evict the working set from the caches.
+#[cfg(target_arch = "x86_64")]
+const NONTEMPORAL_THRESHOLD: usize = 4 * 1024 * 1024;
+
+/// Copies `len` bytes from `src` to `dst`, which must not overlap.
+unsafe fn copy_nonoverlapping(src: *const u8, dst: *mut u8, len: usize) {
+    #[cfg(target_arch = "x86_64")]
+    if len >= NONTEMPORAL_THRESHOLD {
+        return copy_nontemporal(src, dst, len);
+    }
+
+    std::ptr::copy_nonoverlapping(src, dst, len);
+}
+
+/// Copies `len` bytes from `src` to `dst`, which must not overlap, using non-temporal stores for
+/// the part of the destination that is aligned to 16 bytes.
+#[cfg(target_arch = "x86_64")]
+unsafe fn copy_nontemporal(src: *const u8, dst: *mut u8, len: usize) {
+    use std::arch::x86_64::{__m128i, _mm_loadu_si128, _mm_sfence, _mm_stream_si128};
+
+    let head = dst.align_offset(16).min(len);
+    std::ptr::copy_nonoverlapping(src, dst, head);
+
+    let mut offset = head;
+
+    while offset + 16 <= len {
+        let value = _mm_loadu_si128(src.add(offset) as *const __m128i);
+        _mm_stream_si128(dst.add(offset) as *mut __m128i, value);
+        offset += 16;
+    }
+
+    std::ptr::copy_nonoverlapping(src.add(offset), dst.add(offset), len - offset);
+
+    // Non-temporal stores are weakly ordered, so make them visible before returning.
+    _mm_sfence();
+}
+
+impl MmapMut {
+    /// Copies the bytes in the given range to the given offset within the mapping. The source
+    /// and the destination may overlap, like [`slice::copy_within()`].
+    ///
+    /// Large copies between ranges that do not overlap use non-temporal stores on x86-64, such
+    /// that they do not evict the working set from the caches.
+    ///
+    /// Returns [`Error::OutOfBounds`] without copying anything if either the source or the
+    /// destination is out of bounds.
+    pub fn copy_within(&mut self, range: Range<usize>, dest_offset: usize) -> Result<(), Error> {
+        check_range(&range, self.size())?;
+        check_bytes(self.size(), dest_offset, range.len())?;
+
+        let len = range.len();
+        let src = unsafe { self.as_ptr().add(range.start) };
+        let dst = unsafe { self.as_mut_ptr().add(dest_offset) };
+
+        if range.start.max(dest_offset) < range.end.min(dest_offset + len) {
+            unsafe { std::ptr::copy(src, dst, len) };
+        } else {
+            unsafe { copy_nonoverlapping(src, dst, len) };
+        }
+
+        Ok(())
+    }
+
+    /// Copies the bytes in the given range of another mapping to the given offset within this
+    /// mapping. The source can be any type that dereferences to bytes, e.g. an [`crate::Mmap`],
+    /// an [`MmapMut`] or one of their guards.
+    ///
+    /// Large copies use non-temporal stores on x86-64, such that they do not evict the working
+    /// set from the caches.
+    ///
+    /// Returns [`Error::OutOfBounds`] without copying anything if either the source or the
+    /// destination is out of bounds.
+    pub fn copy_from_mmap<M: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        dest_offset: usize,
+        source: &M,
+        range: Range<usize>,
+    ) -> Result<(), Error> {
+        let source = source.as_ref();
+
+        check_range(&range, source.len())?;
+        check_bytes(self.size(), dest_offset, range.len())?;
+
+        unsafe {
+            copy_nonoverlapping(
+                source.as_ptr().add(range.start),
+                self.as_mut_ptr().add(dest_offset),
+                range.len(),
+            )
+        };
+
+        Ok(())
+    }
+}
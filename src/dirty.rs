@@ -0,0 +1,270 @@
+//! This module implements tracking of the pages that have been written to, by write-protecting
+//! the pages and recording the pages that fault, such that snapshots and live migration can copy
+//! only the pages that changed on any platform.
+
+use crate::error::Error;
+use crate::mmap::page_size;
+use crate::MmapMut;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+/// The maximum number of mappings that can be tracked at the same time, as the fault handler
+/// cannot allocate and therefore looks the mappings up in a table of a fixed size.
+const MAX_REGIONS: usize = 64;
+
+/// A mapping that is being tracked, which the fault handler reads without taking any locks. A
+/// start of zero marks an unused entry.
+struct Region {
+    start: AtomicUsize,
+    end: AtomicUsize,
+    bitmap: AtomicPtr<AtomicU64>,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const UNUSED: Region = Region {
+    start: AtomicUsize::new(0),
+    end: AtomicUsize::new(0),
+    bitmap: AtomicPtr::new(std::ptr::null_mut()),
+};
+
+static REGIONS: [Region; MAX_REGIONS] = [UNUSED; MAX_REGIONS];
+
+/// Serializes adding and removing regions, which the fault handler does not need to observe
+/// atomically, as it only looks at regions that are fully set up.
+static REGISTRATION: Mutex<()> = Mutex::new(());
+
+/// Records a write fault at the given address if it lies within a tracked mapping, returning the
+/// start of the page that has to be made writable again. Returns `None` if the fault is not ours,
+/// such that it should be passed on to the previous handler.
+///
+/// This is called from the fault handler, so it must not allocate or take any locks.
+pub(crate) fn record_write_fault(address: usize) -> Option<usize> {
+    let page_size = page_size();
+
+    for region in &REGIONS {
+        let start = region.start.load(Ordering::Acquire);
+
+        if start == 0 || address < start || address >= region.end.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let page = (address - start) / page_size;
+        let bitmap = region.bitmap.load(Ordering::Relaxed);
+
+        unsafe { &*bitmap.add(page / 64) }.fetch_or(1 << (page % 64), Ordering::Relaxed);
+
+        return Some(start + page * page_size);
+    }
+
+    None
+}
+
+/// Tracks the pages of a mapping that have been written to. See [`DirtyTracker::new()`].
+pub struct DirtyTracker {
+    mapping: ManuallyDrop<MmapMut>,
+    /// The index into the table of regions.
+    index: usize,
+    /// The offset of the mapping within its first page, as file mappings may start at an
+    /// unaligned offset. The pages are tracked from the start of the first page.
+    skew: usize,
+    /// One bit per page, which is set by the fault handler when the page is written to.
+    bitmap: Vec<AtomicU64>,
+}
+
+impl DirtyTracker {
+    /// Starts tracking the pages of the mapping that are written to. The mapping is made
+    /// read-only, and the first write to a page raises a fault that records the page as dirty and
+    /// makes the page writable again, such that only the first write to every page is slowed
+    /// down. Use [`DirtyTracker::take_dirty()`] to collect the dirty pages.
+    ///
+    /// This works on every platform, unlike the write tracking of the operating system, such as
+    /// soft-dirty bits on Linux, which are not always available. On Unix, the faults are caught by
+    /// a handler for `SIGSEGV` and `SIGBUS`, which passes on faults outside of the tracked
+    /// mappings to the handler that was installed before, e.g. the stack overflow handler of the
+    /// Rust runtime. On Microsoft Windows, the faults are caught by a vectored exception handler.
+    /// The handlers are installed the first time a mapping is tracked, and are never removed.
+    /// With the `fake` feature, the writes cannot be tracked, so every page is reported as dirty.
+    ///
+    /// The kernel does not raise faults for its own accesses, such that system calls writing into
+    /// pages that have not been written to yet fail instead. E.g. `read(2)` into the tracked
+    /// mapping fails with `EFAULT` on Unix, so read into a separate buffer and copy it over.
+    ///
+    /// Up to 64 mappings can be tracked at the same time. Mappings backed by huge pages cannot be
+    /// tracked, as their pages cannot be protected individually. In case of failure, this returns
+    /// the ownership of the mapping.
+    pub fn new(mapping: MmapMut) -> Result<Self, (MmapMut, Error)> {
+        let skew = mapping.as_ptr() as usize % page_size();
+        let pages = (mapping.size() + skew).div_ceil(page_size());
+        let bitmap: Vec<AtomicU64> = (0..pages.div_ceil(64)).map(|_| AtomicU64::new(0)).collect();
+
+        #[cfg(feature = "fake")]
+        let index = 0;
+
+        #[cfg(not(feature = "fake"))]
+        let index = match register(&mapping, skew, &bitmap) {
+            Ok(index) => index,
+            Err(e) => return Err((mapping, e)),
+        };
+
+        Ok(Self {
+            mapping: ManuallyDrop::new(mapping),
+            index,
+            skew,
+            bitmap,
+        })
+    }
+
+    /// Returns the ranges of the pages that have been written to since the tracking started or
+    /// since the previous call, where adjacent pages are merged into a single range. The pages
+    /// are write-protected again, such that the next write to them is recorded.
+    pub fn take_dirty(&mut self) -> Result<Vec<Range<usize>>, Error> {
+        let size = self.mapping.size();
+
+        #[cfg(feature = "fake")]
+        return Ok(Some(0..size).filter(|range| !range.is_empty()).into_iter().collect());
+
+        #[cfg(not(feature = "fake"))]
+        {
+            let page_size = page_size();
+            let skew = self.skew;
+            let mut ranges: Vec<Range<usize>> = vec![];
+
+            // The ranges are relative to the start of the first page, until they have been
+            // write-protected again.
+            for (index, word) in self.bitmap.iter().enumerate() {
+                let mut bits = word.swap(0, Ordering::Relaxed);
+
+                while bits != 0 {
+                    let page = index * 64 + bits.trailing_zeros() as usize;
+                    let start = page * page_size;
+                    let end = (start + page_size).min(size + skew);
+
+                    match ranges.last_mut() {
+                        Some(last) if last.end == start => last.end = end,
+                        _ => ranges.push(start..end),
+                    }
+
+                    bits &= bits - 1;
+                }
+            }
+
+            let ptr = unsafe { self.mapping.as_mut_ptr().sub(skew) };
+
+            for range in &ranges {
+                unsafe {
+                    platform::write_protect(ptr.add(range.start), range.end - range.start, true)
+                }?;
+            }
+
+            Ok(ranges
+                .into_iter()
+                .map(|range| range.start.saturating_sub(skew)..range.end - skew)
+                .collect())
+        }
+    }
+
+    /// Returns a reference to the mapping that is being tracked.
+    #[inline]
+    pub fn get_ref(&self) -> &MmapMut {
+        &self.mapping
+    }
+
+    /// Stops tracking the mapping and returns it, with all of its pages writable.
+    pub fn into_inner(mut self) -> MmapMut {
+        self.release();
+
+        let mapping = unsafe { ManuallyDrop::take(&mut self.mapping) };
+        std::mem::forget(self);
+
+        mapping
+    }
+
+    /// Removes the mapping from the table of regions and makes the mapping writable again. The
+    /// bitmap is released afterwards, as the fault handler may still be looking at it until the
+    /// region has been removed.
+    fn release(&mut self) {
+        #[cfg(not(feature = "fake"))]
+        {
+            let _guard = REGISTRATION.lock().unwrap_or_else(|e| e.into_inner());
+            let region = &REGIONS[self.index];
+
+            region.start.store(0, Ordering::Release);
+            region.bitmap.store(std::ptr::null_mut(), Ordering::Relaxed);
+
+            let _ = unsafe {
+                platform::write_protect(
+                    self.mapping.as_mut_ptr().sub(self.skew),
+                    self.mapping.size() + self.skew,
+                    false,
+                )
+            };
+        }
+
+        self.bitmap = vec![];
+    }
+}
+
+/// Adds the pages of the mapping, which start `skew` bytes before the mapping, to the table of
+/// regions and write-protects them.
+#[cfg(not(feature = "fake"))]
+fn register(mapping: &MmapMut, skew: usize, bitmap: &[AtomicU64]) -> Result<usize, Error> {
+    platform::install_write_fault_handler()?;
+
+    let _guard = REGISTRATION.lock().unwrap_or_else(|e| e.into_inner());
+
+    let index = REGIONS
+        .iter()
+        .position(|region| region.start.load(Ordering::Relaxed) == 0)
+        .ok_or_else(|| Error::Io(std::io::Error::other(
+            format!("at most {} mappings can be tracked at the same time", MAX_REGIONS),
+        )))?;
+
+    let region = &REGIONS[index];
+    let start = mapping.as_ptr() as usize - skew;
+    let size = mapping.size() + skew;
+
+    region.bitmap.store(bitmap.as_ptr() as *mut AtomicU64, Ordering::Relaxed);
+    region.end.store(start + size, Ordering::Relaxed);
+    region.start.store(start, Ordering::Release);
+
+    let result = unsafe { platform::write_protect(start as *mut u8, size, true) };
+
+    if let Err(e) = result {
+        region.start.store(0, Ordering::Release);
+        return Err(e);
+    }
+
+    Ok(index)
+}
+
+impl Deref for DirtyTracker {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.mapping
+    }
+}
+
+impl DerefMut for DirtyTracker {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.mapping
+    }
+}
+
+impl Drop for DirtyTracker {
+    fn drop(&mut self) {
+        self.release();
+
+        unsafe { ManuallyDrop::drop(&mut self.mapping) };
+    }
+}
@@ -0,0 +1,94 @@
+//! This module implements mapping the same memory twice, once as writable and once as executable,
+//! such that JIT engines can emit code on platforms that forbid memory that is both writable and
+//! executable, without having to change the protection of the code back and forth.
+
+use crate::error::Error;
+use crate::mmap::check_range;
+use std::ops::Range;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// Two views of the same zero-initialized memory at different addresses, where the writable view
+/// is used to emit code and the executable view is used to run it. Writes through the writable
+/// view are visible through the executable view right away, as both views map the same pages.
+/// Neither view is ever both writable and executable, such that this works with the `strict-wx`
+/// feature and on platforms that enforce W^X, as long as they allow executable shared memory.
+///
+/// On Unix, the memory is created using `memfd_create()` on Linux, `SHM_ANON` on FreeBSD and an
+/// unlinked POSIX shared memory object elsewhere, and is mapped twice using `mmap()`. On
+/// Microsoft Windows, a section backed by the paging file is mapped twice using
+/// `MapViewOfFile()`. This is not supported with the `fake` feature.
+///
+/// The writable view still allows code to be changed at its executable address by anyone who
+/// learns the address of the writable view, so the writable view should not be exposed more than
+/// necessary.
+pub struct DualMmap {
+    inner: platform::DualMmap,
+}
+
+impl DualMmap {
+    /// Maps `size` bytes of zero-initialized memory twice, once as readable and writable, and
+    /// once as readable and executable.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the size is zero, and [`Error::WxProtectionDenied`] if
+    /// the platform denies executable shared memory, e.g. SELinux without the `execmem`
+    /// permission or Arbitrary Code Guard on Microsoft Windows.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::OutOfBounds(0..size));
+        }
+
+        Ok(Self {
+            inner: platform::DualMmap::new(size)?,
+        })
+    }
+
+    /// Returns the size of either view in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Returns the contents of the writable view, which is used to emit code.
+    #[inline]
+    pub fn writable(&mut self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.inner.writable_ptr(), self.size())
+        }
+    }
+
+    /// Returns the contents of the executable view, which is used to run the code. The address at
+    /// the offset of a function in the view is the address to call.
+    #[inline]
+    pub fn executable(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(self.inner.executable_ptr(), self.size())
+        }
+    }
+
+    /// Returns a raw pointer to the start of the executable view, which remains valid for as long
+    /// as this object is alive.
+    #[inline]
+    pub fn executable_ptr(&self) -> *const u8 {
+        self.inner.executable_ptr()
+    }
+
+    /// Publishes the code that has been written to the given range of the writable view to the
+    /// threads that run the code through the executable view, by flushing the instruction cache
+    /// for the range of the executable view and serializing the instruction streams of the cores.
+    /// See [`crate::Mmap::publish_code()`].
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is out of bounds.
+    pub fn publish_code(&self, range: Range<usize>) -> Result<(), Error> {
+        check_range(&range, self.size())?;
+
+        self.inner.publish_code(range)
+    }
+}
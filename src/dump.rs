@@ -0,0 +1,150 @@
+//! This module implements dumping the readable memory areas of a process into a simple indexed
+//! archive, which is enough for crash forensics tooling without a full core dump implementation.
+
+use crate::areas::{MemoryArea, MemoryAreas};
+use crate::error::Error;
+use crate::process::ProcessMemory;
+use crate::protection::Protection;
+use std::io::Write;
+
+/// The magic at the start and at the end of the archive.
+const MAGIC: &[u8; 8] = b"MMAPDUMP";
+
+/// The version of the archive format.
+const VERSION: u32 = 1;
+
+/// The number of bytes that are read from the process at once.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// An entry of the index of the archive.
+struct Entry {
+    start: u64,
+    end: u64,
+    protection: u32,
+    offset: u64,
+    len: u64,
+    path: String,
+}
+
+/// Keeps track of the offset within the archive.
+struct Archive<W> {
+    writer: W,
+    offset: u64,
+}
+
+impl<W: Write> Archive<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+
+        Ok(())
+    }
+}
+
+/// Dumps the readable memory areas of the process with the given process ID that pass the filter
+/// into the writer. If no process ID is given, then the memory areas of the current process are
+/// dumped. Returns the number of memory areas that have been dumped.
+///
+/// The memory areas are enumerated using [`MemoryAreas`] and read using [`ProcessMemory`], so
+/// this has the same requirements and platform support. The memory areas are enumerated up front,
+/// such that memory areas that are mapped while dumping, e.g. by the writer, are not included.
+/// Memory areas that cannot be read at all, e.g. `[vvar]` on Linux, are skipped.
+///
+/// The writer is not buffered, so consider wrapping it in a [`std::io::BufWriter`].
+///
+/// # Format
+///
+/// All integers in the archive are stored in little endian. The archive consists of:
+///
+/// - A header of the magic `MMAPDUMP` followed by the version as a `u32`, which is currently 1.
+/// - The contents of the memory areas, one after another.
+/// - An index with an entry per memory area, which consists of the start and the end address of
+///   the memory area as `u64`s, the protection as a `u32` (see [`Protection::bits()`]), the offset
+///   of the contents within the archive and the number of bytes of the contents as `u64`s, and the
+///   length of the path of the file backing the memory area as a `u32`, followed by the path in
+///   UTF-8, where the length is zero if the memory area is not backed by a file.
+/// - A footer of the offset of the index as a `u64`, the number of entries as a `u32` and the
+///   magic `MMAPDUMP`, such that the index can be found by seeking to the end of the archive.
+///
+/// The contents of a memory area may be shorter than the memory area, if reading the memory area
+/// failed part way through, e.g. as the process unmapped it in the meantime.
+pub fn dump_process<W, F>(pid: Option<u32>, writer: W, mut filter: F) -> Result<usize, Error>
+where
+    W: Write,
+    F: FnMut(&MemoryArea) -> bool,
+{
+    let areas = MemoryAreas::open(pid)?
+        .filter(|area| {
+            area.as_ref().map_or(true, |area| area.protection().contains(Protection::READ))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let memory = ProcessMemory::open(pid)?;
+
+    let mut archive = Archive {
+        writer,
+        offset: 0,
+    };
+
+    archive.write(MAGIC)?;
+    archive.write(&VERSION.to_le_bytes())?;
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut entries = vec![];
+
+    for area in areas.iter().filter(|area| filter(area)) {
+        let offset = archive.offset;
+        let mut address = area.start();
+
+        // Stop at the first chunk that cannot be read in full.
+        while address < area.end() {
+            let size = CHUNK_SIZE.min(area.end() - address);
+
+            let count = match memory.read_at(address, &mut buffer[..size]) {
+                Ok(count) => count,
+                Err(_) => break,
+            };
+
+            archive.write(&buffer[..count])?;
+            address += count;
+
+            if count < size {
+                break;
+            }
+        }
+
+        let len = archive.offset - offset;
+
+        if len == 0 {
+            continue;
+        }
+
+        entries.push(Entry {
+            start: area.start() as u64,
+            end: area.end() as u64,
+            protection: area.protection().bits(),
+            offset,
+            len,
+            path: area.path().map(|path| path.to_string_lossy().into_owned()).unwrap_or_default(),
+        });
+    }
+
+    let index = archive.offset;
+
+    for entry in &entries {
+        archive.write(&entry.start.to_le_bytes())?;
+        archive.write(&entry.end.to_le_bytes())?;
+        archive.write(&entry.protection.to_le_bytes())?;
+        archive.write(&entry.offset.to_le_bytes())?;
+        archive.write(&entry.len.to_le_bytes())?;
+        archive.write(&(entry.path.len() as u32).to_le_bytes())?;
+        archive.write(entry.path.as_bytes())?;
+    }
+
+    archive.write(&index.to_le_bytes())?;
+    archive.write(&(entries.len() as u32).to_le_bytes())?;
+    archive.write(MAGIC)?;
+    archive.writer.flush()?;
+
+    Ok(entries.len())
+}
@@ -1,8 +1,83 @@
 //! This module implements the error type used throughout this crate.
 
-use crate::UnsafeMmapFlags;
+use crate::{MmapFlags, UnsafeMmapFlags};
+use std::fmt;
+use std::ops::Range;
 use thiserror::Error;
 
+/// The likely cause of the operating system refusing to map or lock memory due to a lack of
+/// memory, as probed after the failure. As the limits may change concurrently, this is a best
+/// effort.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutOfMemoryCause {
+    /// The address space of the process is limited to the given number of bytes by
+    /// `RLIMIT_AS`.
+    AddressSpaceLimit(u64),
+
+    /// The process has reached the maximum number of mappings, which is configured through the
+    /// `vm.max_map_count` sysctl on Linux.
+    MapCountLimit(u64),
+
+    /// The system has reached its commit limit of the given number of bytes, i.e. the memory
+    /// that can be committed with strict overcommit accounting (`vm.overcommit_memory = 2`) on
+    /// Linux, or the size of physical memory and the page files on Microsoft Windows.
+    CommitLimit(u64),
+
+    /// The heuristic overcommit accounting (`vm.overcommit_memory = 0`) on Linux refused the
+    /// mapping, as it is larger than the physical memory and swap space combined.
+    OvercommitHeuristic,
+
+    /// The amount of memory that can be locked is limited to the given number of bytes by
+    /// `RLIMIT_MEMLOCK` on Unix, or by the maximum working set size on Microsoft Windows.
+    LockedMemoryLimit(u64),
+
+    /// The memory of the control group of the process is limited to the given number of bytes by
+    /// `memory.max` of cgroup v2 on Linux.
+    CgroupLimit(u64),
+
+    /// The cause could not be determined.
+    Unknown,
+}
+
+impl fmt::Display for OutOfMemoryCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddressSpaceLimit(limit) => write!(
+                f,
+                "the address space is limited to {:#x} bytes by RLIMIT_AS (see ulimit -v)",
+                limit,
+            ),
+            Self::MapCountLimit(limit) => write!(
+                f,
+                "the process has reached the maximum of {} mappings (see vm.max_map_count)",
+                limit,
+            ),
+            Self::CommitLimit(limit) => write!(
+                f,
+                "the commit limit of {:#x} bytes has been reached (add swap space or a larger \
+                page file, or relax vm.overcommit_memory)",
+                limit,
+            ),
+            Self::OvercommitHeuristic => write!(
+                f,
+                "the mapping exceeds the physical memory and swap space (use MmapFlags::NO_RESERVE \
+                or relax vm.overcommit_memory)",
+            ),
+            Self::LockedMemoryLimit(limit) => write!(
+                f,
+                "locked memory is limited to {:#x} bytes (see ulimit -l or the working set size)",
+                limit,
+            ),
+            Self::CgroupLimit(limit) => write!(
+                f,
+                "the memory of the cgroup is limited to {:#x} bytes (see memory.max)",
+                limit,
+            ),
+            Self::Unknown => write!(f, "the cause is unknown"),
+        }
+    }
+}
+
 /// The error type.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -10,6 +85,39 @@ pub enum Error {
     #[error("{0:?} must be set")]
     UnsafeFlagNeeded(UnsafeMmapFlags),
 
+    /// The following set of flags cannot be honored for this mapping on the current platform.
+    #[error("{0:?} cannot be honored for this mapping")]
+    UnsupportedFlags(MmapFlags),
+
+    /// The range is out of the bounds of the memory mapping.
+    #[error("the range {0:?} is out of bounds")]
+    OutOfBounds(Range<usize>),
+
+    /// The address, offset or size is not aligned to the required alignment.
+    #[error("{0:#x} is not aligned to {1:#x}")]
+    Unaligned(usize, usize),
+
+    /// Huge pages cannot be used for the mapping, where the message describes how to make them
+    /// available. See [`crate::MmapOptions::check_huge_pages()`].
+    #[error("huge pages are unavailable: {0}")]
+    HugePagesUnavailable(String),
+
+    /// Mapping memory as both writable and executable is forbidden, as the `strict-wx` feature
+    /// has been enabled.
+    #[error("mapping memory as both writable and executable is forbidden by strict W^X enforcement")]
+    WxForbidden,
+
+    /// The platform denied mapping the memory as executable due to its W^X policy, e.g. SELinux,
+    /// PaX, OpenBSD or the hardened runtime on macOS, where the message describes the likely
+    /// cause and how to address it.
+    #[error("executable memory was denied by the W^X policy of the platform: {0}")]
+    WxProtectionDenied(String),
+
+    /// The operating system refused to map or lock the memory due to a lack of memory, where the
+    /// cause has been probed to suggest a fix.
+    #[error("out of memory: {0}")]
+    OutOfMemory(OutOfMemoryCause),
+
     /// Represents [`std::io::Error`].
     #[error(transparent)]
     Io(#[from] std::io::Error),
@@ -42,3 +150,17 @@ pub enum Error {
     #[error(transparent)]
     Windows(#[from] windows::core::Error),
 }
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(e) => e,
+            Error::OutOfMemory(cause) => {
+                std::io::Error::new(std::io::ErrorKind::OutOfMemory, Error::OutOfMemory(cause))
+            }
+            #[cfg(unix)]
+            Error::Nix(e) => std::io::Error::from_raw_os_error(e as i32),
+            e => std::io::Error::other(e),
+        }
+    }
+}
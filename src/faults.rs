@@ -0,0 +1,86 @@
+//! This module implements counting the page faults raised while running a section of code, such
+//! that the cost of a workload on memory mappings can be split into faults that were served from
+//! memory and faults that had to wait for the disk.
+
+use crate::error::Error;
+use std::ops::Sub;
+
+// The page faults are counted by the operating system, including those of heap allocations, so
+// the counters are also available with the `fake` feature.
+use crate::os_impl::process as platform;
+
+/// The number of page faults, as counted by [`PageFaultCounter`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PageFaults {
+    /// The number of page faults that were served without I/O, e.g. by mapping a page from the
+    /// page cache or by allocating a zeroed page.
+    pub minor: u64,
+    /// The number of page faults that had to read the page from the disk or from swap space.
+    pub major: u64,
+}
+
+impl PageFaults {
+    /// Returns the total number of page faults.
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.minor + self.major
+    }
+}
+
+impl Sub for PageFaults {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            minor: self.minor.saturating_sub(other.minor),
+            major: self.major.saturating_sub(other.major),
+        }
+    }
+}
+
+/// Counts the page faults raised since the counter was started. The operating systems do not
+/// count the page faults per mapping, so the page faults are attributed to a mapping by only
+/// counting them around the code that accesses it, e.g. using [`PageFaultCounter::measure()`].
+///
+/// On Linux, FreeBSD and OpenBSD, the page faults of the current thread are counted using
+/// `getrusage()` with `RUSAGE_THREAD`, such that other threads do not skew the counts, as long as
+/// the counter is read on the thread that started it. On other Unix platforms, the page faults of
+/// the whole process are counted using `RUSAGE_SELF`. On Microsoft Windows, the page faults of the
+/// whole process are counted using `GetProcessMemoryInfo()`, which does not tell soft and hard
+/// page faults apart, such that all of them are counted as minor page faults.
+#[derive(Clone, Copy, Debug)]
+pub struct PageFaultCounter {
+    start: PageFaults,
+}
+
+impl PageFaultCounter {
+    /// Starts counting the page faults.
+    pub fn start() -> Result<Self, Error> {
+        Ok(Self {
+            start: current()?,
+        })
+    }
+
+    /// Returns the number of page faults raised since the counter was started.
+    pub fn elapsed(&self) -> Result<PageFaults, Error> {
+        Ok(current()? - self.start)
+    }
+
+    /// Runs the function and returns its result together with the number of page faults raised
+    /// while running it.
+    pub fn measure<T, F: FnOnce() -> T>(f: F) -> Result<(T, PageFaults), Error> {
+        let counter = Self::start()?;
+        let result = f();
+
+        Ok((result, counter.elapsed()?))
+    }
+}
+
+fn current() -> Result<PageFaults, Error> {
+    let (minor, major) = platform::page_faults()?;
+
+    Ok(PageFaults {
+        minor,
+        major,
+    })
+}
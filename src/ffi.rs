@@ -0,0 +1,558 @@
+//! This module implements a C API with a stable ABI on top of this crate, such that C and C++
+//! projects can use it as well. The functions operate on opaque handles and report errors through
+//! [`MmapStatus`]. A description of the last error on the current thread can be retrieved using
+//! [`mmap_rs_last_error_message()`].
+//!
+//! A C header can be generated from this module using [cbindgen] and the `cbindgen.toml` that is
+//! part of this crate:
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --output mmap_rs.h
+//! ```
+//!
+//! C and C++ projects link against a static or a dynamic library, which is built by overriding
+//! the crate type, such that Rust users of the crate only build the Rust library:
+//!
+//! ```text
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! cargo rustc --release --features ffi --crate-type staticlib
+//! ```
+//!
+//! [cbindgen]: https://github.com/mozilla/cbindgen
+
+use crate::error::Error;
+use crate::{MmapFlags, MmapMut, MmapNone, Mmap, MmapOptions, PageSize, Protection, UnsafeMmapFlags};
+use std::cell::RefCell;
+use std::fs::File;
+use std::os::raw::c_char;
+
+/// The status codes returned by the functions of the C API.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MmapStatus {
+    /// The operation succeeded.
+    Ok = 0,
+    /// One of the arguments is invalid, e.g. a null pointer or an unsupported protection.
+    InvalidArgument = 1,
+    /// The operation requires unsafe flags that have not been set.
+    UnsafeFlagNeeded = 2,
+    /// The flags cannot be honored for this mapping on the current platform.
+    UnsupportedFlags = 3,
+    /// The range is out of bounds.
+    OutOfBounds = 4,
+    /// The address, offset or size is not properly aligned.
+    Unaligned = 5,
+    /// The operating system reported an error.
+    Os = 6,
+    /// Any other error.
+    Other = 7,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Stores the error as the last error of the current thread, and converts it into a status code.
+fn set_last_error(error: Error) -> MmapStatus {
+    let status = match &error {
+        Error::UnsafeFlagNeeded(_) => MmapStatus::UnsafeFlagNeeded,
+        Error::UnsupportedFlags(_) => MmapStatus::UnsupportedFlags,
+        Error::OutOfBounds(_) => MmapStatus::OutOfBounds,
+        Error::Unaligned(_, _) => MmapStatus::Unaligned,
+        Error::Io(_) => MmapStatus::Os,
+        #[cfg(unix)]
+        Error::Nix(_) => MmapStatus::Os,
+        #[cfg(windows)]
+        Error::Windows(_) => MmapStatus::Os,
+        _ => MmapStatus::Other,
+    };
+
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(error.to_string()));
+
+    status
+}
+
+/// Stores a message describing an invalid argument as the last error of the current thread.
+fn invalid_argument(message: &str) -> MmapStatus {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message.to_string()));
+
+    MmapStatus::InvalidArgument
+}
+
+/// An opaque handle to the options used to create a memory mapping.
+pub struct MmapOptionsHandle {
+    inner: Option<MmapOptions>,
+}
+
+/// The current state of the memory mapping behind an [`MmapHandle`].
+enum Mapping {
+    None(MmapNone),
+    ReadOnly(Mmap),
+    Mutable(MmapMut),
+    Empty,
+}
+
+/// An opaque handle to a memory mapping.
+pub struct MmapHandle {
+    inner: Mapping,
+}
+
+impl MmapHandle {
+    fn as_ptr(&self) -> *const u8 {
+        match &self.inner {
+            Mapping::None(mapping) => mapping.as_ptr(),
+            Mapping::ReadOnly(mapping) => mapping.as_ptr(),
+            Mapping::Mutable(mapping) => mapping.as_ptr(),
+            Mapping::Empty => std::ptr::null(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match &self.inner {
+            Mapping::None(mapping) => mapping.size(),
+            Mapping::ReadOnly(mapping) => mapping.size(),
+            Mapping::Mutable(mapping) => mapping.size(),
+            Mapping::Empty => 0,
+        }
+    }
+}
+
+/// Updates the options behind the handle using the given function.
+unsafe fn update_options<F>(options: *mut MmapOptionsHandle, f: F) -> MmapStatus
+where
+    F: FnOnce(MmapOptions) -> MmapOptions,
+{
+    let options = match options.as_mut() {
+        Some(options) => options,
+        _ => return invalid_argument("the options handle is null"),
+    };
+
+    match options.inner.take() {
+        Some(inner) => {
+            options.inner = Some(f(inner));
+            MmapStatus::Ok
+        }
+        _ => invalid_argument("the options have already been used"),
+    }
+}
+
+/// Returns the page size of the current platform.
+#[no_mangle]
+pub extern "C" fn mmap_rs_page_size() -> usize {
+    crate::page_size()
+}
+
+/// Returns the allocation granularity of the current platform.
+#[no_mangle]
+pub extern "C" fn mmap_rs_allocation_granularity() -> usize {
+    crate::allocation_granularity()
+}
+
+/// Copies a NUL-terminated description of the last error on the current thread into the buffer,
+/// truncating the description if the buffer is too small. Returns the size of the buffer needed
+/// to hold the full description including the NUL-terminator, or zero if there is no last error.
+///
+/// # Safety
+///
+/// The buffer must either be null or point to at least `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_last_error_message(buffer: *mut c_char, size: usize) -> usize {
+    LAST_ERROR.with(|last_error| {
+        let last_error = last_error.borrow();
+
+        let message = match last_error.as_ref() {
+            Some(message) => message.as_bytes(),
+            _ => return 0,
+        };
+
+        if !buffer.is_null() && size > 0 {
+            let count = message.len().min(size - 1);
+
+            std::ptr::copy_nonoverlapping(message.as_ptr() as *const c_char, buffer, count);
+            *buffer.add(count) = 0;
+        }
+
+        message.len() + 1
+    })
+}
+
+/// Creates the options to create a memory mapping of the given size. The options must either be
+/// consumed by [`mmap_rs_map()`] or released using [`mmap_rs_options_free()`].
+#[no_mangle]
+pub extern "C" fn mmap_rs_options_new(size: usize) -> *mut MmapOptionsHandle {
+    Box::into_raw(Box::new(MmapOptionsHandle {
+        inner: Some(MmapOptions::new(size)),
+    }))
+}
+
+/// Releases the options.
+///
+/// # Safety
+///
+/// The options must either be null or a handle returned by [`mmap_rs_options_new()`] that has not
+/// been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_options_free(options: *mut MmapOptionsHandle) {
+    if !options.is_null() {
+        drop(Box::from_raw(options));
+    }
+}
+
+/// Sets the desired address at which the memory should be mapped.
+///
+/// # Safety
+///
+/// The options must be a valid handle returned by [`mmap_rs_options_new()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_options_with_address(
+    options: *mut MmapOptionsHandle,
+    address: usize,
+) -> MmapStatus {
+    update_options(options, |inner| inner.with_address(address))
+}
+
+/// Sets the flags of the mapping, where the bits are those of [`MmapFlags`].
+///
+/// # Safety
+///
+/// The options must be a valid handle returned by [`mmap_rs_options_new()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_options_with_flags(
+    options: *mut MmapOptionsHandle,
+    flags: u32,
+) -> MmapStatus {
+    let flags = match MmapFlags::from_bits(flags) {
+        Some(flags) => flags,
+        _ => return invalid_argument("unknown flags"),
+    };
+
+    update_options(options, |inner| inner.with_flags(flags))
+}
+
+/// Sets the unsafe flags of the mapping, where the bits are those of [`UnsafeMmapFlags`].
+///
+/// # Safety
+///
+/// The options must be a valid handle returned by [`mmap_rs_options_new()`]. See
+/// [`MmapOptions::with_unsafe_flags()`] for the implications of the flags.
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_options_with_unsafe_flags(
+    options: *mut MmapOptionsHandle,
+    flags: u32,
+) -> MmapStatus {
+    let flags = match UnsafeMmapFlags::from_bits(flags) {
+        Some(flags) => flags,
+        _ => return invalid_argument("unknown unsafe flags"),
+    };
+
+    update_options(options, |inner| inner.with_unsafe_flags(flags))
+}
+
+/// Sets the page size of the mapping in log2 notation.
+///
+/// # Safety
+///
+/// The options must be a valid handle returned by [`mmap_rs_options_new()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_options_with_page_size(
+    options: *mut MmapOptionsHandle,
+    page_size: usize,
+) -> MmapStatus {
+    update_options(options, |inner| inner.with_page_size(PageSize(page_size)))
+}
+
+/// Sets the file descriptor that backs the mapping, and the offset within the file at which the
+/// mapping should start. The file descriptor is duplicated, such that the caller retains the
+/// ownership of the file descriptor passed to this function.
+///
+/// # Safety
+///
+/// The options must be a valid handle returned by [`mmap_rs_options_new()`], and the file
+/// descriptor must be valid. See [`MmapOptions::with_file()`] for the implications of mapping
+/// files.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_options_with_fd(
+    options: *mut MmapOptionsHandle,
+    fd: std::os::raw::c_int,
+    offset: u64,
+) -> MmapStatus {
+    use std::os::unix::io::BorrowedFd;
+
+    let file = match BorrowedFd::borrow_raw(fd).try_clone_to_owned() {
+        Ok(fd) => File::from(fd),
+        Err(e) => return set_last_error(e.into()),
+    };
+
+    update_options(options, |inner| inner.with_file(file, offset))
+}
+
+/// Sets the file handle that backs the mapping, and the offset within the file at which the
+/// mapping should start. The handle is duplicated, such that the caller retains the ownership of
+/// the handle passed to this function.
+///
+/// # Safety
+///
+/// The options must be a valid handle returned by [`mmap_rs_options_new()`], and the file handle
+/// must be valid. See [`MmapOptions::with_file()`] for the implications of mapping files.
+#[cfg(windows)]
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_options_with_handle(
+    options: *mut MmapOptionsHandle,
+    handle: *mut std::ffi::c_void,
+    offset: u64,
+) -> MmapStatus {
+    use std::os::windows::io::BorrowedHandle;
+
+    let file = match BorrowedHandle::borrow_raw(handle).try_clone_to_owned() {
+        Ok(handle) => File::from(handle),
+        Err(e) => return set_last_error(e.into()),
+    };
+
+    update_options(options, |inner| inner.with_file(file, offset))
+}
+
+/// Creates the memory mapping with the given protection, where the bits are those of
+/// [`Protection`]. This consumes the options, even on failure. On success, the handle to the
+/// mapping is stored in `mapping`, which must be released using [`mmap_rs_unmap()`].
+///
+/// # Safety
+///
+/// The options must be a valid handle returned by [`mmap_rs_options_new()`], and `mapping` must
+/// point to writable memory. Mapping memory as both writable and executable requires the `JIT`
+/// flag of [`UnsafeMmapFlags`], see `MmapOptions::map_exec_mut()` for the implications. Mapping
+/// memory as executable fails with [`MmapStatus::InvalidArgument`] if the crate has been built
+/// without the `exec` or `jit` features.
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_map(
+    options: *mut MmapOptionsHandle,
+    protection: u32,
+    mapping: *mut *mut MmapHandle,
+) -> MmapStatus {
+    if options.is_null() || mapping.is_null() {
+        return invalid_argument("the options or the mapping is null");
+    }
+
+    let options = match Box::from_raw(options).inner {
+        Some(options) => options,
+        _ => return invalid_argument("the options have already been used"),
+    };
+
+    let protection = match Protection::from_bits(protection) {
+        Some(protection) => protection,
+        _ => return invalid_argument("unknown protection"),
+    };
+
+    let read = Protection::READ;
+    let write = Protection::READ | Protection::WRITE;
+    #[cfg(feature = "exec")]
+    let exec = Protection::READ | Protection::EXECUTE;
+    #[cfg(feature = "jit")]
+    let rwx = Protection::READ | Protection::WRITE | Protection::EXECUTE;
+
+    let result = match protection {
+        p if p.is_empty() => options.map_none().map(Mapping::None),
+        p if p == read => options.map().map(Mapping::ReadOnly),
+        #[cfg(feature = "exec")]
+        p if p == exec => options.map_exec().map(Mapping::ReadOnly),
+        p if p == write => options.map_mut().map(Mapping::Mutable),
+        #[cfg(feature = "jit")]
+        p if p == rwx => options.map_exec_mut().map(Mapping::Mutable),
+        _ => return invalid_argument("unsupported protection"),
+    };
+
+    match result {
+        Ok(inner) => {
+            *mapping = Box::into_raw(Box::new(MmapHandle { inner }));
+            MmapStatus::Ok
+        }
+        Err(e) => set_last_error(e),
+    }
+}
+
+/// Changes the protection of the memory mapping, where the bits are those of [`Protection`].
+///
+/// # Safety
+///
+/// The mapping must be a valid handle returned by [`mmap_rs_map()`]. Any pointers into the
+/// mapping must no longer be used for accesses that the new protection does not allow. Changing
+/// the protection to both writable and executable requires the `JIT` flag of
+/// [`UnsafeMmapFlags`], see `MmapMut::make_exec_mut()` for the implications. Changing the
+/// protection to executable fails with [`MmapStatus::InvalidArgument`] if the crate has been
+/// built without the `exec` or `jit` features.
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_protect(mapping: *mut MmapHandle, protection: u32) -> MmapStatus {
+    let mapping = match mapping.as_mut() {
+        Some(mapping) => mapping,
+        _ => return invalid_argument("the mapping is null"),
+    };
+
+    let protection = match Protection::from_bits(protection) {
+        Some(protection) => protection,
+        _ => return invalid_argument("unknown protection"),
+    };
+
+    macro_rules! transition {
+        ($inner:expr, $variant:ident, $original:ident) => {
+            match $inner {
+                Ok(inner) => (Mapping::$variant(inner), Ok(())),
+                Err((inner, e)) => (Mapping::$original(inner), Err(Some(e))),
+            }
+        }
+    }
+
+    macro_rules! protect {
+        ($inner:expr, $original:ident) => {
+            match protection {
+                p if p.is_empty() =>
+                    transition!($inner.make_none(), None, $original),
+                p if p == Protection::READ =>
+                    transition!($inner.make_read_only(), ReadOnly, $original),
+                #[cfg(feature = "exec")]
+                p if p == Protection::READ | Protection::EXECUTE =>
+                    transition!($inner.make_exec(), ReadOnly, $original),
+                p if p == Protection::READ | Protection::WRITE =>
+                    transition!($inner.make_mut(), Mutable, $original),
+                #[cfg(feature = "jit")]
+                p if p == Protection::READ | Protection::WRITE | Protection::EXECUTE =>
+                    transition!($inner.make_exec_mut(), Mutable, $original),
+                _ => (Mapping::$original($inner), Err(None)),
+            }
+        }
+    }
+
+    let (inner, result) = match std::mem::replace(&mut mapping.inner, Mapping::Empty) {
+        Mapping::None(inner) => protect!(inner, None),
+        Mapping::ReadOnly(inner) => protect!(inner, ReadOnly),
+        Mapping::Mutable(inner) => protect!(inner, Mutable),
+        Mapping::Empty => (Mapping::Empty, Err(None)),
+    };
+
+    mapping.inner = inner;
+
+    match result {
+        Ok(()) => MmapStatus::Ok,
+        Err(Some(e)) => set_last_error(e),
+        Err(None) => invalid_argument("unsupported protection"),
+    }
+}
+
+/// Returns the start address of the memory mapping, or null if the mapping is null.
+///
+/// # Safety
+///
+/// The mapping must either be null or a valid handle returned by [`mmap_rs_map()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_ptr(mapping: *const MmapHandle) -> *mut u8 {
+    match mapping.as_ref() {
+        Some(mapping) => mapping.as_ptr() as *mut u8,
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Returns the size of the memory mapping, or zero if the mapping is null.
+///
+/// # Safety
+///
+/// The mapping must either be null or a valid handle returned by [`mmap_rs_map()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_size(mapping: *const MmapHandle) -> usize {
+    match mapping.as_ref() {
+        Some(mapping) => mapping.size(),
+        _ => 0,
+    }
+}
+
+/// Runs the given function on the mapping behind the handle.
+unsafe fn with_mapping<F>(mapping: *mut MmapHandle, f: F) -> MmapStatus
+where
+    F: FnOnce(&mut Mapping) -> Result<(), Error>,
+{
+    let mapping = match mapping.as_mut() {
+        Some(mapping) => mapping,
+        _ => return invalid_argument("the mapping is null"),
+    };
+
+    match f(&mut mapping.inner) {
+        Ok(()) => MmapStatus::Ok,
+        Err(e) => set_last_error(e),
+    }
+}
+
+/// Flushes the given range of the memory mapping. If `wait` is non-zero, then this function waits
+/// for the flush to complete.
+///
+/// # Safety
+///
+/// The mapping must be a valid handle returned by [`mmap_rs_map()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_flush(
+    mapping: *mut MmapHandle,
+    offset: usize,
+    size: usize,
+    wait: i32,
+) -> MmapStatus {
+    let range = offset..offset.saturating_add(size);
+
+    with_mapping(mapping, |inner| {
+        crate::mmap::check_range(&range, match inner {
+            Mapping::None(mapping) => mapping.size(),
+            Mapping::ReadOnly(mapping) => mapping.size(),
+            Mapping::Mutable(mapping) => mapping.size(),
+            Mapping::Empty => 0,
+        })?;
+
+        match (inner, wait != 0) {
+            (Mapping::None(mapping), true) => mapping.flush(range),
+            (Mapping::None(mapping), false) => mapping.flush_async(range),
+            (Mapping::ReadOnly(mapping), true) => mapping.flush(range),
+            (Mapping::ReadOnly(mapping), false) => mapping.flush_async(range),
+            (Mapping::Mutable(mapping), true) => mapping.flush(range),
+            (Mapping::Mutable(mapping), false) => mapping.flush_async(range),
+            (Mapping::Empty, _) => Ok(()),
+        }
+    })
+}
+
+/// Locks the pages of the memory mapping into physical memory.
+///
+/// # Safety
+///
+/// The mapping must be a valid handle returned by [`mmap_rs_map()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_lock(mapping: *mut MmapHandle) -> MmapStatus {
+    with_mapping(mapping, |inner| match inner {
+        Mapping::None(mapping) => mapping.lock(),
+        Mapping::ReadOnly(mapping) => mapping.lock(),
+        Mapping::Mutable(mapping) => mapping.lock(),
+        Mapping::Empty => Ok(()),
+    })
+}
+
+/// Unlocks the pages of the memory mapping from physical memory.
+///
+/// # Safety
+///
+/// The mapping must be a valid handle returned by [`mmap_rs_map()`].
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_unlock(mapping: *mut MmapHandle) -> MmapStatus {
+    with_mapping(mapping, |inner| match inner {
+        Mapping::None(mapping) => mapping.unlock(),
+        Mapping::ReadOnly(mapping) => mapping.unlock(),
+        Mapping::Mutable(mapping) => mapping.unlock(),
+        Mapping::Empty => Ok(()),
+    })
+}
+
+/// Unmaps the memory mapping and releases the handle.
+///
+/// # Safety
+///
+/// The mapping must either be null or a valid handle returned by [`mmap_rs_map()`] that has not
+/// been released yet. Any pointers into the mapping must no longer be used.
+#[no_mangle]
+pub unsafe extern "C" fn mmap_rs_unmap(mapping: *mut MmapHandle) {
+    if !mapping.is_null() {
+        drop(Box::from_raw(mapping));
+    }
+}
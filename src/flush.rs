@@ -0,0 +1,400 @@
+//! This module implements a scheduler that flushes the dirty ranges of file mappings in the
+//! background, such that the flush policy does not have to be reimplemented by every user that
+//! persists data through memory mappings.
+
+use crate::error::Error;
+use crate::mmap::check_range;
+use crate::MmapMut;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::File;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// The number of ranges a mapping may accumulate before they are coalesced.
+const COALESCE_THRESHOLD: usize = 64;
+
+/// The schedulers that are alive, such that the ranges of mappings that are about to be unmapped
+/// or moved can be forgotten by every scheduler.
+static SCHEDULERS: Mutex<Vec<Arc<Shared>>> = Mutex::new(Vec::new());
+
+/// The number of schedulers that are alive, such that unmapping pages does not have to take the
+/// lock above when there are none.
+static LIVE_SCHEDULERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts the ranges and merges the ranges that overlap or that are adjacent.
+pub(crate) fn coalesce(ranges: &mut Vec<Range<usize>>) {
+    ranges.sort_unstable_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+
+    for range in ranges.drain(..) {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    *ranges = merged;
+}
+
+/// Removes the given range from the ranges, and returns the parts of the ranges that it covered.
+fn remove(ranges: &mut Vec<Range<usize>>, removed: &Range<usize>) -> Vec<Range<usize>> {
+    let mut covered = vec![];
+    let mut kept = Vec::with_capacity(ranges.len());
+
+    for range in ranges.drain(..) {
+        if range.end <= removed.start || removed.end <= range.start {
+            kept.push(range);
+            continue;
+        }
+
+        if range.start < removed.start {
+            kept.push(range.start..removed.start);
+        }
+
+        if removed.end < range.end {
+            kept.push(removed.end..range.end);
+        }
+
+        covered.push(range.start.max(removed.start)..range.end.min(removed.end));
+    }
+
+    *ranges = kept;
+
+    covered
+}
+
+/// Makes every scheduler forget the dirty ranges within the given range of addresses, which
+/// covers whole pages that are about to be unmapped or moved. This waits for the flushes that are
+/// in progress, such that no scheduler flushes the addresses once they have been unmapped, as they
+/// may be reused by another mapping. The forgotten ranges are flushed asynchronously while the
+/// pages are still mapped, and their files are synchronized by the next barrier instead.
+pub(crate) fn forget(range: Range<usize>) {
+    if LIVE_SCHEDULERS.load(Ordering::Acquire) == 0 {
+        return;
+    }
+
+    let schedulers = SCHEDULERS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+
+    for shared in schedulers {
+        shared.forget(&range);
+    }
+}
+
+/// The dirty ranges of a single mapping, where the ranges are addresses rounded to whole pages.
+/// The target is keyed by the address of the mapping, which is unique for as long as the mapping
+/// is alive, as the ranges are forgotten before the mapping is unmapped or moved.
+struct Target {
+    file: Arc<File>,
+    /// The ranges that have not been flushed yet.
+    pending: Vec<Range<usize>>,
+    /// The ranges that have not been flushed synchronously since the last barrier.
+    unsynced: Vec<Range<usize>>,
+}
+
+#[derive(Default)]
+struct State {
+    targets: HashMap<usize, Target>,
+    /// The files of the ranges that have been forgotten since the last barrier, which have been
+    /// flushed asynchronously and still have to be synchronized.
+    orphans: Vec<Arc<File>>,
+    error: Option<Error>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Held for reading while flushing and for writing while forgetting ranges, such that the
+    /// pages are not unmapped while they are being flushed.
+    flushing: RwLock<()>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    /// Takes the ranges of every mapping out of the state, selected by the given function, and
+    /// flushes the ranges. Only the first error is kept, such that it can be reported later on.
+    fn flush(&self, select: fn(&mut Target) -> Vec<Range<usize>>, sync: bool) -> Result<(), Error> {
+        let _flushing = self.flushing.read().unwrap_or_else(|e| e.into_inner());
+
+        let batches: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+
+            state.targets
+                .values_mut()
+                .map(|target| {
+                    let mut ranges = select(target);
+                    coalesce(&mut ranges);
+
+                    (target.file.clone(), ranges)
+                })
+                .filter(|(_, ranges)| !ranges.is_empty())
+                .collect()
+        };
+
+        let mut result = Ok(());
+
+        for (file, ranges) in batches {
+            for range in ranges {
+                let status = platform::flush_range(
+                    range.start,
+                    range.end - range.start,
+                    Some(&file),
+                    sync,
+                );
+
+                if let Err(e) = status {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Forgets the dirty ranges within the given range of addresses, after flushing them
+    /// asynchronously, and remembers their files such that the next barrier synchronizes them.
+    fn forget(&self, removed: &Range<usize>) {
+        let _flushing = self.flushing.write().unwrap_or_else(|e| e.into_inner());
+
+        let batches: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+            let mut batches = vec![];
+
+            for target in state.targets.values_mut() {
+                remove(&mut target.pending, removed);
+
+                let mut ranges = remove(&mut target.unsynced, removed);
+
+                if !ranges.is_empty() {
+                    coalesce(&mut ranges);
+                    batches.push((target.file.clone(), ranges));
+                }
+            }
+
+            state.targets.retain(|_, target| !target.unsynced.is_empty());
+            state.orphans.extend(batches.iter().map(|(file, _)| file.clone()));
+
+            batches
+        };
+
+        for (file, ranges) in batches {
+            for range in ranges {
+                let result = platform::flush_range(
+                    range.start,
+                    range.end - range.start,
+                    Some(&file),
+                    false,
+                );
+
+                if let Err(e) = result {
+                    self.state.lock().unwrap().error.get_or_insert(e);
+                }
+            }
+        }
+    }
+
+    /// Flushes the pending ranges asynchronously, i.e. without waiting for the pages to be written.
+    fn flush_pending(&self) {
+        let result = self.flush(|target| std::mem::take(&mut target.pending), false);
+
+        if let Err(e) = result {
+            self.state.lock().unwrap().error.get_or_insert(e);
+        }
+    }
+
+    /// Runs the background thread, which flushes the pending ranges once per interval until the
+    /// scheduler is dropped.
+    fn run(&self, interval: Duration) {
+        loop {
+            let shutdown = {
+                let state = self.state.lock().unwrap();
+                let (state, _) = self.wakeup
+                    .wait_timeout_while(state, interval, |state| !state.shutdown)
+                    .unwrap();
+
+                state.shutdown
+            };
+
+            self.flush_pending();
+
+            if shutdown {
+                break;
+            }
+        }
+    }
+}
+
+/// A scheduler that flushes the dirty ranges of file mappings in the background.
+///
+/// The user notifies the scheduler of the ranges that have been written to using
+/// [`FlushScheduler::mark_dirty()`], which may be called for many mappings and from many threads.
+/// The scheduler rounds the ranges to whole pages and coalesces ranges that overlap or that are
+/// adjacent. A background thread then flushes the pending ranges asynchronously at most once per
+/// interval, such that frequent writes to the same pages do not result in a flush per write.
+///
+/// As asynchronous flushes do not guarantee that the pages have been written to the disk,
+/// [`FlushScheduler::barrier()`] synchronously flushes every range that has been marked dirty
+/// since the previous barrier. This should be used for durability points, such as committing a
+/// transaction.
+///
+/// The scheduler never flushes a mapping that has been unmapped. The dirty ranges of a mapping
+/// are flushed asynchronously and forgotten when the mapping is unmapped, truncated or moved,
+/// where the next barrier then synchronizes the file as a whole. Unmapping a mapping waits for the
+/// flushes of the schedulers that are in progress.
+///
+/// Note that the in-memory backend of the `fake` feature cannot flush mappings by their address,
+/// such that the scheduler does not write anything back when that feature is enabled. The
+/// contents are written back when the mapping itself is flushed instead.
+pub struct FlushScheduler {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FlushScheduler {
+    /// Creates the scheduler and spawns the background thread, which flushes the pending ranges
+    /// at most once per given interval.
+    pub fn new(interval: Duration) -> Result<Self, Error> {
+        let shared = Arc::new(Shared::default());
+
+        {
+            let mut schedulers = SCHEDULERS.lock().unwrap_or_else(|e| e.into_inner());
+
+            schedulers.push(shared.clone());
+            LIVE_SCHEDULERS.store(schedulers.len(), Ordering::Release);
+        }
+
+        let thread = {
+            let shared = shared.clone();
+
+            std::thread::Builder::new()
+                .name("mmap-rs-flush".to_string())
+                .spawn(move || shared.run(interval))
+        };
+
+        let thread = match thread {
+            Ok(thread) => thread,
+            Err(e) => {
+                unregister(&shared);
+                return Err(e.into());
+            }
+        };
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Notifies the scheduler that the given range of the mapping has been written to. This does
+    /// nothing for anonymous mappings, as they are not backed by a file.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is out of bounds.
+    pub fn mark_dirty(&self, mapping: &MmapMut, range: Range<usize>) -> Result<(), Error> {
+        check_range(&range, mapping.size())?;
+
+        let file = match mapping.file() {
+            Some(file) => file,
+            _ => return Ok(()),
+        };
+
+        if range.start == range.end {
+            return Ok(());
+        }
+
+        // Round the range to whole pages, as the operating system flushes whole pages anyway.
+        let address = mapping.as_ptr() as usize;
+        let range = crate::range_to_page_bounds(address + range.start..address + range.end);
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        let target = match state.targets.entry(address) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Target {
+                file: Arc::new(file.try_clone()?),
+                pending: vec![],
+                unsynced: vec![],
+            }),
+        };
+
+        target.pending.push(range.clone());
+        target.unsynced.push(range);
+
+        if target.pending.len() >= COALESCE_THRESHOLD {
+            coalesce(&mut target.pending);
+        }
+
+        if target.unsynced.len() >= COALESCE_THRESHOLD {
+            coalesce(&mut target.unsynced);
+        }
+
+        Ok(())
+    }
+
+    /// Synchronously flushes every range that has been marked dirty since the previous barrier,
+    /// i.e. this function waits for the pages to be written to the disk.
+    ///
+    /// Returns the first error that occurred while flushing in the background since the previous
+    /// barrier, if any, or the first error that occurred while flushing synchronously.
+    pub fn barrier(&self) -> Result<(), Error> {
+        let result = self.shared.flush(|target| {
+            target.pending.clear();
+            std::mem::take(&mut target.unsynced)
+        }, true);
+
+        let orphans = std::mem::take(&mut self.shared.state.lock().unwrap().orphans);
+
+        // The ranges of the mappings that have been unmapped or moved have been flushed
+        // asynchronously, so synchronizing their files writes the remaining pages to the disk.
+        let result = orphans.iter().fold(result, |result, file| {
+            let status = file.sync_data();
+            result.and(status.map_err(Error::from))
+        });
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        // Forget about the mappings that are clean.
+        state.targets.retain(|_, target| !target.unsynced.is_empty());
+
+        match state.error.take() {
+            Some(e) => Err(e),
+            _ => result,
+        }
+    }
+}
+
+impl Drop for FlushScheduler {
+    fn drop(&mut self) {
+        // Stop the background thread, which flushes the pending ranges one last time.
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.wakeup.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        unregister(&self.shared);
+    }
+}
+
+/// Removes the scheduler from the schedulers that are alive.
+fn unregister(shared: &Arc<Shared>) {
+    let mut schedulers = SCHEDULERS.lock().unwrap_or_else(|e| e.into_inner());
+
+    schedulers.retain(|other| !Arc::ptr_eq(other, shared));
+    LIVE_SCHEDULERS.store(schedulers.len(), Ordering::Release);
+}
@@ -0,0 +1,132 @@
+//! This module implements a debugging allocator in the style of Electric Fence, which places
+//! every allocation on its own pages next to an inaccessible guard page, such that heap overruns
+//! and use-after-free turn into immediate faults rather than silent memory corruption.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// A global allocator that gives every allocation its own pages, with an inaccessible guard page
+/// immediately after the allocation, or immediately before it in underflow mode. Freed
+/// allocations are made inaccessible and their address range is never reused, such that
+/// accessing memory that has been freed faults as well.
+///
+/// This is meant for debugging, as every allocation uses at least two pages of address space and
+/// one page of memory, and as the address space of freed allocations is never released. Install
+/// it as the global allocator of a test or debug build:
+///
+/// ```no_run
+/// use mmap_rs::GuardedAlloc;
+///
+/// #[global_allocator]
+/// static ALLOC: GuardedAlloc = GuardedAlloc::new();
+/// ```
+///
+/// In overflow mode, the end of the allocation is placed against the guard page as closely as the
+/// alignment permits, such that reading or writing past the end faults right away for types with
+/// an alignment of one byte, and within the alignment otherwise. In underflow mode, the start of
+/// the allocation is placed right after the guard page instead.
+///
+/// Allocations that require an alignment larger than the page size are served by the system
+/// allocator and are not guarded. With the `fake` feature, the guard pages are not enforced.
+#[derive(Clone, Copy, Debug)]
+pub struct GuardedAlloc {
+    underflow: bool,
+}
+
+impl GuardedAlloc {
+    /// Creates an allocator that places the guard page after every allocation, which catches
+    /// reads and writes past the end of an allocation.
+    pub const fn new() -> Self {
+        Self {
+            underflow: false,
+        }
+    }
+
+    /// Creates an allocator that places the guard page before every allocation, which catches
+    /// reads and writes before the start of an allocation.
+    pub const fn underflow() -> Self {
+        Self {
+            underflow: true,
+        }
+    }
+}
+
+impl Default for GuardedAlloc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rounds the size up to a multiple of the page size.
+fn page_align(size: usize, page_size: usize) -> usize {
+    (size + page_size - 1) & !(page_size - 1)
+}
+
+unsafe impl GlobalAlloc for GuardedAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let page_size = crate::page_size();
+
+        if layout.align() > page_size {
+            return System.alloc(layout);
+        }
+
+        let data_size = match layout.size().checked_add(page_size - 1) {
+            Some(size) => size & !(page_size - 1),
+            _ => return std::ptr::null_mut(),
+        };
+
+        let total_size = match data_size.checked_add(page_size) {
+            Some(size) => size,
+            _ => return std::ptr::null_mut(),
+        };
+
+        if self.underflow {
+            let base = platform::map_guarded(total_size, 0);
+
+            if base.is_null() {
+                return base;
+            }
+
+            base.add(page_size)
+        } else {
+            let base = platform::map_guarded(total_size, data_size);
+
+            if base.is_null() {
+                return base;
+            }
+
+            let end = base as usize + data_size;
+            let address = (end - layout.size()) & !(layout.align() - 1);
+
+            base.add(address - base as usize)
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let page_size = crate::page_size();
+
+        if layout.align() > page_size {
+            return System.dealloc(ptr, layout);
+        }
+
+        let data_size = page_align(layout.size(), page_size);
+
+        let base = if self.underflow {
+            ptr.sub(page_size)
+        } else {
+            let end = page_align(ptr as usize + layout.size(), page_size);
+
+            ptr.sub(ptr as usize - (end - data_size))
+        };
+
+        platform::retire_guarded(base, data_size + page_size);
+    }
+}
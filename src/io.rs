@@ -0,0 +1,417 @@
+//! This module implements helpers to move data between memory mappings and [`std::io`] readers
+//! and writers.
+
+use crate::error::Error;
+use crate::mmap::check_range;
+use crate::{Mmap, MmapFlags, MmapMut, MmapOptions};
+use std::fs::File;
+use std::io::{BufRead, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// The default number of bytes that a [`DropBehindReader`] reads ahead of its position.
+const DEFAULT_WINDOW: usize = 8 << 20;
+
+/// Reads from the reader into the buffer until either the buffer is full or the reader reaches
+/// the end of the stream. Returns the number of bytes read.
+fn read_until_full<R: Read>(mut reader: R, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut position = 0;
+
+    while position < buffer.len() {
+        match reader.read(&mut buffer[position..]) {
+            Ok(0) => break,
+            Ok(size) => position += size,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(position)
+}
+
+/// Writes the bytes in the given ranges to the writer, where the ranges are written back-to-back.
+fn write_ranges<W: Write>(bytes: &[u8], mut writer: W, ranges: &[Range<usize>]) -> Result<(), Error> {
+    for range in ranges {
+        check_range(range, bytes.len())?;
+    }
+
+    for range in ranges {
+        writer.write_all(&bytes[range.clone()])?;
+    }
+
+    Ok(())
+}
+
+/// Writes the bytes in the given ranges to the writer, where the ranges are written back-to-back.
+/// Pages that have never been touched are skipped over by seeking, rather than being written, as
+/// long as they end up past the end of the output. Zeros are written over existing output instead.
+fn write_touched_ranges<W: Write + Seek>(
+    bytes: &[u8],
+    mut writer: W,
+    ranges: &[Range<usize>],
+) -> Result<(), Error> {
+    for range in ranges {
+        check_range(range, bytes.len())?;
+    }
+
+    let touched = match platform::touched_pages(bytes.as_ptr() as usize, bytes.len())? {
+        Some(touched) => touched,
+        _ => return write_ranges(bytes, writer, ranges),
+    };
+
+    let page_size = crate::page_size();
+    let mut skipped = false;
+
+    // The output may already have contents past the current position, which have to be zeroed.
+    let mut position = writer.stream_position()?;
+    let output_end = writer.seek(SeekFrom::End(0))?;
+    writer.seek(SeekFrom::Start(position))?;
+
+    for range in ranges {
+        let mut start = range.start;
+
+        while start < range.end {
+            // Find the run of pages that are either all touched or all untouched.
+            let is_touched = touched[start / page_size];
+            let mut end = (start / page_size + 1) * page_size;
+
+            while end < range.end && touched[end / page_size] == is_touched {
+                end += page_size;
+            }
+
+            let end = end.min(range.end);
+
+            let size = (end - start) as u64;
+
+            if is_touched {
+                writer.write_all(&bytes[start..end])?;
+                skipped = false;
+            } else {
+                let zeroed = output_end.saturating_sub(position).min(size);
+
+                std::io::copy(&mut std::io::repeat(0).take(zeroed), &mut writer)?;
+
+                if zeroed < size {
+                    writer.seek(SeekFrom::Current((size - zeroed) as i64))?;
+                }
+
+                skipped = zeroed < size;
+            }
+
+            position += size;
+            start = end;
+        }
+    }
+
+    // Seeking past the end does not extend the output, so write the final byte to extend it.
+    if skipped {
+        writer.seek(SeekFrom::Current(-1))?;
+        writer.write_all(&[0])?;
+    }
+
+    Ok(())
+}
+
+macro_rules! write_impl {
+    ($t:ident) => {
+        impl $t {
+            /// Writes the bytes in the given ranges of the mapping to the writer, where the ranges
+            /// are written back-to-back. The writer should be buffered by the user if needed.
+            ///
+            /// Returns [`Error::OutOfBounds`] without writing anything if any of the ranges is out
+            /// of bounds.
+            pub fn write_to<W: Write>(&self, writer: W, ranges: &[Range<usize>]) -> Result<(), Error> {
+                write_ranges(self.as_slice(), writer, ranges)
+            }
+
+            /// Writes the bytes in the given ranges of the mapping to the writer, where the ranges
+            /// are written back-to-back. For private anonymous mappings, i.e. mapped with
+            /// [`MmapFlags::COPY_ON_WRITE`], the pages that have never been touched are skipped
+            /// over by seeking, as they are known to be zero. This creates sparse files when
+            /// writing to a [`File`]. Skipped pages that overlap with existing contents of the
+            /// output are overwritten with zeros instead.
+            ///
+            /// Skipping untouched pages is currently only supported on Linux, where the page map
+            /// of the process is used to find the pages that are either resident or swapped out.
+            /// On other platforms, for file mappings and for shared anonymous memory, of which the
+            /// pages may have been touched by other processes, all the pages are written.
+            ///
+            /// Returns [`Error::OutOfBounds`] without writing anything if any of the ranges is out
+            /// of bounds.
+            pub fn write_touched_to<W: Write + Seek>(
+                &self,
+                writer: W,
+                ranges: &[Range<usize>],
+            ) -> Result<(), Error> {
+                if self.file().is_some() || !self.is_copy_on_write() {
+                    return write_ranges(self.as_slice(), writer, ranges);
+                }
+
+                write_touched_ranges(self.as_slice(), writer, ranges)
+            }
+        }
+    }
+}
+
+write_impl!(Mmap);
+write_impl!(MmapMut);
+
+impl MmapMut {
+    /// Allocates an anonymous mapping that is sized to the remaining length of the reader, i.e.
+    /// from the current position of the reader until the end, and fills the mapping with the
+    /// contents of the reader.
+    ///
+    /// To allocate the mapping with huge pages or to lock it into physical memory, use
+    /// [`MmapOptions::map_mut_from_reader()`] instead.
+    pub fn read_from<R: Read + Seek>(mut reader: R) -> Result<Self, Error> {
+        let position = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(position))?;
+
+        MmapOptions::new((end - position) as usize)
+            .map_mut_from_reader(reader)
+    }
+
+    /// Allocates an anonymous mapping that is sized to the file and backed by huge pages, and
+    /// reads the contents of the file into the mapping using the given number of threads. See
+    /// [`MmapOptions::map_mut_from_file()`]. This is useful for large datasets that are mostly
+    /// read, as file mappings cannot use explicit huge pages on most systems, while huge pages
+    /// significantly reduce the number of TLB misses.
+    ///
+    /// Explicit huge pages are used if enough of them are available, see
+    /// [`MmapOptions::check_huge_pages()`]. Otherwise, this falls back to a private mapping that
+    /// is advised with `MADV_HUGEPAGE` on Linux, such that the kernel backs the mapping with
+    /// transparent huge pages where possible. As the size of the mapping is rounded up to a
+    /// multiple of the page size, the mapping may be larger than the file, in which case the
+    /// remaining bytes are left zeroed.
+    pub fn load_huge(file: &File, threads: usize) -> Result<Self, Error> {
+        let size = (file.metadata()?.len() as usize).max(1);
+
+        if let Some(huge_page_size) = platform::huge_page_size() {
+            let options = MmapOptions::new(size.div_ceil(huge_page_size) * huge_page_size)
+                .with_flags(MmapFlags::HUGE_PAGES);
+
+            // Other processes may have taken the huge pages in the meantime.
+            if options.check_huge_pages().is_ok() {
+                if let Ok(mapping) = options.map_mut() {
+                    return fill_from_file(mapping, file, 0, threads);
+                }
+            }
+        }
+
+        // Transparent huge pages are only used for private anonymous memory, but copy-on-write
+        // anonymous memory is not supported on Microsoft Windows.
+        let flags = if cfg!(unix) {
+            MmapFlags::COPY_ON_WRITE
+        } else {
+            MmapFlags::empty()
+        };
+
+        let mapping = MmapOptions::new(crate::align_up(size))
+            .with_flags(flags)
+            .map_mut()?;
+
+        // The advice is merely a hint, e.g. transparent huge pages may have been disabled.
+        let _ = mapping.advise_huge_pages();
+
+        fill_from_file(mapping, file, 0, threads)
+    }
+
+    /// Fills the mapping with the contents of the reader, until either the mapping is full or the
+    /// reader reaches the end of the stream. Returns the number of bytes read.
+    pub fn fill_from<R: Read>(&mut self, reader: R) -> Result<usize, Error> {
+        read_until_full(reader, self.as_mut_slice())
+    }
+}
+
+impl MmapOptions {
+    /// Maps the memory as mutable and fills the mapping with the contents of the reader, until
+    /// either the mapping is full or the reader reaches the end of the stream. Any remaining bytes
+    /// are left zeroed.
+    pub fn map_mut_from_reader<R: Read>(self, reader: R) -> Result<MmapMut, Error> {
+        let mut mapping = self.map_mut()?;
+
+        mapping.fill_from(reader)?;
+
+        Ok(mapping)
+    }
+
+    /// Maps the memory as mutable and fills the mapping with the contents of the file starting at
+    /// the given offset, until either the mapping is full or the end of the file is reached. Any
+    /// remaining bytes are left zeroed.
+    ///
+    /// The mapping is split into page-aligned chunks that are read in parallel by the given number
+    /// of threads, using positional reads such that the threads do not contend over the position
+    /// of the file. This is mostly useful to populate large mappings that are backed by huge pages
+    /// or that are locked into physical memory.
+    pub fn map_mut_from_file(self, file: &File, offset: u64, threads: usize) -> Result<MmapMut, Error> {
+        fill_from_file(self.map_mut()?, file, offset, threads)
+    }
+}
+
+/// Fills the mapping with the contents of the file starting at the given offset, where the
+/// mapping is split into page-aligned chunks that are read in parallel by the given number of
+/// threads. See [`MmapOptions::map_mut_from_file()`].
+fn fill_from_file(
+    mut mapping: MmapMut,
+    file: &File,
+    offset: u64,
+    threads: usize,
+) -> Result<MmapMut, Error> {
+    let file_size = file.metadata()?.len();
+    let size = file_size.saturating_sub(offset).min(mapping.size() as u64) as usize;
+
+    if size == 0 {
+        return Ok(mapping);
+    }
+
+    let page_size = crate::page_size();
+    let chunk_size = size.div_ceil(threads.max(1)).div_ceil(page_size) * page_size;
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = mapping[..size]
+            .chunks_mut(chunk_size)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let offset = offset + (index * chunk_size) as u64;
+
+                scope.spawn(move || platform::read_exact_at(file, chunk, offset))
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().unwrap())
+    })?;
+
+    Ok(mapping)
+}
+
+/// A reader that scans a memory mapping sequentially in a single pass. As the reader advances, the
+/// pages ahead of the reader are read ahead, while the pages behind the reader are dropped from
+/// the resident memory, such that scanning a very large file neither bloats the resident set of
+/// the process nor evicts the rest of the page cache.
+///
+/// On Unix, the mapping is advised with `MADV_SEQUENTIAL` for as long as the reader is alive, and
+/// the pages ahead are advised with `MADV_WILLNEED`. The pages behind are dropped with
+/// `MADV_DONTNEED` and, where supported, are dropped from the page cache with
+/// `POSIX_FADV_DONTNEED`. As dropping pages discards their contents, the pages behind are only
+/// dropped for shared file mappings, which read the pages back from the file when they are
+/// accessed again.
+///
+/// On Microsoft Windows, the pages ahead are prefetched with `PrefetchVirtualMemory()` and the
+/// pages behind are removed from the working set, unless the mapping has been locked.
+///
+/// The advice is issued once the reader advances by half of the window, and any errors are
+/// ignored as the advice is merely a hint.
+pub struct DropBehindReader<'a> {
+    mapping: &'a Mmap,
+    position: usize,
+    window: usize,
+    ahead: usize,
+    behind: usize,
+}
+
+impl<'a> DropBehindReader<'a> {
+    fn new(mapping: &'a Mmap) -> Self {
+        let mut reader = Self {
+            mapping,
+            position: 0,
+            window: DEFAULT_WINDOW,
+            ahead: 0,
+            behind: 0,
+        };
+
+        let _ = mapping.set_sequential(true);
+        reader.advise();
+        reader
+    }
+
+    /// Sets the number of bytes to read ahead of the position of the reader, which is rounded up
+    /// to the page size. This defaults to 8 MiB.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = crate::align_up(window.max(1));
+        self.advise();
+        self
+    }
+
+    /// Returns the position of the reader within the mapping.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Issues the advice for the pages ahead of and behind the position of the reader, once the
+    /// reader advanced far enough to be worth the system calls.
+    fn advise(&mut self) {
+        let size = self.mapping.size();
+
+        if self.ahead < size && self.position + self.window / 2 >= self.ahead {
+            let end = crate::align_up(self.position + self.window).min(size);
+
+            let _ = self.mapping.read_ahead(self.ahead..end);
+            self.ahead = end;
+        }
+
+        // Only drop the pages that have been consumed entirely, unless the end has been reached.
+        let behind = if self.position == size {
+            size
+        } else {
+            crate::align_down(self.position)
+        };
+
+        if behind > self.behind && (behind == size || behind - self.behind >= self.window / 2) {
+            let _ = self.mapping.drop_behind(self.behind..behind);
+            self.behind = behind;
+        }
+    }
+}
+
+impl Drop for DropBehindReader<'_> {
+    fn drop(&mut self) {
+        let _ = self.mapping.set_sequential(false);
+    }
+}
+
+impl Read for DropBehindReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes = self.fill_buf()?;
+        let size = bytes.len().min(buf.len());
+
+        buf[..size].copy_from_slice(&bytes[..size]);
+        self.consume(size);
+
+        Ok(size)
+    }
+}
+
+impl BufRead for DropBehindReader<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        // Only hand out the bytes up to the pages that have been read ahead, such that the
+        // advice keeps up with readers that consume everything they are given.
+        let end = self.ahead.max(self.position + 1).min(self.mapping.size());
+
+        Ok(&self.mapping.as_slice()[self.position..end])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.mapping.size());
+        self.advise();
+    }
+}
+
+impl Mmap {
+    /// Returns a [`DropBehindReader`] that scans this mapping sequentially from the start, while
+    /// dropping the pages that have been read.
+    pub fn drop_behind_reader(&self) -> DropBehindReader<'_> {
+        DropBehindReader::new(self)
+    }
+}
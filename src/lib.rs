@@ -1,11 +1,101 @@
 #![doc = include_str!("../README.md")]
 #![deny(missing_docs, rustdoc::broken_intra_doc_links)]
 
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "areas")]
 mod areas;
+mod atomic;
+#[cfg(feature = "tokio")]
+mod async_io;
+mod capabilities;
+mod child;
+#[cfg_attr(feature = "fake", allow(dead_code))]
+mod dirty;
+#[cfg(feature = "exec")]
+mod dual;
+#[cfg(feature = "areas")]
+mod dump;
+#[cfg(feature = "yoke")]
+mod cart;
+mod chunks;
+mod copy;
+pub mod compat;
 pub mod error;
+mod faults;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod flush;
+mod guarded;
+mod io;
+mod lock;
+mod memory_map;
+mod mirrored;
 mod mmap;
+pub mod os;
 mod os_impl;
+mod paired;
+mod persist;
+mod persistent;
+mod process;
+mod protection;
+mod range_lock;
+#[cfg(feature = "exec")]
+mod quarantine;
+mod reservation;
+mod reserved;
+#[cfg(feature = "areas")]
+mod scanner;
+mod shared;
+mod stack;
+mod stats;
+#[cfg(any(feature = "bytemuck", feature = "zerocopy"))]
+mod typed;
+mod values;
+mod view;
+mod volatile;
+mod watch;
 
+#[cfg(feature = "areas")]
 pub use areas::*;
+pub use atomic::AtomicValue;
+pub use capabilities::{capabilities, Capabilities};
+pub use child::{SHARED_ADDRESS_VAR, SHARED_HANDLE_VAR, SHARED_OFFSET_VAR, SHARED_SIZE_VAR};
+pub use chunks::MmapChunks;
+pub use dirty::DirtyTracker;
+#[cfg(feature = "exec")]
+pub use dual::DualMmap;
+#[cfg(feature = "areas")]
+pub use dump::dump_process;
+#[cfg(feature = "tokio")]
+pub use async_io::*;
 pub use error::Error;
+pub use faults::{PageFaultCounter, PageFaults};
+pub use flush::FlushScheduler;
+pub use guarded::GuardedAlloc;
+pub use io::DropBehindReader;
+pub use lock::{lock_all, unlock_all, BackgroundLock, LockAllFlags};
+pub use memory_map::MemoryMap;
+pub use mirrored::MirroredMmap;
 pub use mmap::*;
+pub use paired::PairedMmap;
+pub use persistent::PersistentMmap;
+pub use process::ProcessMemory;
+pub use protection::Protection;
+#[cfg(feature = "exec")]
+pub use quarantine::CodeQuarantine;
+pub use range_lock::RangeLock;
+pub use reservation::Reservation;
+pub use reserved::ReservedMmap;
+#[cfg(feature = "areas")]
+pub use scanner::{MemoryScanner, Pattern};
+pub use shared::SharedMemory;
+pub use stack::StackMmap;
+#[cfg(feature = "registry")]
+pub use stats::{live_mappings, LiveMapping};
+#[cfg(feature = "stats")]
+pub use stats::{stats, MappingStats, MmapStats};
+pub use view::MmapView;
+pub use volatile::VolatileValue;
+pub use watch::{FileEvent, FileWatcher, ReloadingMmap};
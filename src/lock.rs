@@ -0,0 +1,107 @@
+//! This module implements locking all the memory of the process, rather than the pages of
+//! individual mappings, for latency-critical applications that want everything to be pinned, as
+//! well as locking large mappings one chunk at a time on a background thread.
+
+use bitflags::bitflags;
+use crate::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+bitflags! {
+    /// The pages to lock with [`lock_all()`].
+    #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+    pub struct LockAllFlags: u32 {
+        /// Locks the pages that are currently mapped into the process.
+        const CURRENT  = 1 << 0;
+
+        /// Locks the pages that will be mapped into the process in the future.
+        const FUTURE   = 1 << 1;
+
+        /// Locks the pages as they are faulted in, rather than populating them right away. This
+        /// has to be combined with [`LockAllFlags::CURRENT`] or [`LockAllFlags::FUTURE`] and is
+        /// only supported on Linux and Android.
+        const ON_FAULT = 1 << 2;
+    }
+}
+
+/// Locks all the pages of the process into physical memory, such that accessing them causes no
+/// page faults. This uses `mlockall()` on Unix, where the amount of memory that can be locked is
+/// limited by `RLIMIT_MEMLOCK`.
+///
+/// Microsoft Windows has no equivalent. Instead, the minimum working set size is raised to the
+/// current working set size and enforced as a hard limit, such that the pages that are currently
+/// resident stay resident. As such, only [`LockAllFlags::CURRENT`] is supported on Microsoft
+/// Windows. With the `fake` feature, this does nothing, as heap allocations are always resident.
+///
+/// Returns an error of the kind [`std::io::ErrorKind::Unsupported`] if any of the flags are not
+/// supported on the current platform.
+pub fn lock_all(flags: LockAllFlags) -> Result<(), Error> {
+    platform::lock_all(flags)
+}
+
+/// Unlocks all the pages of the process, undoing [`lock_all()`]. On Unix, this uses
+/// `munlockall()`, which also unlocks the pages of mappings that have been locked individually
+/// using [`crate::MmapMut::lock()`] or [`crate::MmapFlags::LOCKED`]. On Microsoft Windows, this
+/// disables the hard limit on the minimum working set size.
+pub fn unlock_all() -> Result<(), Error> {
+    platform::unlock_all()
+}
+
+/// A mapping that is being locked one chunk at a time on a background thread. See
+/// [`crate::MmapMut::lock_in_background()`].
+pub struct BackgroundLock<T> {
+    cancelled: Arc<AtomicBool>,
+    thread: JoinHandle<Result<T, (T, Error)>>,
+}
+
+impl<T: Send + 'static> BackgroundLock<T> {
+    pub(crate) fn spawn<F>(mut mapping: T, f: F) -> Self
+    where
+        F: FnOnce(&mut T, &AtomicBool) -> Result<(), Error> + Send + 'static,
+    {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let cancelled = cancelled.clone();
+
+            std::thread::spawn(move || match f(&mut mapping, &cancelled) {
+                Ok(()) => Ok(mapping),
+                Err(e) => Err((mapping, e)),
+            })
+        };
+
+        Self {
+            cancelled,
+            thread,
+        }
+    }
+}
+
+impl<T> BackgroundLock<T> {
+    /// Cancels the locking after the current chunk, in which case the chunks that have been
+    /// locked so far are unlocked again.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the locking has finished, either successfully or not.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the locking to finish and returns the mapping. In case of failure or
+    /// cancellation, this returns the ownership of the mapping together with the error.
+    pub fn join(self) -> Result<T, (T, Error)> {
+        self.thread.join().unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
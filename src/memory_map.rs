@@ -0,0 +1,151 @@
+//! This module implements a trait that covers the operations that all memory mappings have in
+//! common, such that libraries can accept any kind of mapping, or a mock of one, without being
+//! tied to a particular type or backend.
+
+use crate::error::Error;
+use crate::protection::Protection;
+use crate::{Mmap, MmapMut, MmapNone, ProtectionGuard};
+use std::fs::File;
+use std::ops::Range;
+
+/// The operations that all memory mappings have in common, which is implemented by [`Mmap`],
+/// [`MmapMut`] and [`MmapNone`]. Libraries can accept `impl MemoryMap` or `Box<dyn MemoryMap>`
+/// rather than a particular type of mapping, such that they can be tested using the in-memory
+/// backend of the `fake` feature, or using a mock that implements this trait, and such that
+/// mappings can be wrapped in layers that add behavior.
+///
+/// The functions behave like the functions of the same name on the types of mappings, e.g.
+/// [`Mmap::flush()`].
+pub trait MemoryMap {
+    /// Returns the file backing the mapping, if any.
+    fn file(&self) -> Option<&File>;
+
+    /// Returns a pointer to the start of the mapping.
+    fn as_ptr(&self) -> *const u8;
+
+    /// Returns the size of the mapping in bytes.
+    fn size(&self) -> usize;
+
+    /// Returns the protection of the mapping as last set through this crate.
+    fn protection(&self) -> Protection;
+
+    /// Temporarily changes the protection of the mapping. See [`ProtectionGuard`].
+    fn push_protection(&mut self, protection: Protection) -> Result<ProtectionGuard<'_>, Error>;
+
+    /// Flushes the given range of the mapping synchronously.
+    fn flush(&self, range: Range<usize>) -> Result<(), Error>;
+
+    /// Flushes the given range of the mapping asynchronously.
+    fn flush_async(&self, range: Range<usize>) -> Result<(), Error>;
+
+    /// Locks the pages of the mapping into physical memory.
+    fn lock(&mut self) -> Result<(), Error>;
+
+    /// Unlocks the pages of the mapping.
+    fn unlock(&mut self) -> Result<(), Error>;
+}
+
+macro_rules! memory_map_impl {
+    ($t:ident) => {
+        impl MemoryMap for $t {
+            #[inline]
+            fn file(&self) -> Option<&File> {
+                $t::file(self)
+            }
+
+            #[inline]
+            fn as_ptr(&self) -> *const u8 {
+                $t::as_ptr(self)
+            }
+
+            #[inline]
+            fn size(&self) -> usize {
+                $t::size(self)
+            }
+
+            #[inline]
+            fn protection(&self) -> Protection {
+                $t::protection(self)
+            }
+
+            #[inline]
+            fn push_protection(
+                &mut self,
+                protection: Protection,
+            ) -> Result<ProtectionGuard<'_>, Error> {
+                $t::push_protection(self, protection)
+            }
+
+            #[inline]
+            fn flush(&self, range: Range<usize>) -> Result<(), Error> {
+                $t::flush(self, range)
+            }
+
+            #[inline]
+            fn flush_async(&self, range: Range<usize>) -> Result<(), Error> {
+                $t::flush_async(self, range)
+            }
+
+            #[inline]
+            fn lock(&mut self) -> Result<(), Error> {
+                $t::lock(self)
+            }
+
+            #[inline]
+            fn unlock(&mut self) -> Result<(), Error> {
+                $t::unlock(self)
+            }
+        }
+    }
+}
+
+memory_map_impl!(MmapNone);
+memory_map_impl!(Mmap);
+memory_map_impl!(MmapMut);
+
+impl<T: MemoryMap + ?Sized> MemoryMap for Box<T> {
+    #[inline]
+    fn file(&self) -> Option<&File> {
+        (**self).file()
+    }
+
+    #[inline]
+    fn as_ptr(&self) -> *const u8 {
+        (**self).as_ptr()
+    }
+
+    #[inline]
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+
+    #[inline]
+    fn protection(&self) -> Protection {
+        (**self).protection()
+    }
+
+    #[inline]
+    fn push_protection(&mut self, protection: Protection) -> Result<ProtectionGuard<'_>, Error> {
+        (**self).push_protection(protection)
+    }
+
+    #[inline]
+    fn flush(&self, range: Range<usize>) -> Result<(), Error> {
+        (**self).flush(range)
+    }
+
+    #[inline]
+    fn flush_async(&self, range: Range<usize>) -> Result<(), Error> {
+        (**self).flush_async(range)
+    }
+
+    #[inline]
+    fn lock(&mut self) -> Result<(), Error> {
+        (**self).lock()
+    }
+
+    #[inline]
+    fn unlock(&mut self) -> Result<(), Error> {
+        (**self).unlock()
+    }
+}
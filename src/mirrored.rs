@@ -0,0 +1,91 @@
+//! This module implements mirrored mappings, which map the same memory twice back-to-back, such
+//! that ring buffers can hand out contiguous slices without copying the bytes that wrap around.
+
+use crate::error::Error;
+use crate::mmap::{allocation_granularity, check_range};
+use std::ops::Range;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// Represents a mapping of the same shared memory twice back-to-back, which is also known as a
+/// magic ring buffer. The byte at offset `i` and the byte at offset `i + size` are the same byte,
+/// such that any range of up to `size` bytes starting within the first half is contiguous, even
+/// if it wraps around the end of the buffer.
+///
+/// On Unix, the memory is created using `memfd_create()` on Linux, `SHM_ANON` on FreeBSD and an
+/// unlinked POSIX shared memory object elsewhere, and both views are mapped over a reservation of
+/// the address space. On Microsoft Windows, the views of a section are mapped over a placeholder,
+/// which requires Microsoft Windows 10, version 1803 or newer. This is not supported with the
+/// `fake` feature.
+pub struct MirroredMmap {
+    inner: platform::MirroredMmap,
+}
+
+impl MirroredMmap {
+    /// Maps `size` bytes of zero-initialized memory twice back-to-back, where `size` must be a
+    /// multiple of the allocation granularity. See [`allocation_granularity()`].
+    ///
+    /// Returns [`Error::Unaligned`] if the size is not a multiple of the allocation granularity.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let granularity = allocation_granularity();
+
+        if !size.is_multiple_of(granularity) {
+            return Err(Error::Unaligned(size, granularity));
+        }
+
+        Ok(Self {
+            inner: platform::MirroredMmap::new(size)?,
+        })
+    }
+
+    /// Returns the size of the buffer, which is half of the address space spanned by the
+    /// mapping.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Returns a raw pointer to the start of the mapping.
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.inner.as_ptr()
+    }
+
+    /// Returns a raw mutable pointer to the start of the mapping.
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.inner.as_mut_ptr()
+    }
+
+    /// Extracts a slice spanning both views, i.e. twice the size of the buffer.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(self.as_ptr(), self.size() * 2)
+        }
+    }
+
+    /// Extracts a mutable slice of the given range of both views. As the views alias, the range
+    /// may span at most the size of the buffer, such that no byte appears twice in the slice.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is out of bounds or spans more than the size of
+    /// the buffer.
+    pub fn slice_mut(&mut self, range: Range<usize>) -> Result<&mut [u8], Error> {
+        check_range(&range, self.size() * 2)?;
+
+        if range.end - range.start > self.size() {
+            return Err(Error::OutOfBounds(range));
+        }
+
+        Ok(unsafe {
+            std::slice::from_raw_parts_mut(self.as_mut_ptr().add(range.start), range.end - range.start)
+        })
+    }
+}
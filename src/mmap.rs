@@ -1,20 +1,38 @@
 use bitflags::bitflags;
+use crate::chunks::MmapChunks;
 use crate::error::Error;
+use crate::lock::BackgroundLock;
+use crate::protection::Protection;
+use crate::range_lock::RangeLock;
+use crate::reservation::Reservation;
+use crate::reserved::ReservedMmap;
+use crate::stack::StackMmap;
+use crate::view::MmapView;
+use std::convert::TryFrom;
 use std::fs::File;
 use std::ops::{Deref, DerefMut, Range};
+use std::sync::{Arc, OnceLock};
 
-#[cfg(unix)]
+#[cfg(all(unix, not(feature = "fake")))]
 use crate::os_impl::unix as platform;
 
-#[cfg(windows)]
+#[cfg(all(windows, not(feature = "fake")))]
 use crate::os_impl::windows as platform;
 
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
 bitflags! {
     /// The available flags to configure the allocated mapping.
+    #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
     pub struct MmapFlags: u32 {
         /// May initially map the pages as shared between multiple mappings, but creates a private
         /// copy when writing to the pages such that any modifications are not visible to any other
         /// processes.
+        ///
+        /// File mappings are mapped as private mappings, using `MAP_PRIVATE` on Unix and views
+        /// mapped with `FILE_MAP_COPY` on Microsoft Windows, such that modifications never reach
+        /// the file, also after changing the protection of the mapping.
         const COPY_ON_WRITE = 1 << 0;
 
         /// Ensure the allocated pages are populated, such that they do not cause page faults.
@@ -23,10 +41,14 @@ bitflags! {
         /// Do not reserve swap space for this allocation.
         const NO_RESERVE    = 1 << 2;
 
-        /// Use huge pages for this allocation.
+        /// Use huge pages for this allocation. On Microsoft Windows, this enables the
+        /// `SeLockMemoryPrivilege` privilege in the access token of the process when mapping, see
+        /// `mmap_rs::os::windows::enable_lock_memory_privilege()`.
         const HUGE_PAGES    = 1 << 3;
 
-        /// The region grows downward like a stack.
+        /// The region grows downward like a stack. On Unix, this uses `MAP_STACK` where available
+        /// and maps the memory as private, such that it is not shared with child processes. See
+        /// [`MmapOptions::map_stack()`] to map a stack with a guard page.
         const STACK         = 1 << 4;
 
         /// The pages will not be included in a core dump.
@@ -35,10 +57,87 @@ bitflags! {
         /// Lock the physical memory to prevent page faults from happening when accessing the
         /// pages.
         const LOCKED        = 1 << 6;
+
+        /// Map the pages as uncached, such that every access goes straight to the device or
+        /// memory backing the pages. This is mostly useful to map memory-mapped I/O registers.
+        ///
+        /// On Microsoft Windows, this uses `PAGE_NOCACHE` for anonymous mappings and
+        /// `SEC_NOCACHE` for file mappings. On Unix, the caching attributes are decided by the
+        /// driver backing the file rather than by `mmap()`. Therefore this flag requires a file
+        /// mapping of a character device or a sysfs file on Linux, of which the file has been
+        /// opened with `O_SYNC` (e.g. `/dev/mem` on Linux). Mapping fails with
+        /// [`Error::UnsupportedFlags`] if the attributes cannot be honored.
+        const UNCACHED      = 1 << 7;
+
+        /// Map the pages as write-combining, such that writes may be buffered and combined into
+        /// larger bursts, while reads are uncached. This is mostly useful to map framebuffers and
+        /// other device memory that is predominantly written to.
+        ///
+        /// On Microsoft Windows, this uses `PAGE_WRITECOMBINE` for anonymous mappings and
+        /// `SEC_WRITECOMBINE` for file mappings. On Linux, this flag requires a file mapping of a
+        /// character device or a sysfs file where the driver provides write-combining semantics,
+        /// such as the `resourceN_wc` files of PCI devices. Other Unix platforms provide no way to
+        /// request write-combining memory. Mapping fails with [`Error::UnsupportedFlags`] if the
+        /// attributes cannot be honored. This flag cannot be combined with
+        /// [`MmapFlags::UNCACHED`].
+        const WRITE_COMBINE = 1 << 8;
+
+        /// Overwrites the mapping with zeroes before unmapping it, such that sensitive data does
+        /// not linger in physical pages that are reused later on. The zeroes are written using
+        /// volatile writes, such that the writes cannot be elided by the compiler. This changes
+        /// the protection of the mapping to read-write first, as the mapping may have been made
+        /// read-only or inaccessible.
+        ///
+        /// As this would overwrite the contents of the file, this flag cannot be used for shared
+        /// file mappings, i.e. file mappings must also set [`MmapFlags::COPY_ON_WRITE`].
+        const ZERO_ON_DROP  = 1 << 9;
+
+        /// Lets the kernel extend the mapping downward when a page fault occurs just below it,
+        /// the way the main thread stack grows, which is useful for the stacks of threads and
+        /// guests that should only consume address space as they grow. The lowest page of the
+        /// mapping acts as a guard page that triggers the growth.
+        ///
+        /// This uses `MAP_GROWSDOWN` and is only supported for anonymous mappings on Linux and
+        /// Android, where mapping fails with [`Error::UnsupportedFlags`] otherwise. As Linux only
+        /// grows private mappings, the pages are not shared with child processes. Note that the
+        /// kernel does not guarantee that the address space below the mapping remains available,
+        /// such that the portable approach of mapping the full stack with a guard page below it is
+        /// more robust.
+        const GROWS_DOWN    = 1 << 10;
+
+        /// Checks whether the pages fit within the memory limits before populating or locking
+        /// them, i.e. when mapping with [`MmapFlags::POPULATE`] or [`MmapFlags::LOCKED`] on Unix,
+        /// and when calling `lock()` or `populate_parallel()` later on. This fails early with
+        /// [`Error::OutOfMemory`], rather than triggering the OOM killer or exhausting the commit
+        /// limit halfway through. As the limits may change concurrently, this is a best effort.
+        ///
+        /// On Linux, this consults `memory.max` and `memory.current` of the cgroup v2 of the
+        /// process and of its ancestors. On Microsoft Windows, this consults the commit limit of
+        /// the system. The whole mapping is accounted for, even if some of the pages are resident
+        /// already. This has no effect on other platforms or with the `fake` feature.
+        const CHECK_MEMORY_LIMIT = 1 << 11;
+
+        /// Advises the kernel to back the mapping with transparent huge pages, which reduces the
+        /// pressure on the TLB for large mappings that are accessed throughout, without having to
+        /// reserve huge pages up front like [`MmapFlags::HUGE_PAGES`].
+        ///
+        /// On Linux and Android, this uses `madvise()` with `MADV_HUGEPAGE`, which is ignored if
+        /// the kernel has been built without transparent huge pages. This has no effect on other
+        /// platforms, where huge pages are either used transparently or not at all.
+        const TRANSPARENT_HUGE_PAGES = 1 << 12;
+
+        /// Excludes the pages from being merged with identical pages of other mappings, which
+        /// leaks the contents of the pages through the timing of copy-on-write faults.
+        ///
+        /// On Linux, this uses `madvise()` with `MADV_UNMERGEABLE`, which opts out of KSM even if
+        /// merging has been enabled for the whole process through `PR_SET_MEMORY_MERGE`. This is
+        /// ignored if the kernel has been built without KSM, and has no effect on other platforms.
+        const NO_MERGE      = 1 << 13;
     }
 
     /// The available flags to configure the allocated mapping, but that are considered unsafe to
     /// use.
+    #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
     pub struct UnsafeMmapFlags: u32 {
         /// Maps the memory mapping at the address specified, replacing any pages that have been
         /// mapped at that address range.
@@ -66,17 +165,48 @@ bitflags! {
         /// If the user modified the pages, then executing the code may result in undefined
         /// behavior. To ensure correct behavior a user has to flush the instruction cache after
         /// modifying and before executing the page.
+        #[cfg(feature = "jit")]
         const JIT       = 1 << 1;
+
+        /// Requests anonymous memory that has not been zeroed, which avoids the cost of clearing
+        /// the pages for memory that is fully overwritten anyway, e.g. on embedded systems.
+        ///
+        /// As the pages may contain the data of other processes, this is only honored on Linux
+        /// kernels that have been configured with `CONFIG_MMAP_ALLOW_UNINITIALIZED`, which is
+        /// generally limited to systems without an MMU. Otherwise, the memory is zeroed as usual,
+        /// so this flag merely is a hint. The contents of the mapping should be written before
+        /// they are read.
+        const UNINITIALIZED = 1 << 2;
     }
 }
 
 /// The preferred size of the pages uses, where the size is in log2 notation.
 ///
-/// Note that not all the offered page sizes may be available on the current platform.
+/// Note that not all the offered page sizes may be available on the current platform. Besides the
+/// constants, any power of two can be used, see [`PageSize::from_bytes()`], such that unusual
+/// huge page sizes, e.g. 32 MiB pages on some arm64 configurations, can be used on Linux, where
+/// the size is passed to the kernel using `MAP_HUGE_SHIFT`. Sizes up to the page size of the
+/// system use regular pages.
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct PageSize(pub usize);
 
 impl PageSize {
+    /// Returns the page size for the given size in bytes, or `None` if the size is not a power of
+    /// two.
+    pub fn from_bytes(bytes: usize) -> Option<Self> {
+        bytes
+            .is_power_of_two()
+            .then(|| Self(bytes.trailing_zeros() as usize))
+    }
+
+    /// Returns the page size in bytes, or `None` if it does not fit in a `usize`.
+    pub fn bytes(self) -> Option<usize> {
+        u32::try_from(self.0)
+            .ok()
+            .and_then(|shift| 1usize.checked_shl(shift))
+    }
+
     /// Map the mapping using 4 KiB pages.
     pub const _4K:   Self = Self(12);
     /// Map the mapping using 64 KiB pages.
@@ -107,6 +237,329 @@ impl PageSize {
     pub const _16G:  Self = Self(34);
 }
 
+/// The way in which an advisory lock is acquired on the file backing a mapping. See
+/// [`MmapOptions::with_file_lock()`] for more information.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum FileLock {
+    /// Waits until the lock can be acquired.
+    Wait,
+    /// Fails immediately if a conflicting lock is held through another file handle.
+    Try,
+}
+
+/// What happens to the pages of a mapping when the mapping is dropped. See
+/// [`MmapOptions::with_drop_behavior()`] for more information.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum DropBehavior {
+    /// Unmaps the pages right away. The dirty pages of shared file mappings are written back to
+    /// the file by the operating system at some point afterwards.
+    #[default]
+    Unmap,
+    /// Synchronously flushes the dirty pages of file mappings before unmapping the pages, such
+    /// that the contents have been written to the file once the mapping has been dropped.
+    Flush,
+    /// Tells the operating system that the contents of the pages are no longer needed before
+    /// unmapping the pages, such that private and anonymous pages can be reclaimed right away.
+    /// This is meant for scratch data. The dirty pages of shared file mappings are still written
+    /// back to the file.
+    Discard,
+}
+
+/// How the pages should be evicted from memory. See [`Mmap::evict()`] for more information.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Eviction {
+    /// Reclaims the pages right away, writing them back to the file or to swap space as needed.
+    PageOut,
+    /// Marks the pages as inactive, such that they are the first to be reclaimed once the system
+    /// runs low on memory.
+    Cold,
+}
+
+/// How the pages of a mapping are expected to be accessed, or whether their contents are still
+/// needed. See [`Mmap::advise()`] for more information.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Advice {
+    /// The pages will be accessed soon, such that they should be read ahead.
+    WillNeed,
+    /// The pages will not be accessed soon, such that they can be reclaimed right away. The
+    /// contents of private and anonymous pages may be discarded.
+    DontNeed,
+    /// The pages will be accessed sequentially, such that they can be read ahead aggressively and
+    /// reclaimed soon after they have been accessed.
+    Sequential,
+    /// The pages will be accessed in random order, such that reading ahead is pointless.
+    Random,
+    /// The contents of the pages are no longer needed, such that the pages can be reclaimed
+    /// lazily once the system runs low on memory. The contents of private and anonymous pages may
+    /// be discarded.
+    Free,
+}
+
+/// A combination of flags that suits a common workload, as set by [`MmapOptions::preset()`].
+/// These encode the practices of projects that rely on memory mappings heavily, such that they
+/// serve as a starting point rather than having to be rediscovered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Preset {
+    /// The memory of a virtual machine guest, which is large and sparsely touched. Swap space is
+    /// not reserved, such that guests can be overcommitted, transparent huge pages reduce the
+    /// cost of nested page walks, and the memory is excluded from core dumps of the host process.
+    VmGuestMemory,
+    /// A cache of a database, which is accessed randomly and may be evicted at any time. The cache
+    /// is excluded from core dumps and checked against the memory limits when populated or
+    /// locked. Transparent huge pages are deliberately not requested, as the compaction needed to
+    /// provide them results in latency spikes for this access pattern.
+    DatabaseCache,
+    /// A code cache of a JIT compiler, which is reserved up front and filled incrementally, such
+    /// that swap space is not reserved. The code should be written through [`MmapMut`] and then
+    /// made executable using `MmapMut::make_exec()`, rather than mapping it as both writable
+    /// and executable.
+    JitCode,
+    /// A secret such as a key, which should never leave physical memory. The pages are locked, so
+    /// that they cannot be swapped out, are excluded from core dumps and from page merging, and
+    /// are zeroed when dropped. This is meant for small anonymous mappings, as the amount of
+    /// memory that can be locked is limited.
+    SecureSecret,
+}
+
+impl Preset {
+    /// Returns the flags that the preset sets on the current platform.
+    pub fn flags(self) -> MmapFlags {
+        match self {
+            Self::VmGuestMemory =>
+                MmapFlags::NO_RESERVE | MmapFlags::TRANSPARENT_HUGE_PAGES | MmapFlags::NO_CORE_DUMP,
+            Self::DatabaseCache => MmapFlags::NO_CORE_DUMP | MmapFlags::CHECK_MEMORY_LIMIT,
+            Self::JitCode => MmapFlags::NO_RESERVE,
+            Self::SecureSecret =>
+                MmapFlags::LOCKED |
+                MmapFlags::NO_CORE_DUMP |
+                MmapFlags::NO_MERGE |
+                MmapFlags::ZERO_ON_DROP,
+        }
+    }
+}
+
+/// Queries the page size and the allocation granularity once, as this requires a system call.
+fn page_sizes() -> (usize, usize) {
+    static PAGE_SIZES: OnceLock<(usize, usize)> = OnceLock::new();
+
+    *PAGE_SIZES.get_or_init(platform::MmapOptions::page_size)
+}
+
+/// Returns the smallest possible page size for the current platform. The size of an allocation
+/// must be aligned to the page size for the allocation to succeed.
+///
+/// The page size is queried from the operating system once and cached afterwards.
+#[inline]
+pub fn page_size() -> usize {
+    page_sizes().0
+}
+
+/// Returns the allocation granularity of the current platform, which may be a multiple of the
+/// page size on some platforms, e.g. 64 KiB on Microsoft Windows. The start address of an
+/// allocation must be aligned to the allocation granularity for the allocation to succeed.
+///
+/// The allocation granularity is queried from the operating system once and cached afterwards.
+#[inline]
+pub fn allocation_granularity() -> usize {
+    page_sizes().1
+}
+
+/// Rounds the address or size up to the next multiple of the page size.
+///
+/// # Panics
+///
+/// Panics if the rounded address overflows `usize`.
+#[inline]
+pub fn align_up(address: usize) -> usize {
+    let page_size = page_size();
+
+    address.div_ceil(page_size) * page_size
+}
+
+/// Rounds the address or size down to the previous multiple of the page size.
+#[inline]
+pub fn align_down(address: usize) -> usize {
+    let page_size = page_size();
+
+    address / page_size * page_size
+}
+
+/// Returns the offset of the address within its page.
+#[inline]
+pub fn page_offset(address: usize) -> usize {
+    address % page_size()
+}
+
+/// Extends the range to the bounds of the pages that it covers, i.e. the start is rounded down
+/// and the end is rounded up to a multiple of the page size. This is useful to turn an arbitrary
+/// range into a range that can be passed to functions that operate on whole pages.
+///
+/// # Panics
+///
+/// Panics if the rounded end overflows `usize`.
+#[inline]
+pub fn range_to_page_bounds(range: Range<usize>) -> Range<usize> {
+    align_down(range.start)..align_up(range.end)
+}
+
+/// Checks whether the pages of the mapping may be given the protection, as executable memory
+/// requires the `exec` feature, and memory that is both writable and executable requires the `jit`
+/// feature as well as [`UnsafeMmapFlags::JIT`].
+pub(crate) fn check_protection(inner: &platform::Mmap, protection: Protection) -> Result<(), Error> {
+    if !protection.contains(Protection::EXECUTE) {
+        return Ok(());
+    }
+
+    if cfg!(not(feature = "exec")) {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "executable memory requires the exec feature",
+        )));
+    }
+
+    if !protection.contains(Protection::WRITE) {
+        return Ok(());
+    }
+
+    #[cfg(feature = "jit")]
+    {
+        check_wx()?;
+
+        if !inner.is_jit() {
+            return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "jit"))]
+    {
+        let _ = inner;
+
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "writable and executable memory requires the jit feature",
+        )))
+    }
+}
+
+/// Copies the contents of the mapping into a new anonymous mapping with the flags of the mapping
+/// that still apply to anonymous memory.
+fn copy_to_anon(
+    inner: &platform::Mmap,
+    bytes: &[u8],
+    page_size: Option<PageSize>,
+) -> Result<MmapMut, Error> {
+    let mut options = MmapOptions::new(bytes.len()).with_flags(inner.anon_flags());
+
+    if let Some(page_size) = page_size {
+        options = options.with_page_size(page_size);
+    }
+
+    options.map_mut_from_slice(bytes)
+}
+
+/// Faults in the pages of the mapping for reading or writing, where the mapping is split into
+/// page-aligned chunks of equal size that are populated by the given number of threads. Zero uses
+/// the available parallelism instead.
+fn populate_parallel(inner: &platform::Mmap, threads: usize, write: bool) -> Result<(), Error> {
+    inner.check_memory_limit()?;
+
+    let size = inner.size();
+
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+
+    let chunk_size = align_up(size.div_ceil(threads).max(1));
+
+    if chunk_size >= size {
+        return inner.populate(0..size, write);
+    }
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..size)
+            .step_by(chunk_size)
+            .map(|start| {
+                let range = start..size.min(start + chunk_size);
+
+                scope.spawn(move || inner.populate(range, write))
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+    })
+}
+
+/// Checks whether the range is within the bounds of a mapping of the given size.
+pub(crate) fn check_range(range: &Range<usize>, size: usize) -> Result<(), Error> {
+    if range.start > range.end || range.end > size {
+        return Err(Error::OutOfBounds(range.clone()));
+    }
+
+    Ok(())
+}
+
+/// Checks whether the ranges are within the bounds of a mapping of the given size, and turns them
+/// into the smallest set of sorted page ranges that covers them, such that every range can be
+/// flushed with a single call.
+pub(crate) fn coalesce_page_ranges(
+    ranges: &[Range<usize>],
+    size: usize,
+) -> Result<Vec<Range<usize>>, Error> {
+    let mut pages = Vec::with_capacity(ranges.len());
+
+    for range in ranges {
+        check_range(range, size)?;
+
+        if !range.is_empty() {
+            pages.push(align_down(range.start)..align_up(range.end).min(size));
+        }
+    }
+
+    crate::flush::coalesce(&mut pages);
+
+    Ok(pages)
+}
+
+/// Checks whether the address range in which a mapping has to be placed is aligned to the
+/// allocation granularity.
+#[cfg(not(feature = "fake"))]
+pub(crate) fn check_address_range(range: &Range<usize>) -> Result<(), Error> {
+    let granularity = allocation_granularity();
+
+    if range.start > range.end {
+        return Err(Error::OutOfBounds(range.clone()));
+    }
+
+    for bound in [range.start, range.end] {
+        if !bound.is_multiple_of(granularity) {
+            return Err(Error::Unaligned(bound, granularity));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks whether memory may be mapped as both writable and executable, which is never the case
+/// when the `strict-wx` feature is enabled.
+#[cfg(feature = "jit")]
+fn check_wx() -> Result<(), Error> {
+    if cfg!(feature = "strict-wx") {
+        return Err(Error::WxForbidden);
+    }
+
+    Ok(())
+}
+
 macro_rules! mmap_impl {
     ($t:ident) => {
         impl $t {
@@ -116,6 +569,23 @@ macro_rules! mmap_impl {
                 self.inner.file()
             }
 
+            /// Yields the offset within the file at which this mapping starts, as passed to
+            /// [`MmapOptions::with_file()`], or zero if this mapping is not backed by a file.
+            #[inline]
+            pub fn offset(&self) -> u64 {
+                self.inner.file_offset()
+            }
+
+            /// Yields the offset within the file from which the pages of this mapping have been
+            /// mapped, i.e. [`Self::offset()`] rounded down to the allocation granularity. The
+            /// bytes between the two offsets are mapped as well, but are not part of this mapping.
+            #[inline]
+            pub fn aligned_offset(&self) -> u64 {
+                let offset = self.offset();
+
+                offset - offset % allocation_granularity() as u64
+            }
+
             /// Yields a raw immutable pointer of this mapping.
             #[inline]
             pub fn as_ptr(&self) -> *const u8 {
@@ -128,6 +598,38 @@ macro_rules! mmap_impl {
                 self.inner.size()
             }
 
+            /// Whether the pages of this mapping are private, i.e. mapped with
+            /// [`MmapFlags::COPY_ON_WRITE`], rather than shared with other processes.
+            #[inline]
+            #[allow(dead_code)]
+            pub(crate) fn is_copy_on_write(&self) -> bool {
+                self.inner.is_copy_on_write()
+            }
+
+            /// Yields the protection of this mapping as last set through this crate. On Microsoft
+            /// Windows, the protection of the pages is queried instead, as it is not tracked. Use
+            /// [`Self::protection_of()`] to query the effective protection of a range.
+            pub fn protection(&self) -> Protection {
+                self.inner.protection()
+            }
+
+            /// Queries the effective protection of the pages in the given range, i.e. the
+            /// protection that all the pages in the range have in common, as the pages may also
+            /// have been protected by other means than this crate. On Unix, this looks up the
+            /// memory areas of the process, which requires the `areas` feature, while on
+            /// Microsoft Windows, this uses `VirtualQuery()`.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the range is empty or out of bounds.
+            pub fn protection_of(&self, range: Range<usize>) -> Result<Protection, Error> {
+                check_range(&range, self.size())?;
+
+                if range.is_empty() {
+                    return Err(Error::OutOfBounds(range));
+                }
+
+                self.inner.protection_of(range)
+            }
+
             /// Locks the physical pages in memory such that accessing the mapping causes no page faults.
             pub fn lock(&mut self) -> Result<(), Error> {
                 self.inner.lock()
@@ -139,6 +641,91 @@ macro_rules! mmap_impl {
                 self.inner.unlock()
             }
 
+            /// Locks the physical pages in memory one chunk of the given size at a time, rather
+            /// than all at once, as locking hundreds of GiB in a single call stalls for a long
+            /// time without any indication of progress. After every chunk, the progress function
+            /// is called with the number of bytes locked so far and the size of the mapping, and
+            /// returning `false` from it cancels the locking.
+            ///
+            /// If locking a chunk fails or the locking is cancelled, the chunks that have been
+            /// locked so far are unlocked again, such that the mapping is left as it was. This
+            /// includes pages that had been locked before. Cancelling returns an error of the kind
+            /// [`std::io::ErrorKind::Interrupted`]. Returns [`Error::Unaligned`] if the chunk size
+            /// is not a non-zero multiple of the page size.
+            pub fn lock_in_chunks<F>(
+                &mut self,
+                chunk_size: usize,
+                mut progress: F,
+            ) -> Result<(), Error>
+            where
+                F: FnMut(usize, usize) -> bool,
+            {
+                let page_size = page_size();
+
+                if chunk_size == 0 || !chunk_size.is_multiple_of(page_size) {
+                    return Err(Error::Unaligned(chunk_size, page_size));
+                }
+
+                self.inner.check_memory_limit()?;
+
+                let size = self.size();
+                let mut locked = 0;
+
+                let result = loop {
+                    if locked == size {
+                        break Ok(());
+                    }
+
+                    let end = (locked + chunk_size).min(size);
+
+                    if let Err(e) = self.inner.lock_pages(locked..end, true) {
+                        break Err(e);
+                    }
+
+                    locked = end;
+
+                    if !progress(locked, size) {
+                        break Err(Error::Io(std::io::Error::new(
+                            std::io::ErrorKind::Interrupted,
+                            "locking has been cancelled",
+                        )));
+                    }
+                };
+
+                if let Err(e) = result {
+                    if locked > 0 {
+                        let _ = self.inner.lock_pages(0..locked, false);
+                    }
+
+                    return Err(e);
+                }
+
+                self.inner.set_locked();
+
+                Ok(())
+            }
+
+            /// Locks the physical pages in memory one chunk at a time on a background thread,
+            /// which takes ownership of the mapping until the locking has finished. See
+            /// [`Self::lock_in_chunks()`] and [`BackgroundLock`]. The progress function is called
+            /// from the background thread.
+            pub fn lock_in_background<F>(
+                self,
+                chunk_size: usize,
+                mut progress: F,
+            ) -> BackgroundLock<Self>
+            where
+                F: FnMut(usize, usize) + Send + 'static,
+            {
+                BackgroundLock::spawn(self, move |mapping, cancelled| {
+                    mapping.lock_in_chunks(chunk_size, |locked, size| {
+                        progress(locked, size);
+
+                        !cancelled.load(std::sync::atomic::Ordering::Relaxed)
+                    })
+                })
+            }
+
             /// Flushes the memory mapping synchronously, i.e. this function waits for the flush to
             /// complete.
             pub fn flush(&self, range: Range<usize>) -> Result<(), Error> {
@@ -150,6 +737,25 @@ macro_rules! mmap_impl {
                 self.inner.flush_async(range)
             }
 
+            /// Flushes multiple ranges of the memory mapping synchronously, e.g. the scattered
+            /// dirty pages of a checkpoint. The ranges may be given in any order and may overlap,
+            /// as they are sorted and merged first, such that every page is flushed once. The
+            /// pages are written back with a single call per merged range, followed by a single
+            /// sync of the file, rather than syncing the file for every range.
+            pub fn flush_ranges(&self, ranges: &[Range<usize>]) -> Result<(), Error> {
+                let ranges = coalesce_page_ranges(ranges, self.size())?;
+
+                self.inner.flush_ranges(&ranges, true)
+            }
+
+            /// Flushes multiple ranges of the memory mapping asynchronously. See
+            /// [`Self::flush_ranges()`].
+            pub fn flush_ranges_async(&self, ranges: &[Range<usize>]) -> Result<(), Error> {
+                let ranges = coalesce_page_ranges(ranges, self.size())?;
+
+                self.inner.flush_ranges(&ranges, false)
+            }
+
             /// This function can be used to flush the instruction cache on architectures where
             /// this is required.
             ///
@@ -162,6 +768,544 @@ macro_rules! mmap_impl {
                 self.inner.flush_icache()
             }
 
+            /// Publishes freshly written code in the given range to the threads that may be
+            /// running on other cores. Flushing the instruction cache alone does not suffice for
+            /// that, as other cores may still execute instructions that they fetched before the
+            /// code was written, so this flushes the instruction cache for the range, and then
+            /// makes every core that runs a thread of the process serialize its instruction
+            /// stream.
+            ///
+            /// On Linux, this uses `membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE)`, which
+            /// requires Linux 4.16 or newer. On Microsoft Windows, this uses
+            /// `FlushProcessWriteBuffers()`. Elsewhere, or if the kernel does not support the
+            /// command, the protection of a helper page is changed to interrupt the other cores,
+            /// which is more expensive.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the range is out of bounds.
+            pub fn publish_code(&self, range: Range<usize>) -> Result<(), Error> {
+                check_range(&range, self.size())?;
+
+                self.inner.publish_code(range)
+            }
+
+            /// Shrinks the memory mapping in place to the given size, which releases the pages past
+            /// the new size, such that over-provisioned mappings can return memory and address
+            /// space without having to be remapped. If [`MmapFlags::ZERO_ON_DROP`] has been set,
+            /// the released pages are scrubbed first.
+            ///
+            /// On Microsoft Windows, anonymous memory is decommitted rather than released, as the
+            /// address space of an allocation can only be released as a whole. Views of file
+            /// mappings can only be unmapped as a whole and cannot be shrunk, in which case this
+            /// fails with an error of the kind [`std::io::ErrorKind::Unsupported`].
+            ///
+            /// Returns [`Error::OutOfBounds`] if the size is zero or larger than the size of the
+            /// mapping.
+            pub fn truncate(&mut self, size: usize) -> Result<(), Error> {
+                if size == 0 || size > self.size() {
+                    return Err(Error::OutOfBounds(0..size));
+                }
+
+                self.inner.truncate(size)
+            }
+
+            /// Resizes the memory mapping to the given size, e.g. such that append-only logs can
+            /// grow their mapping along with the log. Shrinking the mapping releases the pages
+            /// past the new size like [`Self::truncate()`]. Growing the mapping first extends the
+            /// file of a file mapping with zeros if the file is too small to back the new size,
+            /// which requires the mapping to be writable, and then grows the mapping in place if
+            /// the address range after the mapping is free, or moves the mapping elsewhere
+            /// otherwise, like `realloc()`. The contents, the protection and whether the pages are
+            /// locked are preserved, while the pages that are added to anonymous mappings are
+            /// zero-initialized.
+            ///
+            /// On Linux, this uses `mremap()`, except for anonymous memory that is not
+            /// copy-on-write, as it is backed by an object of a fixed size. Otherwise, and on other
+            /// Unix platforms, the pages that are added are mapped right after the mapping if
+            /// possible, and the mapping is moved like [`Self::remap_to()`] does otherwise. As
+            /// moving anonymous memory that is not copy-on-write would stop sharing it with the
+            /// child processes that have been forked, growing it fails with an error of the kind
+            /// [`std::io::ErrorKind::Unsupported`] if it has to be moved. On Microsoft Windows,
+            /// anonymous memory that has been truncated before is committed again in place, and is
+            /// copied to new memory otherwise, while views of file mappings are mapped elsewhere
+            /// from a new file mapping object, which is not supported for copy-on-write views as
+            /// their private pages would be lost. With the `fake` feature, the contents are copied
+            /// to a larger allocation. Views of a [`Reservation`] cannot grow, in which case this
+            /// fails with an error of the kind [`std::io::ErrorKind::Unsupported`].
+            ///
+            /// Returns [`Error::OutOfBounds`] if the size is zero.
+            pub fn resize(&mut self, size: usize) -> Result<(), Error> {
+                if size == 0 {
+                    return Err(Error::OutOfBounds(0..size));
+                }
+
+                if size <= self.size() {
+                    return self.inner.truncate(size);
+                }
+
+                if let Some(file) = self.inner.file() {
+                    let end = self.inner.file_offset() + size as u64;
+
+                    if file.metadata()?.len() < end {
+                        // The file must not change through a mapping that cannot write to it.
+                        if !self.protection().contains(Protection::WRITE) {
+                            return Err(Error::Io(std::io::Error::new(
+                                std::io::ErrorKind::PermissionDenied,
+                                "the file cannot be extended through a mapping that is not writable",
+                            )));
+                        }
+
+                        file.set_len(end)?;
+                    }
+                }
+
+                self.inner.resize(size)
+            }
+
+            /// Splits the mapping at the given offset into two mappings that can be dropped
+            /// independently, where this mapping keeps the pages before the offset and the
+            /// returned mapping owns the pages from the offset onward, e.g. such that the code and
+            /// the data of a JIT engine can be allocated together and protected separately
+            /// afterwards. The pages are not moved, and both mappings keep the protection and the
+            /// flags of the original mapping. The file of a file mapping is duplicated for the
+            /// returned mapping, while an advisory lock on the file stays with this mapping.
+            ///
+            /// With the `fake` feature, the pages from the offset onward are copied to a new
+            /// allocation instead. Mappings cannot be split on Microsoft Windows, as allocations
+            /// and views can only be released as a whole, nor can views of a [`Reservation`] be
+            /// split, in which case this fails with an error of the kind
+            /// [`std::io::ErrorKind::Unsupported`].
+            ///
+            /// Returns [`Error::OutOfBounds`] if the offset is zero or not less than the size of
+            /// the mapping, or [`Error::Unaligned`] if the offset is not aligned to the page size.
+            /// For huge pages, the offset must be aligned to the size of the huge pages.
+            pub fn split_off(&mut self, at: usize) -> Result<Self, Error> {
+                if at == 0 || at >= self.size() {
+                    return Err(Error::OutOfBounds(at..self.size()));
+                }
+
+                let page_size = page_size();
+
+                if !(self.as_ptr() as usize + at).is_multiple_of(page_size) {
+                    return Err(Error::Unaligned(at, page_size));
+                }
+
+                Ok(Self {
+                    inner: self.inner.split_off(at)?,
+                })
+            }
+
+            /// Splits the mapping at the given offset into two mappings that can be dropped
+            /// independently, where the returned mapping owns the pages before the offset and this
+            /// mapping keeps the pages from the offset onward. See [`Self::split_off()`].
+            pub fn split_to(&mut self, at: usize) -> Result<Self, Error> {
+                let tail = self.split_off(at)?;
+
+                Ok(std::mem::replace(self, tail))
+            }
+
+            /// Moves the memory mapping to the given address, e.g. to relocate memory in order to
+            /// satisfy layout constraints that are only discovered after mapping the memory. The
+            /// contents, the protection and whether the pages are locked are preserved.
+            ///
+            /// On Linux, this moves the pages using `mremap()`. On other platforms, this maps the
+            /// file again at the given address for shared file mappings, or copies the contents to
+            /// new memory at the given address otherwise, and then unmaps the old mapping. This
+            /// means that the pages of private file mappings are no longer backed by the file.
+            /// Anonymous memory that is not copy-on-write cannot be moved on other Unix platforms,
+            /// as it would no longer be shared with the child processes that have been forked
+            /// before, in which case this fails with an error of the kind
+            /// [`std::io::ErrorKind::Unsupported`].
+            ///
+            /// The pages of the mapping are moved to the address, such that a mapping that starts
+            /// within its first page, as its offset within the file has been rounded down, still
+            /// does so afterwards.
+            ///
+            /// Returns [`Error::Unaligned`] if the address is not aligned to the allocation
+            /// granularity.
+            ///
+            /// # Safety
+            ///
+            /// Any pages that have been mapped at the new address range are replaced, similar to
+            /// [`UnsafeMmapFlags::MAP_FIXED`], so the caller must ensure that the address range is
+            /// not in use. On Microsoft Windows, the address range must be free instead. Outside of
+            /// Linux, the address range must not overlap with the mapping itself.
+            pub unsafe fn remap_to(&mut self, address: usize) -> Result<(), Error> {
+                let granularity = allocation_granularity();
+
+                if !address.is_multiple_of(granularity) {
+                    return Err(Error::Unaligned(address, granularity));
+                }
+
+                self.inner.remap_to(address)
+            }
+
+            /// Acquires an advisory byte-range lock on the part of the backing file that the given
+            /// range of the mapping covers, such that processes editing different regions of a
+            /// shared file mapping can coordinate. The lock is shared unless `exclusive` is set,
+            /// and is released when the returned [`RangeLock`] is dropped. The range does not have
+            /// to be aligned to the page size.
+            ///
+            /// On Linux, this uses open file description locks through `fcntl()`, which are owned
+            /// by the file handle of the mapping, such that locks acquired through different
+            /// mappings of the same file conflict even within the same process, whereas locks
+            /// acquired through the same mapping do not. On other Unix platforms, this uses POSIX
+            /// record locks, which are owned by the process and therefore never conflict within
+            /// the same process, and which are all released as soon as any handle to the file is
+            /// closed by the process. On Microsoft Windows, this uses `LockFileEx()`, which also
+            /// prevents other handles from reading or writing the range through `ReadFile()` and
+            /// `WriteFile()`, but does not affect the mapping itself. These locks are independent
+            /// of the lock acquired through [`MmapOptions::with_file_lock()`].
+            ///
+            /// Returns [`Error::OutOfBounds`] if the range is empty or out of bounds, or an error
+            /// of the kind [`std::io::ErrorKind::InvalidInput`] if the mapping is not backed by a
+            /// file. With [`FileLock::Try`], this fails if a conflicting lock is held instead of
+            /// waiting for it to be released.
+            pub fn lock_range(
+                &self,
+                range: Range<usize>,
+                exclusive: bool,
+                lock: FileLock,
+            ) -> Result<RangeLock<'_>, Error> {
+                check_range(&range, self.size())?;
+
+                if range.is_empty() {
+                    return Err(Error::OutOfBounds(range));
+                }
+
+                let file = self.inner.file().ok_or_else(|| Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "anonymous mappings are not backed by a file that can be locked",
+                )))?;
+
+                RangeLock::acquire(
+                    file,
+                    self.inner.file_offset(),
+                    range,
+                    exclusive,
+                    lock == FileLock::Wait,
+                )
+            }
+
+            /// Spawns the command as a child process that shares this mapping, such that the child
+            /// can map the same memory using [`MmapOptions::from_parent()`]. The child inherits the
+            /// file backing the mapping, which is described to the child through the environment
+            /// variables [`SHARED_HANDLE_VAR`], [`SHARED_OFFSET_VAR`] and [`SHARED_SIZE_VAR`]. If
+            /// `same_address` is set, the child has to map the memory at the same address as this
+            /// mapping, which is passed through [`SHARED_ADDRESS_VAR`].
+            ///
+            /// On Unix, the file descriptor is only made inheritable in the child. On Microsoft
+            /// Windows, an inheritable duplicate of the handle exists while the child is being
+            /// spawned, which other processes spawned at the same time inherit as well. Changes
+            /// to copy-on-write mappings are not shared.
+            ///
+            /// Returns an error of the kind [`std::io::ErrorKind::InvalidInput`] if the mapping
+            /// is not backed by a file, e.g. as it has been mapped from shared memory.
+            ///
+            /// [`SHARED_HANDLE_VAR`]: crate::SHARED_HANDLE_VAR
+            /// [`SHARED_OFFSET_VAR`]: crate::SHARED_OFFSET_VAR
+            /// [`SHARED_SIZE_VAR`]: crate::SHARED_SIZE_VAR
+            /// [`SHARED_ADDRESS_VAR`]: crate::SHARED_ADDRESS_VAR
+            pub fn spawn_with(
+                &self,
+                command: &mut std::process::Command,
+                same_address: bool,
+            ) -> Result<std::process::Child, Error> {
+                crate::child::spawn(
+                    command,
+                    self.inner.file(),
+                    self.inner.file_offset(),
+                    self.size(),
+                    same_address.then(|| self.as_ptr() as usize),
+                )
+            }
+
+            /// Marks the pages of the mapping as not accessed, which starts an interval at the end
+            /// of which [`Self::accessed_ranges()`] reports the pages that have been accessed in
+            /// the meantime. Sampling the working set this way lets caches tell the pages that are
+            /// in active use apart from pages that were merely touched once, e.g. to implement
+            /// scan-resistant eviction.
+            ///
+            /// On Linux, this uses idle page tracking through `/sys/kernel/mm/page_idle/bitmap`,
+            /// which requires `CAP_SYS_ADMIN` and a kernel built with `CONFIG_IDLE_PAGE_TRACKING`.
+            /// The page frame numbers are looked up through `/proc/self/pagemap`. On Microsoft
+            /// Windows, the pages are removed from the working set, such that the pages that are
+            /// accessed afterwards are faulted back in cheaply from the standby or modified list,
+            /// which is not supported for locked mappings. This fails with an error of the kind
+            /// [`std::io::ErrorKind::Unsupported`] on other platforms and with the `fake`
+            /// feature.
+            pub fn clear_accessed(&self) -> Result<(), Error> {
+                self.inner.clear_accessed()
+            }
+
+            /// Returns the ranges of the pages that have been accessed since
+            /// [`Self::clear_accessed()`] was called, where adjacent pages are merged into a
+            /// single range. Pages that have been evicted from memory in the meantime are not
+            /// reported, so this is an estimate. As the accessed bits may be cleared without
+            /// flushing the TLB, accesses through cached translations may also be missed.
+            pub fn accessed_ranges(&self) -> Result<Vec<Range<usize>>, Error> {
+                let page_size = page_size();
+                let size = self.size();
+                let mut ranges: Vec<Range<usize>> = vec![];
+
+                // The mapping may start past the start of its first page.
+                let skew = page_offset(self.as_ptr() as usize);
+
+                for (page, accessed) in self.inner.accessed_pages()?.into_iter().enumerate() {
+                    if !accessed {
+                        continue;
+                    }
+
+                    let start = (page * page_size).saturating_sub(skew);
+                    let end = ((page + 1) * page_size - skew).min(size);
+
+                    match ranges.last_mut() {
+                        Some(last) if last.end == start => last.end = end,
+                        _ => ranges.push(start..end),
+                    }
+                }
+
+                Ok(ranges)
+            }
+
+            /// Evicts the pages in the given range from memory without discarding their contents,
+            /// such that long-running processes can shed memory that is no longer accessed
+            /// frequently, e.g. when the system signals memory pressure. Accessing the pages
+            /// afterwards faults them back in. The range is extended to the bounds of the pages
+            /// that it covers. This is merely a hint and locked pages are not evicted.
+            ///
+            /// On Linux, this uses `madvise()` with `MADV_PAGEOUT` or `MADV_COLD`, which requires
+            /// Linux 5.4 or newer. On other Unix platforms, this uses `madvise()` with
+            /// `MADV_DONTNEED`, which does not discard the contents of the pages on those
+            /// platforms. On Microsoft Windows, the pages are removed from the working set of the
+            /// process, which moves them to the standby or modified list for either mode.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the range is out of bounds.
+            pub fn evict(&self, range: Range<usize>, eviction: Eviction) -> Result<(), Error> {
+                check_range(&range, self.size())?;
+
+                if range.is_empty() {
+                    return Ok(());
+                }
+
+                self.inner.evict(range_to_page_bounds(range), eviction)
+            }
+
+            /// Advises the operating system how the pages in the given range will be accessed,
+            /// or that their contents are no longer needed, such that databases and caches can
+            /// control the paging behavior. The range is extended to the bounds of the pages that
+            /// it covers, except for [`Advice::DontNeed`] and [`Advice::Free`], where the range
+            /// must be aligned to the page size instead, as the contents of the pages may be
+            /// discarded. Discarded private and anonymous pages read as either their previous
+            /// contents or zeros afterwards, while file pages read as the contents of the file.
+            ///
+            /// On Unix, this uses `madvise()`, where [`Advice::Free`] uses `MADV_FREE` for the
+            /// pages it applies to, and `MADV_DONTNEED` otherwise. On Microsoft Windows,
+            /// [`Advice::WillNeed`] uses `PrefetchVirtualMemory()`, [`Advice::DontNeed`] and
+            /// [`Advice::Free`] use `DiscardVirtualMemory()` for anonymous memory and remove the
+            /// pages of file mappings from the working set, and [`Advice::Sequential`] and
+            /// [`Advice::Random`] have no effect. With the `fake` feature, discarding the pages of
+            /// anonymous memory zeroes them.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the range is out of bounds, or
+            /// [`Error::Unaligned`] if the range is not aligned to the page size where required.
+            pub fn advise(&mut self, range: Range<usize>, advice: Advice) -> Result<(), Error> {
+                check_range(&range, self.size())?;
+
+                if range.is_empty() {
+                    return Ok(());
+                }
+
+                let range = match advice {
+                    Advice::DontNeed | Advice::Free => {
+                        let page_size = page_size();
+                        let address = self.as_ptr() as usize;
+
+                        if !(address + range.start).is_multiple_of(page_size) {
+                            return Err(Error::Unaligned(range.start, page_size));
+                        }
+
+                        if !(address + range.end).is_multiple_of(page_size) &&
+                            range.end != self.size() {
+                            return Err(Error::Unaligned(range.end, page_size));
+                        }
+
+                        range
+                    }
+                    _ => range_to_page_bounds(range),
+                };
+
+                self.inner.advise(range, advice)
+            }
+
+            /// Changes the protection of multiple disjoint ranges of the mapping in one call, e.g.
+            /// to protect the segments of an executable that has been loaded into a reservation.
+            /// All the ranges are validated before any protection is changed, and adjacent ranges
+            /// with the same protection are merged to minimize the number of system calls. The
+            /// ranges must start and end at a multiple of the page size, except that a range may
+            /// end at the end of the mapping.
+            ///
+            /// As the protection is only tracked for the mapping as a whole, this does not affect
+            /// [`Self::protection()`]. Use [`Self::protection_of()`] to query the protection of
+            /// the ranges instead.
+            ///
+            /// Returns [`Error::OutOfBounds`] if a range is out of bounds or overlaps with another
+            /// range, [`Error::Unaligned`] if a range is not aligned to the page size, or
+            /// [`Error::UnsafeFlagNeeded`] if a range is both writable and executable without
+            /// [`UnsafeMmapFlags::JIT`] having been set.
+            ///
+            /// # Safety
+            ///
+            /// The type of the mapping no longer reflects the protection of the pages in the
+            /// ranges, so the caller must ensure that the pages are not accessed in any way that
+            /// their new protection does not permit, e.g. by dereferencing the mapping after
+            /// making some of the pages inaccessible.
+            pub unsafe fn protect_ranges(
+                &mut self,
+                ranges: &[(Range<usize>, Protection)],
+            ) -> Result<(), Error> {
+                let page_size = page_size();
+                let address = self.as_ptr() as usize;
+                let mut ranges = ranges.to_vec();
+                ranges.sort_by_key(|(range, _)| range.start);
+
+                let mut merged: Vec<(Range<usize>, Protection)> = Vec::with_capacity(ranges.len());
+
+                for (range, protection) in ranges {
+                    check_range(&range, self.size())?;
+
+                    if range.is_empty() {
+                        continue;
+                    }
+
+                    if !(address + range.start).is_multiple_of(page_size) {
+                        return Err(Error::Unaligned(range.start, page_size));
+                    }
+
+                    if !(address + range.end).is_multiple_of(page_size) &&
+                        range.end != self.size() {
+                        return Err(Error::Unaligned(range.end, page_size));
+                    }
+
+                    check_protection(&self.inner, protection)?;
+
+                    match merged.last_mut() {
+                        Some((last, _)) if last.end > range.start => {
+                            return Err(Error::OutOfBounds(range));
+                        }
+                        Some((last, last_protection))
+                            if last.end == range.start && *last_protection == protection => {
+                            last.end = range.end;
+                        }
+                        _ => merged.push((range, protection)),
+                    }
+                }
+
+                self.inner.protect_ranges(&merged)
+            }
+
+            /// Changes the protection of the pages in the given range to inaccessible, e.g. to
+            /// place a guard page within the mapping. See [`Self::protect_ranges()`] for the
+            /// alignment of the range and the errors.
+            ///
+            /// # Safety
+            ///
+            /// See [`Self::protect_ranges()`].
+            pub unsafe fn make_none_range(&mut self, range: Range<usize>) -> Result<(), Error> {
+                self.protect_ranges(&[(range, Protection::empty())])
+            }
+
+            /// Changes the protection of the pages in the given range to read-only, e.g. for the
+            /// read-only data segment of a loaded image. See [`Self::protect_ranges()`] for the
+            /// alignment of the range and the errors.
+            ///
+            /// # Safety
+            ///
+            /// See [`Self::protect_ranges()`].
+            pub unsafe fn make_read_only_range(&mut self, range: Range<usize>) -> Result<(), Error> {
+                self.protect_ranges(&[(range, Protection::READ)])
+            }
+
+            /// Changes the protection of the pages in the given range to readable and executable,
+            /// e.g. for the text segment of a loaded image, and flushes the instruction cache for
+            /// the range like [`Self::make_exec()`]. See [`Self::protect_ranges()`] for the
+            /// alignment of the range and the errors.
+            ///
+            /// # Safety
+            ///
+            /// See [`Self::protect_ranges()`].
+            #[cfg(feature = "exec")]
+            pub unsafe fn make_exec_range(&mut self, range: Range<usize>) -> Result<(), Error> {
+                self.protect_ranges(&[(range.clone(), Protection::READ | Protection::EXECUTE)])?;
+
+                self.inner.flush_icache_range(range)
+            }
+
+            /// Changes the protection of the pages in the given range to readable and writable,
+            /// e.g. for the data segment of a loaded image. See [`Self::protect_ranges()`] for the
+            /// alignment of the range and the errors.
+            ///
+            /// # Safety
+            ///
+            /// See [`Self::protect_ranges()`].
+            pub unsafe fn make_mut_range(&mut self, range: Range<usize>) -> Result<(), Error> {
+                self.protect_ranges(&[(range, Protection::READ | Protection::WRITE)])
+            }
+
+            /// Borrows the given range of the mapping as a view, through which the protection,
+            /// flushing, locking and advice can be scoped to the range, e.g. for every segment of
+            /// an image that has been loaded into the mapping. See [`MmapView`].
+            ///
+            /// Returns [`Error::OutOfBounds`] if the range is empty or out of bounds, or
+            /// [`Error::Unaligned`] if the range does not start and end at a multiple of the page
+            /// size, except that it may end at the end of the mapping.
+            pub fn as_view(&mut self, range: Range<usize>) -> Result<MmapView<'_>, Error> {
+                MmapView::new(&mut self.inner, range)
+            }
+
+            /// Temporarily changes the protection of the whole mapping and returns a guard that
+            /// restores the previous protection when it is popped or dropped, such that temporary
+            /// transitions, e.g. making the mapping writable to patch it, do not require the
+            /// caller to keep track of the original protection. The mapping can only be accessed
+            /// through the guard until then. See [`ProtectionGuard`].
+            ///
+            /// Returns [`Error::UnsafeFlagNeeded`] if the protection is both writable and
+            /// executable without [`UnsafeMmapFlags::JIT`] having been set.
+            pub fn push_protection(
+                &mut self,
+                protection: Protection,
+            ) -> Result<ProtectionGuard<'_>, Error> {
+                ProtectionGuard::push(&mut self.inner, protection)
+            }
+
+            /// Unmaps the memory mapping, which is what dropping the mapping does, except that
+            /// this reports the failure to unmap the memory rather than ignoring it. The
+            /// [`DropBehavior`] and [`MmapFlags::ZERO_ON_DROP`] are honored as they would be when
+            /// dropping the mapping.
+            ///
+            /// In case of failure, this returns the ownership of `self`.
+            pub fn unmap(mut self) -> Result<(), (Self, Error)> {
+                if let Err(e) = self.inner.unmap() {
+                    return Err((self, e));
+                }
+
+                Ok(())
+            }
+
+            /// Flushes the memory mapping synchronously if it is backed by a file and then unmaps
+            /// it, such that applications that care about durability can observe the failure of
+            /// either step. See [`Self::unmap()`].
+            ///
+            /// In case of failure, this returns the ownership of `self`.
+            pub fn flush_and_unmap(self) -> Result<(), (Self, Error)> {
+                if self.file().is_some() {
+                    if let Err(e) = self.flush(0..self.size()) {
+                        return Err((self, e));
+                    }
+                }
+
+                self.unmap()
+            }
+
             /// Remaps this memory mapping as inaccessible.
             ///
             /// In case of failure, this returns the ownership of `self`.
@@ -191,6 +1335,7 @@ macro_rules! mmap_impl {
             /// Remaps this memory mapping as executable.
             ///
             /// In case of failure, this returns the ownership of `self`.
+            #[cfg(feature = "exec")]
             pub fn make_exec(self) -> Result<Mmap, (Self, Error)> {
                 if let Err(e) = self.inner.make_exec() {
                     return Err((self, e));
@@ -208,13 +1353,16 @@ macro_rules! mmap_impl {
             /// Remaps this memory mapping as executable, but does not flush the instruction cache.
             /// Note that this is **unsafe**.
             ///
+            /// In case of failure, this returns the ownership of `self`.
+            ///
+            /// # Safety
+            ///
             /// While the x86 and x86-64 architectures guarantee cache coherency between the L1 instruction
             /// and the L1 data cache, other architectures such as Arm and AArch64 do not. If the user
             /// modified the pages, then executing the code may result in undefined behavior. To ensure
             /// correct behavior a user has to flush the instruction cache after modifying and before
             /// executing the page.
-            ///
-            /// In case of failure, this returns the ownership of `self`.
+            #[cfg(feature = "exec")]
             pub unsafe fn make_exec_no_flush(self) -> Result<Mmap, (Self, Error)> {
                 if let Err(e) = self.inner.make_exec() {
                     return Err((self, e));
@@ -248,6 +1396,12 @@ macro_rules! mmap_impl {
             /// As it may be tempting to use this function, this function has been marked as **unsafe**.
             /// Make sure to read the text below to understand the complications of this function before
             /// using it. The [`UnsafeMmapFlags::JIT`] flag must be set for this function to succeed.
+            /// This function always fails with [`Error::WxForbidden`] when the `strict-wx` feature is
+            /// enabled.
+            ///
+            /// In case of failure, this returns the ownership of `self`.
+            ///
+            /// # Safety
             ///
             /// RWX pages are an interesting targets to attackers, e.g. for buffer overflow attacks, as RWX
             /// mappings can potentially simplify such attacks. Without RWX mappings, attackers instead
@@ -261,10 +1415,9 @@ macro_rules! mmap_impl {
             /// modified the pages, then executing the code may result in undefined behavior. To ensure
             /// correct behavior a user has to flush the instruction cache after modifying and before
             /// executing the page.
-            ///
-            /// In case of failure, this returns the ownership of `self`.
+            #[cfg(feature = "jit")]
             pub unsafe fn make_exec_mut(self) -> Result<MmapMut, (Self, Error)> {
-                if let Err(e) = self.inner.make_exec_mut() {
+                if let Err(e) = check_wx().and_then(|_| self.inner.make_exec_mut()) {
                     return Err((self, e));
                 }
 
@@ -298,12 +1451,72 @@ impl Mmap {
     pub fn as_slice(&self) -> &[u8] {
         &self[..]
     }
+
+    /// Copies the bytes in the given range of the mapping into a newly allocated [`Vec`].
+    pub fn to_vec(&self, range: Range<usize>) -> Result<Vec<u8>, Error> {
+        check_range(&range, self.size())?;
+
+        Ok(self[range].to_vec())
+    }
+
+    /// Consumes and leaks the mapping, returning a slice of the contents that lives for the rest
+    /// of the program, similar to [`Box::leak()`]. This is useful for data that is needed for the
+    /// lifetime of the process, such as configuration snapshots and interned tables.
+    ///
+    /// The pages are never unmapped and the file backing the mapping, if any, is never closed.
+    pub fn leak(self) -> &'static [u8] {
+        let mapping = std::mem::ManuallyDrop::new(self);
+
+        unsafe {
+            std::slice::from_raw_parts(mapping.as_ptr(), mapping.size())
+        }
+    }
+
+    /// Copies the contents into a new anonymous mapping of the same size, e.g. to detach from the
+    /// file or from memory shared with other processes while preserving the page-granular
+    /// layout. [`MmapFlags::LOCKED`] and [`MmapFlags::ZERO_ON_DROP`] carry over to the new
+    /// mapping, which can optionally be backed by pages of the given size, in which case the size
+    /// of the mapping must be a multiple of that page size.
+    pub fn to_owned_anon(&self, page_size: Option<PageSize>) -> Result<MmapMut, Error> {
+        copy_to_anon(&self.inner, self, page_size)
+    }
+
+    /// Faults in all the pages of the mapping from the given number of threads, where zero uses
+    /// the available parallelism, such that accessing the pages afterwards causes no page faults.
+    /// This is much faster than [`MmapFlags::POPULATE`] or faulting in the pages from a single
+    /// thread for mappings spanning many gigabytes, as the page faults are handled in parallel.
+    /// The mapping is split into page-aligned chunks of equal size, one per thread.
+    ///
+    /// On Linux 5.14 or newer, this uses `madvise()` with `MADV_POPULATE_READ`, which also reads
+    /// the pages of file mappings from the file. Otherwise, a byte of every page is read.
+    pub fn populate_parallel(&self, threads: usize) -> Result<(), Error> {
+        populate_parallel(&self.inner, threads, false)
+    }
+
+    /// Advises the operating system whether this mapping will be accessed sequentially.
+    pub(crate) fn set_sequential(&self, sequential: bool) -> Result<(), Error> {
+        self.inner.set_sequential(sequential)
+    }
+
+    /// Advises the operating system to read the pages in the given range ahead.
+    pub(crate) fn read_ahead(&self, range: Range<usize>) -> Result<(), Error> {
+        self.inner.read_ahead(range)
+    }
+
+    /// Drops the pages in the given range from the resident memory, if this can be done without
+    /// losing their contents.
+    pub(crate) fn drop_behind(&self, range: Range<usize>) -> Result<(), Error> {
+        self.inner.drop_behind(range)
+    }
 }
 
 impl Deref for Mmap {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
+        #[cfg(feature = "fake")]
+        self.inner.check_access(Protection::READ);
+
         unsafe {
             std::slice::from_raw_parts(self.as_ptr(), self.size())
         }
@@ -312,6 +1525,9 @@ impl Deref for Mmap {
 
 impl AsRef<[u8]> for Mmap {
     fn as_ref(&self) -> &[u8] {
+        #[cfg(feature = "fake")]
+        self.inner.check_access(Protection::READ);
+
         unsafe {
             std::slice::from_raw_parts(self.as_ptr(), self.size())
         }
@@ -342,17 +1558,127 @@ impl MmapMut {
         &mut self[..]
     }
 
+    /// Copies the bytes in the given range of the mapping into a newly allocated [`Vec`].
+    pub fn to_vec(&self, range: Range<usize>) -> Result<Vec<u8>, Error> {
+        check_range(&range, self.size())?;
+
+        Ok(self[range].to_vec())
+    }
+
     /// Yields a raw mutable pointer to this mapping.
     #[inline]
     pub fn as_mut_ptr(&mut self) -> *mut u8 {
         self.inner.as_mut_ptr()
     }
+
+    /// Freezes the mapping once its contents have been built, by making it read-only and handing
+    /// it out as an [`Arc`], such that it can be cloned cheaply and shared between threads, e.g.
+    /// for data structures that are built once and read many times.
+    ///
+    /// If `seal` is set, the mapping is also sealed against changes to its protection and
+    /// against being unmapped, such that the contents cannot be changed through this process
+    /// even by code that has been compromised. This uses `mseal()` on Linux 6.10 or newer and
+    /// `mimmutable()` on OpenBSD. The file backing a shared mapping is sealed against writes as
+    /// well, if it has been created using `memfd_create()` with `MFD_ALLOW_SEALING`. As sealed
+    /// pages cannot be unmapped, the address space of a sealed mapping is never released, even
+    /// once the last reference has been dropped. Sealing fails with an error of the kind
+    /// [`std::io::ErrorKind::Unsupported`] on other platforms, with the `fake` feature and for
+    /// views of a [`Reservation`]. Sealing also fails if the backing file has been sealed
+    /// against further seals, before anything has been sealed.
+    ///
+    /// In case of failure, this returns the ownership of `self`, which is writable again, unless
+    /// the protection cannot be restored after sealing failed, or sealing the backing file failed
+    /// after the pages have been sealed. The mapping is then left read-only, as reported by
+    /// [`MmapMut::protection()`], and writing to it faults.
+    pub fn freeze(self, seal: bool) -> Result<Arc<Mmap>, (Self, Error)> {
+        let mut mapping = self.make_read_only()?;
+
+        if seal {
+            if let Err(e) = mapping.inner.seal() {
+                // Restore the protection the mapping had a moment ago where possible. Otherwise,
+                // the mapping stays read-only, which its tracked protection reflects.
+                let _ = mapping.inner.make_mut();
+
+                return Err((MmapMut { inner: mapping.inner }, e));
+            }
+        }
+
+        Ok(Arc::new(mapping))
+    }
+
+    /// Consumes and leaks the mapping, returning a mutable slice of the contents that lives for
+    /// the rest of the program, similar to [`Box::leak()`]. See [`Mmap::leak()`].
+    pub fn leak(self) -> &'static mut [u8] {
+        let mut mapping = std::mem::ManuallyDrop::new(self);
+
+        unsafe {
+            std::slice::from_raw_parts_mut(mapping.as_mut_ptr(), mapping.size())
+        }
+    }
+
+    /// Copies the contents into a new anonymous mapping of the same size, e.g. to detach from the
+    /// file or from memory shared with other processes while preserving the page-granular
+    /// layout. [`MmapFlags::LOCKED`] and [`MmapFlags::ZERO_ON_DROP`] carry over to the new
+    /// mapping, which can optionally be backed by pages of the given size, in which case the size
+    /// of the mapping must be a multiple of that page size.
+    pub fn to_owned_anon(&self, page_size: Option<PageSize>) -> Result<MmapMut, Error> {
+        copy_to_anon(&self.inner, self, page_size)
+    }
+
+    /// Faults in all the pages of the mapping from the given number of threads, where zero uses
+    /// the available parallelism, such that accessing the pages afterwards causes no page faults.
+    /// This is much faster than [`MmapFlags::POPULATE`] or faulting in the pages from a single
+    /// thread for mappings spanning many gigabytes, as the page faults are handled in parallel.
+    /// The mapping is split into page-aligned chunks of equal size, one per thread.
+    ///
+    /// The pages are faulted in for writing, which allocates private copies of the pages of
+    /// copy-on-write mappings. On Linux 5.14 or newer, this uses `madvise()` with
+    /// `MADV_POPULATE_WRITE`. Otherwise, a byte of every page is read and written back, which
+    /// leaves the contents intact, but requires exclusive access to the mapping.
+    pub fn populate_parallel(&mut self, threads: usize) -> Result<(), Error> {
+        populate_parallel(&self.inner, threads, true)
+    }
+
+    /// Makes the stores to the given range durable by writing back the cache lines covering the
+    /// range, like `pmem_persist()`. For mappings of persistent memory through DAX, e.g. mapped
+    /// with `MAP_SHARED_VALIDATE | MAP_SYNC` using
+    /// [`os::unix::MmapOptionsExt::with_raw_map_flags()`], this is much faster than
+    /// [`MmapMut::flush()`], as no system call is needed.
+    ///
+    /// This uses `CLWB` or `CLFLUSHOPT` followed by `SFENCE` on x86 and x86-64, and `DC CVAP`
+    /// followed by `DSB` on AArch64, falling back to `CLFLUSH` and `DC CVAC` respectively on
+    /// older CPUs. On other architectures and with the `fake` feature, this falls back to
+    /// [`MmapMut::flush()`]. Note that the stores are only durable if the pages are backed by
+    /// persistent memory, rather than by the page cache.
+    ///
+    /// [`os::unix::MmapOptionsExt::with_raw_map_flags()`]: crate::os::unix::MmapOptionsExt::with_raw_map_flags
+    pub fn persist(&self, range: Range<usize>) -> Result<(), Error> {
+        check_range(&range, self.size())?;
+
+        let persisted = !cfg!(feature = "fake") && unsafe {
+            crate::persist::write_back(self.as_ptr().add(range.start), range.end - range.start)
+        };
+
+        if !persisted {
+            return self.flush(range);
+        }
+
+        Ok(())
+    }
+
+    /// Advises the operating system to back this mapping with transparent huge pages.
+    pub(crate) fn advise_huge_pages(&self) -> Result<(), Error> {
+        self.inner.advise_huge_pages()
+    }
 }
 
 impl Deref for MmapMut {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
+        #[cfg(feature = "fake")]
+        self.inner.check_access(Protection::READ);
+
         unsafe {
             std::slice::from_raw_parts(self.as_ptr(), self.size())
         }
@@ -361,6 +1687,9 @@ impl Deref for MmapMut {
 
 impl DerefMut for MmapMut {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        #[cfg(feature = "fake")]
+        self.inner.check_access(Protection::READ | Protection::WRITE);
+
         unsafe {
             std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.size())
         }
@@ -369,6 +1698,9 @@ impl DerefMut for MmapMut {
 
 impl AsRef<[u8]> for MmapMut {
     fn as_ref(&self) -> &[u8] {
+        #[cfg(feature = "fake")]
+        self.inner.check_access(Protection::READ);
+
         unsafe {
             std::slice::from_raw_parts(self.as_ptr(), self.size())
         }
@@ -377,15 +1709,136 @@ impl AsRef<[u8]> for MmapMut {
 
 impl AsMut<[u8]> for MmapMut {
     fn as_mut(&mut self) -> &mut [u8] {
+        #[cfg(feature = "fake")]
+        self.inner.check_access(Protection::READ | Protection::WRITE);
+
         unsafe {
             std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.size())
         }
     }
 }
 
+impl TryFrom<&[u8]> for MmapMut {
+    type Error = Error;
+
+    /// Allocates an anonymous mapping that is the size of the slice, and copies the slice into the
+    /// mapping.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the slice is empty, as empty mappings cannot be mapped.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.is_empty() {
+            return Err(Error::OutOfBounds(0..0));
+        }
+
+        MmapOptions::new(bytes.len())
+            .map_mut_from_slice(bytes)
+    }
+}
+
+impl TryFrom<Vec<u8>> for MmapMut {
+    type Error = Error;
+
+    /// Allocates an anonymous mapping that is the size of the vector, and copies the vector into
+    /// the mapping.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the vector is empty.
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::try_from(bytes.as_slice())
+    }
+}
+
+/// A guard that temporarily changes the protection of a memory mapping, as returned by
+/// `push_protection()`, e.g. [`Mmap::push_protection()`]. The previous protection is restored
+/// when the guard is popped using [`ProtectionGuard::pop_protection()`] or when it is dropped.
+///
+/// The guard exclusively borrows the mapping, such that the mapping can only be accessed through
+/// the guard while its protection differs from what the type of the mapping reflects. If the
+/// previous protection cannot be restored when dropping the guard, the pages keep the protection
+/// of the guard, such that accessing the mapping afterwards may fault. Use
+/// [`ProtectionGuard::pop_protection()`] to handle the failure instead.
+pub struct ProtectionGuard<'a> {
+    inner: &'a mut platform::Mmap,
+    previous: Protection,
+    current: Protection,
+    restored: bool,
+}
+
+impl<'a> ProtectionGuard<'a> {
+    fn push(inner: &'a mut platform::Mmap, protection: Protection) -> Result<Self, Error> {
+        check_protection(inner, protection)?;
+
+        let previous = inner.protection();
+        inner.protect_ranges(&[(0..inner.size(), protection)])?;
+
+        Ok(Self {
+            inner,
+            previous,
+            current: protection,
+            restored: false,
+        })
+    }
+
+    /// Returns the protection that the mapping has while the guard is alive.
+    #[inline]
+    pub fn protection(&self) -> Protection {
+        self.current
+    }
+
+    /// Returns the protection that is restored when the guard is popped.
+    #[inline]
+    pub fn previous_protection(&self) -> Protection {
+        self.previous
+    }
+
+    /// Extracts a slice containing the entire mapping, if the pages are readable.
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        if !self.current.contains(Protection::READ) {
+            return None;
+        }
+
+        Some(unsafe { std::slice::from_raw_parts(self.inner.as_ptr(), self.inner.size()) })
+    }
+
+    /// Extracts a mutable slice containing the entire mapping, if the pages are readable and
+    /// writable.
+    pub fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        if !self.current.contains(Protection::READ | Protection::WRITE) {
+            return None;
+        }
+
+        Some(unsafe { std::slice::from_raw_parts_mut(self.inner.as_mut_ptr(), self.inner.size()) })
+    }
+
+    /// Restores the protection that the mapping had before the guard was created. If the
+    /// previous protection was executable, the instruction cache is flushed as well, as the pages
+    /// may have been patched.
+    pub fn pop_protection(mut self) -> Result<(), Error> {
+        self.restore()
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        self.inner.protect_ranges(&[(0..self.inner.size(), self.previous)])?;
+        self.restored = true;
+
+        if self.previous.contains(Protection::EXECUTE) {
+            self.inner.flush_icache()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ProtectionGuard<'_> {
+    fn drop(&mut self) {
+        if !self.restored {
+            let _ = self.restore();
+        }
+    }
+}
+
 /// Represents the options for the memory mapping.
 pub struct MmapOptions {
-    inner: platform::MmapOptions,
+    pub(crate) inner: platform::MmapOptions,
 }
 
 impl MmapOptions {
@@ -401,29 +1854,85 @@ impl MmapOptions {
     /// granularity. On some platforms the allocation granularity may be a multiple of the page
     /// size. The start address of the allocation must be aligned to the allocation granularity,
     /// while the allocation size must be aligned to the page size for the allocation to succeed.
+    #[deprecated(note = "use `page_size()` and `allocation_granularity()` instead")]
+    #[inline]
     pub fn page_size() -> (usize, usize) {
-        platform::MmapOptions::page_size()
+        (page_size(), allocation_granularity())
     }
 
     /// The desired address at which the memory should be mapped.
+    ///
+    /// On Microsoft Windows, the address is not merely a hint: mapping fails with an error of the
+    /// kind [`std::io::ErrorKind::AddrInUse`] if the address range is unavailable. For file
+    /// mappings, the address must also be aligned to the allocation granularity, or mapping
+    /// fails with [`Error::Unaligned`]. On OpenBSD, which maps the memory at a random address if
+    /// the hint is in use, the hint is moved to the next free spot found using `mquery()`.
     pub fn with_address(self, address: usize) -> Self {
         Self {
             inner: self.inner.with_address(address),
         }
     }
 
+    /// Confines the mapping to the given address range, such that the whole mapping lies within
+    /// the range, e.g. to keep the mappings of a sandbox within an arena of the address space.
+    /// Both bounds must be multiples of the allocation granularity. An address set through
+    /// [`MmapOptions::with_address()`] is tried first if it lies within the range, and is ignored
+    /// otherwise, except on Microsoft Windows where mapping fails instead, as the address is not
+    /// merely a hint there. This is ignored for views of a reservation.
+    ///
+    /// On Microsoft Windows, this uses `MEM_ADDRESS_REQUIREMENTS`, which requires Microsoft
+    /// Windows 10, version 1803 or newer. On Unix, the range is probed for a free spot using
+    /// `MAP_FIXED_NOREPLACE` on Linux, `MAP_FIXED | MAP_EXCL` on FreeBSD, `mquery()` followed by a
+    /// hint on OpenBSD and a hint elsewhere, which may take a while if the range is crowded. With
+    /// the `fake` feature, the range is ignored, just like the address.
+    ///
+    /// Mapping fails with [`Error::Unaligned`] if the bounds are not aligned to the allocation
+    /// granularity, or with an error of the kind [`std::io::ErrorKind::AddrNotAvailable`] if no
+    /// free spot could be found within the range.
+    pub fn with_address_range(self, range: Range<usize>) -> Self {
+        Self {
+            inner: self.inner.with_address_range(range),
+        }
+    }
+
+    /// Maps the memory as a view at the given offset within the reservation, rather than at an
+    /// address picked by the operating system or set through [`MmapOptions::with_address()`].
+    /// The offset must be a multiple of the allocation granularity, and the view must fit in the
+    /// reservation without overlapping any other view. When the mapping is dropped, its pages are
+    /// returned to the reservation. See [`Reservation`].
+    ///
+    /// Mapping fails with [`Error::Unaligned`] if the offset is not aligned to the allocation
+    /// granularity, with [`Error::OutOfBounds`] if the view does not fit in the reservation, or
+    /// with an error of the kind [`std::io::ErrorKind::AddrInUse`] if the view overlaps with
+    /// another view. Views cannot be moved using `remap_to()`.
+    pub fn with_reservation(self, reservation: &Reservation, offset: usize) -> Self {
+        Self {
+            inner: self.inner.with_reservation(reservation.shared(), offset),
+        }
+    }
+
     /// Whether the memory mapping should be backed by a [`File`] or not. If the memory mapping
     /// should be mapped by a [`File`], then the user can also specify the offset within the file
     /// at which the mapping should start.
     ///
+    /// The offset does not have to be aligned to the allocation granularity. The pages are mapped
+    /// from the offset rounded down to the allocation granularity instead, after which the pointer
+    /// and the size of the mapping are adjusted to start at the offset, such that the mapping
+    /// starts within its first page. See [`Mmap::offset()`] and [`Mmap::aligned_offset()`]. The
+    /// functions that operate on whole pages then expect their ranges to start and end at page
+    /// boundaries of the memory, rather than at multiples of the page size within the mapping.
+    /// With the `fake` feature, the contents are read from the offset itself.
+    ///
     /// On Microsoft Windows, it may not be possible to extend the protection beyond the access
     /// mask that has been used to open the file. For instance, if a file has been opened with read
     /// access, then [`Mmap::make_mut()`] will not work. Furthermore, [`std::fs::OpenOptions`] does
     /// not in itself provide a standardized way to open the file with executable access. However,
     /// if the file is not opened with executable access, then it may not be possible to use
-    /// [`Mmap::make_exec()`]. Fortunately, Rust provides [`OpenOptionsExt`] that allows you to
+    /// `Mmap::make_exec()`. Fortunately, Rust provides [`OpenOptionsExt`] that allows you to
     /// open the file with executable access rights. See [`access_mode`] for more information.
     ///
+    /// # Safety
+    ///
     /// This function is marked as **unsafe** as the user should be aware that even in the case
     /// that a file is mapped as immutable in the address space of the current process, it does not
     /// guarantee that there does not exist any other mutable mapping to the file.
@@ -431,9 +1940,10 @@ impl MmapOptions {
     /// On Microsoft Windows, it is possible to limit the access to shared reading or to be fully
     /// exclusive using [`share_mode`].
     ///
-    /// On most Unix systems, it is possible to use [`nix::fcntl::flock`]. However, keep in mind
-    /// that this provides an **advisory** locking scheme, and that implementations are therefore
-    /// required to be co-operative.
+    /// On most Unix systems, it is possible to use [`nix::fcntl::flock`], or to let the mapping
+    /// acquire the lock using [`MmapOptions::with_file_lock()`]. However, keep in mind that this
+    /// provides an **advisory** locking scheme, and that implementations are therefore required to
+    /// be co-operative.
     ///
     /// On Linux, it is also possible to mark the file as immutable. See `man 2 ioctl_iflags` and
     /// `man 1 chattr` for more information.
@@ -448,6 +1958,95 @@ impl MmapOptions {
         }
     }
 
+    /// Maps the memory of a device, such as `/dev/mem`, a UIO device or a region handed out by a
+    /// driver, rather than the contents of a regular file. The offset is interpreted by the driver,
+    /// e.g. UIO devices select the N-th region at an offset of N pages. Devices report a size of
+    /// zero, so the size of the mapping is not validated against the metadata of the file, and the
+    /// size and offset must instead match what the driver provides.
+    ///
+    /// As reading device memory may have side effects, the pages are never populated:
+    /// [`MmapFlags::POPULATE`] fails with [`Error::UnsupportedFlags`] and `populate_parallel()`
+    /// fails with an error of the kind [`std::io::ErrorKind::Unsupported`]. Devices cannot be
+    /// mapped on Microsoft Windows or with the `fake` feature, where mapping fails with an error of
+    /// the kind [`std::io::ErrorKind::Unsupported`].
+    ///
+    /// # Safety
+    ///
+    /// In addition to the requirements of [`MmapOptions::with_file()`], the caller must ensure
+    /// that the device provides memory for the whole mapping, as accessing pages that the driver
+    /// does not provide raises `SIGBUS`. Device memory may change at any time and accesses may
+    /// have side effects, such that the contents should be accessed using volatile reads and
+    /// writes, e.g. [`Mmap::read_volatile_at()`] and [`MmapMut::write_volatile_at()`], rather than
+    /// through slices. Mapping physical memory through `/dev/mem` may also alias memory that is in
+    /// use by the kernel or by other processes.
+    pub unsafe fn with_device(self, file: File, offset: u64) -> Self {
+        Self {
+            inner: self.inner.with_device(file, offset),
+        }
+    }
+
+    /// Acquires an advisory lock on the backing file before mapping the file. A shared lock is
+    /// acquired for mappings that cannot write to the file, i.e. inaccessible, read-only,
+    /// executable and copy-on-write mappings, while an exclusive lock is acquired for writable
+    /// mappings. The lock is held for the lifetime of the mapping and released when the mapping is
+    /// dropped. This option has no effect on anonymous mappings.
+    ///
+    /// The lock is decided by the protection the mapping is created with, and is not upgraded or
+    /// downgraded when the protection changes afterwards. Use [`MmapOptions::map_mut()`] rather
+    /// than [`Mmap::make_mut()`] if the mapping should be protected by an exclusive lock.
+    ///
+    /// On Unix, the lock is acquired using `flock()`, which only co-operates with other processes
+    /// using `flock()` on the same file. On Microsoft Windows, the lock is acquired using
+    /// `LockFileEx()` on the whole file, which also prevents other processes from reading or
+    /// writing the file through `ReadFile()` and `WriteFile()` while an exclusive lock is held.
+    pub fn with_file_lock(self, lock: FileLock) -> Self {
+        Self {
+            inner: self.inner.with_file_lock(lock),
+        }
+    }
+
+    /// Sets what happens to the pages of the mapping when the mapping is dropped, such that the
+    /// latency of dropping the mapping can be traded against durability explicitly. This defaults
+    /// to [`DropBehavior::Unmap`]. Errors are ignored, as they cannot be reported when the mapping
+    /// is dropped.
+    ///
+    /// On Unix, [`DropBehavior::Flush`] uses `msync()` with `MS_SYNC` and [`DropBehavior::Discard`]
+    /// uses `madvise()` with `MADV_DONTNEED`. On Microsoft Windows, [`DropBehavior::Flush`] uses
+    /// `FlushViewOfFile()` followed by `FlushFileBuffers()` and [`DropBehavior::Discard`] uses
+    /// `DiscardVirtualMemory()` for anonymous mappings.
+    pub fn with_drop_behavior(self, behavior: DropBehavior) -> Self {
+        Self {
+            inner: self.inner.with_drop_behavior(behavior),
+        }
+    }
+
+    /// Checks whether enough huge pages are available to map the mapping with the configured
+    /// flags and page size, such that the caller can fail fast with an actionable error instead
+    /// of a generic out-of-memory error when mapping. This does nothing if the mapping does not
+    /// use huge pages.
+    ///
+    /// On Linux, this checks the number of free huge pages of the page size in
+    /// `/sys/kernel/mm/hugepages`, where the default huge page size is used if no page size has
+    /// been configured. On Microsoft Windows, this checks whether large pages, or 1 GiB pages if
+    /// [`PageSize::_1G`] has been configured, are supported, whether the size is a multiple of
+    /// the page size and whether the `SeLockMemoryPrivilege` privilege can be enabled, which
+    /// enables it as a side effect. On other platforms, huge pages are used transparently, so there
+    /// is nothing to check.
+    ///
+    /// Returns [`Error::HugePagesUnavailable`] or [`Error::Unaligned`] if the mapping is bound to
+    /// fail. As other processes may allocate huge pages in the meantime, mapping may still fail.
+    pub fn check_huge_pages(&self) -> Result<(), Error> {
+        self.inner.check_huge_pages()
+    }
+
+    /// Configures the flags of the mapping for a common workload. See [`Preset`] for the flags
+    /// that each preset sets. As this replaces the flags, like [`MmapOptions::with_flags()`], use
+    /// [`Preset::flags()`] to combine a preset with other flags, e.g.
+    /// `with_flags(Preset::VmGuestMemory.flags() | MmapFlags::POPULATE)`.
+    pub fn preset(self, preset: Preset) -> Self {
+        self.with_flags(preset.flags())
+    }
+
     /// The desired configuration of the mapping. See [`MmapFlags`] for available options.
     pub fn with_flags(self, flags: MmapFlags) -> Self {
         Self {
@@ -457,6 +2056,8 @@ impl MmapOptions {
 
     /// The desired configuration of the mapping. See [`UnsafeMmapFlags`] for available options.
     ///
+    /// # Safety
+    ///
     /// Note this function is **unsafe** as the flags that can be passed to this function have
     /// unsafe behavior associated with them.
     pub unsafe fn with_unsafe_flags(self, flags: UnsafeMmapFlags) -> Self {
@@ -466,12 +2067,31 @@ impl MmapOptions {
     }
 
     /// Whether this memory mapped should be backed by a specific page size or not.
+    ///
+    /// On Microsoft Windows, only [`PageSize::_1G`] is supported, which backs anonymous mappings
+    /// with 1 GiB pages through `VirtualAlloc2()`. This requires Microsoft Windows 10, version 1803
+    /// or newer, a processor that supports 1 GiB pages, the `SeLockMemoryPrivilege` privilege and
+    /// a size that is a multiple of 1 GiB, see [`MmapOptions::check_huge_pages()`]. Mapping fails
+    /// with [`Error::HugePagesUnavailable`] if the pages cannot be allocated, e.g. as physical
+    /// memory is too fragmented, in which case [`MmapFlags::HUGE_PAGES`] may be used instead.
+    /// Other page sizes are ignored on Microsoft Windows.
     pub fn with_page_size(self, page_size: PageSize) -> Self {
         Self {
             inner: self.inner.with_page_size(page_size),
         }
     }
 
+    /// Reserves the address space without committing any memory, such that the pages can be
+    /// committed on demand using [`ReservedMmap::commit()`], e.g. to let an arena grow in place.
+    /// Only the size and the address hint apply, as the pages are always anonymous, and committed
+    /// pages are readable and writable.
+    ///
+    /// Returns an error of the kind [`std::io::ErrorKind::InvalidInput`] if a file or a
+    /// [`Reservation`] has been set.
+    pub fn reserve(self) -> Result<ReservedMmap, Error> {
+        Ok(ReservedMmap::new(self.inner.reserve()?))
+    }
+
     /// Maps the memory as inaccessible.
     pub fn map_none(self) -> Result<MmapNone, Error> {
         Ok(MmapNone {
@@ -486,7 +2106,34 @@ impl MmapOptions {
         })
     }
 
+    /// Maps the file one chunk at a time, rather than mapping the whole size at once, and returns
+    /// an iterator over consecutive read-only mappings of the file, starting at the offset given to
+    /// [`MmapOptions::with_file()`]. Every chunk is `chunk_size` bytes, except for the last chunk,
+    /// which covers the remainder of the size. The flags of these options apply to every chunk.
+    ///
+    /// This allows streaming processors to handle files of any size using a bounded amount of
+    /// address space, as long as every chunk is dropped before the next one is mapped, which is
+    /// the case when iterating using a `for` loop. The iterator stops after the first chunk that
+    /// fails to map.
+    ///
+    /// Returns [`Error::Unaligned`] if the chunk size is not a non-zero multiple of the
+    /// allocation granularity, as the offset of every chunk must be aligned to it, and an error
+    /// of the kind [`std::io::ErrorKind::InvalidInput`] if no file has been set.
+    pub fn map_chunks(self, chunk_size: usize) -> Result<MmapChunks, Error> {
+        let granularity = allocation_granularity();
+
+        if chunk_size == 0 || !chunk_size.is_multiple_of(granularity) {
+            return Err(Error::Unaligned(chunk_size, granularity));
+        }
+
+        // Check that a file has been set, such that the iterator does not have to fail later on.
+        self.inner.window(0, 0)?;
+
+        Ok(MmapChunks::new(self, chunk_size))
+    }
+
     /// Maps the memory as executable.
+    #[cfg(feature = "exec")]
     pub fn map_exec(self) -> Result<Mmap, Error> {
         Ok(Mmap {
             inner: self.inner.map_exec()?,
@@ -500,6 +2147,23 @@ impl MmapOptions {
         })
     }
 
+    /// Maps anonymous memory as a stack for a thread or a coroutine, where the size is the usable
+    /// size of the stack. An inaccessible guard page is mapped below the stack, unless
+    /// [`MmapFlags::GROWS_DOWN`] has been set, and [`MmapFlags::STACK`] is set implicitly. The
+    /// initial stack pointer is [`StackMmap::top()`]. See [`StackMmap`].
+    ///
+    /// Returns [`Error::OutOfBounds`] if the size is zero, and an error of the kind
+    /// [`std::io::ErrorKind::InvalidInput`] if a file or a [`Reservation`] has been set.
+    pub fn map_stack(self) -> Result<StackMmap, Error> {
+        if self.inner.size() == 0 {
+            return Err(Error::OutOfBounds(0..0));
+        }
+
+        let (inner, guard_size) = self.inner.map_stack()?;
+
+        Ok(StackMmap::new(inner, guard_size))
+    }
+
     /// Maps the memory as executable and mutable. While this may seem useful for self-modifying
     /// code and JIT engines, it is instead recommended to convert between mutable and executable
     /// mappings using [`Mmap::make_mut()`] and [`MmapMut::make_exec()`] instead.
@@ -507,6 +2171,10 @@ impl MmapOptions {
     /// As it may be tempting to use this function, this function has been marked as **unsafe**.
     /// Make sure to read the text below to understand the complications of this function before
     /// using it. The [`UnsafeMmapFlags::JIT`] flag must be set for this function to succeed.
+    /// This function always fails with [`Error::WxForbidden`] when the `strict-wx` feature is
+    /// enabled.
+    ///
+    /// # Safety
     ///
     /// RWX pages are an interesting targets to attackers, e.g. for buffer overflow attacks, as RWX
     /// mappings can potentially simplify such attacks. Without RWX mappings, attackers instead
@@ -520,9 +2188,30 @@ impl MmapOptions {
     /// modified the pages, then executing the code may result in undefined behavior. To ensure
     /// correct behavior a user has to flush the instruction cache after  modifying and before
     /// executing the page.
+    #[cfg(feature = "jit")]
     pub unsafe fn map_exec_mut(self) -> Result<MmapMut, Error> {
+        check_wx()?;
+
         Ok(MmapMut {
             inner: self.inner.map_exec_mut()?,
         })
     }
+
+    /// Maps the memory as mutable and copies the given bytes to the start of the mapping. This is
+    /// useful to move data that lives on the heap into memory that is backed by huge pages or that
+    /// is locked into physical memory, by setting the appropriate [`MmapFlags`].
+    ///
+    /// Returns [`Error::OutOfBounds`] if the bytes do not fit in the mapping.
+    pub fn map_mut_from_slice(self, bytes: &[u8]) -> Result<MmapMut, Error> {
+        // Check the size before mapping, such that a file is not touched by a failing call.
+        if bytes.len() > self.inner.size() {
+            return Err(Error::OutOfBounds(0..bytes.len()));
+        }
+
+        let mut mapping = self.map_mut()?;
+
+        mapping[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(mapping)
+    }
 }
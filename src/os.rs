@@ -0,0 +1,300 @@
+//! This module provides platform-specific extensions, most notably to pass raw flags to the
+//! operating system, for flags that are too exotic or too platform-specific to be exposed through
+//! [`MmapFlags`]. These are escape hatches: the flags are passed on as is, without any validation,
+//! such that it is up to the caller to ensure that they are supported and do not conflict with the
+//! flags set by this crate.
+//!
+//! [`MmapFlags`]: crate::MmapFlags
+
+/// Unix-specific extensions.
+#[cfg(unix)]
+pub mod unix {
+    use crate::MmapOptions;
+
+    /// Unix-specific extensions to [`MmapOptions`].
+    pub trait MmapOptionsExt {
+        /// Adds the given raw `MAP_*` flags to the flags that are passed to `mmap()`, e.g.
+        /// `MAP_32BIT` on Linux.
+        ///
+        /// # Safety
+        ///
+        /// The flags are passed on as is. Flags that change the semantics of the mapping, such as
+        /// `MAP_FIXED` or `MAP_SHARED_VALIDATE`, may break the assumptions of this crate and
+        /// result in undefined behavior. This has no effect with the `fake` feature.
+        unsafe fn with_raw_map_flags(self, flags: libc::c_int) -> Self;
+
+        /// Adds the given raw `PROT_*` flags to the protection that is passed to `mmap()` and to
+        /// `mprotect()` when changing the protection later on, e.g. `PROT_BTI` or `PROT_MTE` on
+        /// AArch64 Linux.
+        ///
+        /// # Safety
+        ///
+        /// The flags are passed on as is. Flags that grant access, such as `PROT_WRITE` or
+        /// `PROT_EXEC`, bypass the protection tracked by this crate and may result in undefined
+        /// behavior. This has no effect with the `fake` feature.
+        unsafe fn with_raw_prot(self, prot: libc::c_int) -> Self;
+    }
+
+    impl MmapOptionsExt for MmapOptions {
+        unsafe fn with_raw_map_flags(mut self, flags: libc::c_int) -> Self {
+            self.inner = self.inner.with_raw_map_flags(flags);
+            self
+        }
+
+        unsafe fn with_raw_prot(mut self, prot: libc::c_int) -> Self {
+            self.inner = self.inner.with_raw_prot(prot);
+            self
+        }
+    }
+}
+
+/// Linux-specific extensions to map buffers that are exported by drivers, such as dma-bufs shared
+/// by GPU, camera and video drivers, and grant references of other domains on Xen, and to map
+/// anonymous files created using `memfd_create()` that can be sealed and passed to other
+/// processes.
+#[cfg(target_os = "linux")]
+pub mod linux {
+    use crate::{Error, MmapMut, MmapOptions};
+    use bitflags::bitflags;
+    use std::fs::File;
+
+    /// Linux-specific extensions to [`MmapOptions`].
+    pub trait MmapOptionsExt {
+        /// Maps a dma-buf, as exported by a GPU driver through `DRM_IOCTL_PRIME_HANDLE_TO_FD`, by
+        /// a V4L2 driver through `VIDIOC_EXPBUF` or allocated from a DMA heap. The whole buffer
+        /// is mapped from offset zero, where the size can be retrieved using [`dma_buf_size()`].
+        /// dma-bufs can only be mapped as shared memory, and the pages are never populated.
+        ///
+        /// The CPU caches are not necessarily coherent with the device, such that every access by
+        /// the CPU has to be bracketed by [`begin_cpu_access()`] and [`end_cpu_access()`], or
+        /// by holding on to a [`CpuAccessGuard`], which flushes or invalidates the caches as
+        /// needed. Some exporters do not support mapping the buffer at all, in which case mapping
+        /// fails with [`Error::Nix`].
+        ///
+        /// # Safety
+        ///
+        /// See [`MmapOptions::with_device()`]. The device may access the buffer at any time
+        /// outside of the CPU access brackets.
+        unsafe fn with_dma_buf(self, file: File) -> Self;
+
+        /// Maps the grant references that have been mapped at the given offset of
+        /// `/dev/xen/gntdev` using [`map_grant_refs()`]. The size must cover the pages of all of
+        /// the grant references, i.e. one page per grant reference.
+        ///
+        /// # Safety
+        ///
+        /// See [`MmapOptions::with_device()`]. The memory is shared with another domain, which
+        /// may modify it at any time.
+        unsafe fn with_grant_refs(self, file: File, index: u64) -> Self;
+
+        /// Creates an anonymous file using `memfd_create()`, which is as large as the mapping,
+        /// and maps it as shared memory. The name is only used for debugging purposes, as it
+        /// shows up in `/proc/self/maps`, and does not have to be unique. The file can be
+        /// retrieved using [`MmapMut::file()`], e.g. to pass it to another process over a Unix
+        /// socket using `SCM_RIGHTS`, which can then map it using [`MmapOptions::with_file()`].
+        ///
+        /// The given seals are added to the file once it has been mapped, where
+        /// [`Seals::FUTURE_WRITE`] lets this mapping keep writing to the file while preventing
+        /// other processes from mapping it as writable, such that the contents can be shared
+        /// read-only. [`Seals::WRITE`] fails with `EBUSY` while the file is mapped as writable,
+        /// so it has to be added using [`add_seals()`] once the writable mapping is gone. The
+        /// file supports sealing even if no seals are given, such that seals can be added later
+        /// on, unless [`Seals::SEAL`] is given.
+        ///
+        /// # Safety
+        ///
+        /// Other processes that the file is passed to may modify it at any time, unless it has
+        /// been sealed against writes, so the caller must co-ordinate the accesses with them. See
+        /// [`MmapOptions::with_file()`].
+        unsafe fn map_memfd(self, name: &str, seals: Seals) -> Result<MmapMut, Error>;
+    }
+
+    impl MmapOptionsExt for MmapOptions {
+        unsafe fn with_dma_buf(self, file: File) -> Self {
+            self.with_device(file, 0)
+        }
+
+        unsafe fn with_grant_refs(self, file: File, index: u64) -> Self {
+            self.with_device(file, index)
+        }
+
+        unsafe fn map_memfd(mut self, name: &str, seals: Seals) -> Result<MmapMut, Error> {
+            let file = crate::os_impl::linux::memfd(name, self.inner.size())?;
+
+            self = self.with_file(file, 0);
+
+            let mapping = self.map_mut()?;
+
+            if !seals.is_empty() {
+                // The file is always set, as the mapping has been created from it.
+                if let Some(file) = mapping.file() {
+                    add_seals(file, seals)?;
+                }
+            }
+
+            Ok(mapping)
+        }
+    }
+
+    bitflags! {
+        /// The seals that restrict the changes that can be made to a file created using
+        /// `memfd_create()`, by any process that has access to the file. Seals cannot be removed
+        /// once they have been added. See `man 2 memfd_create` for more information.
+        pub struct Seals: i32 {
+            /// Prevents further seals from being added, i.e. `F_SEAL_SEAL`.
+            const SEAL         = libc::F_SEAL_SEAL;
+            /// Prevents the file from shrinking, i.e. `F_SEAL_SHRINK`.
+            const SHRINK       = libc::F_SEAL_SHRINK;
+            /// Prevents the file from growing, i.e. `F_SEAL_GROW`.
+            const GROW         = libc::F_SEAL_GROW;
+            /// Prevents any writes to the file, i.e. `F_SEAL_WRITE`, which can only be added
+            /// while the file is not mapped as shared and writable.
+            const WRITE        = libc::F_SEAL_WRITE;
+            /// Prevents writes through new mappings and file descriptors, while existing
+            /// writable mappings keep working, i.e. `F_SEAL_FUTURE_WRITE`. This requires Linux
+            /// 5.1 or newer.
+            const FUTURE_WRITE = libc::F_SEAL_FUTURE_WRITE;
+        }
+    }
+
+    /// Adds the given seals to a file created using `memfd_create()`, e.g. through
+    /// [`MmapOptionsExt::map_memfd()`]. Fails with `EPERM` if the file has been sealed with
+    /// [`Seals::SEAL`], and with `EINVAL` if the file does not support sealing.
+    pub fn add_seals(file: &File, seals: Seals) -> Result<(), Error> {
+        crate::os_impl::linux::add_seals(file, seals.bits())
+    }
+
+    /// Returns the seals of a file, such that a process that received the file from another
+    /// process can verify that the file has been sealed, e.g. against writes and shrinking,
+    /// before mapping it. Fails with `EINVAL` if the file does not support sealing.
+    pub fn seals(file: &File) -> Result<Seals, Error> {
+        crate::os_impl::linux::get_seals(file).map(Seals::from_bits_truncate)
+    }
+
+    /// Returns the size of a dma-buf in bytes, which dma-bufs report by seeking to their end
+    /// rather than through their metadata. This resets the file offset.
+    pub fn dma_buf_size(file: &File) -> Result<usize, Error> {
+        crate::os_impl::linux::dma_buf_size(file)
+    }
+
+    bitflags! {
+        /// The kind of access by the CPU to a dma-buf, which determines whether the caches are
+        /// invalidated before the access and written back after the access.
+        pub struct CpuAccess: u64 {
+            /// The CPU reads from the buffer, such that stale cache lines have to be invalidated.
+            const READ  = 1 << 0;
+            /// The CPU writes to the buffer, such that the cache lines have to be written back.
+            const WRITE = 1 << 1;
+        }
+    }
+
+    /// Begins access by the CPU to a mapped dma-buf using `DMA_BUF_IOCTL_SYNC`, waiting for the
+    /// device to finish its access and preparing the caches. Must be followed by
+    /// [`end_cpu_access()`] with the same access once the CPU is done with the buffer.
+    pub fn begin_cpu_access(file: &File, access: CpuAccess) -> Result<(), Error> {
+        crate::os_impl::linux::dma_buf_sync(file, access.bits(), false)
+    }
+
+    /// Ends access by the CPU to a mapped dma-buf that was begun by [`begin_cpu_access()`], such
+    /// that the device can access the buffer again.
+    pub fn end_cpu_access(file: &File, access: CpuAccess) -> Result<(), Error> {
+        crate::os_impl::linux::dma_buf_sync(file, access.bits(), true)
+    }
+
+    /// Begins access by the CPU to a mapped dma-buf and returns a guard that ends the access when
+    /// dropped. See [`begin_cpu_access()`].
+    pub fn cpu_access(file: &File, access: CpuAccess) -> Result<CpuAccessGuard<'_>, Error> {
+        begin_cpu_access(file, access)?;
+
+        Ok(CpuAccessGuard { file, access })
+    }
+
+    /// Ends the access by the CPU to a dma-buf when dropped. See [`cpu_access()`]. Errors while
+    /// ending the access are ignored, use [`CpuAccessGuard::end()`] to handle them.
+    pub struct CpuAccessGuard<'a> {
+        file: &'a File,
+        access: CpuAccess,
+    }
+
+    impl CpuAccessGuard<'_> {
+        /// Ends the access by the CPU to the dma-buf.
+        pub fn end(self) -> Result<(), Error> {
+            let result = end_cpu_access(self.file, self.access);
+            std::mem::forget(self);
+            result
+        }
+    }
+
+    impl Drop for CpuAccessGuard<'_> {
+        fn drop(&mut self) {
+            let _ = end_cpu_access(self.file, self.access);
+        }
+    }
+
+    /// A reference to a page that has been granted by another domain on Xen.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct GrantRef {
+        /// The ID of the domain that granted the page.
+        pub domain: u32,
+        /// The grant reference as handed out by the domain.
+        pub reference: u32,
+    }
+
+    /// Maps the given grant references into `/dev/xen/gntdev` using
+    /// `IOCTL_GNTDEV_MAP_GRANT_REF`, returning the offset at which the pages can be mapped using
+    /// [`MmapOptionsExt::with_grant_refs()`]. The grant references have to be released using
+    /// [`unmap_grant_refs()`] once the mapping has been dropped.
+    pub fn map_grant_refs(file: &File, refs: &[GrantRef]) -> Result<u64, Error> {
+        let refs: Vec<(u32, u32)> = refs.iter().map(|r| (r.domain, r.reference)).collect();
+
+        crate::os_impl::linux::map_grant_refs(file, &refs)
+    }
+
+    /// Releases the given number of grant references that were mapped at the given offset by
+    /// [`map_grant_refs()`]. The pages remain mapped until the mapping has been dropped.
+    pub fn unmap_grant_refs(file: &File, index: u64, count: u32) -> Result<(), Error> {
+        crate::os_impl::linux::unmap_grant_refs(file, index, count)
+    }
+}
+
+/// Microsoft Windows-specific extensions.
+#[cfg(windows)]
+pub mod windows {
+    use crate::MmapOptions;
+
+    /// Microsoft Windows-specific extensions to [`MmapOptions`].
+    pub trait MmapOptionsExt {
+        /// Adds the given raw `PAGE_*` flags to the page protection that is passed to
+        /// `VirtualAlloc()` and `VirtualProtect()`, e.g. `PAGE_TARGETS_INVALID` for Control Flow
+        /// Guard.
+        ///
+        /// # Safety
+        ///
+        /// The flags are passed on as is. Flags that change the access to the pages, such as
+        /// `PAGE_GUARD`, may break the assumptions of this crate and result in undefined behavior.
+        /// This has no effect with the `fake` feature.
+        unsafe fn with_raw_page_protection(self, protect: u32) -> Self;
+    }
+
+    impl MmapOptionsExt for MmapOptions {
+        unsafe fn with_raw_page_protection(mut self, protect: u32) -> Self {
+            self.inner = self.inner.with_raw_page_protection(protect);
+            self
+        }
+    }
+
+    /// Enables the `SeLockMemoryPrivilege` privilege in the access token of the current process,
+    /// which is needed to allocate large pages. This is done automatically when mapping memory
+    /// with [`MmapFlags::HUGE_PAGES`], but may be called up front, e.g. before dropping the rights
+    /// to adjust the access token.
+    ///
+    /// The privilege can only be enabled if the "Lock pages in memory" user right has been granted
+    /// to the user. Returns [`Error::HugePagesUnavailable`] describing why the privilege could not
+    /// be enabled otherwise.
+    ///
+    /// [`MmapFlags::HUGE_PAGES`]: crate::MmapFlags::HUGE_PAGES
+    /// [`Error::HugePagesUnavailable`]: crate::Error::HugePagesUnavailable
+    pub fn enable_lock_memory_privilege() -> Result<(), crate::Error> {
+        crate::os_impl::process::enable_lock_memory_privilege()
+    }
+}
@@ -0,0 +1,1002 @@
+//! This module implements a deterministic in-memory backend that is used instead of the operating
+//! system when the `fake` feature is enabled. Mappings are zeroed heap allocations aligned to a
+//! fixed page size of 4 KiB, and the protection of a mapping is only tracked as bookkeeping, such
+//! that the logic built on top of this crate can be run under Miri and in unit tests without any
+//! memory mapping system calls.
+//!
+//! File mappings are emulated by reading the contents of the file into the allocation when the
+//! mapping is created, and by writing the contents back to the file when the mapping is flushed,
+//! unless the mapping is copy-on-write. Changes made to the file by others are therefore not
+//! visible through the mapping.
+
+use crate::capabilities::Capabilities;
+use crate::error::Error;
+use crate::protection::Protection;
+use crate::reservation::{Shared, Slot};
+use crate::stats::Record;
+use crate::{Advice, DropBehavior, Eviction, FileLock, LockAllFlags, MmapFlags, PageSize, UnsafeMmapFlags};
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::fs::File;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub use super::file::{lock_file_range, read_exact_at, unlock_file_range};
+
+/// The page size and allocation granularity of the in-memory backend, which are the same on
+/// every platform to keep the behavior deterministic.
+const PAGE_SIZE: usize = 4096;
+
+/// Writes all the bytes to the file at the given offset, without changing the position of the
+/// file.
+#[cfg(unix)]
+fn write_all_at(file: &File, buffer: &[u8], offset: u64) -> Result<(), Error> {
+    use std::os::unix::fs::FileExt;
+
+    Ok(file.write_all_at(buffer, offset)?)
+}
+
+/// Writes all the bytes to the file at the given offset.
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buffer: &[u8], mut offset: u64) -> Result<(), Error> {
+    use std::os::windows::fs::FileExt;
+
+    while !buffer.is_empty() {
+        match file.seek_write(buffer, offset) {
+            Ok(0) => return Err(std::io::Error::from(ErrorKind::WriteZero))?,
+            Ok(size) => {
+                buffer = &buffer[size..];
+                offset += size as u64;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)?,
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Mmap {
+    file: Option<(File, u64)>,
+    ptr: *mut u8,
+    size: usize,
+    /// The size of the allocation, which is aligned to the page size, as the mapping may have been
+    /// truncated.
+    capacity: usize,
+    protection: AtomicU32,
+    flags: MmapFlags,
+    #[cfg(feature = "jit")]
+    jit: bool,
+    drop_behavior: DropBehavior,
+    /// The range of the reservation that has been claimed for the mapping, if any.
+    slot: Option<Box<Slot>>,
+    record: Record,
+}
+
+// The mapping exclusively owns the allocation it points to, such that it can be safely sent to
+// and shared between threads.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    #[inline]
+    pub fn file(&self) -> Option<&File> {
+        self.file.as_ref().map(|(file, _)| file)
+    }
+
+    /// Returns the offset within the file at which the contents have been read from.
+    #[inline]
+    pub fn file_offset(&self) -> u64 {
+        self.file.as_ref().map_or(0, |(_, offset)| *offset)
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The flags that carry over to an anonymous copy of the mapping.
+    pub fn anon_flags(&self) -> MmapFlags {
+        self.flags & (MmapFlags::LOCKED | MmapFlags::ZERO_ON_DROP | MmapFlags::CHECK_MEMORY_LIMIT)
+    }
+
+    /// Heap allocations are not subject to any memory limits that this backend could check.
+    pub fn check_memory_limit(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub fn protection(&self) -> Protection {
+        Protection::from_bits_truncate(self.protection.load(Ordering::Relaxed))
+    }
+
+    /// The protection is only tracked for the mapping as a whole.
+    pub fn protection_of(&self, _range: Range<usize>) -> Result<Protection, Error> {
+        Ok(self.protection())
+    }
+
+    /// Panics in debug builds if the current protection of the mapping does not allow the given
+    /// access, which would have resulted in a fault with a real mapping.
+    #[inline]
+    pub fn check_access(&self, access: Protection) {
+        let protection = Protection::from_bits_truncate(self.protection.load(Ordering::Relaxed));
+
+        debug_assert!(
+            protection.contains(access),
+            "{:?} access to the mapping at {:p} is not allowed by {:?}",
+            access,
+            self.ptr,
+            protection,
+        );
+    }
+
+    pub fn lock(&mut self) -> Result<(), Error> {
+        self.record.lock();
+
+        Ok(())
+    }
+
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        self.record.unlock();
+
+        Ok(())
+    }
+
+    /// Heap allocations are always resident, so there is nothing to lock.
+    pub fn lock_pages(&self, _range: Range<usize>, _lock: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub fn set_locked(&mut self) {
+        self.record.lock();
+    }
+
+    pub fn flush(&self, range: Range<usize>) -> Result<(), Error> {
+        crate::mmap::check_range(&range, self.size)?;
+
+        let (file, offset) = match self.file.as_ref() {
+            Some((file, offset)) => (file, *offset),
+            _ => return Ok(()),
+        };
+
+        // Changes to copy-on-write mappings are private to the mapping.
+        if self.flags.contains(MmapFlags::COPY_ON_WRITE) {
+            return Ok(());
+        }
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(self.ptr.add(range.start), range.end - range.start)
+        };
+
+        write_all_at(file, bytes, offset + range.start as u64)
+    }
+
+    pub fn flush_async(&self, range: Range<usize>) -> Result<(), Error> {
+        self.flush(range)
+    }
+
+    /// Writes the ranges back to the file one by one, as the contents of the heap allocation are
+    /// only written to the file when flushing.
+    pub fn flush_ranges(&self, ranges: &[Range<usize>], _sync: bool) -> Result<(), Error> {
+        ranges.iter().try_for_each(|range| self.flush(range.clone()))
+    }
+
+    /// Heap allocations have no access patterns to advise.
+    pub fn set_sequential(&self, _sequential: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Heap allocations are always resident.
+    pub fn read_ahead(&self, _range: Range<usize>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Heap allocations never use huge pages.
+    pub fn advise_huge_pages(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Heap allocations are always resident.
+    pub fn populate(&self, _range: Range<usize>, _write: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Heap allocations have no page tables of which the accessed bits could be sampled.
+    pub fn clear_accessed(&self) -> Result<(), Error> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "the accessed bits of pages cannot be sampled by the in-memory backend",
+        ))?
+    }
+
+    pub fn accessed_pages(&self) -> Result<Vec<bool>, Error> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "the accessed bits of pages cannot be sampled by the in-memory backend",
+        ))?
+    }
+
+    /// Heap allocations are always resident.
+    pub fn evict(&self, _range: Range<usize>, _eviction: Eviction) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Heap allocations are always resident, but discarding anonymous memory zeroes it, as Linux
+    /// does, such that code relying on the contents being preserved fails in tests.
+    pub fn advise(&self, range: Range<usize>, advice: Advice) -> Result<(), Error> {
+        if matches!(advice, Advice::DontNeed | Advice::Free) && self.file.is_none() {
+            unsafe {
+                std::ptr::write_bytes(self.ptr.add(range.start), 0, range.end - range.start)
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Heap allocations cannot be dropped without losing their contents.
+    pub fn drop_behind(&self, _range: Range<usize>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub fn flush_icache(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[cfg(feature = "exec")]
+    pub fn flush_icache_range(&self, _range: Range<usize>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The memory is never executed, so there is nothing to publish.
+    pub fn publish_code(&self, _range: Range<usize>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn do_make(&self, protection: Protection) -> Result<(), Error> {
+        self.protection.store(protection.bits(), Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// The protection is only tracked for the mapping as a whole, so changing the protection of
+    /// parts of the mapping is accepted without any bookkeeping.
+    pub fn protect_ranges(&self, _ranges: &[(Range<usize>, Protection)]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Whether the pages are private to this process rather than shared with other mappings.
+    #[inline]
+    pub fn is_copy_on_write(&self) -> bool {
+        self.flags.contains(MmapFlags::COPY_ON_WRITE)
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn is_jit(&self) -> bool {
+        self.jit
+    }
+
+    pub fn unmap(&mut self) -> Result<(), Error> {
+        // The contents of file mappings are only written to the file when flushing. Heap
+        // allocations have nothing to discard.
+        if self.drop_behavior == DropBehavior::Flush {
+            let _ = self.flush(0..self.size);
+        }
+
+        crate::flush::forget(self.ptr as usize..self.ptr as usize + self.capacity);
+
+        if self.flags.contains(MmapFlags::ZERO_ON_DROP) {
+            unsafe {
+                super::scrub(self.ptr, self.size)
+            };
+        }
+
+        unsafe {
+            dealloc(self.ptr, Layout::from_size_align_unchecked(self.capacity, PAGE_SIZE))
+        };
+
+        self.ptr = std::ptr::null_mut();
+
+        Ok(())
+    }
+
+    /// The allocation is kept as is, as the allocator cannot shrink it in place.
+    pub fn truncate(&mut self, size: usize) -> Result<(), Error> {
+        if self.flags.contains(MmapFlags::ZERO_ON_DROP) {
+            unsafe {
+                super::scrub(self.ptr.add(size), self.size - size)
+            };
+        }
+
+        if let Some(slot) = self.slot.as_mut() {
+            slot.truncate(size);
+        }
+
+        self.size = size;
+        self.record.resize(size);
+
+        Ok(())
+    }
+
+    /// The allocator cannot split an allocation, so the remainder is copied into an allocation
+    /// of its own, while this allocation is kept as is.
+    pub fn split_off(&mut self, at: usize) -> Result<Self, Error> {
+        if self.slot.is_some() {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "views of a reservation cannot be split",
+            ))?;
+        }
+
+        let size = self.size - at;
+        let layout = size
+            .checked_next_multiple_of(PAGE_SIZE)
+            .and_then(|size| Layout::from_size_align(size, PAGE_SIZE).ok())
+            .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+
+        let file = self.file
+            .as_ref()
+            .map(|(file, offset)| Ok::<_, Error>((file.try_clone()?, offset + at as u64)))
+            .transpose()?;
+
+        let ptr = unsafe {
+            alloc_zeroed(layout)
+        };
+
+        if ptr.is_null() {
+            return Err(std::io::Error::from(ErrorKind::OutOfMemory))?;
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.ptr.add(at), ptr, size)
+        };
+
+        if self.flags.contains(MmapFlags::ZERO_ON_DROP) {
+            unsafe {
+                super::scrub(self.ptr.add(at), size)
+            };
+        }
+
+        let tail = Self {
+            file,
+            ptr,
+            size,
+            capacity: layout.size(),
+            protection: AtomicU32::new(self.protection.load(Ordering::Relaxed)),
+            flags: self.flags,
+            #[cfg(feature = "jit")]
+            jit: self.jit,
+            drop_behavior: self.drop_behavior,
+            slot: None,
+            record: self.record.split_off(ptr as usize, at),
+        };
+
+        self.size = at;
+
+        Ok(tail)
+    }
+
+    /// The allocator cannot grow an allocation in place, so the contents are copied into a larger
+    /// allocation unless the allocation is large enough already, e.g. as the mapping has been
+    /// truncated before. The part of the file that the added range covers is read into it.
+    pub fn resize(&mut self, size: usize) -> Result<(), Error> {
+        if self.slot.is_some() {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "views of a reservation cannot grow",
+            ))?;
+        }
+
+        if size > self.capacity {
+            let layout = size
+                .checked_next_multiple_of(PAGE_SIZE)
+                .and_then(|size| Layout::from_size_align(size, PAGE_SIZE).ok())
+                .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+
+            let ptr = unsafe {
+                alloc_zeroed(layout)
+            };
+
+            if ptr.is_null() {
+                return Err(std::io::Error::from(ErrorKind::OutOfMemory))?;
+            }
+
+            crate::flush::forget(self.ptr as usize..self.ptr as usize + self.capacity);
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(self.ptr, ptr, self.size);
+
+                if self.flags.contains(MmapFlags::ZERO_ON_DROP) {
+                    super::scrub(self.ptr, self.size);
+                }
+
+                dealloc(self.ptr, Layout::from_size_align_unchecked(self.capacity, PAGE_SIZE));
+            }
+
+            self.ptr = ptr;
+            self.capacity = layout.size();
+        } else {
+            unsafe {
+                std::ptr::write_bytes(self.ptr.add(self.size), 0, size - self.size)
+            };
+        }
+
+        if let Some((file, offset)) = self.file.as_ref() {
+            let start = offset + self.size as u64;
+            let end = file.metadata()?.len().min(offset + size as u64);
+
+            if start < end {
+                let bytes = unsafe {
+                    std::slice::from_raw_parts_mut(self.ptr.add(self.size), (end - start) as usize)
+                };
+
+                read_exact_at(file, bytes, start)?;
+            }
+        }
+
+        self.size = size;
+        self.record.relocate(self.ptr as usize);
+        self.record.resize(size);
+
+        Ok(())
+    }
+
+    /// The address is only a hint, which the in-memory backend ignores.
+    pub fn remap_to(&mut self, _address: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub fn make_none(&self) -> Result<(), Error> {
+        self.do_make(Protection::empty())
+    }
+
+    /// The protection is merely tracked by the in-memory backend, so it cannot be sealed.
+    pub fn seal(&mut self) -> Result<(), Error> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "mappings cannot be sealed by the in-memory backend",
+        ))?
+    }
+
+    pub fn make_read_only(&self) -> Result<(), Error> {
+        self.do_make(Protection::READ)
+    }
+
+    #[cfg(feature = "exec")]
+    pub fn make_exec(&self) -> Result<(), Error> {
+        self.do_make(Protection::READ | Protection::EXECUTE)
+    }
+
+    pub fn make_mut(&self) -> Result<(), Error> {
+        self.do_make(Protection::READ | Protection::WRITE)
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn make_exec_mut(&self) -> Result<(), Error> {
+        if !self.jit {
+            return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
+        }
+
+        self.do_make(Protection::READ | Protection::WRITE | Protection::EXECUTE)
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            let _ = self.unmap();
+        }
+    }
+}
+
+pub struct MmapOptions {
+    file: Option<(File, u64)>,
+    size: usize,
+    flags: MmapFlags,
+    unsafe_flags: UnsafeMmapFlags,
+    drop_behavior: DropBehavior,
+    reservation: Option<(Arc<Shared>, usize)>,
+    device: bool,
+}
+
+impl MmapOptions {
+    pub fn new(size: usize) -> Self {
+        Self {
+            file: None,
+            size,
+            flags: MmapFlags::empty(),
+            unsafe_flags: UnsafeMmapFlags::empty(),
+            drop_behavior: DropBehavior::Unmap,
+            reservation: None,
+            device: false,
+        }
+    }
+
+    pub fn page_size() -> (usize, usize) {
+        (PAGE_SIZE, PAGE_SIZE)
+    }
+
+    /// The address is only a hint, which the in-memory backend ignores.
+    pub fn with_address(self, _address: usize) -> Self {
+        self
+    }
+
+    /// The address range is ignored, just like the address.
+    pub fn with_address_range(self, _range: Range<usize>) -> Self {
+        self
+    }
+
+    /// The mapping is allocated on the heap, but the range is still claimed from the reservation.
+    pub fn with_reservation(mut self, reservation: Arc<Shared>, offset: usize) -> Self {
+        self.reservation = Some((reservation, offset));
+        self
+    }
+
+    pub fn with_file(mut self, file: File, offset: u64) -> Self {
+        self.file = Some((file, offset));
+        self
+    }
+
+    pub fn with_device(mut self, file: File, offset: u64) -> Self {
+        self.file = Some((file, offset));
+        self.device = true;
+        self
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the options to map the given window of the file, relative to the offset of these
+    /// options, with the same flags as these options, but without the placement of the mapping,
+    /// as the windows are mapped one after the other.
+    pub fn window(&self, start: usize, size: usize) -> Result<Self, Error> {
+        let (file, offset) = match self.file {
+            Some((ref file, offset)) => (file, offset + start as u64),
+            _ => return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "only files can be mapped in chunks",
+            ))?,
+        };
+
+        Ok(Self {
+            file: Some((file.try_clone()?, offset)),
+            size,
+            flags: self.flags,
+            unsafe_flags: self.unsafe_flags,
+            drop_behavior: self.drop_behavior,
+            reservation: None,
+            device: self.device,
+        })
+    }
+
+    pub fn with_flags(mut self, flags: MmapFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn with_unsafe_flags(mut self, flags: UnsafeMmapFlags) -> Self {
+        self.unsafe_flags = flags;
+        self
+    }
+
+    /// Heap allocations always use the default page size.
+    pub fn with_page_size(self, _page_size: PageSize) -> Self {
+        self
+    }
+
+    /// Heap allocations never use huge pages, so there is nothing to check.
+    pub fn check_huge_pages(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// There are no other processes to co-operate with.
+    pub fn with_file_lock(self, _lock: FileLock) -> Self {
+        self
+    }
+
+    pub fn with_drop_behavior(mut self, behavior: DropBehavior) -> Self {
+        self.drop_behavior = behavior;
+        self
+    }
+
+    /// Heap allocations have no flags to pass to the operating system.
+    #[cfg(unix)]
+    pub unsafe fn with_raw_map_flags(self, _flags: libc::c_int) -> Self {
+        self
+    }
+
+    #[cfg(unix)]
+    pub unsafe fn with_raw_prot(self, _prot: libc::c_int) -> Self {
+        self
+    }
+
+    #[cfg(windows)]
+    pub unsafe fn with_raw_page_protection(self, _protect: u32) -> Self {
+        self
+    }
+
+    fn do_map(self, protection: Protection) -> Result<Mmap, Error> {
+        // Heap allocations are always cached.
+        let cache_flags = self.flags & (MmapFlags::UNCACHED | MmapFlags::WRITE_COMBINE);
+
+        if !cache_flags.is_empty() {
+            return Err(Error::UnsupportedFlags(cache_flags));
+        }
+
+        // Scrubbing a shared file mapping would overwrite the contents of the file.
+        if self.flags.contains(MmapFlags::ZERO_ON_DROP) && self.file.is_some() &&
+            !self.flags.contains(MmapFlags::COPY_ON_WRITE) {
+            return Err(Error::UnsupportedFlags(MmapFlags::ZERO_ON_DROP));
+        }
+
+        // The memory of a device cannot be emulated by reading it into the heap, as reading it
+        // may have side effects.
+        if self.device {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "devices cannot be mapped by the in-memory backend",
+            ))?;
+        }
+
+        // Heap allocations cannot grow downward.
+        if self.flags.contains(MmapFlags::GROWS_DOWN) {
+            return Err(Error::UnsupportedFlags(MmapFlags::GROWS_DOWN));
+        }
+
+        // Mapping zero bytes fails on every platform.
+        if self.size == 0 {
+            return Err(std::io::Error::from(ErrorKind::InvalidInput))?;
+        }
+
+        let slot = self.reservation
+            .as_ref()
+            .map(|(reservation, offset)| Slot::claim(reservation.clone(), *offset, self.size))
+            .transpose()?;
+
+        let layout = self.size
+            .checked_next_multiple_of(PAGE_SIZE)
+            .and_then(|size| Layout::from_size_align(size, PAGE_SIZE).ok())
+            .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+
+        let ptr = unsafe {
+            alloc_zeroed(layout)
+        };
+
+        if ptr.is_null() {
+            return Err(std::io::Error::from(ErrorKind::OutOfMemory))?;
+        }
+
+        let mapping = Mmap {
+            ptr,
+            size: self.size,
+            capacity: layout.size(),
+            protection: AtomicU32::new(protection.bits()),
+            flags: self.flags,
+            #[cfg(feature = "jit")]
+            jit: self.unsafe_flags.contains(UnsafeMmapFlags::JIT),
+            drop_behavior: self.drop_behavior,
+            slot: slot.map(Box::new),
+            record: Record::new(ptr as usize, self.size, self.flags, self.file.is_some()),
+            file: self.file,
+        };
+
+        // Read the contents of the file into the mapping, leaving the bytes past the end of the
+        // file zeroed.
+        if let Some((file, offset)) = mapping.file.as_ref() {
+            let file_size = file.metadata()?.len();
+            let size = file_size.saturating_sub(*offset).min(mapping.size as u64) as usize;
+
+            let bytes = unsafe {
+                std::slice::from_raw_parts_mut(mapping.ptr, size)
+            };
+
+            read_exact_at(file, bytes, *offset)?;
+        }
+
+        Ok(mapping)
+    }
+
+    pub fn reserve(self) -> Result<ReservedMmap, Error> {
+        if self.file.is_some() || self.reservation.is_some() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "reserved mappings must be anonymous",
+            ))?;
+        }
+
+        ReservedMmap::new(self.size)
+    }
+
+    pub fn map_none(self) -> Result<Mmap, Error> {
+        self.do_map(Protection::empty())
+    }
+
+    pub fn map(self) -> Result<Mmap, Error> {
+        self.do_map(Protection::READ)
+    }
+
+    #[cfg(feature = "exec")]
+    pub fn map_exec(self) -> Result<Mmap, Error> {
+        self.do_map(Protection::READ | Protection::EXECUTE)
+    }
+
+    pub fn map_mut(self) -> Result<Mmap, Error> {
+        self.do_map(Protection::READ | Protection::WRITE)
+    }
+
+    /// Maps a stack with a guard page below it, which is not enforced, and returns the mapping
+    /// along with the size of the guard page.
+    pub fn map_stack(mut self) -> Result<(Mmap, usize), Error> {
+        if self.file.is_some() || self.reservation.is_some() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "stacks must be anonymous",
+            ))?;
+        }
+
+        self.size = self.size.checked_add(PAGE_SIZE).ok_or(Error::OutOfBounds(0..self.size))?;
+
+        Ok((self.map_mut()?, PAGE_SIZE))
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn map_exec_mut(self) -> Result<Mmap, Error> {
+        if !self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
+            return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
+        }
+
+        self.do_map(Protection::READ | Protection::WRITE | Protection::EXECUTE)
+    }
+}
+
+/// Reservations only keep track of the ranges that have been claimed, as heap allocations cannot
+/// be placed at a chosen address.
+pub struct Reservation {
+    size: usize,
+}
+
+impl Reservation {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        Ok(Self {
+            size,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        std::ptr::null()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// A reservation on the heap, where the memory is allocated up front, such that committing pages
+/// only validates the range and uncommitting pages zeroes them.
+pub struct ReservedMmap {
+    ptr: *mut u8,
+    size: usize,
+}
+
+// The allocation is exclusively owned, such that it can be safely sent to and shared between
+// threads.
+unsafe impl Send for ReservedMmap {}
+unsafe impl Sync for ReservedMmap {}
+
+impl ReservedMmap {
+    fn new(size: usize) -> Result<Self, Error> {
+        let layout = Layout::from_size_align(size.max(1), PAGE_SIZE)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+
+        let ptr = unsafe { alloc_zeroed(layout) };
+
+        if ptr.is_null() {
+            return Err(std::io::Error::from(ErrorKind::OutOfMemory))?;
+        }
+
+        Ok(Self {
+            ptr,
+            size,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn commit(&self, _range: Range<usize>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub fn uncommit(&self, range: Range<usize>) -> Result<(), Error> {
+        unsafe {
+            std::ptr::write_bytes(self.ptr.add(range.start), 0, range.end - range.start)
+        };
+
+        Ok(())
+    }
+}
+
+impl Drop for ReservedMmap {
+    fn drop(&mut self) {
+        unsafe {
+            dealloc(self.ptr, Layout::from_size_align_unchecked(self.size.max(1), PAGE_SIZE))
+        };
+    }
+}
+
+/// Heap allocations cannot be mapped twice, so mirrored mappings cannot be created.
+pub struct MirroredMmap(std::convert::Infallible);
+
+impl MirroredMmap {
+    pub fn new(_size: usize) -> Result<Self, Error> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "mirrored mappings are not supported by the in-memory backend",
+        ))?
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        match self.0 {}
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        match self.0 {}
+    }
+
+    pub fn size(&self) -> usize {
+        match self.0 {}
+    }
+}
+
+#[cfg(feature = "exec")]
+pub struct DualMmap(std::convert::Infallible);
+
+#[cfg(feature = "exec")]
+impl DualMmap {
+    pub fn new(_size: usize) -> Result<Self, Error> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "dual mappings are not supported by the in-memory backend",
+        ))?
+    }
+
+    pub fn writable_ptr(&self) -> *mut u8 {
+        match self.0 {}
+    }
+
+    pub fn executable_ptr(&self) -> *const u8 {
+        match self.0 {}
+    }
+
+    pub fn size(&self) -> usize {
+        match self.0 {}
+    }
+
+    pub fn publish_code(&self, _range: Range<usize>) -> Result<(), Error> {
+        match self.0 {}
+    }
+}
+
+pub struct SharedMemory(std::convert::Infallible);
+
+impl SharedMemory {
+    pub fn create(_name: &str, _size: usize) -> Result<Self, Error> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "shared memory is not supported by the in-memory backend",
+        ))?
+    }
+
+    pub fn open(name: &str) -> Result<Self, Error> {
+        Self::create(name, 0)
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        match self.0 {}
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        match self.0 {}
+    }
+
+    pub fn size(&self) -> usize {
+        match self.0 {}
+    }
+}
+
+/// Allocates zeroed memory on the heap, where the guard page is not enforced, as the protection
+/// is only tracked as bookkeeping.
+pub fn map_guarded(size: usize, _guard: usize) -> *mut u8 {
+    match Layout::from_size_align(size, PAGE_SIZE) {
+        Ok(layout) => unsafe { alloc_zeroed(layout) },
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Frees the memory, as accesses to retired memory cannot be caught.
+pub fn retire_guarded(ptr: *mut u8, size: usize) {
+    unsafe {
+        dealloc(ptr, Layout::from_size_align_unchecked(size, PAGE_SIZE))
+    };
+}
+
+/// The memory of a fake mapping cannot be shared with another process.
+pub fn spawn_inheriting(
+    _command: &mut std::process::Command,
+    _file: &File,
+    _variable: &str,
+) -> Result<std::process::Child, Error> {
+    Err(Error::Io(std::io::Error::new(
+        ErrorKind::Unsupported,
+        "mappings cannot be shared with child processes with the fake feature",
+    )))
+}
+
+/// Fake mappings are never shared with child processes, so there is nothing to inherit.
+pub unsafe fn inherited_file(_handle: u64) -> Result<File, Error> {
+    Err(Error::Io(std::io::Error::new(
+        ErrorKind::Unsupported,
+        "mappings cannot be shared with child processes with the fake feature",
+    )))
+}
+
+/// Heap allocations never use huge pages.
+pub fn huge_page_size() -> Option<usize> {
+    None
+}
+
+/// Heap allocations are always resident.
+pub fn lock_all(_flags: LockAllFlags) -> Result<(), Error> {
+    Ok(())
+}
+
+pub fn unlock_all() -> Result<(), Error> {
+    Ok(())
+}
+
+/// The in-memory backend supports none of the features.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        page_size: PAGE_SIZE,
+        allocation_granularity: PAGE_SIZE,
+        huge_page_sizes: vec![],
+        fixed_noreplace: false,
+        memfd: false,
+        sealing: false,
+        jit: false,
+        write_watch: false,
+        numa_nodes: 1,
+    }
+}
+
+/// Heap allocations cannot be written back by their address, as the offset within the backing
+/// file is only known to the mapping itself, so this does nothing. The contents are written back
+/// when the mapping itself is flushed, or dropped with [`DropBehavior::Flush`].
+pub fn flush_range(_address: usize, _size: usize, _file: Option<&File>, _sync: bool) -> Result<(), Error> {
+    Ok(())
+}
+
+/// The pages of the in-memory backend are not tracked by the operating system.
+pub fn touched_pages(_address: usize, _size: usize) -> Result<Option<Vec<bool>>, Error> {
+    Ok(None)
+}
@@ -0,0 +1,185 @@
+//! This module implements the helpers for files that the platform backends share with the
+//! in-memory backend of the `fake` feature, as the files are real files in either case.
+
+use crate::error::Error;
+use std::fs::File;
+
+#[cfg(unix)]
+use nix::errno::Errno;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+#[cfg(windows)]
+use std::os::windows::io::AsRawHandle;
+#[cfg(windows)]
+use windows::Win32::Foundation::HANDLE;
+#[cfg(windows)]
+use windows::Win32::Storage::FileSystem::{
+    LockFileEx, UnlockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, LOCK_FILE_FLAGS,
+};
+#[cfg(windows)]
+use windows::Win32::System::IO::OVERLAPPED;
+
+/// Acquires a byte-range lock on the file using `fcntl()`. On Linux, open file description locks
+/// are used, which are owned by the file handle rather than by the process, such that they are not
+/// released when another handle to the same file is closed, and such that they conflict with the
+/// locks held through other handles within the same process.
+#[cfg(unix)]
+pub fn lock_file_range(
+    file: &File,
+    offset: u64,
+    len: u64,
+    exclusive: bool,
+    wait: bool,
+) -> Result<(), Error> {
+    #[cfg(target_os = "linux")]
+    let command = if wait { libc::F_OFD_SETLKW } else { libc::F_OFD_SETLK };
+    #[cfg(not(target_os = "linux"))]
+    let command = if wait { libc::F_SETLKW } else { libc::F_SETLK };
+
+    let kind = if exclusive { libc::F_WRLCK } else { libc::F_RDLCK };
+
+    set_file_range_lock(file, offset, len, kind, command)
+}
+
+/// Releases a byte-range lock on the file that was acquired using [`lock_file_range()`].
+#[cfg(unix)]
+pub fn unlock_file_range(file: &File, offset: u64, len: u64) -> Result<(), Error> {
+    #[cfg(target_os = "linux")]
+    let command = libc::F_OFD_SETLK;
+    #[cfg(not(target_os = "linux"))]
+    let command = libc::F_SETLK;
+
+    set_file_range_lock(file, offset, len, libc::F_UNLCK, command)
+}
+
+#[cfg(unix)]
+fn set_file_range_lock(
+    file: &File,
+    offset: u64,
+    len: u64,
+    kind: libc::c_int,
+    command: libc::c_int,
+) -> Result<(), Error> {
+    // Open file description locks require the process ID to be zero.
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = kind as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = offset as libc::off_t;
+    lock.l_len = len as libc::off_t;
+
+    loop {
+        let result = Errno::result(unsafe { libc::fcntl(file.as_raw_fd(), command, &lock) });
+
+        trace_op!("lock_file_range", result, offset = offset, len = len, kind = kind);
+
+        match result {
+            // Waiting for the lock is interrupted by signals.
+            Err(Errno::EINTR) => continue,
+            result => return Ok(result.map(|_| ())?),
+        }
+    }
+}
+
+/// Reads exactly enough bytes from the file at the given offset to fill the buffer, without
+/// changing the position of the file.
+#[cfg(unix)]
+pub fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> Result<(), Error> {
+    use std::os::unix::fs::FileExt;
+
+    file.read_exact_at(buffer, offset)?;
+
+    Ok(())
+}
+
+/// Acquires a byte-range lock on the file using `LockFileEx()`, which is owned by the file handle.
+#[cfg(windows)]
+pub fn lock_file_range(
+    file: &File,
+    offset: u64,
+    len: u64,
+    exclusive: bool,
+    wait: bool,
+) -> Result<(), Error> {
+    let mut flags = LOCK_FILE_FLAGS(0);
+
+    if exclusive {
+        flags |= LOCKFILE_EXCLUSIVE_LOCK;
+    }
+
+    if !wait {
+        flags |= LOCKFILE_FAIL_IMMEDIATELY;
+    }
+
+    let mut overlapped = file_range_overlapped(offset);
+
+    let status = unsafe {
+        LockFileEx(
+            HANDLE(file.as_raw_handle() as isize),
+            flags,
+            0,
+            (len & 0xffff_ffff) as u32,
+            (len >> 32) as u32,
+            &mut overlapped,
+        )
+    }.as_bool();
+
+    if !status {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    Ok(())
+}
+
+/// Releases a byte-range lock on the file that was acquired using [`lock_file_range()`].
+#[cfg(windows)]
+pub fn unlock_file_range(file: &File, offset: u64, len: u64) -> Result<(), Error> {
+    let mut overlapped = file_range_overlapped(offset);
+
+    let status = unsafe {
+        UnlockFileEx(
+            HANDLE(file.as_raw_handle() as isize),
+            0,
+            (len & 0xffff_ffff) as u32,
+            (len >> 32) as u32,
+            &mut overlapped,
+        )
+    }.as_bool();
+
+    if !status {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    Ok(())
+}
+
+/// Returns an `OVERLAPPED` structure that passes the offset of a byte range.
+#[cfg(windows)]
+fn file_range_overlapped(offset: u64) -> OVERLAPPED {
+    let mut overlapped = OVERLAPPED::default();
+
+    overlapped.Anonymous.Anonymous.Offset = (offset & 0xffff_ffff) as u32;
+    overlapped.Anonymous.Anonymous.OffsetHigh = (offset >> 32) as u32;
+
+    overlapped
+}
+
+/// Reads exactly enough bytes from the file at the given offset to fill the buffer.
+#[cfg(windows)]
+pub fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> Result<(), Error> {
+    use std::os::windows::fs::FileExt;
+
+    while !buffer.is_empty() {
+        match file.seek_read(buffer, offset) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?,
+            Ok(size) => {
+                buffer = &mut buffer[size..];
+                offset += size as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)?,
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use bitflags::bitflags;
-use crate::areas::{MemoryArea, Protection, ShareMode};
+use crate::areas::{MemoryArea, PathPattern, ShareMode};
+use crate::protection::Protection;
 use crate::error::Error;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -29,6 +30,7 @@ bitflags! {
 pub struct MemoryAreas<B> {
     entries: Vec<libc::kinfo_vmentry>,
     index: usize,
+    pattern: Option<PathPattern>,
     marker: PhantomData<B>,
 }
 
@@ -56,22 +58,51 @@ impl MemoryAreas<BufReader<File>> {
         Ok(Self {
             entries,
             index: 0,
+            pattern: None,
             marker: PhantomData,
         })
     }
+
+    pub fn query(pid: Option<u32>, address: usize) -> Result<Option<MemoryArea>, Error> {
+        let areas = Self::open(pid)?;
+
+        let entry = areas.entries
+            .iter()
+            .find(|entry| entry.kve_start as usize <= address && address < entry.kve_end as usize);
+
+        entry.and_then(|entry| areas.next_area(entry)).transpose()
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    pub fn filter_path(&mut self, pattern: PathPattern) {
+        self.pattern = Some(pattern);
+    }
 }
 
 impl<B: BufRead> Iterator for MemoryAreas<B> {
     type Item = Result<MemoryArea, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= self.entries.len() {
-            return None;
-        }
+        loop {
+            if self.index >= self.entries.len() {
+                return None;
+            }
 
-        let entry = &self.entries[self.index];
-        self.index += 1;
+            let entry = &self.entries[self.index];
+            self.index += 1;
 
+            if let Some(area) = self.next_area(entry) {
+                return Some(area);
+            }
+        }
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    /// Converts the entry into a memory area, unless the path of the entry does not match the
+    /// pattern.
+    fn next_area(&self, entry: &libc::kinfo_vmentry) -> Option<Result<MemoryArea, Error>> {
         let flags = KvmeProtection::from_bits_truncate(entry.kve_protection);
 
         let mut protection = Protection::empty();
@@ -111,14 +142,22 @@ impl<B: BufRead> Iterator for MemoryAreas<B> {
         let path = if last == 0 {
             None
         } else {
-            let path = match std::str::from_utf8(&path) {
+            let path = match std::str::from_utf8(&path[..last]) {
                 Ok(path) => path,
                 Err(e) => return Some(Err(Error::Utf8(e))),
             };
 
-            Some((Path::new(path).to_path_buf(), offset))
+            Some(path)
         };
 
+        if let Some(pattern) = &self.pattern {
+            if !matches!(path, Some(path) if pattern.matches(path)) {
+                return None;
+            }
+        }
+
+        let path = path.map(|path| (Path::new(path).to_path_buf(), offset));
+
         Some(Ok(MemoryArea {
             range: start..end,
             protection,
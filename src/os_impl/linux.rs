@@ -1,44 +1,55 @@
-use crate::areas::{MemoryArea, Protection, ShareMode};
 use crate::error::Error;
+use nix::errno::Errno;
+use std::fs::File;
+
+#[cfg(feature = "areas")]
+use crate::areas::{MemoryArea, PathPattern, ShareMode};
+#[cfg(feature = "areas")]
+use crate::protection::Protection;
+#[cfg(feature = "areas")]
 use combine::{
-    EasyParser, Parser, Stream,
+    EasyParser, Parser, RangeStream,
     error::ParseError,
-    parser::{
-        char::hex_digit,
-        repeat::many1,
-    },
+    parser::range::take_while1,
     token,
 };
-use std::fs::File;
+#[cfg(feature = "areas")]
 use std::io::{BufRead, BufReader};
-use std::io::Lines;
+#[cfg(feature = "areas")]
 use std::ops::Range;
+#[cfg(feature = "areas")]
 use std::path::PathBuf;
 
-fn hex_digit1<Input>() -> impl Parser<Input, Output = String>
+#[cfg(feature = "areas")]
+fn hex_digit1<Input>() -> impl Parser<Input, Output = Input::Range>
 where
-    Input: Stream<Token = char>,
+    Input: RangeStream<Token = char>,
+    Input::Range: combine::stream::Range + AsRef<str>,
 {
-    many1(hex_digit())
+    take_while1(|c: char| c.is_ascii_hexdigit())
 }
 
+#[cfg(feature = "areas")]
 fn address_range<Input>() -> impl Parser<Input, Output = Range<usize>>
 where
-    Input: Stream<Token = char>,
+    Input: RangeStream<Token = char>,
+    Input::Range: combine::stream::Range + AsRef<str>,
     <Input::Error as ParseError<Input::Token, Input::Range, Input::Position>>::StreamError:
         From<::std::num::ParseIntError>,
 {
     (
-        hex_digit1().and_then(|s| usize::from_str_radix(s.as_str(), 16)),
+        hex_digit1().and_then(|s: Input::Range| usize::from_str_radix(s.as_ref(), 16)),
         token('-'),
-        hex_digit1().and_then(|s| usize::from_str_radix(s.as_str(), 16)),
+        hex_digit1().and_then(|s: Input::Range| usize::from_str_radix(s.as_ref(), 16)),
     )
         .map(|(start, _, end)| start..end)
 }
 
+#[cfg(feature = "areas")]
 fn permissions<Input>() -> impl Parser<Input, Output = (Protection, ShareMode)>
 where
-    Input: Stream<Token = char>,
+    Input: RangeStream<Token = char>,
+    Input::Range: combine::stream::Range + AsRef<str>,
 {
     use combine::parser::{
         char::char,
@@ -54,33 +65,37 @@ where
         .map(|(r, w, x, s)| (r | w | x, s))
 }
 
+#[cfg(feature = "areas")]
 fn device_id<Input>() -> impl Parser<Input, Output = (u8, u8)>
 where
-    Input: Stream<Token = char>,
+    Input: RangeStream<Token = char>,
+    Input::Range: combine::stream::Range + AsRef<str>,
     <Input::Error as ParseError<Input::Token, Input::Range, Input::Position>>::StreamError:
         From<::std::num::ParseIntError>,
 {
     (
-        hex_digit1().and_then(|s| u8::from_str_radix(s.as_str(), 16)),
+        hex_digit1().and_then(|s: Input::Range| u8::from_str_radix(s.as_ref(), 16)),
         token(':'),
-        hex_digit1().and_then(|s| u8::from_str_radix(s.as_str(), 16)),
+        hex_digit1().and_then(|s: Input::Range| u8::from_str_radix(s.as_ref(), 16)),
     )
         .map(|(major, _, minor)| (major, minor))
 }
 
+#[cfg(feature = "areas")]
 fn path<Input>() -> impl Parser<Input, Output = PathBuf>
 where
-    Input: Stream<Token = char>,
+    Input: RangeStream<Token = char>,
+    Input::Range: combine::stream::Range + AsRef<str>,
 {
-    use combine::parser::token::satisfy;
-
-    many1(satisfy(|c| c != '\n'))
-        .map(|s: String| PathBuf::from(s))
+    take_while1(|c| c != '\n')
+        .map(|s: Input::Range| PathBuf::from(s.as_ref()))
 }
 
+#[cfg(feature = "areas")]
 fn memory_region<Input>() -> impl Parser<Input, Output = MemoryArea>
 where
-    Input: Stream<Token = char>,
+    Input: RangeStream<Token = char>,
+    Input::Range: combine::stream::Range + AsRef<str>,
     <Input::Error as ParseError<Input::Token, Input::Range, Input::Position>>::StreamError:
         From<::std::num::ParseIntError>,
 {
@@ -94,7 +109,7 @@ where
         spaces(),
         permissions(),
         spaces(),
-        hex_digit1().and_then(|s| u64::from_str_radix(s.as_str(), 16)),
+        hex_digit1().and_then(|s: Input::Range| u64::from_str_radix(s.as_ref(), 16)),
         spaces(),
         device_id(),
         spaces(),
@@ -118,10 +133,43 @@ where
         })
 }
 
+/// Returns the path of a line of `/proc/[pid]/maps`, if any, by skipping the other fields
+/// without parsing them.
+#[cfg(feature = "areas")]
+fn line_path(line: &str) -> Option<&str> {
+    let mut rest = line;
+
+    for _ in 0..5 {
+        rest = rest.trim_start_matches(' ');
+        rest = &rest[rest.find(' ')?..];
+    }
+
+    match rest.trim_start_matches(' ') {
+        "" => None,
+        path => Some(path),
+    }
+}
+
+/// Returns the address range of a line of `/proc/[pid]/maps` without parsing the other fields.
+#[cfg(feature = "areas")]
+fn line_range(line: &str) -> Option<Range<usize>> {
+    let range = &line[..line.find(' ')?];
+    let (start, end) = range.split_at(range.find('-')?);
+
+    let start = usize::from_str_radix(start, 16).ok()?;
+    let end = usize::from_str_radix(&end[1..], 16).ok()?;
+
+    Some(start..end)
+}
+
+#[cfg(feature = "areas")]
 pub struct MemoryAreas<B> {
-    lines: Lines<B>,
+    reader: B,
+    line: String,
+    pattern: Option<PathPattern>,
 }
 
+#[cfg(feature = "areas")]
 impl MemoryAreas<BufReader<File>> {
     pub fn open(pid: Option<u32>) -> Result<Self, Error> {
         let path = match pid {
@@ -131,31 +179,321 @@ impl MemoryAreas<BufReader<File>> {
 
         let file = File::open(path)?;
         let reader = BufReader::new(file);
-        let lines = reader.lines();
 
         Ok(Self {
-            lines,
+            reader,
+            line: String::new(),
+            pattern: None,
         })
     }
+
+    /// Scans the lines of `/proc/[pid]/maps`, which are sorted by address, until the line
+    /// containing the address, such that only that line is parsed as a whole.
+    pub fn query(pid: Option<u32>, address: usize) -> Result<Option<MemoryArea>, Error> {
+        let mut areas = Self::open(pid)?;
+
+        loop {
+            areas.line.clear();
+
+            if areas.reader.read_line(&mut areas.line)? == 0 {
+                return Ok(None);
+            }
+
+            let line = areas.line.trim_end_matches('\n');
+
+            match line_range(line) {
+                Some(range) if range.end <= address => continue,
+                Some(range) if range.start <= address => (),
+                Some(_) => return Ok(None),
+                None => continue,
+            }
+
+            use combine::stream::position::Stream;
+
+            return Ok(memory_region()
+                .easy_parse(Stream::new(line))
+                .ok()
+                .map(|(region, _)| region));
+        }
+    }
+}
+
+#[cfg(feature = "areas")]
+impl<B> MemoryAreas<B> {
+    pub fn filter_path(&mut self, pattern: PathPattern) {
+        self.pattern = Some(pattern);
+    }
 }
 
+#[cfg(feature = "areas")]
 impl<B: BufRead> Iterator for MemoryAreas<B> {
     type Item = Result<MemoryArea, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let line = match self.lines.next() {
-            Some(Ok(line)) => line,
-            Some(Err(e)) => return Some(Err(Error::Io(e))),
-            None => return None,
+        let line = loop {
+            // Reuse the same line buffer for every line, such that only the path gets allocated.
+            self.line.clear();
+
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => (),
+                Err(e) => return Some(Err(Error::Io(e))),
+            }
+
+            let line = self.line.trim_end_matches('\n');
+
+            match (&self.pattern, line_path(line)) {
+                (None, _) => break line,
+                (Some(pattern), Some(path)) if pattern.matches(path) => break line,
+                _ => continue,
+            }
         };
 
         use combine::stream::position::Stream;
 
-        let result = match memory_region().easy_parse(Stream::new(line.as_str())) {
+        match memory_region().easy_parse(Stream::new(line)) {
             Ok((region, _)) => Some(Ok(region)),
             _ => None,
+        }
+    }
+}
+
+/// `DMA_BUF_IOCTL_SYNC`, i.e. `_IOW('b', 0, struct dma_buf_sync)`.
+const DMA_BUF_IOCTL_SYNC: libc::c_ulong = 0x4008_6200;
+const DMA_BUF_SYNC_END: u64 = 1 << 2;
+
+/// `IOCTL_GNTDEV_MAP_GRANT_REF`, i.e. `_IOC(_IOC_NONE, 'G', 0, 24)`.
+const IOCTL_GNTDEV_MAP_GRANT_REF: libc::c_ulong = 0x0018_4700;
+/// `IOCTL_GNTDEV_UNMAP_GRANT_REF`, i.e. `_IOC(_IOC_NONE, 'G', 1, 16)`.
+const IOCTL_GNTDEV_UNMAP_GRANT_REF: libc::c_ulong = 0x0010_4701;
+
+/// Issues an ioctl, restarting it when interrupted by a signal or when the driver asks to try
+/// again, as the synchronization of dma-bufs may wait on fences.
+fn ioctl<T>(file: &File, request: libc::c_ulong, arg: *mut T) -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        match Errno::result(unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg) }) {
+            Err(Errno::EINTR) | Err(Errno::EAGAIN) => continue,
+            result => return result.map(|_| ()).map_err(Error::Nix),
+        }
+    }
+}
+
+/// Returns the size of a dma-buf, which is reported by seeking to its end.
+pub fn dma_buf_size(file: &File) -> Result<usize, Error> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut file = file;
+    let size = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(size as usize)
+}
+
+/// Begins (or ends if `end` is set) CPU access to a dma-buf, where `access` is a combination of
+/// `DMA_BUF_SYNC_READ` and `DMA_BUF_SYNC_WRITE`.
+pub fn dma_buf_sync(file: &File, access: u64, end: bool) -> Result<(), Error> {
+    let mut flags = access;
+
+    if end {
+        flags |= DMA_BUF_SYNC_END;
+    }
+
+    ioctl(file, DMA_BUF_IOCTL_SYNC, &mut flags)
+}
+
+/// Maps the given grant references of other domains through `/dev/xen/gntdev`, returning the
+/// offset at which they can be mapped.
+pub fn map_grant_refs(file: &File, refs: &[(u32, u32)]) -> Result<u64, Error> {
+    // struct ioctl_gntdev_map_grant_ref { u32 count; u32 pad; u64 index; ... }
+    #[repr(C)]
+    struct MapGrantRef {
+        count: u32,
+        pad: u32,
+        index: u64,
+    }
+
+    // struct ioctl_gntdev_grant_ref { u32 domid; u32 ref; }
+    #[repr(C)]
+    struct GrantRef {
+        domid: u32,
+        reference: u32,
+    }
+
+    // The grant references follow the header, which is 16 bytes, so allocate the structure as
+    // 64-bit words to keep it aligned.
+    let mut buffer = vec![0u64; 2 + refs.len()];
+    let header = buffer.as_mut_ptr() as *mut MapGrantRef;
+
+    unsafe {
+        header.write(MapGrantRef {
+            count: refs.len() as u32,
+            pad: 0,
+            index: 0,
+        });
+
+        let grants = buffer.as_mut_ptr().add(2) as *mut GrantRef;
+
+        for (i, &(domid, reference)) in refs.iter().enumerate() {
+            grants.add(i).write(GrantRef { domid, reference });
+        }
+    }
+
+    ioctl(file, IOCTL_GNTDEV_MAP_GRANT_REF, header)?;
+
+    Ok(unsafe { (*header).index })
+}
+
+/// Unmaps the grant references that were mapped at the given offset, once the mapping has been
+/// unmapped.
+pub fn unmap_grant_refs(file: &File, index: u64, count: u32) -> Result<(), Error> {
+    // struct ioctl_gntdev_unmap_grant_ref { u64 index; u32 count; u32 pad; }
+    #[repr(C)]
+    struct UnmapGrantRef {
+        index: u64,
+        count: u32,
+        pad: u32,
+    }
+
+    let mut unmap = UnmapGrantRef {
+        index,
+        count,
+        pad: 0,
+    };
+
+    ioctl(file, IOCTL_GNTDEV_UNMAP_GRANT_REF, &mut unmap)
+}
+
+/// Creates an anonymous file of the given size using `memfd_create()` with `MFD_ALLOW_SEALING`,
+/// where the name only shows up in `/proc/self/fd` and `/proc/self/maps`.
+pub fn memfd(name: &str, size: usize) -> Result<File, Error> {
+    use std::ffi::CString;
+    use std::os::unix::io::FromRawFd;
+
+    let name = CString::new(name)
+        .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+
+    let fd = Errno::result(unsafe {
+        libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+    })?;
+    let file = unsafe { File::from_raw_fd(fd) };
+
+    file.set_len(size as u64)?;
+
+    Ok(file)
+}
+
+/// Adds the given `F_SEAL_*` seals to the file using `fcntl()` with `F_ADD_SEALS`.
+pub fn add_seals(file: &File, seals: libc::c_int) -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+
+    Errno::result(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) })?;
+
+    Ok(())
+}
+
+/// Returns the `F_SEAL_*` seals of the file using `fcntl()` with `F_GET_SEALS`, which fails with
+/// `EINVAL` for files that do not support sealing.
+pub fn get_seals(file: &File) -> Result<libc::c_int, Error> {
+    use std::os::unix::io::AsRawFd;
+
+    Ok(Errno::result(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) })?)
+}
+
+/// Watches the directory containing a file using inotify, as watching the directory rather than
+/// the file also reports the file being replaced through `rename()` or being recreated.
+pub fn watch_directory(directory: &std::path::Path) -> Result<File, Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let fd = Errno::result(unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) })?;
+    let inotify = unsafe { File::from_raw_fd(fd) };
+
+    let path = CString::new(directory.as_os_str().as_bytes())
+        .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+
+    let mask = libc::IN_MODIFY |
+        libc::IN_ATTRIB |
+        libc::IN_CLOSE_WRITE |
+        libc::IN_CREATE |
+        libc::IN_DELETE |
+        libc::IN_MOVED_FROM |
+        libc::IN_MOVED_TO |
+        libc::IN_DELETE_SELF |
+        libc::IN_MOVE_SELF;
+
+    Errno::result(unsafe { libc::inotify_add_watch(inotify.as_raw_fd(), path.as_ptr(), mask) })?;
+
+    Ok(inotify)
+}
+
+/// Waits up to the given number of milliseconds for inotify events, and returns whether any of
+/// the events concern the file with the given name, or the directory itself.
+pub fn wait_for_changes(
+    inotify: &File,
+    name: &std::ffi::OsStr,
+    timeout: libc::c_int,
+) -> Result<bool, Error> {
+    use std::convert::TryInto;
+    use std::io::Read;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let mut poll = libc::pollfd {
+        fd: inotify.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    match Errno::result(unsafe { libc::poll(&mut poll, 1, timeout) }) {
+        Ok(0) | Err(Errno::EINTR) => return Ok(false),
+        Ok(_) => (),
+        Err(e) => return Err(Error::Nix(e)),
+    }
+
+    // struct inotify_event { int wd; u32 mask; u32 cookie; u32 len; char name[]; }
+    const HEADER: usize = 16;
+
+    let mut buffer = [0u8; 4096];
+    let mut changed = false;
+    let mut inotify = inotify;
+
+    loop {
+        let size = match inotify.read(&mut buffer) {
+            Ok(size) => size,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::Io(e)),
         };
 
-        result
+        let mut offset = 0;
+
+        while offset + HEADER <= size {
+            let field = |index: usize| {
+                let start = offset + index * 4;
+                u32::from_ne_bytes(buffer[start..start + 4].try_into().unwrap())
+            };
+
+            let mask = field(1);
+            let len = field(3) as usize;
+            let end = (offset + HEADER + len).min(size);
+
+            // The name is padded with NUL bytes.
+            let event_name = &buffer[offset + HEADER..end];
+            let event_name = &event_name[..event_name.iter().position(|&b| b == 0).unwrap_or(len)];
+
+            // Events without a name concern the directory itself, or an overflow of the queue.
+            if event_name.is_empty() ||
+                event_name == name.as_bytes() ||
+                mask & libc::IN_Q_OVERFLOW != 0 {
+                changed = true;
+            }
+
+            offset = end;
+        }
     }
+
+    Ok(changed)
 }
@@ -1,4 +1,5 @@
-use crate::areas::{MemoryArea, Protection, ShareMode};
+use crate::areas::{MemoryArea, PathPattern, ShareMode};
+use crate::protection::Protection;
 use crate::error::Error;
 use libc::proc_regionfilename;
 use mach2::{
@@ -21,6 +22,7 @@ pub struct MemoryAreas<B> {
     pid: u32,
     task: mach_port_name_t,
     address: mach_vm_address_t,
+    pattern: Option<PathPattern>,
     marker: PhantomData<B>,
 }
 
@@ -48,89 +50,117 @@ impl MemoryAreas<BufReader<File>> {
             pid: pid.unwrap_or(getpid().as_raw() as _),
             task,
             address: 0,
+            pattern: None,
             marker: PhantomData,
         })
     }
+
+    /// Queries the region at or after the address using `mach_vm_region()`, which only contains
+    /// the address if the address is mapped.
+    pub fn query(pid: Option<u32>, address: usize) -> Result<Option<MemoryArea>, Error> {
+        let mut areas = Self::open(pid)?;
+        areas.address = address as _;
+
+        match areas.next() {
+            Some(Ok(area)) if area.range.contains(&address) => Ok(Some(area)),
+            Some(Err(e)) => Err(e),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    pub fn filter_path(&mut self, pattern: PathPattern) {
+        self.pattern = Some(pattern);
+    }
 }
 
 impl<B: BufRead> Iterator for MemoryAreas<B> {
     type Item = Result<MemoryArea, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut size = 0;
-        let mut info: vm_region_basic_info_64 = unsafe { std::mem::zeroed() };
-
-        let result = unsafe {
-            mach_vm_region(
-                self.task,
-                &mut self.address,
-                &mut size,
-                VM_REGION_BASIC_INFO_64,
-                (&mut info as *mut _) as vm_region_info_t,
-                &mut vm_region_basic_info_64::count(),
-                &mut 0,
-            )
-        };
+        loop {
+            let mut size = 0;
+            let mut info: vm_region_basic_info_64 = unsafe { std::mem::zeroed() };
 
-        match result {
-            KERN_INVALID_ADDRESS => None,
-            KERN_SUCCESS => {
-                let start = self.address as usize;
-                let end = start + size as usize;
-                let range = start..end;
+            let result = unsafe {
+                mach_vm_region(
+                    self.task,
+                    &mut self.address,
+                    &mut size,
+                    VM_REGION_BASIC_INFO_64,
+                    (&mut info as *mut _) as vm_region_info_t,
+                    &mut vm_region_basic_info_64::count(),
+                    &mut 0,
+                )
+            };
 
-                let mut protection = Protection::empty();
+            return match result {
+                KERN_INVALID_ADDRESS => None,
+                KERN_SUCCESS => {
+                    let start = self.address as usize;
+                    let end = start + size as usize;
+                    let range = start..end;
 
-                if info.protection & VM_PROT_READ == VM_PROT_READ {
-                    protection |= Protection::READ;
-                }
+                    let mut protection = Protection::empty();
 
-                if info.protection & VM_PROT_WRITE == VM_PROT_WRITE {
-                    protection |= Protection::WRITE;
-                }
+                    if info.protection & VM_PROT_READ == VM_PROT_READ {
+                        protection |= Protection::READ;
+                    }
 
-                if info.protection & VM_PROT_EXECUTE == VM_PROT_EXECUTE {
-                    protection |= Protection::EXECUTE;
-                }
+                    if info.protection & VM_PROT_WRITE == VM_PROT_WRITE {
+                        protection |= Protection::WRITE;
+                    }
+
+                    if info.protection & VM_PROT_EXECUTE == VM_PROT_EXECUTE {
+                        protection |= Protection::EXECUTE;
+                    }
 
-                let share_mode = if info.shared != 0 {
-                    ShareMode::Shared
-                } else {
-                    ShareMode::Private
-                };
-
-                let mut bytes = [0u8; libc::PATH_MAX as _];
-
-                let result = unsafe {
-                    proc_regionfilename(
-                        self.pid as _,
-                        self.address,
-                        bytes.as_mut_ptr() as _,
-                        bytes.len() as _,
-                    )
-                };
-
-                let path = if result == 0 {
-                    None
-                } else {
-                    let path = match std::str::from_utf8(&bytes[..result as usize]) {
-                        Ok(path) => path,
-                        Err(e) => return Some(Err(Error::Utf8(e))),
+                    let share_mode = if info.shared != 0 {
+                        ShareMode::Shared
+                    } else {
+                        ShareMode::Private
                     };
 
-                    Some((Path::new(path).to_path_buf(), info.offset as u64))
-                };
+                    let mut bytes = [0u8; libc::PATH_MAX as _];
 
-                self.address = self.address.saturating_add(size);
+                    let result = unsafe {
+                        proc_regionfilename(
+                            self.pid as _,
+                            self.address,
+                            bytes.as_mut_ptr() as _,
+                            bytes.len() as _,
+                        )
+                    };
 
-                Some(Ok(MemoryArea {
-                    range,
-                    protection,
-                    share_mode,
-                    path,
-                }))
-            }
-            _ => Some(Err(Error::Mach(result))),
+                    let path = if result == 0 {
+                        None
+                    } else {
+                        match std::str::from_utf8(&bytes[..result as usize]) {
+                            Ok(path) => Some(path),
+                            Err(e) => return Some(Err(Error::Utf8(e))),
+                        }
+                    };
+
+                    self.address = self.address.saturating_add(size);
+
+                    if let Some(pattern) = &self.pattern {
+                        if !matches!(path, Some(path) if pattern.matches(path)) {
+                            continue;
+                        }
+                    }
+
+                    let path = path.map(|path| (Path::new(path).to_path_buf(), info.offset as u64));
+
+                    Some(Ok(MemoryArea {
+                        range,
+                        protection,
+                        share_mode,
+                        path,
+                    }))
+                }
+                _ => Some(Err(Error::Mach(result))),
+            };
         }
     }
 }
@@ -1,14 +1,70 @@
-#[cfg(unix)]
+#[cfg(feature = "fake")]
+pub mod fake;
+
+#[cfg(all(unix, not(feature = "fake")))]
 pub mod unix;
 
-#[cfg(windows)]
+#[cfg(all(windows, not(feature = "fake")))]
 pub mod windows;
 
-#[cfg(target_os = "freebsd")]
+#[cfg(any(unix, windows))]
+pub mod file;
+
+#[cfg(any(unix, windows))]
+pub mod process;
+
+#[cfg(all(target_os = "freebsd", feature = "areas"))]
 pub mod freebsd;
 
 #[cfg(target_os = "linux")]
 pub mod linux;
 
-#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[cfg(all(any(target_os = "macos", target_os = "ios"), feature = "areas"))]
 pub mod macos;
+
+#[cfg(all(target_os = "netbsd", feature = "areas"))]
+pub mod netbsd;
+
+#[cfg(all(target_os = "openbsd", feature = "areas"))]
+pub mod openbsd;
+
+/// Overwrites the memory with zeroes using volatile writes, such that the writes cannot be elided
+/// even though the memory is about to be unmapped.
+///
+/// # Safety
+///
+/// The memory must be writable and must not be accessed through any references.
+pub(crate) unsafe fn scrub(ptr: *mut u8, size: usize) {
+    // Use word-sized writes for the bulk of the memory, as the pointer is page-aligned.
+    let words = size / std::mem::size_of::<usize>();
+
+    for index in 0..words {
+        std::ptr::write_volatile((ptr as *mut usize).add(index), 0);
+    }
+
+    for index in words * std::mem::size_of::<usize>()..size {
+        std::ptr::write_volatile(ptr.add(index), 0);
+    }
+}
+
+/// Faults in the pages in the given range by accessing a byte of every page. Pages are faulted in
+/// for writing by writing back the byte that has been read, which leaves the contents intact.
+///
+/// # Safety
+///
+/// The memory must be readable, and writable as well if `write` is set, in which case no other
+/// thread may access the memory concurrently.
+#[cfg(not(feature = "fake"))]
+pub(crate) unsafe fn touch(ptr: *mut u8, size: usize, write: bool) {
+    let page_size = crate::page_size();
+
+    for offset in (0..size).step_by(page_size) {
+        let byte = ptr.add(offset);
+
+        if write {
+            std::ptr::write_volatile(byte, std::ptr::read_volatile(byte));
+        } else {
+            std::ptr::read_volatile(byte);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::areas::{MemoryArea, PathPattern, ShareMode};
+use crate::protection::Protection;
+use crate::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::marker::PhantomData;
+use std::path::Path;
+
+pub struct MemoryAreas<B> {
+    entries: Vec<libc::kinfo_vmentry>,
+    index: usize,
+    pattern: Option<PathPattern>,
+    marker: PhantomData<B>,
+}
+
+impl MemoryAreas<BufReader<File>> {
+    pub fn open(pid: Option<u32>) -> Result<Self, Error> {
+        // Default to the current process if no PID was specified.
+        let pid = match pid {
+            Some(pid) => pid as _,
+            _ => unsafe { libc::getpid() },
+        };
+
+        let mut count = 0;
+        let entries_ptr = unsafe {
+            libc::kinfo_getvmmap(pid, &mut count)
+        };
+
+        if entries_ptr.is_null() {
+            return Err(std::io::Error::last_os_error())?;
+        }
+
+        let entries = unsafe {
+            core::slice::from_raw_parts(entries_ptr, count)
+        }.to_vec();
+
+        unsafe {
+            libc::free(entries_ptr as *mut core::ffi::c_void);
+        }
+
+        Ok(Self {
+            entries,
+            index: 0,
+            pattern: None,
+            marker: PhantomData,
+        })
+    }
+
+    pub fn query(pid: Option<u32>, address: usize) -> Result<Option<MemoryArea>, Error> {
+        let areas = Self::open(pid)?;
+
+        let entry = areas.entries
+            .iter()
+            .find(|entry| entry.kve_start as usize <= address && address < entry.kve_end as usize);
+
+        entry.and_then(|entry| areas.next_area(entry)).transpose()
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    pub fn filter_path(&mut self, pattern: PathPattern) {
+        self.pattern = Some(pattern);
+    }
+}
+
+impl<B: BufRead> Iterator for MemoryAreas<B> {
+    type Item = Result<MemoryArea, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.index >= self.entries.len() {
+                return None;
+            }
+
+            let entry = &self.entries[self.index];
+            self.index += 1;
+
+            if let Some(area) = self.next_area(entry) {
+                return Some(area);
+            }
+        }
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    /// Converts the entry into a memory area, unless the path of the entry does not match the
+    /// pattern.
+    fn next_area(&self, entry: &libc::kinfo_vmentry) -> Option<Result<MemoryArea, Error>> {
+        let flags = entry.kve_protection as libc::c_int;
+
+        let mut protection = Protection::empty();
+
+        if flags & libc::KVME_PROT_READ != 0 {
+            protection |= Protection::READ;
+        }
+
+        if flags & libc::KVME_PROT_WRITE != 0 {
+            protection |= Protection::WRITE;
+        }
+
+        if flags & libc::KVME_PROT_EXEC != 0 {
+            protection |= Protection::EXECUTE;
+        }
+
+        // Shared mappings are the only ones that are shared with child processes by default.
+        let share_mode = if entry.kve_flags as libc::c_int & libc::KVME_FLAG_COW != 0 {
+            ShareMode::CopyOnWrite
+        } else if entry.kve_inheritance as libc::c_int == libc::MAP_INHERIT_SHARE {
+            ShareMode::Shared
+        } else {
+            ShareMode::Private
+        };
+
+        let start = entry.kve_start as usize;
+        let end = entry.kve_end as usize;
+        let offset = entry.kve_offset;
+
+        // Parse the path.
+        let path: Vec<u8> = entry.kve_path.iter().map(|byte| *byte as u8).collect();
+
+        let last = match path.iter().position(|&c| c == 0) {
+            Some(end) => end,
+            _ => path.len(),
+        };
+
+        let path = if last == 0 {
+            None
+        } else {
+            let path = match std::str::from_utf8(&path[..last]) {
+                Ok(path) => path,
+                Err(e) => return Some(Err(Error::Utf8(e))),
+            };
+
+            Some(path)
+        };
+
+        if let Some(pattern) = &self.pattern {
+            if !matches!(path, Some(path) if pattern.matches(path)) {
+                return None;
+            }
+        }
+
+        let path = path.map(|path| (Path::new(path).to_path_buf(), offset));
+
+        Some(Ok(MemoryArea {
+            range: start..end,
+            protection,
+            share_mode,
+            path,
+        }))
+    }
+}
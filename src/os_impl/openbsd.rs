@@ -0,0 +1,158 @@
+use crate::areas::{MemoryArea, PathPattern, ShareMode};
+use crate::protection::Protection;
+use crate::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::marker::PhantomData;
+
+/// The number of entries that are requested from the kernel at once.
+const BATCH_SIZE: usize = 64;
+
+pub struct MemoryAreas<B> {
+    entries: Vec<libc::kinfo_vmentry>,
+    index: usize,
+    pattern: Option<PathPattern>,
+    marker: PhantomData<B>,
+}
+
+impl MemoryAreas<BufReader<File>> {
+    /// Reads the entries using `sysctl()` with `KERN_PROC_VMMAP`, which fills the buffer with the
+    /// entries starting at the address of the first entry in the buffer, and fails with `ENOMEM`
+    /// if there are more entries than fit in the buffer.
+    pub fn open(pid: Option<u32>) -> Result<Self, Error> {
+        // Default to the current process if no PID was specified.
+        let pid = match pid {
+            Some(pid) => pid as _,
+            _ => unsafe { libc::getpid() },
+        };
+
+        let mib = [libc::CTL_KERN, libc::KERN_PROC_VMMAP, pid];
+        let mut buffer: Vec<libc::kinfo_vmentry> = vec![unsafe { std::mem::zeroed() }; BATCH_SIZE];
+        let mut entries = vec![];
+        let mut start = 0;
+
+        loop {
+            buffer[0].kve_start = start;
+
+            let mut len = buffer.len() * std::mem::size_of::<libc::kinfo_vmentry>();
+
+            let status = unsafe {
+                libc::sysctl(
+                    mib.as_ptr(),
+                    mib.len() as libc::c_uint,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    &mut len,
+                    std::ptr::null_mut(),
+                    0,
+                )
+            };
+
+            let more = if status < 0 {
+                let error = std::io::Error::last_os_error();
+
+                if error.raw_os_error() != Some(libc::ENOMEM) {
+                    return Err(error)?;
+                }
+
+                true
+            } else {
+                false
+            };
+
+            let count = len / std::mem::size_of::<libc::kinfo_vmentry>();
+
+            if count == 0 {
+                break;
+            }
+
+            entries.extend_from_slice(&buffer[..count]);
+            start = buffer[count - 1].kve_end;
+
+            if !more {
+                break;
+            }
+        }
+
+        Ok(Self {
+            entries,
+            index: 0,
+            pattern: None,
+            marker: PhantomData,
+        })
+    }
+
+    pub fn query(pid: Option<u32>, address: usize) -> Result<Option<MemoryArea>, Error> {
+        let areas = Self::open(pid)?;
+
+        let entry = areas.entries
+            .iter()
+            .find(|entry| entry.kve_start as usize <= address && address < entry.kve_end as usize);
+
+        entry.and_then(|entry| areas.next_area(entry)).transpose()
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    pub fn filter_path(&mut self, pattern: PathPattern) {
+        self.pattern = Some(pattern);
+    }
+}
+
+impl<B: BufRead> Iterator for MemoryAreas<B> {
+    type Item = Result<MemoryArea, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.index >= self.entries.len() {
+                return None;
+            }
+
+            let entry = &self.entries[self.index];
+            self.index += 1;
+
+            if let Some(area) = self.next_area(entry) {
+                return Some(area);
+            }
+        }
+    }
+}
+
+impl<B> MemoryAreas<B> {
+    /// Converts the entry into a memory area. OpenBSD does not expose the paths of the files
+    /// backing the memory areas, so no memory area matches a pattern.
+    fn next_area(&self, entry: &libc::kinfo_vmentry) -> Option<Result<MemoryArea, Error>> {
+        if self.pattern.is_some() || entry.kve_etype & libc::KVE_ET_HOLE != 0 {
+            return None;
+        }
+
+        let mut protection = Protection::empty();
+
+        if entry.kve_protection & libc::KVE_PROT_READ != 0 {
+            protection |= Protection::READ;
+        }
+
+        if entry.kve_protection & libc::KVE_PROT_WRITE != 0 {
+            protection |= Protection::WRITE;
+        }
+
+        if entry.kve_protection & libc::KVE_PROT_EXEC != 0 {
+            protection |= Protection::EXECUTE;
+        }
+
+        // Shared mappings are the only ones that are shared with child processes by default.
+        let share_mode = if entry.kve_etype & libc::KVE_ET_COPYONWRITE != 0 {
+            ShareMode::CopyOnWrite
+        } else if entry.kve_inheritance == libc::KVE_INH_SHARE {
+            ShareMode::Shared
+        } else {
+            ShareMode::Private
+        };
+
+        Some(Ok(MemoryArea {
+            range: entry.kve_start as usize..entry.kve_end as usize,
+            protection,
+            share_mode,
+            path: None,
+        }))
+    }
+}
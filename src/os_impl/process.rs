@@ -0,0 +1,343 @@
+//! This module implements the helpers for the current process and for other processes that the
+//! platform backends share with the in-memory backend of the `fake` feature, as they do not
+//! depend on the mappings of the backend.
+
+use crate::error::Error;
+
+#[cfg(unix)]
+use nix::errno::Errno;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use nix::unistd::getpid;
+
+#[cfg(windows)]
+use windows::core::PCWSTR;
+#[cfg(windows)]
+use windows::Win32::Foundation::{CloseHandle, ERROR_NOT_ALL_ASSIGNED, HANDLE, LUID};
+#[cfg(windows)]
+use windows::Win32::Security::{
+    AdjustTokenPrivileges, GetTokenInformation, LookupPrivilegeValueW, TokenPrivileges,
+    LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
+    TOKEN_QUERY,
+};
+#[cfg(windows)]
+use windows::Win32::System::ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+#[cfg(windows)]
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+/// Reads the memory of a process using `process_vm_readv()`, which fails rather than faulting if
+/// the pages are not readable, so it is used for the current process as well.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub struct ProcessMemory {
+    pid: libc::pid_t,
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl ProcessMemory {
+    pub fn open(pid: Option<u32>) -> Result<Self, Error> {
+        let pid = match pid {
+            Some(pid) => pid as libc::pid_t,
+            _ => getpid().as_raw(),
+        };
+
+        Ok(Self {
+            pid,
+        })
+    }
+
+    pub fn read(&self, address: usize, buffer: &mut [u8]) -> Result<usize, Error> {
+        let local = libc::iovec {
+            iov_base: buffer.as_mut_ptr() as *mut std::ffi::c_void,
+            iov_len: buffer.len(),
+        };
+
+        let remote = libc::iovec {
+            iov_base: address as *mut std::ffi::c_void,
+            iov_len: buffer.len(),
+        };
+
+        let count = unsafe {
+            libc::process_vm_readv(self.pid, &local, 1, &remote, 1, 0)
+        };
+
+        Ok(Errno::result(count)? as usize)
+    }
+}
+
+/// Reading the memory of a process is only supported on Linux.
+#[cfg(all(unix, not(any(target_os = "android", target_os = "linux"))))]
+pub enum ProcessMemory {}
+
+#[cfg(all(unix, not(any(target_os = "android", target_os = "linux"))))]
+impl ProcessMemory {
+    pub fn open(_pid: Option<u32>) -> Result<Self, Error> {
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "reading the memory of a process is not supported on this platform",
+        )))
+    }
+
+    pub fn read(&self, _address: usize, _buffer: &mut [u8]) -> Result<usize, Error> {
+        match *self {}
+    }
+}
+
+/// Returns the number of minor and major page faults of the current thread, or of the whole
+/// process on platforms without `RUSAGE_THREAD`.
+#[cfg(unix)]
+pub fn page_faults() -> Result<(u64, u64), Error> {
+    #[cfg(any(target_os = "freebsd", target_os = "linux", target_os = "openbsd"))]
+    let who = libc::RUSAGE_THREAD;
+    #[cfg(not(any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")))]
+    let who = libc::RUSAGE_SELF;
+
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+
+    Errno::result(unsafe { libc::getrusage(who, &mut usage) })?;
+
+    Ok((usage.ru_minflt as u64, usage.ru_majflt as u64))
+}
+
+/// Reads the memory of a process using `ReadProcessMemory()`, which fails rather than faulting if
+/// the pages are not readable, so it is used for the current process as well.
+#[cfg(windows)]
+pub struct ProcessMemory {
+    handle: HANDLE,
+    /// Whether the handle has to be closed, which is not the case for the pseudo handle of the
+    /// current process.
+    owned: bool,
+}
+
+#[cfg(windows)]
+unsafe impl Send for ProcessMemory {}
+#[cfg(windows)]
+unsafe impl Sync for ProcessMemory {}
+
+#[cfg(windows)]
+impl ProcessMemory {
+    pub fn open(pid: Option<u32>) -> Result<Self, Error> {
+        use windows::Win32::System::Threading::{
+            OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ,
+        };
+
+        let pid = match pid {
+            Some(pid) => pid,
+            _ => return Ok(Self {
+                handle: unsafe { GetCurrentProcess() },
+                owned: false,
+            }),
+        };
+
+        let handle = unsafe {
+            OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, false, pid)
+        }?;
+
+        Ok(Self {
+            handle,
+            owned: true,
+        })
+    }
+
+    /// `ReadProcessMemory()` fails with `ERROR_PARTIAL_COPY` if only part of the range could be
+    /// read, in which case the number of bytes read is returned instead.
+    pub fn read(&self, address: usize, buffer: &mut [u8]) -> Result<usize, Error> {
+        use windows::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+
+        let mut count = 0;
+
+        let status = unsafe {
+            ReadProcessMemory(
+                self.handle,
+                address as *const std::ffi::c_void,
+                buffer.as_mut_ptr() as *mut std::ffi::c_void,
+                buffer.len(),
+                &mut count,
+            )
+        }.as_bool();
+
+        if !status && count == 0 {
+            return Err(std::io::Error::last_os_error())?;
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ProcessMemory {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe {
+                CloseHandle(self.handle)
+            };
+        }
+    }
+}
+
+/// Returns the number of page faults of the whole process, as Microsoft Windows neither counts
+/// the page faults per thread nor tells soft and hard page faults apart, such that all of them are
+/// reported as minor page faults.
+#[cfg(windows)]
+pub fn page_faults() -> Result<(u64, u64), Error> {
+    let mut counters = PROCESS_MEMORY_COUNTERS {
+        cb: std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        ..Default::default()
+    };
+
+    let process = unsafe { GetCurrentProcess() };
+
+    if !unsafe { K32GetProcessMemoryInfo(process, &mut counters, counters.cb) }.as_bool() {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    Ok((counters.PageFaultCount as u64, 0))
+}
+
+/// Looks up the locally unique identifier of the `SeLockMemoryPrivilege` privilege.
+#[cfg(windows)]
+fn lock_memory_privilege() -> Result<LUID, Error> {
+    let name = widestring::U16CString::from_str("SeLockMemoryPrivilege").unwrap();
+    let mut luid = LUID::default();
+
+    let status = unsafe {
+        LookupPrivilegeValueW(PCWSTR::null(), PCWSTR(name.as_ptr()), &mut luid)
+    }.as_bool();
+
+    if !status {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    Ok(luid)
+}
+
+/// Checks whether the `SeLockMemoryPrivilege` privilege, which is needed to allocate large pages,
+/// is enabled in the access token of the current process.
+#[cfg(windows)]
+fn has_lock_memory_privilege() -> Result<bool, Error> {
+    let luid = lock_memory_privilege()?;
+    let mut token = HANDLE::default();
+
+    let status = unsafe {
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)
+    }.as_bool();
+
+    if !status {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    // Query the size of the privileges first, and then query the privileges themselves. The
+    // buffer consists of `u64`s to satisfy the alignment of `TOKEN_PRIVILEGES`.
+    let mut size = 0;
+
+    unsafe {
+        GetTokenInformation(token, TokenPrivileges, std::ptr::null_mut(), 0, &mut size)
+    };
+
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+
+    let status = unsafe {
+        GetTokenInformation(
+            token,
+            TokenPrivileges,
+            buffer.as_mut_ptr() as *mut std::ffi::c_void,
+            size,
+            &mut size,
+        )
+    }.as_bool();
+
+    let result = if status {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    };
+
+    unsafe {
+        CloseHandle(token)
+    };
+
+    result?;
+
+    let privileges = unsafe {
+        let header = &*(buffer.as_ptr() as *const TOKEN_PRIVILEGES);
+
+        std::slice::from_raw_parts(
+            header.Privileges.as_ptr() as *const LUID_AND_ATTRIBUTES,
+            header.PrivilegeCount as usize,
+        )
+    };
+
+    Ok(privileges.iter().any(|privilege| {
+        privilege.Luid.LowPart == luid.LowPart &&
+            privilege.Luid.HighPart == luid.HighPart &&
+            privilege.Attributes & SE_PRIVILEGE_ENABLED == SE_PRIVILEGE_ENABLED
+    }))
+}
+
+/// Enables the `SeLockMemoryPrivilege` privilege in the access token of the current process, if
+/// it is not enabled already. `AdjustTokenPrivileges()` can only enable privileges that have been
+/// granted to the user, in which case it succeeds with `ERROR_NOT_ALL_ASSIGNED`.
+#[cfg(windows)]
+pub fn enable_lock_memory_privilege() -> Result<(), Error> {
+    if has_lock_memory_privilege()? {
+        return Ok(());
+    }
+
+    let luid = lock_memory_privilege()?;
+    let mut token = HANDLE::default();
+
+    let status = unsafe {
+        OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY, &mut token)
+    }.as_bool();
+
+    if !status {
+        return Err(Error::HugePagesUnavailable(format!(
+            "the access token of the process cannot be opened to enable the SeLockMemoryPrivilege \
+            privilege: {}",
+            std::io::Error::last_os_error(),
+        )));
+    }
+
+    let privileges = TOKEN_PRIVILEGES {
+        PrivilegeCount: 1,
+        Privileges: [LUID_AND_ATTRIBUTES {
+            Luid: luid,
+            Attributes: SE_PRIVILEGE_ENABLED,
+        }],
+    };
+
+    let status = unsafe {
+        AdjustTokenPrivileges(
+            token,
+            false,
+            &privileges,
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    }.as_bool();
+
+    // AdjustTokenPrivileges() sets the last error even if it succeeds.
+    let error = std::io::Error::last_os_error();
+
+    unsafe {
+        CloseHandle(token)
+    };
+
+    let result = if !status {
+        Err(Error::HugePagesUnavailable(format!(
+            "the SeLockMemoryPrivilege privilege cannot be enabled: {}",
+            error,
+        )))
+    } else if error.raw_os_error() == Some(ERROR_NOT_ALL_ASSIGNED.0 as i32) {
+        Err(Error::HugePagesUnavailable(
+            "the \"Lock pages in memory\" user right has not been granted to the user, grant it \
+            through the Local Security Policy and sign in again, or run the process as a user \
+            that holds it".to_string(),
+        ))
+    } else {
+        Ok(())
+    };
+
+    trace_op!("enable_lock_memory_privilege", result, luid = luid.LowPart);
+
+    result
+}
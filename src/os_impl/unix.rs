@@ -1,11 +1,23 @@
 use bitflags::bitflags;
-use crate::{MmapFlags, PageSize, UnsafeMmapFlags};
-use crate::error::Error;
+use crate::{Advice, DropBehavior, Eviction, FileLock, LockAllFlags, MmapFlags, PageSize, UnsafeMmapFlags};
+use crate::capabilities::Capabilities;
+use crate::reservation::{Shared, Slot};
+use crate::error::{Error, OutOfMemoryCause};
+use crate::mmap::check_address_range;
+use crate::protection::Protection;
+use crate::stats::Record;
+
+pub use super::file::{lock_file_range, read_exact_at, unlock_file_range};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, flock, FcntlArg, FlockArg, OFlag};
+use nix::sys::resource::{getrlimit, Resource};
 use nix::sys::mman::*;
 use nix::unistd::*;
 use std::fs::File;
 use std::ops::Range;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
 
 #[cfg(target_os = "ios")]
 extern "C" {
@@ -18,314 +30,2669 @@ extern "C" {
     fn __clear_cache(start: *mut core::ffi::c_void, end: *mut core::ffi::c_void);
 }
 
+/// Flushes the instruction cache for the given range.
+///
+/// # Safety
+///
+/// The range must be mapped.
+#[cfg(target_os = "ios")]
+unsafe fn flush_icache(ptr: *mut u8, size: usize) {
+    sys_icache_invalidate(ptr as *mut std::ffi::c_void, size)
+}
+
+/// Flushes the instruction cache for the given range.
+///
+/// # Safety
+///
+/// The range must be mapped.
+#[cfg(not(target_os = "ios"))]
+unsafe fn flush_icache(ptr: *mut u8, size: usize) {
+    __clear_cache(ptr as *mut std::ffi::c_void, ptr.add(size) as *mut std::ffi::c_void)
+}
+
+/// The flag to request uninitialized anonymous memory, which is not exposed by nix.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+const MAP_UNINITIALIZED: libc::c_int = 0x400_0000;
+
 bitflags! {
     struct Flags: u32 {
-        const JIT = 1 << 0;
+        const JIT           = 1 << 0;
+        const COPY_ON_WRITE = 1 << 1;
+        const ZERO_ON_DROP  = 1 << 2;
+        const LOCKED        = 1 << 3;
+        const CHECK_MEMORY_LIMIT = 1 << 4;
+        const DEVICE        = 1 << 5;
+        const SEALED        = 1 << 6;
     }
 }
 
 pub struct Mmap {
     file: Option<File>,
+    offset: u64,
     ptr: *mut u8,
     size: usize,
+    /// The offset of the mapping within its first page, as the offset within the file has been
+    /// rounded down to the page size. The pointer, the size and the offset above cover the pages.
+    skew: usize,
     flags: Flags,
+    /// The protection of the mapping, as the pages have to be remapped with the same protection.
+    protect: AtomicI32,
+    drop_behavior: DropBehavior,
+    raw_prot: ProtFlags,
+    file_locked: bool,
+    /// The range of the reservation that the mapping has been mapped in, if any, which is boxed as
+    /// most mappings are not part of a reservation.
+    slot: Option<Box<Slot>>,
+    record: Record,
 }
 
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
 impl Mmap {
     #[inline]
     pub fn file(&self) -> Option<&File> {
         self.file.as_ref()
     }
 
+    /// Returns the offset within the file at which the mapping starts.
+    #[inline]
+    pub fn file_offset(&self) -> u64 {
+        self.offset + self.skew as u64
+    }
+
     #[inline]
     pub fn as_ptr(&self) -> *const u8 {
-        self.ptr
+        unsafe { self.ptr.add(self.skew) }
     }
 
     #[inline]
     pub fn as_mut_ptr(&self) -> *mut u8 {
-        self.ptr
+        unsafe { self.ptr.add(self.skew) }
     }
 
     #[inline]
     pub fn size(&self) -> usize {
-        self.size
+        self.size - self.skew
+    }
+
+    /// Translates the range of the mapping into the range of the pages that cover it, relative to
+    /// the start of the pages, as the system calls require the start to be page-aligned.
+    fn page_range(&self, range: Range<usize>) -> Range<usize> {
+        let end = (range.end + self.skew).min(crate::align_up(self.size));
+
+        crate::align_down(range.start + self.skew)..end
+    }
+
+    /// The flags that carry over to an anonymous copy of the mapping.
+    pub fn anon_flags(&self) -> MmapFlags {
+        let mut flags = MmapFlags::empty();
+
+        if self.flags.contains(Flags::LOCKED) {
+            flags |= MmapFlags::LOCKED;
+        }
+
+        if self.flags.contains(Flags::ZERO_ON_DROP) {
+            flags |= MmapFlags::ZERO_ON_DROP;
+        }
+
+        if self.flags.contains(Flags::CHECK_MEMORY_LIMIT) {
+            flags |= MmapFlags::CHECK_MEMORY_LIMIT;
+        }
+
+        flags
+    }
+
+    /// Checks whether the whole mapping fits within the memory limits, if requested.
+    pub fn check_memory_limit(&self) -> Result<(), Error> {
+        if self.flags.contains(Flags::CHECK_MEMORY_LIMIT) {
+            check_memory_limit(self.size)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn protection(&self) -> Protection {
+        to_protection(ProtFlags::from_bits_truncate(self.protect.load(Ordering::Relaxed)))
+    }
+
+    /// Looks up the memory areas overlapping the range, as the pages may have been protected
+    /// through other means than this crate.
+    #[cfg(feature = "areas")]
+    pub fn protection_of(&self, range: Range<usize>) -> Result<Protection, Error> {
+        let start = self.as_ptr() as usize + range.start;
+        let end = self.as_ptr() as usize + range.end;
+        let mut protection = Protection::all();
+
+        for area in crate::areas::MemoryAreas::open(None)? {
+            let area = area?;
+
+            if area.end() <= start || area.start() >= end {
+                continue;
+            }
+
+            protection &= area.protection();
+        }
+
+        Ok(protection)
+    }
+
+    /// The memory areas of the process cannot be looked up without the `areas` feature.
+    #[cfg(not(feature = "areas"))]
+    pub fn protection_of(&self, _range: Range<usize>) -> Result<Protection, Error> {
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "querying the protection requires the areas feature",
+        )))
     }
 
     pub fn lock(&mut self) -> Result<(), Error> {
-        unsafe {
+        self.check_memory_limit()?;
+
+        let result = unsafe {
             mlock(
                 self.ptr as *const std::ffi::c_void,
                 self.size,
-            )?;
-        }
+            )
+        };
+
+        trace_op!("lock", result, address = self.ptr, size = self.size);
+
+        result.map_err(|e| lock_error(e, self.size))?;
+        self.flags |= Flags::LOCKED;
+        self.record.lock();
 
         Ok(())
     }
 
     pub fn unlock(&mut self) -> Result<(), Error> {
-        unsafe {
+        let result = unsafe {
             munlock(
                 self.ptr as *const std::ffi::c_void,
                 self.size,
-            )?;
-        }
+            )
+        };
+
+        trace_op!("unlock", result, address = self.ptr, size = self.size);
+
+        result?;
+        self.flags.remove(Flags::LOCKED);
+        self.record.unlock();
 
         Ok(())
     }
 
+    /// Locks or unlocks the pages in the given range, without marking the mapping as locked, such
+    /// that large mappings can be locked one chunk at a time.
+    pub fn lock_pages(&self, range: Range<usize>, lock: bool) -> Result<(), Error> {
+        let range = self.page_range(range);
+        let address = unsafe { self.ptr.add(range.start) } as *const std::ffi::c_void;
+        let size = range.end - range.start;
+
+        let result = unsafe {
+            if lock {
+                mlock(address, size)
+            } else {
+                munlock(address, size)
+            }
+        };
+
+        trace_op!("lock_pages", result, address = self.ptr, range = range, lock = lock);
+
+        result.map_err(|e| lock_error(e, size))
+    }
+
+    /// Marks the mapping as locked, after all of its pages have been locked using `lock_pages()`.
+    pub fn set_locked(&mut self) {
+        self.flags |= Flags::LOCKED;
+        self.record.lock();
+    }
+
     pub fn flush(&self, range: Range<usize>) -> Result<(), Error> {
-        unsafe {
+        let range = self.page_range(range);
+
+        let result = unsafe {
             msync(
-                self.ptr.offset(range.start as isize) as *mut std::ffi::c_void,
+                self.ptr.add(range.start) as *mut std::ffi::c_void,
                 range.end - range.start,
                 MsFlags::MS_SYNC,
             )
-        }?;
+        };
 
-        Ok(())
+        trace_op!("flush", result, address = self.ptr, range = range);
+
+        Ok(result?)
     }
 
     pub fn flush_async(&self, range: Range<usize>) -> Result<(), Error> {
-        unsafe {
+        let range = self.page_range(range);
+
+        let result = unsafe {
             msync(
-                self.ptr.offset(range.start as isize) as *mut std::ffi::c_void,
+                self.ptr.add(range.start) as *mut std::ffi::c_void,
                 range.end - range.start,
                 MsFlags::MS_ASYNC,
             )
+        };
+
+        trace_op!("flush_async", result, address = self.ptr, range = range);
+
+        Ok(result?)
+    }
+
+    /// Flushes the sorted, page-aligned ranges of the mapping. When flushing synchronously, the
+    /// pages of a file mapping are only scheduled for writeback per range, and the file is synced
+    /// once afterwards, as syncing every range would write back the metadata of the file every
+    /// time.
+    pub fn flush_ranges(&self, ranges: &[Range<usize>], sync: bool) -> Result<(), Error> {
+        let flags = match self.file {
+            Some(_) => MsFlags::MS_ASYNC,
+            None if sync => MsFlags::MS_SYNC,
+            None => MsFlags::MS_ASYNC,
+        };
+
+        let mut result = ranges.iter().try_for_each(|range| unsafe {
+            let range = self.page_range(range.clone());
+
+            msync(
+                self.ptr.add(range.start) as *mut std::ffi::c_void,
+                range.end - range.start,
+                flags,
+            )
+        }).map_err(std::io::Error::from);
+
+        if let (Ok(()), Some(file), true) = (&result, &self.file, sync) {
+            result = file.sync_data();
+        }
+
+        trace_op!("flush_ranges", result, address = self.ptr, ranges = ranges.len(), sync = sync);
+
+        Ok(result?)
+    }
+
+    /// Advises the operating system whether the pages of the mapping will be accessed sequentially.
+    /// The advice is always given for the whole mapping, as advising parts of the mapping splits
+    /// the mapping in the kernel.
+    pub fn set_sequential(&self, sequential: bool) -> Result<(), Error> {
+        let advice = if sequential {
+            MmapAdvise::MADV_SEQUENTIAL
+        } else {
+            MmapAdvise::MADV_NORMAL
+        };
+
+        unsafe {
+            madvise(self.ptr as *mut std::ffi::c_void, self.size, advice)
         }?;
 
         Ok(())
     }
 
-    #[cfg(target_os = "ios")]
-    pub fn flush_icache(&self) -> Result<(), Error> {
+    /// Advises the operating system that the pages in the given range will be accessed soon, such
+    /// that they can be read ahead.
+    pub fn read_ahead(&self, range: Range<usize>) -> Result<(), Error> {
+        let range = self.page_range(range);
+
         unsafe {
-            sys_icache_invalidate(
-                self.ptr as *mut std::ffi::c_void,
-                self.size as usize,
+            madvise(
+                self.ptr.add(range.start) as *mut std::ffi::c_void,
+                range.end - range.start,
+                MmapAdvise::MADV_WILLNEED,
             )
-        };
+        }?;
 
         Ok(())
     }
 
-    #[cfg(not(target_os = "ios"))]
-    pub fn flush_icache(&self) -> Result<(), Error> {
+    /// Advises the kernel to back the mapping with transparent huge pages.
+    pub fn advise_huge_pages(&self) -> Result<(), Error> {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
         unsafe {
-            __clear_cache(
-                self.ptr as *mut std::ffi::c_void,
-                self.ptr.offset(self.size as isize) as *mut std::ffi::c_void,
-            )
-        };
+            madvise(self.ptr as *mut std::ffi::c_void, self.size, MmapAdvise::MADV_HUGEPAGE)
+        }?;
 
         Ok(())
     }
 
-    fn do_make(&self, protect: ProtFlags) -> Result<(), Error> {
-        let ptr  = self.ptr as *const u8;
-        let size = self.size;
+    /// Faults in the pages in the given range. On Linux 5.14 or newer, this uses `madvise()` with
+    /// `MADV_POPULATE_READ` or `MADV_POPULATE_WRITE`, which reports errors rather than raising
+    /// `SIGBUS`. Otherwise, the pages are touched instead.
+    pub fn populate(&self, range: Range<usize>, write: bool) -> Result<(), Error> {
+        // Reading device memory may have side effects.
+        if self.flags.contains(Flags::DEVICE) {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "device memory cannot be populated",
+            )));
+        }
 
-        unsafe {
-            mprotect(
-                ptr as *mut std::ffi::c_void,
-                size,
-                protect,
-            )?;
+        let range = self.page_range(range);
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            let advice = if write {
+                libc::MADV_POPULATE_WRITE
+            } else {
+                libc::MADV_POPULATE_READ
+            };
+
+            let result = Errno::result(unsafe {
+                libc::madvise(
+                    self.ptr.add(range.start) as *mut std::ffi::c_void,
+                    range.end - range.start,
+                    advice,
+                )
+            });
+
+            // Older kernels reject the advice as invalid.
+            if result != Err(Errno::EINVAL) {
+                trace_op!("populate", result, address = self.ptr, range = range, write = write);
+
+                result?;
+
+                return Ok(());
+            }
         }
 
+        unsafe {
+            super::touch(self.ptr.add(range.start), range.end - range.start, write)
+        };
+
         Ok(())
     }
 
-    pub fn make_none(&self) -> Result<(), Error> {
-        self.do_make(ProtFlags::PROT_NONE)
-    }
+    /// Marks the pages of the mapping as idle. See [`clear_accessed()`].
+    pub fn clear_accessed(&self) -> Result<(), Error> {
+        let result = clear_accessed(self.ptr as usize, self.size);
 
-    pub fn make_read_only(&self) -> Result<(), Error> {
-        self.do_make(ProtFlags::PROT_READ)
-    }
+        trace_op!("clear_accessed", result, address = self.ptr, size = self.size);
 
-    pub fn make_exec(&self) -> Result<(), Error> {
-        self.do_make(ProtFlags::PROT_READ | ProtFlags::PROT_EXEC)
+        result
     }
 
-    pub fn make_mut(&self) -> Result<(), Error> {
-        self.do_make(ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
+    /// Returns for every page from the page that the mapping starts in whether it has been
+    /// accessed. See [`accessed_pages()`].
+    pub fn accessed_pages(&self) -> Result<Vec<bool>, Error> {
+        let start = crate::align_down(self.skew);
+
+        accessed_pages(self.ptr as usize + start, self.size - start)
     }
 
-    pub fn make_exec_mut(&self) -> Result<(), Error> {
-        if !self.flags.contains(Flags::JIT) {
-            return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
-        }
+    pub fn evict(&self, range: Range<usize>, eviction: Eviction) -> Result<(), Error> {
+        // MADV_PAGEOUT and MADV_COLD are not exposed by nix.
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let advice = match eviction {
+            Eviction::PageOut => libc::MADV_PAGEOUT,
+            Eviction::Cold => libc::MADV_COLD,
+        };
 
-        self.do_make(ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC)
-    }
-}
+        // Contrary to Linux, MADV_DONTNEED merely deactivates the pages on other platforms.
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        let advice = {
+            let _ = eviction;
+            libc::MADV_DONTNEED
+        };
 
-impl Drop for Mmap {
-    fn drop(&mut self) {
-        let _ = unsafe {
-            munmap(
-                self.ptr as *mut _,
-                self.size,
+        let range = self.page_range(range);
+
+        let result = Errno::result(unsafe {
+            libc::madvise(
+                self.ptr.add(range.start) as *mut std::ffi::c_void,
+                range.end - range.start,
+                advice,
             )
+        });
+
+        trace_op!("evict", result, address = self.ptr, range = range, eviction = eviction);
+
+        result?;
+
+        Ok(())
+    }
+
+    pub fn advise(&self, range: Range<usize>, advice: Advice) -> Result<(), Error> {
+        let range = self.page_range(range);
+        let ptr = unsafe { self.ptr.add(range.start) } as *mut std::ffi::c_void;
+        let size = range.end - range.start;
+        let advise = |advice| unsafe { madvise(ptr, size, advice) };
+
+        let result = match advice {
+            Advice::WillNeed => advise(MmapAdvise::MADV_WILLNEED),
+            Advice::DontNeed => advise(MmapAdvise::MADV_DONTNEED),
+            Advice::Sequential => advise(MmapAdvise::MADV_SEQUENTIAL),
+            Advice::Random => advise(MmapAdvise::MADV_RANDOM),
+            // On Linux, MADV_FREE requires Linux 4.5 or newer and only applies to private
+            // anonymous pages.
+            Advice::Free => match advise(MmapAdvise::MADV_FREE) {
+                Err(Errno::EINVAL) => advise(MmapAdvise::MADV_DONTNEED),
+                result => result,
+            },
         };
+
+        trace_op!("advise", result, address = self.ptr, range = range, advice = advice);
+
+        result?;
+
+        Ok(())
     }
-}
 
-pub struct MmapOptions {
-    address: Option<usize>,
-    file: Option<(File, u64)>,
-    size: usize,
-    flags: MmapFlags,
-    unsafe_flags: UnsafeMmapFlags,
-    page_size: Option<PageSize>,
-}
+    /// Drops the pages in the given range from the mapping and from the page cache. This is only
+    /// done for shared file mappings, as the contents of any other pages would be lost.
+    pub fn drop_behind(&self, range: Range<usize>) -> Result<(), Error> {
+        let file = match self.file.as_ref() {
+            Some(file) if !self.flags.contains(Flags::COPY_ON_WRITE) => file,
+            _ => return Ok(()),
+        };
 
-impl MmapOptions {
-    pub fn new(size: usize) -> Self {
-        Self {
-            address: None,
-            file: None,
-            size,
-            flags: MmapFlags::empty(),
-            unsafe_flags: UnsafeMmapFlags::empty(),
-            page_size: None,
-        }
+        let range = self.page_range(range);
+
+        unsafe {
+            madvise(
+                self.ptr.add(range.start) as *mut std::ffi::c_void,
+                range.end - range.start,
+                MmapAdvise::MADV_DONTNEED,
+            )
+        }?;
+
+        #[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+        nix::fcntl::posix_fadvise(
+            file.as_raw_fd(),
+            (self.offset + range.start as u64) as _,
+            (range.end - range.start) as _,
+            nix::fcntl::PosixFadviseAdvice::POSIX_FADV_DONTNEED,
+        )?;
+
+        #[cfg(not(any(target_os = "android", target_os = "freebsd", target_os = "linux")))]
+        let _ = (file, self.offset);
+
+        Ok(())
     }
 
-    pub fn page_size() -> (usize, usize) {
-        let status = sysconf(SysconfVar::PAGE_SIZE);
+    pub fn flush_icache(&self) -> Result<(), Error> {
+        self.flush_icache_range(0..self.size())
+    }
 
-        let size = match status {
-            Ok(Some(page_size)) => page_size as usize,
-            _ => 4096,
+    pub fn flush_icache_range(&self, range: Range<usize>) -> Result<(), Error> {
+        unsafe {
+            flush_icache(self.as_mut_ptr().add(range.start), range.end - range.start)
         };
 
-        (size, size)
+        Ok(())
     }
 
-    pub fn with_address(mut self, address: usize) -> Self {
-        self.address = Some(address);
-        self
-    }
+    /// Flushes the instruction cache for the range, and then makes every core that runs a thread
+    /// of the process serialize its instruction stream, such that no core keeps executing stale
+    /// instructions that it fetched before the code was written.
+    pub fn publish_code(&self, range: Range<usize>) -> Result<(), Error> {
+        self.flush_icache_range(range.clone())?;
 
-    pub fn with_file(mut self, file: File, offset: u64) -> Self {
-        self.file = Some((file, offset));
-        self
+        let result = serialize_cores();
+
+        trace_op!(
+            "publish_code",
+            result,
+            address = self.as_ptr() as usize + range.start,
+            size = range.end - range.start,
+        );
+
+        result
     }
 
-    pub fn with_flags(mut self, flags: MmapFlags) -> Self {
-        self.flags = flags;
-        self
+    fn do_make(&self, protect: ProtFlags) -> Result<(), Error> {
+        let ptr  = self.ptr as *const u8;
+        let size = self.size;
+
+        let result = unsafe {
+            mprotect(
+                ptr as *mut std::ffi::c_void,
+                size,
+                protect | self.raw_prot,
+            )
+        };
+
+        trace_op!("protect", result, address = ptr, size = size, protect = protect);
+
+        result.map_err(|e| wx_error(e, protect))?;
+        self.protect.store(protect.bits(), Ordering::Relaxed);
+
+        Ok(())
     }
 
-    pub fn with_unsafe_flags(mut self, flags: UnsafeMmapFlags) -> Self {
-        self.unsafe_flags = flags;
-        self
+    /// Changes the protection of the given ranges, which is not tracked, as the protection is
+    /// only tracked for the mapping as a whole.
+    pub fn protect_ranges(&self, ranges: &[(Range<usize>, Protection)]) -> Result<(), Error> {
+        for (range, protection) in ranges {
+            let range = self.page_range(range.clone());
+            let ptr = unsafe { self.ptr.add(range.start) };
+            let size = range.end - range.start;
+            let protect = from_protection(*protection);
+
+            let result = unsafe {
+                mprotect(
+                    ptr as *mut std::ffi::c_void,
+                    size,
+                    protect | self.raw_prot,
+                )
+            };
+
+            trace_op!("protect", result, address = ptr, size = size, protect = protect);
+
+            result.map_err(|e| wx_error(e, protect))?;
+        }
+
+        Ok(())
     }
 
-    pub fn with_page_size(mut self, page_size: PageSize) -> Self {
-        self.page_size = Some(page_size);
-        self
+    /// Whether the pages are private to this process rather than shared with other mappings.
+    #[inline]
+    pub fn is_copy_on_write(&self) -> bool {
+        self.flags.contains(Flags::COPY_ON_WRITE)
     }
 
-    fn flags(&self) -> MapFlags {
-        let mut flags = MapFlags::empty();
+    #[cfg(feature = "jit")]
+    pub fn is_jit(&self) -> bool {
+        self.flags.contains(Flags::JIT)
+    }
 
-        if self.file.is_none() {
-            flags |= MapFlags::MAP_ANONYMOUS;
+    /// Unmaps the pages, after which the mapping must no longer be used. The pointer is reset to
+    /// indicate that the pages have been unmapped.
+    pub fn unmap(&mut self) -> Result<(), Error> {
+        match self.drop_behavior {
+            DropBehavior::Flush if self.file.is_some() => {
+                let _ = self.flush(0..self.size());
+            }
+            DropBehavior::Discard => {
+                let _ = unsafe {
+                    madvise(
+                        self.ptr as *mut std::ffi::c_void,
+                        self.size,
+                        MmapAdvise::MADV_DONTNEED,
+                    )
+                };
+            }
+            _ => (),
         }
 
-        flags |= if self.flags.contains(MmapFlags::COPY_ON_WRITE) {
-            MapFlags::MAP_PRIVATE
-        } else {
-            MapFlags::MAP_SHARED
-        };
+        crate::flush::forget(self.ptr as usize..self.ptr as usize + crate::align_up(self.size));
 
-        #[cfg(any(target_os = "android", target_os = "linux"))]
-        if self.flags.contains(MmapFlags::POPULATE) {
-            flags |= MapFlags::MAP_POPULATE;
+        // Sealed pages can neither be scrubbed nor unmapped, so the address space is leaked.
+        if self.flags.contains(Flags::SEALED) {
+            self.ptr = std::ptr::null_mut();
+            return Ok(());
         }
 
-        #[cfg(not(any(target_os = "dragonfly", target_os = "freebsd")))]
-        if self.flags.contains(MmapFlags::NO_RESERVE) {
-            flags |= MapFlags::MAP_NORESERVE;
+        if self.flags.contains(Flags::ZERO_ON_DROP) && self.make_mut().is_ok() {
+            unsafe {
+                super::scrub(self.ptr, self.size)
+            };
         }
 
-        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let result = self.release(self.ptr, self.size);
+
+        trace_op!("unmap", result, address = self.ptr, size = self.size);
+
+        result?;
+        self.ptr = std::ptr::null_mut();
+
+        // Release the lock explicitly, as the file descriptor may have been duplicated.
+        if let (true, Some(file)) = (self.file_locked, self.file.as_ref()) {
+            let _ = flock(file.as_raw_fd(), FlockArg::Unlock);
+        }
+
+        Ok(())
+    }
+
+    /// Unmaps the pages in the given range, or returns them to the reservation if the mapping has
+    /// been mapped in one.
+    fn release(&self, ptr: *mut u8, size: usize) -> Result<(), Error> {
+        match self.slot.as_ref() {
+            Some(_) => reserve(ptr, size),
+            _ => Ok(unsafe { munmap(ptr as *mut std::ffi::c_void, size) }?),
+        }
+    }
+
+    pub fn truncate(&mut self, size: usize) -> Result<(), Error> {
+        // The size of the pages includes the part of the first page before the mapping.
+        let size = size + self.skew;
+        let start = crate::align_up(size);
+        let end = crate::align_up(self.size);
+
+        if start < end {
+            let ptr = unsafe { self.ptr.add(start) } as *mut std::ffi::c_void;
+
+            crate::flush::forget(ptr as usize..ptr as usize + (end - start));
+
+            if self.flags.contains(Flags::ZERO_ON_DROP) {
+                let result = unsafe {
+                    mprotect(ptr, end - start, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
+                };
+
+                if result.is_ok() {
+                    unsafe {
+                        super::scrub(ptr as *mut u8, end - start)
+                    };
+                }
+            }
+
+            let result = self.release(ptr as *mut u8, end - start);
+
+            trace_op!("unmap", result, address = ptr, size = end - start);
+
+            result?;
+        }
+
+        if let Some(slot) = self.slot.as_mut() {
+            slot.truncate(size);
+        }
+
+        self.size = size;
+        self.record.resize(size);
+
+        Ok(())
+    }
+
+    /// Splits the mapping at the given offset, where this mapping keeps the pages before the
+    /// offset and the returned mapping owns the remaining pages. The file descriptor is duplicated
+    /// for the returned mapping, while an advisory lock on the file stays with this mapping.
+    pub fn split_off(&mut self, at: usize) -> Result<Self, Error> {
+        if self.slot.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "views of a reservation cannot be split",
+            )));
+        }
+
+        let at = at + self.skew;
+        let file = self.file.as_ref().map(|file| file.try_clone()).transpose()?;
+        let ptr = unsafe { self.ptr.add(at) };
+
+        let tail = Self {
+            file,
+            offset: self.offset + at as u64,
+            ptr,
+            size: self.size - at,
+            skew: 0,
+            flags: self.flags,
+            protect: AtomicI32::new(self.protect.load(Ordering::Relaxed)),
+            drop_behavior: self.drop_behavior,
+            raw_prot: self.raw_prot,
+            file_locked: false,
+            slot: None,
+            record: self.record.split_off(ptr as usize, at),
+        };
+
+        self.size = at;
+
+        Ok(tail)
+    }
+
+    pub fn remap_to(&mut self, address: usize) -> Result<(), Error> {
+        if self.slot.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "views of a reservation cannot be moved",
+            )));
+        }
+
+        crate::flush::forget(self.ptr as usize..self.ptr as usize + crate::align_up(self.size));
+
+        let result = self.do_remap_to(address);
+
+        trace_op!("remap", result, address = self.ptr, size = self.size, new_address = address);
+
+        self.ptr = result? as *mut u8;
+        self.record.relocate(self.ptr as usize);
+
+        Ok(())
+    }
+
+    /// Grows the mapping to the given size, which may move the mapping. Shrinking the mapping is
+    /// left to `truncate()`.
+    pub fn resize(&mut self, size: usize) -> Result<(), Error> {
+        if self.slot.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "views of a reservation cannot grow",
+            )));
+        }
+
+        let size = size + self.skew;
+
+        crate::flush::forget(self.ptr as usize..self.ptr as usize + crate::align_up(self.size));
+
+        let result = self.do_resize(size);
+
+        trace_op!("remap", result, address = self.ptr, size = self.size, new_size = size);
+
+        self.ptr = result? as *mut u8;
+        self.size = size;
+        self.record.relocate(self.ptr as usize);
+        self.record.resize(size);
+
+        Ok(())
+    }
+
+    /// Moves the pages to the new address, which preserves the contents, the protection and
+    /// whether the pages are locked.
+    #[cfg(target_os = "linux")]
+    fn do_remap_to(&self, address: usize) -> Result<*mut std::ffi::c_void, Error> {
+        Ok(unsafe {
+            mremap(
+                self.ptr as *mut std::ffi::c_void,
+                self.size,
+                self.size,
+                MRemapFlags::MREMAP_MAYMOVE | MRemapFlags::MREMAP_FIXED,
+                Some(address as *mut std::ffi::c_void),
+            )
+        }?)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn do_remap_to(&self, address: usize) -> Result<*mut std::ffi::c_void, Error> {
+        // The new mapping would replace the pages before they could be copied.
+        let old = self.ptr as usize;
+
+        if address < old + self.size && old < address + self.size {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        }
+
+        self.relocate(Some(address), self.size)
+    }
+
+    /// Grows the mapping using `mremap()` on Linux, which grows the mapping in place if the
+    /// address range after the mapping is free, and moves it elsewhere otherwise. Elsewhere, or
+    /// if the mapping cannot be remapped, the pages that the mapping grows by are mapped right
+    /// after the mapping, the same way as the mapping itself, and the mapping is moved elsewhere
+    /// if the address range is in use.
+    fn do_resize(&self, size: usize) -> Result<*mut std::ffi::c_void, Error> {
+        // Shared anonymous memory is backed by an object of a fixed size, such that accessing the
+        // pages that `mremap()` would add faults.
+        #[cfg(target_os = "linux")]
+        if self.file.is_some() || self.flags.contains(Flags::COPY_ON_WRITE) {
+            let result = unsafe {
+                mremap(
+                    self.ptr as *mut std::ffi::c_void,
+                    self.size,
+                    size,
+                    MRemapFlags::MREMAP_MAYMOVE,
+                    None,
+                )
+            };
+
+            match result {
+                Ok(ptr) => return Ok(ptr),
+                // The mapping spans multiple areas in the kernel, e.g. as parts of it have been
+                // protected differently, which cannot be remapped at once.
+                Err(Errno::EFAULT) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let start = crate::align_up(self.size);
+        let end = crate::align_up(size);
+
+        if start == end {
+            return Ok(self.ptr as *mut std::ffi::c_void);
+        }
+
+        let protect = ProtFlags::from_bits_truncate(self.protect.load(Ordering::Relaxed));
+        let address = self.ptr as usize + start;
+
+        let flags = if self.flags.contains(Flags::COPY_ON_WRITE) {
+            MapFlags::MAP_PRIVATE
+        } else {
+            MapFlags::MAP_SHARED
+        };
+
+        let result = unsafe {
+            match self.file.as_ref() {
+                Some(file) => mmap(
+                    address as *mut std::ffi::c_void,
+                    end - start,
+                    protect | self.raw_prot,
+                    flags | no_replace_flags(),
+                    file.as_raw_fd(),
+                    (self.offset + start as u64) as _,
+                ),
+                _ => mmap(
+                    address as *mut std::ffi::c_void,
+                    end - start,
+                    protect | self.raw_prot,
+                    flags | MapFlags::MAP_ANONYMOUS | no_replace_flags(),
+                    -1,
+                    0,
+                ),
+            }
+        };
+
+        match result {
+            Ok(ptr) if ptr as usize == address => {
+                if self.flags.contains(Flags::LOCKED) {
+                    let _ = unsafe { mlock(ptr, end - start) };
+                }
+
+                return Ok(self.ptr as *mut std::ffi::c_void);
+            }
+            // Without MAP_FIXED_NOREPLACE, the address is only a hint that may have been ignored.
+            Ok(ptr) => {
+                let _ = unsafe { munmap(ptr, end - start) };
+            }
+            Err(_) => (),
+        }
+
+        self.relocate(None, size)
+    }
+
+    /// Maps the file again for shared file mappings, or copies the contents to a new anonymous
+    /// mapping otherwise, and then unmaps the old mapping. The new mapping of the given size is
+    /// placed at the given address, replacing any pages there, or anywhere if there is none.
+    fn relocate(&self, address: Option<usize>, size: usize) -> Result<*mut std::ffi::c_void, Error> {
+        // Copying shared anonymous memory to a new object would silently stop sharing it with the
+        // child processes that have been forked before.
+        if self.file.is_none() && !self.flags.contains(Flags::COPY_ON_WRITE) {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "shared anonymous memory cannot be moved, as it would no longer be shared",
+            )));
+        }
+
+        let (address, fixed) = match address {
+            Some(address) => (address as *mut std::ffi::c_void, MapFlags::MAP_FIXED),
+            _ => (std::ptr::null_mut(), MapFlags::empty()),
+        };
+
+        let protect = ProtFlags::from_bits_truncate(self.protect.load(Ordering::Relaxed));
+
+        let ptr = match self.file.as_ref() {
+            Some(file) if !self.flags.contains(Flags::COPY_ON_WRITE) => unsafe {
+                mmap(
+                    address,
+                    size,
+                    protect | self.raw_prot,
+                    MapFlags::MAP_SHARED | fixed,
+                    file.as_raw_fd(),
+                    self.offset as _,
+                )
+            }?,
+            _ => {
+                // Only private pages are copied, as shared anonymous memory has been rejected.
+                let ptr = unsafe {
+                    mmap(
+                        address,
+                        size,
+                        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                        MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | fixed,
+                        -1,
+                        0,
+                    )
+                }?;
+
+                // Protect the pages against writes while they are copied, without changing the
+                // protection that is tracked for the mapping, as the new mapping takes it over.
+                let result = unsafe {
+                    mprotect(
+                        self.ptr as *mut std::ffi::c_void,
+                        self.size,
+                        ProtFlags::PROT_READ | self.raw_prot,
+                    )
+                }.map_err(Error::from).and_then(|_| {
+                    unsafe {
+                        std::ptr::copy_nonoverlapping(self.ptr, ptr as *mut u8, self.size);
+                    }
+
+                    // The pages that are added to a private file mapping are read from the file.
+                    if let (Some(file), true) = (self.file.as_ref(), size > self.size) {
+                        let bytes = unsafe {
+                            std::slice::from_raw_parts_mut(
+                                (ptr as *mut u8).add(self.size),
+                                size - self.size,
+                            )
+                        };
+
+                        read_exact_at(file, bytes, self.offset + self.size as u64)?;
+                    }
+
+                    unsafe {
+                        mprotect(ptr, size, protect | self.raw_prot)
+                    }.map_err(Error::from)
+                });
+
+                if let Err(e) = result {
+                    let _ = unsafe { munmap(ptr, size) };
+                    let _ = unsafe {
+                        mprotect(
+                            self.ptr as *mut std::ffi::c_void,
+                            self.size,
+                            protect | self.raw_prot,
+                        )
+                    };
+
+                    return Err(e);
+                }
+
+                ptr
+            }
+        };
+
+        if self.flags.contains(Flags::LOCKED) {
+            let _ = unsafe { mlock(ptr, size) };
+        }
+
+        let _ = unsafe { munmap(self.ptr as *mut std::ffi::c_void, self.size) };
+
+        Ok(ptr)
+    }
+
+    pub fn make_none(&self) -> Result<(), Error> {
+        self.do_make(ProtFlags::PROT_NONE)
+    }
+
+    pub fn make_read_only(&self) -> Result<(), Error> {
+        self.do_make(ProtFlags::PROT_READ)
+    }
+
+    #[cfg(feature = "exec")]
+    pub fn make_exec(&self) -> Result<(), Error> {
+        self.do_make(ProtFlags::PROT_READ | ProtFlags::PROT_EXEC)
+    }
+
+    pub fn make_mut(&self) -> Result<(), Error> {
+        self.do_make(ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
+    }
+
+    /// Seals the mapping against changes to its protection and against being unmapped, using
+    /// `mseal()` on Linux and `mimmutable()` on OpenBSD. The file backing a shared mapping is
+    /// sealed against writes through other mappings and file descriptors as well, if the file
+    /// supports sealing, i.e. if it has been created using `memfd_create()` with
+    /// `MFD_ALLOW_SEALING`. Files that have been sealed against further seals are rejected
+    /// before anything has been sealed.
+    pub fn seal(&mut self) -> Result<(), Error> {
+        if self.slot.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "views of a reservation cannot be sealed, as their pages return to the reservation",
+            )));
+        }
+
+        // Probe for mseal() with an empty range, such that nothing has been sealed yet if it is
+        // not supported.
+        #[cfg(target_os = "linux")]
+        if let Err(e) = Errno::result(unsafe { libc::syscall(libc::SYS_mseal, self.ptr, 0, 0) }) {
+            return Err(match e {
+                Errno::ENOSYS => Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "sealing requires mseal(), which was added in Linux 6.10",
+                )),
+                e => Error::Nix(e),
+            });
+        }
+
+        // Existing mappings are exempt from F_SEAL_FUTURE_WRITE, unlike F_SEAL_WRITE which fails
+        // while the file is mapped as shared and writable.
+        #[cfg(target_os = "linux")]
+        let seals = libc::F_SEAL_FUTURE_WRITE |
+            libc::F_SEAL_SHRINK |
+            libc::F_SEAL_GROW |
+            libc::F_SEAL_SEAL;
+
+        // The seals of a file cannot be removed again, so the file is sealed only once the pages
+        // have been sealed. Check up front whether the file can be sealed, as the pages cannot be
+        // unsealed either. Files that do not support sealing fail with EINVAL and are left as
+        // they are.
+        #[cfg(target_os = "linux")]
+        let file = match (self.file.as_ref(), self.flags.contains(Flags::COPY_ON_WRITE)) {
+            (Some(file), false) => {
+                match Errno::result(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) }) {
+                    Ok(current) if current & seals == seals => None,
+                    Ok(current) if current & libc::F_SEAL_SEAL != 0 => {
+                        return Err(Error::Io(std::io::Error::new(
+                            std::io::ErrorKind::PermissionDenied,
+                            "the file has been sealed against adding further seals",
+                        )));
+                    }
+                    Ok(_) => Some(file),
+                    Err(Errno::EINVAL) => None,
+                    Err(e) => return Err(Error::Nix(e)),
+                }
+            }
+            _ => None,
+        };
+
+        #[cfg(target_os = "linux")]
+        let result = Errno::result(unsafe {
+            libc::syscall(libc::SYS_mseal, self.ptr, self.size, 0)
+        }).map(|_| ());
+
+        #[cfg(target_os = "openbsd")]
+        let result = Errno::result(unsafe {
+            libc::mimmutable(self.ptr as *mut std::ffi::c_void, self.size)
+        }).map(|_| ());
+
+        #[cfg(not(any(target_os = "linux", target_os = "openbsd")))]
+        let result: Result<(), Errno> = Err(Errno::ENOSYS);
+
+        trace_op!("seal", result, address = self.ptr, size = self.size);
+
+        match result {
+            Ok(()) => self.flags |= Flags::SEALED,
+            Err(Errno::ENOSYS) => return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "mappings cannot be sealed on this platform",
+            ))),
+            Err(e) => return Err(Error::Nix(e)),
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(file) = file {
+            let result = Errno::result(unsafe {
+                libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals)
+            });
+
+            trace_op!("seal_file", result, address = self.ptr, seals = seals);
+
+            result?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn make_exec_mut(&self) -> Result<(), Error> {
+        if !self.flags.contains(Flags::JIT) {
+            return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
+        }
+
+        self.do_make(ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC)
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            let _ = self.unmap();
+        }
+    }
+}
+
+/// Returns true if the file is backed by a driver rather than by the page cache, i.e. if it is a
+/// character device, or a file in sysfs on Linux, such as the resource files of PCI devices.
+fn is_device_file(file: &File) -> Result<bool, Error> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file.metadata()?.file_type().is_char_device() {
+        return Ok(true);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+
+        Errno::result(unsafe { libc::fstatfs(file.as_raw_fd(), stat.as_mut_ptr()) })?;
+
+        let stat = unsafe { stat.assume_init() };
+
+        if stat.f_type as u64 == libc::SYSFS_MAGIC as u64 {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Converts the protection flags as used by `mmap()` and `mprotect()` into a [`Protection`].
+fn to_protection(protect: ProtFlags) -> Protection {
+    let mut protection = Protection::empty();
+
+    if protect.contains(ProtFlags::PROT_READ) {
+        protection |= Protection::READ;
+    }
+
+    if protect.contains(ProtFlags::PROT_WRITE) {
+        protection |= Protection::WRITE;
+    }
+
+    if protect.contains(ProtFlags::PROT_EXEC) {
+        protection |= Protection::EXECUTE;
+    }
+
+    protection
+}
+
+/// Converts the [`Protection`] into the protection flags as used by `mprotect()`.
+fn from_protection(protection: Protection) -> ProtFlags {
+    let mut protect = ProtFlags::PROT_NONE;
+
+    if protection.contains(Protection::READ) {
+        protect |= ProtFlags::PROT_READ;
+    }
+
+    if protection.contains(Protection::WRITE) {
+        protect |= ProtFlags::PROT_WRITE;
+    }
+
+    if protection.contains(Protection::EXECUTE) {
+        protect |= ProtFlags::PROT_EXEC;
+    }
+
+    protect
+}
+
+/// Classifies the failure to map or protect memory as executable, as the platform returns a bare
+/// `EACCES`, `EPERM` or `ENOTSUP` when it enforces a W^X policy, which is easily misdiagnosed.
+fn wx_error(error: Errno, protect: ProtFlags) -> Error {
+    if !protect.contains(ProtFlags::PROT_EXEC) {
+        return error.into();
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    let message = match error {
+        Errno::EACCES | Errno::EPERM => "SELinux may deny the execmem permission (see the audit \
+            log), PaX MPROTECT may be enabled for the executable (see paxctl), or the file may \
+            reside on a file system that is mounted noexec",
+        _ => return error.into(),
+    };
+
+    #[cfg(any(target_os = "ios", target_os = "macos"))]
+    let message = match error {
+        Errno::EACCES | Errno::EPERM => "the hardened runtime requires the \
+            com.apple.security.cs.allow-jit entitlement together with UnsafeMmapFlags::JIT, or \
+            the com.apple.security.cs.allow-unsigned-executable-memory entitlement",
+        _ => return error.into(),
+    };
+
+    #[cfg(target_os = "netbsd")]
+    let message = match error {
+        Errno::EACCES | Errno::EPERM => "PaX MPROTECT is enabled, which can be disabled for the \
+            executable using paxctl +m or globally using the security.pax.mprotect.enabled sysctl",
+        _ => return error.into(),
+    };
+
+    #[cfg(target_os = "openbsd")]
+    let message = match error {
+        Errno::ENOTSUP | Errno::EACCES => "W^X is enforced unless the executable has been linked \
+            with -z wxneeded and resides on a file system that is mounted wxallowed",
+        _ => return error.into(),
+    };
+
+    #[cfg(not(any(
+        target_os = "android",
+        target_os = "linux",
+        target_os = "ios",
+        target_os = "macos",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    )))]
+    let message = match error {
+        Errno::EACCES | Errno::EPERM => "a security policy may deny executable memory, or the \
+            file may reside on a file system that is mounted noexec",
+        _ => return error.into(),
+    };
+
+    Error::WxProtectionDenied(message.to_string())
+}
+
+/// Converts the failure to lock memory into an error, where a lack of memory is probed for the
+/// likely cause.
+fn lock_error(error: Errno, size: usize) -> Error {
+    match error {
+        Errno::ENOMEM | Errno::EAGAIN => out_of_memory(size, true),
+        e => e.into(),
+    }
+}
+
+/// Probes the likely cause of the failure to map or lock memory of the given size due to a lack of
+/// memory, as `ENOMEM` has many distinct causes that each have a different fix.
+// The type of `rlim_t` differs between platforms.
+#[allow(clippy::unnecessary_cast)]
+#[cfg_attr(not(any(target_os = "android", target_os = "linux")), allow(unused_variables))]
+fn out_of_memory(size: usize, locked: bool) -> Error {
+    if locked {
+        if let Ok((limit, _)) = getrlimit(Resource::RLIMIT_MEMLOCK) {
+            if limit != libc::RLIM_INFINITY {
+                return Error::OutOfMemory(OutOfMemoryCause::LockedMemoryLimit(limit as u64));
+            }
+        }
+    }
+
+    #[cfg(not(any(target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+    if let Ok((limit, _)) = getrlimit(Resource::RLIMIT_AS) {
+        // Only Linux reports the size of the address space, so assume the limit is the cause
+        // elsewhere.
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let exceeded = address_space_size()
+            .map_or(true, |used| used + size as u64 > limit as u64);
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        let exceeded = true;
+
+        if limit != libc::RLIM_INFINITY && exceeded {
+            return Error::OutOfMemory(OutOfMemoryCause::AddressSpaceLimit(limit as u64));
+        }
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    if let Ok(Some(cause)) = probe_system_limits(size as u64) {
+        return Error::OutOfMemory(cause);
+    }
+
+    Error::OutOfMemory(OutOfMemoryCause::Unknown)
+}
+
+/// Returns the size of the address space of the current process as reported by
+/// `/proc/self/status`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn address_space_size() -> Result<u64, Error> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+
+    let size = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmSize:"))
+        .and_then(|size| size.trim().strip_suffix("kB"))
+        .map(|size| size.trim().parse::<u64>())
+        .transpose()?
+        .unwrap_or(0);
+
+    Ok(size << 10)
+}
+
+/// Probes the system-wide limits on Linux, i.e. the maximum number of mappings and the overcommit
+/// accounting.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn probe_system_limits(size: u64) -> Result<Option<OutOfMemoryCause>, Error> {
+    let max_map_count: u64 = std::fs::read_to_string("/proc/sys/vm/max_map_count")?
+        .trim()
+        .parse()?;
+    let map_count = std::fs::read("/proc/self/maps")?
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count() as u64;
+
+    // The mapping may have needed more than one entry, e.g. for guard pages.
+    if map_count + 2 >= max_map_count {
+        return Ok(Some(OutOfMemoryCause::MapCountLimit(max_map_count)));
+    }
+
+    let mode: u32 = std::fs::read_to_string("/proc/sys/vm/overcommit_memory")?
+        .trim()
+        .parse()?;
+    let meminfo = std::fs::read_to_string("/proc/meminfo")?;
+
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().strip_suffix("kB"))
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(|value| value << 10)
+    };
+
+    let cause = match mode {
+        // Heuristic overcommit refuses mappings that exceed the physical memory and swap space.
+        0 => match (field("MemTotal:"), field("SwapTotal:")) {
+            (Some(memory), Some(swap)) if size > memory + swap => {
+                Some(OutOfMemoryCause::OvercommitHeuristic)
+            }
+            _ => None,
+        },
+        // Strict overcommit refuses mappings that exceed the commit limit.
+        2 => match (field("CommitLimit:"), field("Committed_AS:")) {
+            (Some(limit), Some(committed)) if committed + size > limit => {
+                Some(OutOfMemoryCause::CommitLimit(limit))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    Ok(cause)
+}
+
+/// Checks whether `size` more bytes fit within the memory limits of the cgroup v2 of the process
+/// and of its ancestors, as exceeding a limit triggers the OOM killer rather than failing. Limits
+/// that cannot be read, e.g. when the memory controller is only available through cgroup v1, are
+/// ignored.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn check_memory_limit(size: usize) -> Result<(), Error> {
+    let root = match cgroup2_mount() {
+        Some(root) => root,
+        _ => return Ok(()),
+    };
+
+    // The unified hierarchy of cgroup v2 is listed as `0::<path>`.
+    let path = match std::fs::read_to_string("/proc/self/cgroup") {
+        Ok(cgroups) => match cgroups.lines().find_map(|line| line.strip_prefix("0::")) {
+            Some(path) => root.join(path.trim_start_matches('/')),
+            _ => return Ok(()),
+        },
+        _ => return Ok(()),
+    };
+
+    let read = |path: &std::path::Path, name: &str| {
+        std::fs::read_to_string(path.join(name))
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    // The root of the hierarchy has no limit, unless it is the root of a cgroup namespace.
+    for ancestor in path.ancestors().take_while(|ancestor| ancestor.starts_with(&root)) {
+        // The limit is `max` if the cgroup is unlimited, which fails to parse.
+        let max = read(ancestor, "memory.max");
+        let current = read(ancestor, "memory.current");
+
+        if let (Some(max), Some(current)) = (max, current) {
+            if current.saturating_add(size as u64) > max {
+                return Err(Error::OutOfMemory(OutOfMemoryCause::CgroupLimit(max)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Looks up where the unified hierarchy of cgroup v2 has been mounted, which is `/sys/fs/cgroup`
+/// on most systems, but `/sys/fs/cgroup/unified` on systems that also mount cgroup v1.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn cgroup2_mount() -> Option<std::path::PathBuf> {
+    let mounts = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+
+    // The mount point is the fifth field, and the file system type follows the separator.
+    mounts.lines().find_map(|line| {
+        let (fields, rest) = line.split_once(" - ")?;
+
+        if rest.split_whitespace().next()? != "cgroup2" {
+            return None;
+        }
+
+        fields.split_whitespace().nth(4).map(std::path::PathBuf::from)
+    })
+}
+
+/// Only Linux has a memory limit that is enforced by the OOM killer.
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+fn check_memory_limit(_size: usize) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reads a counter of the pool of huge pages of the given size from sysfs.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn read_huge_page_counter(size: usize, name: &str) -> Result<usize, Error> {
+    let path = format!("/sys/kernel/mm/hugepages/hugepages-{}kB/{}", size >> 10, name);
+
+    Ok(std::fs::read_to_string(path)?.trim().parse()?)
+}
+
+/// Returns the default huge page size as reported by `/proc/meminfo`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn default_huge_page_size() -> Result<Option<usize>, Error> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo")?;
+
+    let size = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Hugepagesize:"))
+        .and_then(|size| size.trim().strip_suffix("kB"))
+        .map(|size| size.trim().parse::<usize>())
+        .transpose()?;
+
+    Ok(size.map(|size| size << 10))
+}
+
+/// An inaccessible anonymous mapping that reserves address space for views.
+pub struct Reservation {
+    ptr: *mut u8,
+    size: usize,
+}
+
+// The reservation merely holds on to the address space, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for Reservation {}
+unsafe impl Sync for Reservation {}
+
+impl Reservation {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let result = unsafe {
+            mmap(std::ptr::null_mut(), size, ProtFlags::PROT_NONE, reserve_flags(), -1, 0)
+        };
+
+        trace_op!("reserve", result, address = result.as_ref().ok(), size = size);
+
+        Ok(Self {
+            ptr: result? as *mut u8,
+            size,
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let result = unsafe {
+            munmap(self.ptr as *mut std::ffi::c_void, self.size)
+        };
+
+        trace_op!("unreserve", result, address = self.ptr, size = self.size);
+    }
+}
+
+/// A reservation of address space, in which pages are committed on demand.
+pub struct ReservedMmap {
+    ptr: *mut u8,
+    size: usize,
+    /// Keeps the mapping registered for as long as it is alive.
+    _record: Record,
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for ReservedMmap {}
+unsafe impl Sync for ReservedMmap {}
+
+impl ReservedMmap {
+    fn new(address: Option<usize>, size: usize) -> Result<Self, Error> {
+        let result = unsafe {
+            mmap(
+                address.unwrap_or(0) as *mut std::ffi::c_void,
+                size,
+                ProtFlags::PROT_NONE,
+                reserve_flags(),
+                -1,
+                0,
+            )
+        };
+
+        trace_op!("reserve", result, address = result.as_ref().ok(), size = size);
+
+        let ptr = result? as *mut u8;
+
+        Ok(Self {
+            ptr,
+            size,
+            _record: Record::new(ptr as usize, size, MmapFlags::NO_RESERVE, false),
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Makes the pages readable and writable, which commits them. Pages that are committed
+    /// already keep their contents.
+    pub fn commit(&self, range: Range<usize>) -> Result<(), Error> {
+        let ptr = unsafe { self.ptr.add(range.start) };
+        let size = range.end - range.start;
+
+        let result = unsafe {
+            mprotect(
+                ptr as *mut std::ffi::c_void,
+                size,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+            )
+        };
+
+        trace_op!("commit", result, address = ptr, size = size);
+
+        result?;
+
+        Ok(())
+    }
+
+    /// Replaces the pages with fresh inaccessible pages, which releases the memory right away and
+    /// guarantees that the pages are zero-initialized when they are committed again, contrary to
+    /// `MADV_FREE`.
+    pub fn uncommit(&self, range: Range<usize>) -> Result<(), Error> {
+        let ptr = unsafe { self.ptr.add(range.start) };
+        let size = range.end - range.start;
+
+        let result = reserve(ptr, size);
+
+        trace_op!("uncommit", result, address = ptr, size = size);
+
+        result
+    }
+}
+
+impl Drop for ReservedMmap {
+    fn drop(&mut self) {
+        let result = unsafe {
+            munmap(self.ptr as *mut std::ffi::c_void, self.size)
+        };
+
+        trace_op!("unreserve", result, address = self.ptr, size = self.size);
+    }
+}
+
+/// Gives advice that the kernel may not have been built to support, in which case `madvise()`
+/// fails with `EINVAL` and the advice is ignored.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn advise_if_supported(
+    ptr: *mut std::ffi::c_void,
+    size: usize,
+    advice: MmapAdvise,
+) -> Result<(), Errno> {
+    match unsafe { madvise(ptr, size, advice) } {
+        Err(Errno::EINVAL) => Ok(()),
+        result => result,
+    }
+}
+
+/// Checks whether the page size can be encoded in the flags of `mmap()`, and returns it in bytes.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn check_page_size(page_size: PageSize) -> Result<usize, Error> {
+    page_size
+        .bytes()
+        .filter(|_| page_size.0 <= libc::MAP_HUGE_MASK as usize)
+        .ok_or_else(|| Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("pages of 2^{} bytes are not supported", page_size.0),
+        )))
+}
+
+/// Whether the page size is larger than the page size of the system, and thus requires huge pages.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn is_huge_page_size(page_size: PageSize) -> bool {
+    page_size.bytes().is_none_or(|bytes| bytes > crate::page_size())
+}
+
+/// Probes the address range for a free spot to map `size` bytes at, starting at the hint if it lies
+/// within the range. Each candidate address is mapped without replacing existing pages, and the
+/// mapping is only kept if it ended up within the range. Returns `None` if the range has no free
+/// spot that is large enough.
+fn map_within<F>(
+    range: Range<usize>,
+    hint: Option<usize>,
+    size: usize,
+    map: F,
+) -> Result<Option<*mut std::ffi::c_void>, Errno>
+where
+    F: Fn(Option<usize>, MapFlags) -> Result<*mut std::ffi::c_void, Errno>,
+{
+    let granularity = crate::allocation_granularity();
+    let span = crate::align_up(size);
+
+    let mut address = hint
+        .map(|hint| hint - hint % granularity)
+        .filter(|hint| range.contains(hint))
+        .unwrap_or(range.start);
+
+    loop {
+        // Skip the spots that are known to be in use, where the platform can tell.
+        address = match next_free(address, span) {
+            Some(address) => address,
+            _ => break,
+        };
+
+        if address.checked_add(span).is_none_or(|end| end > range.end) {
+            break;
+        }
+
+        let ptr = match map(Some(address), no_replace_flags()) {
+            Ok(ptr) => ptr,
+            // The candidate overlaps with pages that have been mapped already.
+            Err(Errno::EEXIST) => {
+                address += granularity;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Without MAP_FIXED_NOREPLACE, the address is only a hint that may have been ignored.
+        let start = ptr as usize;
+
+        if range.start <= start && start + span <= range.end {
+            return Ok(Some(ptr));
+        }
+
+        let _ = unsafe { munmap(ptr, size) };
+
+        address += granularity;
+    }
+
+    Ok(None)
+}
+
+#[cfg(target_os = "openbsd")]
+extern "C" {
+    fn mquery(
+        addr: *mut std::ffi::c_void,
+        len: libc::size_t,
+        prot: libc::c_int,
+        flags: libc::c_int,
+        fd: libc::c_int,
+        offset: libc::off_t,
+    ) -> *mut std::ffi::c_void;
+}
+
+/// Finds the first free spot of `size` bytes at or above the address using `mquery()`. OpenBSD
+/// has neither `/proc/self/maps` nor a way to map without replacing pages, and places the mapping
+/// at a random address rather than near the hint if the hint is in use. Returns `None` if there is
+/// no such spot.
+#[cfg(target_os = "openbsd")]
+fn next_free(address: usize, size: usize) -> Option<usize> {
+    let ptr = unsafe {
+        mquery(address as *mut std::ffi::c_void, size, libc::PROT_NONE, 0, -1, 0)
+    };
+
+    if ptr == libc::MAP_FAILED {
+        return None;
+    }
+
+    Some(ptr as usize).filter(|&free| free >= address)
+}
+
+/// Other platforms cannot tell whether an address is in use without trying to map it.
+#[cfg(not(target_os = "openbsd"))]
+fn next_free(address: usize, _size: usize) -> Option<usize> {
+    Some(address)
+}
+
+/// The flags to map memory at an address without replacing the pages that have been mapped there
+/// already, or none if the platform can only treat the address as a hint.
+fn no_replace_flags() -> MapFlags {
+    #[cfg(target_os = "linux")]
+    let flags = MapFlags::MAP_FIXED_NOREPLACE;
+
+    #[cfg(target_os = "freebsd")]
+    let flags = MapFlags::MAP_FIXED | MapFlags::MAP_EXCL;
+
+    #[cfg(not(any(target_os = "freebsd", target_os = "linux")))]
+    let flags = MapFlags::empty();
+
+    flags
+}
+
+/// Returns the pages in the given range to a reservation, by replacing them with inaccessible
+/// anonymous pages.
+fn reserve(ptr: *mut u8, size: usize) -> Result<(), Error> {
+    unsafe {
+        mmap(
+            ptr as *mut std::ffi::c_void,
+            size,
+            ProtFlags::PROT_NONE,
+            reserve_flags() | MapFlags::MAP_FIXED,
+            -1,
+            0,
+        )
+    }?;
+
+    Ok(())
+}
+
+/// The flags to reserve address space without reserving swap space for it.
+fn reserve_flags() -> MapFlags {
+    let flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS;
+
+    #[cfg(not(any(target_os = "dragonfly", target_os = "freebsd")))]
+    let flags = flags | MapFlags::MAP_NORESERVE;
+
+    flags
+}
+
+/// A mapping of the same shared memory twice back-to-back.
+pub struct MirroredMmap {
+    ptr: *mut u8,
+    size: usize,
+    /// Keeps the mapping registered for as long as it is alive.
+    _record: Record,
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for MirroredMmap {}
+unsafe impl Sync for MirroredMmap {}
+
+impl MirroredMmap {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let result = Self::map(size);
+
+        trace_op!(
+            "map_mirrored",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            size = size,
+        );
+
+        result
+    }
+
+    /// Reserves the address space for both views first, and then maps the views over the
+    /// reservation, such that the views are guaranteed to end up back-to-back.
+    fn map(size: usize) -> Result<Self, Error> {
+        let file = shared_memory(size)?;
+        let span = size.checked_mul(2).ok_or(Error::OutOfBounds(0..size))?;
+
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                span,
+                ProtFlags::PROT_NONE,
+                MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        }?;
+
+        for offset in [0, size] {
+            let result = unsafe {
+                mmap(
+                    (ptr as *mut u8).add(offset) as *mut std::ffi::c_void,
+                    size,
+                    ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                    MapFlags::MAP_SHARED | MapFlags::MAP_FIXED,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+
+            if let Err(e) = result {
+                let _ = unsafe { munmap(ptr, span) };
+
+                return Err(e.into());
+            }
+        }
+
+        // The views keep the shared memory alive, so the file can be closed.
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            size,
+            _record: Record::new(ptr as usize, span, MmapFlags::empty(), false),
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for MirroredMmap {
+    fn drop(&mut self) {
+        let result = unsafe {
+            munmap(self.ptr as *mut std::ffi::c_void, self.size * 2)
+        };
+
+        trace_op!("unmap_mirrored", result, address = self.ptr, size = self.size);
+    }
+}
+
+/// Two views of the same shared memory, one readable and writable, and one readable and
+/// executable.
+#[cfg(feature = "exec")]
+pub struct DualMmap {
+    writable: *mut u8,
+    executable: *mut u8,
+    size: usize,
+    /// Keep the views registered for as long as they are alive.
+    _records: [Record; 2],
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+#[cfg(feature = "exec")]
+unsafe impl Send for DualMmap {}
+#[cfg(feature = "exec")]
+unsafe impl Sync for DualMmap {}
+
+#[cfg(feature = "exec")]
+impl DualMmap {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let result = Self::map(size);
+
+        trace_op!(
+            "map_dual",
+            result,
+            writable = result.as_ref().map(|mapping| mapping.writable).ok(),
+            executable = result.as_ref().map(|mapping| mapping.executable).ok(),
+            size = size,
+        );
+
+        result
+    }
+
+    /// Maps the shared memory as writable first, such that the executable view only has to be
+    /// unmapped again if mapping it fails.
+    fn map(size: usize) -> Result<Self, Error> {
+        let file = shared_memory(size)?;
+
+        let writable = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                size,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        }?;
+
+        let protect = ProtFlags::PROT_READ | ProtFlags::PROT_EXEC;
+
+        let executable = unsafe {
+            mmap(std::ptr::null_mut(), size, protect, MapFlags::MAP_SHARED, file.as_raw_fd(), 0)
+        };
+
+        let executable = match executable {
+            Ok(executable) => executable,
+            Err(e) => {
+                let _ = unsafe { munmap(writable, size) };
+
+                return Err(wx_error(e, protect));
+            }
+        };
+
+        // The views keep the shared memory alive, so the file can be closed.
+        Ok(Self {
+            writable: writable as *mut u8,
+            executable: executable as *mut u8,
+            size,
+            _records: [
+                Record::new(writable as usize, size, MmapFlags::empty(), false),
+                Record::new(executable as usize, size, MmapFlags::empty(), false),
+            ],
+        })
+    }
+
+    #[inline]
+    pub fn writable_ptr(&self) -> *mut u8 {
+        self.writable
+    }
+
+    #[inline]
+    pub fn executable_ptr(&self) -> *const u8 {
+        self.executable
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Flushes the instruction cache for the range of the executable view, and then serializes
+    /// the instruction streams of the cores, like `Mmap::publish_code()`.
+    pub fn publish_code(&self, range: Range<usize>) -> Result<(), Error> {
+        unsafe {
+            flush_icache(self.executable.add(range.start), range.end - range.start)
+        };
+
+        let result = serialize_cores();
+
+        trace_op!(
+            "publish_code",
+            result,
+            address = self.executable as usize + range.start,
+            size = range.end - range.start,
+        );
+
+        result
+    }
+}
+
+#[cfg(feature = "exec")]
+impl Drop for DualMmap {
+    fn drop(&mut self) {
+        for ptr in [self.writable, self.executable] {
+            let result = unsafe {
+                munmap(ptr as *mut std::ffi::c_void, self.size)
+            };
+
+            trace_op!("unmap_dual", result, address = ptr, size = self.size);
+        }
+    }
+}
+
+/// A mapping of a POSIX shared memory object, which other processes can open by its name.
+pub struct SharedMemory {
+    ptr: *mut u8,
+    size: usize,
+    /// The name of the shared memory object, starting with a slash.
+    path: String,
+    /// Whether the shared memory object has been created by this object, in which case the name is
+    /// unlinked when this object is dropped.
+    owned: bool,
+    /// Keeps the mapping registered for as long as it is alive.
+    _record: Record,
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl SharedMemory {
+    pub fn create(name: &str, size: usize) -> Result<Self, Error> {
+        let result = Self::map(name, Some(size));
+
+        trace_op!(
+            "map_shared",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            name = name,
+            size = size,
+        );
+
+        result
+    }
+
+    pub fn open(name: &str) -> Result<Self, Error> {
+        let result = Self::map(name, None);
+
+        trace_op!(
+            "map_shared",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            name = name,
+        );
+
+        result
+    }
+
+    /// Creates the shared memory object with the given size, or opens it if no size is given, and
+    /// maps it. The file descriptor is closed afterwards, as the mapping keeps the object alive.
+    fn map(name: &str, size: Option<usize>) -> Result<Self, Error> {
+        use nix::sys::stat::Mode;
+
+        // Portable names of shared memory objects start with a slash.
+        let path = if name.starts_with('/') {
+            name.to_string()
+        } else {
+            format!("/{}", name)
+        };
+
+        let flags = match size {
+            Some(_) => OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL,
+            _ => OFlag::O_RDWR,
+        };
+
+        let fd = shm_open(path.as_str(), flags, Mode::S_IRUSR | Mode::S_IWUSR)
+            .map_err(std::io::Error::from)?;
+        let file = unsafe { File::from_raw_fd(fd) };
+
+        let result = match size {
+            Some(size) => file.set_len(size as u64).map(|_| size),
+            _ => file.metadata().map(|metadata| metadata.len() as usize),
+        }.map_err(Error::from).and_then(|size| {
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    size,
+                    ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                    MapFlags::MAP_SHARED,
+                    file.as_raw_fd(),
+                    0,
+                )
+            }?;
+
+            Ok((ptr as *mut u8, size))
+        });
+
+        let (ptr, size) = match result {
+            Ok(mapping) => mapping,
+            Err(e) => {
+                if size.is_some() {
+                    let _ = shm_unlink(path.as_str());
+                }
+
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            ptr,
+            size,
+            path,
+            owned: flags.contains(OFlag::O_CREAT),
+            _record: Record::new(ptr as usize, size, MmapFlags::empty(), false),
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let result = unsafe {
+            munmap(self.ptr as *mut std::ffi::c_void, self.size)
+        };
+
+        trace_op!("unmap_shared", result, address = self.ptr, size = self.size);
+
+        if self.owned {
+            let _ = shm_unlink(self.path.as_str());
+        }
+    }
+}
+
+/// Creates anonymous shared memory of the given size, using `memfd_create()` on Linux and
+/// `SHM_ANON` on FreeBSD. Elsewhere, a POSIX shared memory object with a unique name is created
+/// and unlinked right away.
+fn shared_memory(size: usize) -> Result<File, Error> {
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    let fd = unsafe {
+        libc::memfd_create(b"mmap-rs\0".as_ptr() as *const libc::c_char, libc::MFD_CLOEXEC)
+    };
+
+    #[cfg(target_os = "freebsd")]
+    let fd = unsafe {
+        libc::shm_open(libc::SHM_ANON, libc::O_RDWR | libc::O_CLOEXEC, 0o600)
+    };
+
+    #[cfg(not(any(target_os = "android", target_os = "freebsd", target_os = "linux")))]
+    let fd = {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        // Names of shared memory objects are limited to 31 bytes on macOS.
+        let name = std::ffi::CString::new(format!(
+            "/mmap-rs.{}.{}",
+            getpid(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        )).unwrap();
+
+        let fd = unsafe {
+            libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CREAT | libc::O_EXCL, 0o600)
+        };
+
+        if fd >= 0 {
+            unsafe {
+                libc::shm_unlink(name.as_ptr())
+            };
+        }
+
+        fd
+    };
+
+    if fd < 0 {
+        return Err(Error::Io(std::io::Error::last_os_error()));
+    }
+
+    let file = unsafe { File::from_raw_fd(fd) };
+
+    file.set_len(size as u64)?;
+
+    Ok(file)
+}
+
+/// Returns the size of the huge pages that are used by [`MmapFlags::HUGE_PAGES`], if explicit
+/// huge pages are supported.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub fn huge_page_size() -> Option<usize> {
+    default_huge_page_size().ok().flatten()
+}
+
+/// Huge pages are used transparently on this platform.
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+pub fn huge_page_size() -> Option<usize> {
+    None
+}
+
+pub struct MmapOptions {
+    address: Option<usize>,
+    file: Option<(File, u64)>,
+    size: usize,
+    flags: MmapFlags,
+    unsafe_flags: UnsafeMmapFlags,
+    page_size: Option<PageSize>,
+    file_lock: Option<FileLock>,
+    drop_behavior: DropBehavior,
+    raw_map_flags: MapFlags,
+    raw_prot: ProtFlags,
+    reservation: Option<(Arc<Shared>, usize)>,
+    address_range: Option<Range<usize>>,
+    device: bool,
+}
+
+impl MmapOptions {
+    pub fn new(size: usize) -> Self {
+        Self {
+            address: None,
+            file: None,
+            size,
+            flags: MmapFlags::empty(),
+            unsafe_flags: UnsafeMmapFlags::empty(),
+            page_size: None,
+            file_lock: None,
+            drop_behavior: DropBehavior::Unmap,
+            raw_map_flags: MapFlags::empty(),
+            raw_prot: ProtFlags::empty(),
+            reservation: None,
+            address_range: None,
+            device: false,
+        }
+    }
+
+    pub fn page_size() -> (usize, usize) {
+        let status = sysconf(SysconfVar::PAGE_SIZE);
+
+        let size = match status {
+            Ok(Some(page_size)) => page_size as usize,
+            _ => 4096,
+        };
+
+        (size, size)
+    }
+
+    pub fn with_address(mut self, address: usize) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub fn with_reservation(mut self, reservation: Arc<Shared>, offset: usize) -> Self {
+        self.reservation = Some((reservation, offset));
+        self
+    }
+
+    pub fn with_address_range(mut self, range: Range<usize>) -> Self {
+        self.address_range = Some(range);
+        self
+    }
+
+    pub fn with_file(mut self, file: File, offset: u64) -> Self {
+        self.file = Some((file, offset));
+        self
+    }
+
+    pub fn with_device(mut self, file: File, offset: u64) -> Self {
+        self.file = Some((file, offset));
+        self.device = true;
+        self
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the options to map the given window of the file, relative to the offset of these
+    /// options, with the same flags as these options, but without the placement of the mapping,
+    /// as the windows are mapped one after the other.
+    pub fn window(&self, start: usize, size: usize) -> Result<Self, Error> {
+        let (file, offset) = match self.file {
+            Some((ref file, offset)) => (file, offset + start as u64),
+            _ => return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "only files can be mapped in chunks",
+            ))?,
+        };
+
+        Ok(Self {
+            address: None,
+            file: Some((file.try_clone()?, offset)),
+            size,
+            flags: self.flags,
+            unsafe_flags: self.unsafe_flags,
+            page_size: self.page_size,
+            file_lock: self.file_lock,
+            drop_behavior: self.drop_behavior,
+            raw_map_flags: self.raw_map_flags,
+            raw_prot: self.raw_prot,
+            reservation: None,
+            address_range: None,
+            device: self.device,
+        })
+    }
+
+    pub fn with_flags(mut self, flags: MmapFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn with_unsafe_flags(mut self, flags: UnsafeMmapFlags) -> Self {
+        self.unsafe_flags = flags;
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: PageSize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    pub fn check_huge_pages(&self) -> Result<(), Error> {
+        if !self.flags.contains(MmapFlags::HUGE_PAGES) && self.page_size.is_none() {
+            return Ok(());
+        }
+
+        let page_size = match self.page_size {
+            Some(page_size) if !is_huge_page_size(page_size) => return Ok(()),
+            Some(page_size) => check_page_size(page_size)?,
+            _ => match default_huge_page_size()? {
+                Some(page_size) => page_size,
+                _ => return Err(Error::HugePagesUnavailable(
+                    "the kernel has not been built with support for huge pages".to_string(),
+                )),
+            },
+        };
+
+        let total = match read_huge_page_counter(page_size, "nr_hugepages") {
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::HugePagesUnavailable(format!(
+                    "the kernel does not support huge pages of {} KiB",
+                    page_size >> 10,
+                )));
+            }
+            result => result?,
+        };
+
+        // Reserved huge pages are still counted as free, but are promised to other mappings.
+        let free = read_huge_page_counter(page_size, "free_hugepages")?;
+        let reserved = read_huge_page_counter(page_size, "resv_hugepages")?;
+        let available = free.saturating_sub(reserved);
+        let needed = self.size.div_ceil(page_size);
+
+        if needed > available {
+            return Err(Error::HugePagesUnavailable(format!(
+                "{} huge pages of {} KiB are needed, but only {} of the {} huge pages are available, \
+                reserve more huge pages through /sys/kernel/mm/hugepages/hugepages-{}kB/nr_hugepages",
+                needed,
+                page_size >> 10,
+                available,
+                total,
+                page_size >> 10,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Huge pages are used transparently on this platform, so there is nothing to check.
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    pub fn check_huge_pages(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub fn with_file_lock(mut self, lock: FileLock) -> Self {
+        self.file_lock = Some(lock);
+        self
+    }
+
+    pub fn with_drop_behavior(mut self, behavior: DropBehavior) -> Self {
+        self.drop_behavior = behavior;
+        self
+    }
+
+    pub unsafe fn with_raw_map_flags(mut self, flags: libc::c_int) -> Self {
+        self.raw_map_flags |= MapFlags::from_bits_unchecked(flags);
+        self
+    }
+
+    pub unsafe fn with_raw_prot(mut self, prot: libc::c_int) -> Self {
+        self.raw_prot |= ProtFlags::from_bits_unchecked(prot);
+        self
+    }
+
+    fn flags(&self) -> MapFlags {
+        let mut flags = MapFlags::empty();
+
+        if self.file.is_none() {
+            flags |= MapFlags::MAP_ANONYMOUS;
+        }
+
+        // Linux refuses to grow shared mappings downward, and OpenBSD only accepts private stacks.
+        // Stacks should not be shared with child processes either way.
+        let private = MmapFlags::COPY_ON_WRITE | MmapFlags::GROWS_DOWN | MmapFlags::STACK;
+
+        flags |= if self.flags.intersects(private) {
+            MapFlags::MAP_PRIVATE
+        } else {
+            MapFlags::MAP_SHARED
+        };
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.flags.contains(MmapFlags::POPULATE) {
+            flags |= MapFlags::MAP_POPULATE;
+        }
+
+        #[cfg(not(any(target_os = "dragonfly", target_os = "freebsd")))]
+        if self.flags.contains(MmapFlags::NO_RESERVE) {
+            flags |= MapFlags::MAP_NORESERVE;
+        }
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
         if self.flags.contains(MmapFlags::HUGE_PAGES) {
             flags |= MapFlags::MAP_HUGETLB;
         }
 
+        // The size is encoded in log2 notation above MAP_HUGE_SHIFT, such that any size the
+        // kernel supports can be used, rather than only those that have a MAP_HUGE_* constant.
+        // Sizes up to the page size use regular pages.
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if let Some(page_size) = self.page_size.filter(|page_size| is_huge_page_size(*page_size)) {
+            let bits = (page_size.0 as libc::c_int & libc::MAP_HUGE_MASK) << libc::MAP_HUGE_SHIFT;
+
+            // nix does not define every combination of the bits as a flag.
+            flags |= MapFlags::MAP_HUGETLB | unsafe { MapFlags::from_bits_unchecked(bits) };
+        }
+
+        #[cfg(target_os = "freebsd")]
+        if self.flags.contains(MmapFlags::HUGE_PAGES) {
+            flags |= MapFlags::MAP_ALIGNED_SUPER;
+        }
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.flags.contains(MmapFlags::LOCKED) {
+            flags |= MapFlags::MAP_LOCKED;
+        }
+
+        #[cfg(target_os = "netbsd")]
+        if self.flags.contains(MmapFlags::LOCKED) {
+            flags |= MapFlags::MAP_WIRED;
+        }
+
+        #[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "linux", target_os = "openbsd"))]
+        if self.flags.contains(MmapFlags::STACK) {
+            flags |= MapFlags::MAP_STACK;
+        }
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.flags.contains(MmapFlags::GROWS_DOWN) {
+            flags |= MapFlags::MAP_GROWSDOWN;
+        }
+
+        #[cfg(target_os = "openbsd")]
+        if self.flags.contains(MmapFlags::NO_CORE_DUMP) {
+            flags |= MapFlags::MAP_CONCEAL;
+        }
+
+        if self.unsafe_flags.contains(UnsafeMmapFlags::MAP_FIXED) {
+            flags |= MapFlags::MAP_FIXED;
+        }
+
+        // The kernel ignores this flag unless it has been configured to allow uninitialized
+        // anonymous memory, and it has no meaning for file mappings.
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.file.is_none() && self.unsafe_flags.contains(UnsafeMmapFlags::UNINITIALIZED) {
+            flags |= unsafe { MapFlags::from_bits_unchecked(MAP_UNINITIALIZED) };
+        }
+
+        #[cfg(all(feature = "jit", any(target_os = "ios", target_os = "macos")))]
+        if self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
+            flags |= MapFlags::MAP_JIT;
+        }
+
+        flags | self.raw_map_flags
+    }
+
+    /// The caching attributes of a mapping are decided by the driver backing the file rather than
+    /// by `mmap()` on Unix. This is a helper function that checks whether the requested caching
+    /// attributes can be honored, rather than silently returning a cached mapping.
+    fn check_cache_attributes(&self) -> Result<(), Error> {
+        let cache_flags = self.flags & (MmapFlags::UNCACHED | MmapFlags::WRITE_COMBINE);
+
+        if cache_flags.is_empty() {
+            return Ok(());
+        }
+
+        // Uncached and write-combining are mutually exclusive.
+        if cache_flags == MmapFlags::UNCACHED | MmapFlags::WRITE_COMBINE {
+            return Err(Error::UnsupportedFlags(cache_flags));
+        }
+
+        // Anonymous memory is always mapped as cached memory.
+        let file = match self.file.as_ref() {
+            Some((file, _)) => file,
+            _ => return Err(Error::UnsupportedFlags(cache_flags)),
+        };
+
+        // Regular files are mapped from the page cache, which is always cached memory, such that
+        // only the drivers backing device files can honor the caching attributes.
+        if !is_device_file(file)? {
+            return Err(Error::UnsupportedFlags(cache_flags));
+        }
+
+        // Only Linux lets drivers provide write-combining mappings, e.g. through the
+        // `resourceN_wc` files of PCI devices in sysfs or the framebuffer devices.
+        if cfg!(not(target_os = "linux")) && cache_flags.contains(MmapFlags::WRITE_COMBINE) {
+            return Err(Error::UnsupportedFlags(cache_flags));
+        }
+
+        // Drivers such as /dev/mem use O_SYNC to decide whether the mapping should be uncached.
+        if cache_flags.contains(MmapFlags::UNCACHED) {
+            let status = OFlag::from_bits_truncate(fcntl(file.as_raw_fd(), FcntlArg::F_GETFL)?);
+
+            if !status.contains(OFlag::O_SYNC) {
+                return Err(Error::UnsupportedFlags(cache_flags));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Acquires the advisory lock on the backing file, if requested. Returns true if the file has
+    /// been locked. The lock is exclusive if writes to the mapping are written back to the file.
+    fn lock_file(&self, protect: ProtFlags) -> Result<bool, Error> {
+        let (file, lock) = match (self.file.as_ref(), self.file_lock) {
+            (Some((file, _)), Some(lock)) => (file, lock),
+            _ => return Ok(false),
+        };
+
+        let exclusive = protect.contains(ProtFlags::PROT_WRITE) &&
+            !self.flags.contains(MmapFlags::COPY_ON_WRITE);
+
+        let arg = match (exclusive, lock) {
+            (false, FileLock::Wait) => FlockArg::LockShared,
+            (false, FileLock::Try) => FlockArg::LockSharedNonblock,
+            (true, FileLock::Wait) => FlockArg::LockExclusive,
+            (true, FileLock::Try) => FlockArg::LockExclusiveNonblock,
+        };
+
+        flock(file.as_raw_fd(), arg)?;
+
+        Ok(true)
+    }
+
+    fn do_map(self, protect: ProtFlags) -> Result<Mmap, Error> {
+        let hint = self.address;
+        let size = self.size;
+        let flags = self.flags;
+        let unsafe_flags = self.unsafe_flags;
+
+        let result = self.map_with(protect);
+
+        trace_op!(
+            "map",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            hint = hint,
+            size = size,
+            flags = flags,
+            unsafe_flags = unsafe_flags,
+            protect = protect,
+        );
+
+        result
+    }
+
+    /// Rounds the offset within the file down to the allocation granularity, as `mmap()` requires,
+    /// and grows the size to cover the bytes in between, of which the number is returned.
+    fn align_file_offset(&mut self) -> Result<usize, Error> {
+        let granularity = crate::allocation_granularity() as u64;
+
+        let skew = match self.file.as_mut() {
+            Some((_, offset)) => {
+                let skew = *offset % granularity;
+                *offset -= skew;
+                skew as usize
+            }
+            _ => return Ok(0),
+        };
+
+        self.size = self.size.checked_add(skew).ok_or(Error::OutOfBounds(0..self.size))?;
+
+        Ok(skew)
+    }
+
+    fn map_with(mut self, protect: ProtFlags) -> Result<Mmap, Error> {
+        self.check_cache_attributes()?;
+
+        let skew = self.align_file_offset()?;
+
         #[cfg(any(target_os = "android", target_os = "linux"))]
         if let Some(page_size) = self.page_size {
-            flags |= MapFlags::MAP_HUGETLB;
+            check_page_size(page_size)?;
+        }
 
-            flags |= match page_size {
-                PageSize::_64K  => MapFlags::MAP_HUGE_64KB,
-                PageSize::_512K => MapFlags::MAP_HUGE_512KB,
-                PageSize::_1M   => MapFlags::MAP_HUGE_1MB,
-                PageSize::_2M   => MapFlags::MAP_HUGE_2MB,
-                PageSize::_8M   => MapFlags::MAP_HUGE_8MB,
-                PageSize::_16M  => MapFlags::MAP_HUGE_16MB,
-                PageSize::_32M  => MapFlags::MAP_HUGE_32MB,
-                PageSize::_256M => MapFlags::MAP_HUGE_256MB,
-                PageSize::_512M => MapFlags::MAP_HUGE_512MB,
-                PageSize::_1G   => MapFlags::MAP_HUGE_1GB,
-                PageSize::_2G   => MapFlags::MAP_HUGE_2GB,
-                PageSize::_16G  => MapFlags::MAP_HUGE_16GB,
-                _ => MapFlags::empty(),
-            };
+        // Scrubbing a shared file mapping would overwrite the contents of the file.
+        if self.flags.contains(MmapFlags::ZERO_ON_DROP) && self.file.is_some() &&
+            !self.flags.contains(MmapFlags::COPY_ON_WRITE) {
+            return Err(Error::UnsupportedFlags(MmapFlags::ZERO_ON_DROP));
         }
 
-        #[cfg(any(target_os = "freebsd"))]
-        if self.flags.contains(MmapFlags::HUGE_PAGES) {
-            flags |= MapFlags::MAP_ALIGNED_SUPER;
+        // Only anonymous mappings can grow downward, and only on Linux.
+        if self.flags.contains(MmapFlags::GROWS_DOWN) &&
+            (self.file.is_some() || cfg!(not(any(target_os = "android", target_os = "linux")))) {
+            return Err(Error::UnsupportedFlags(MmapFlags::GROWS_DOWN));
         }
 
-        #[cfg(any(target_os = "android", target_os = "linux"))]
-        if self.flags.contains(MmapFlags::LOCKED) {
-            flags |= MapFlags::MAP_LOCKED;
+        // Reading device memory may have side effects.
+        if self.device && self.flags.contains(MmapFlags::POPULATE) {
+            return Err(Error::UnsupportedFlags(MmapFlags::POPULATE));
         }
 
-        #[cfg(target_os = "netbsd")]
-        if self.flags.contains(MmapFlags::LOCKED) {
-            flags |= MapFlags::MAP_WIRED;
+        if self.flags.contains(MmapFlags::CHECK_MEMORY_LIMIT) &&
+            self.flags.intersects(MmapFlags::POPULATE | MmapFlags::LOCKED) {
+            check_memory_limit(self.size)?;
         }
 
-        #[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "linux", target_os = "openbsd"))]
-        if self.flags.contains(MmapFlags::STACK) {
-            flags |= MapFlags::MAP_STACK;
+        // The pages of the reservation are replaced, which is safe as the range has been claimed.
+        let slot = self.reservation
+            .as_ref()
+            .map(|(reservation, offset)| Slot::claim(reservation.clone(), *offset, self.size))
+            .transpose()?;
+
+        let (address, fixed) = match slot.as_ref() {
+            Some(slot) => (Some(slot.address()), MapFlags::MAP_FIXED),
+            _ => (self.address, MapFlags::empty()),
+        };
+
+        // Views of a reservation are placed by the reservation instead.
+        let address_range = self.address_range.clone().filter(|_| slot.is_none());
+
+        if let Some(range) = address_range.as_ref() {
+            check_address_range(range)?;
         }
 
-        #[cfg(target_os = "openbsd")]
-        if self.flags.contains(MmapFlags::NO_CORE_DUMP) {
-            flags |= MapFlags::MAP_CONCEAL;
+        let file_locked = self.lock_file(protect)?;
+
+        let size = self.size;
+        let ptr = match self.map_pages(protect, address, fixed, address_range, slot.is_some()) {
+            Ok(ptr) => ptr,
+            Err(e) => {
+                // The lock belongs to the open file description, which may be shared with
+                // duplicates of the file descriptor, such that closing the file may not release it.
+                if let (true, Some((file, _))) = (file_locked, self.file.as_ref()) {
+                    let _ = flock(file.as_raw_fd(), FlockArg::Unlock);
+                }
+
+                return Err(e);
+            }
+        };
+
+        let mut flags = Flags::empty();
+
+        #[cfg(feature = "jit")]
+        if self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
+            flags |= Flags::JIT;
         }
 
-        if self.unsafe_flags.contains(UnsafeMmapFlags::MAP_FIXED) {
-            flags |= MapFlags::MAP_FIXED;
+        if self.flags.contains(MmapFlags::COPY_ON_WRITE) {
+            flags |= Flags::COPY_ON_WRITE;
         }
 
-        #[cfg(any(target_os = "ios", target_os = "macos"))]
-        if self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
-            flags |= MapFlags::MAP_JIT;
+        if self.flags.contains(MmapFlags::ZERO_ON_DROP) {
+            flags |= Flags::ZERO_ON_DROP;
         }
 
-        flags
+        if self.flags.contains(MmapFlags::LOCKED) {
+            flags |= Flags::LOCKED;
+        }
+
+        if self.flags.contains(MmapFlags::CHECK_MEMORY_LIMIT) {
+            flags |= Flags::CHECK_MEMORY_LIMIT;
+        }
+
+        if self.device {
+            flags |= Flags::DEVICE;
+        }
+
+        let record = Record::new(ptr as usize, size, self.flags, self.file.is_some());
+
+        Ok(Mmap {
+            offset: self.file.as_ref().map(|(_, offset)| *offset).unwrap_or(0),
+            file: self.file.map(|(file, _)| file),
+            ptr: ptr as *mut u8,
+            size,
+            skew,
+            flags,
+            protect: AtomicI32::new(protect.bits()),
+            drop_behavior: self.drop_behavior,
+            raw_prot: self.raw_prot,
+            file_locked,
+            slot: slot.map(Box::new),
+            record,
+        })
     }
 
-    fn do_map(self, protect: ProtFlags) -> Result<Mmap, Error> {
+    /// Maps the pages at the given address, or within the given address range, and applies the
+    /// advice and the locking that the flags ask for. The pages are unmapped again, or returned to
+    /// the reservation, if that fails.
+    fn map_pages(
+        &self,
+        protect: ProtFlags,
+        address: Option<usize>,
+        fixed: MapFlags,
+        address_range: Option<Range<usize>>,
+        reserved: bool,
+    ) -> Result<*mut std::ffi::c_void, Error> {
         let size = self.size;
-        let ptr = unsafe {
+        let map = |address: Option<usize>, flags: MapFlags| unsafe {
             mmap(
-                self.address
+                address
                     .map(|address| address as *mut std::ffi::c_void)
                     .unwrap_or(std::ptr::null_mut()),
                 size,
-                protect,
-                self.flags(),
+                protect | self.raw_prot,
+                self.flags() | flags,
                 self.file
                     .as_ref()
                     .map(|(file, _)| file.as_raw_fd())
@@ -335,8 +2702,55 @@ impl MmapOptions {
                     .map(|(_, offset)| *offset as _)
                     .unwrap_or(0),
             )
-        }?;
+        };
+
+        let map_error = |e| match e {
+            Errno::ENOMEM => out_of_memory(size, false),
+            // MAP_LOCKED fails with EAGAIN when the pages cannot be locked.
+            Errno::EAGAIN if self.flags.contains(MmapFlags::LOCKED) => out_of_memory(size, true),
+            e => wx_error(e, protect),
+        };
+
+        let ptr = match address_range {
+            Some(range) => map_within(range.clone(), address, size, map)
+                .map_err(map_error)?
+                .ok_or_else(|| Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::AddrNotAvailable,
+                    format!(
+                        "no free address range of {} bytes within {:#x}..{:#x}",
+                        size,
+                        range.start,
+                        range.end,
+                    ),
+                )))?,
+            // Move a hint that is in use to the next free spot, if the platform can tell.
+            _ if fixed.is_empty() => {
+                let hint = address.map(|address| {
+                    next_free(address, crate::align_up(size)).unwrap_or(address)
+                });
+
+                map(hint, fixed).map_err(map_error)?
+            }
+            _ => map(address, fixed).map_err(map_error)?,
+        };
+
+        if let Err(e) = self.prepare_pages(ptr, size) {
+            if reserved {
+                let _ = reserve(ptr as *mut u8, size);
+            } else {
+                let _ = unsafe { munmap(ptr, size) };
+            }
+
+            return Err(e);
+        }
+
+        Ok(ptr)
+    }
 
+    /// Applies the advice and the locking that the flags ask for to the pages that have just been
+    /// mapped.
+    #[cfg_attr(target_os = "netbsd", allow(unused_variables))]
+    fn prepare_pages(&self, ptr: *mut std::ffi::c_void, size: usize) -> Result<(), Error> {
         #[cfg(any(target_os = "android", target_os = "linux"))]
         if self.flags.contains(MmapFlags::NO_CORE_DUMP) {
             unsafe {
@@ -359,6 +2773,16 @@ impl MmapOptions {
             }?;
         }
 
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.flags.contains(MmapFlags::TRANSPARENT_HUGE_PAGES) {
+            advise_if_supported(ptr, size, MmapAdvise::MADV_HUGEPAGE)?;
+        }
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.flags.contains(MmapFlags::NO_MERGE) {
+            advise_if_supported(ptr, size, MmapAdvise::MADV_UNMERGEABLE)?;
+        }
+
         #[cfg(not(any(target_os = "android", target_os = "linux", target_os = "netbsd")))]
         if self.flags.contains(MmapFlags::LOCKED) {
             unsafe {
@@ -366,21 +2790,21 @@ impl MmapOptions {
                     ptr,
                     size,
                 )
-            }?;
+            }.map_err(|e| lock_error(e, size))?;
         }
 
-        let mut flags = Flags::empty();
+        Ok(())
+    }
 
-        if self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
-            flags |= Flags::JIT;
+    pub fn reserve(self) -> Result<ReservedMmap, Error> {
+        if self.file.is_some() || self.reservation.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "reserved mappings must be anonymous",
+            )));
         }
 
-        Ok(Mmap {
-            file: self.file.map(|(file, _)| file),
-            ptr: ptr as *mut u8,
-            size,
-            flags,
-        })
+        ReservedMmap::new(self.address, self.size)
     }
 
     pub fn map_none(self) -> Result<Mmap, Error> {
@@ -391,6 +2815,7 @@ impl MmapOptions {
         self.do_map(ProtFlags::PROT_READ)
     }
 
+    #[cfg(feature = "exec")]
     pub fn map_exec(self) -> Result<Mmap, Error> {
         self.do_map(ProtFlags::PROT_READ | ProtFlags::PROT_EXEC)
     }
@@ -399,6 +2824,37 @@ impl MmapOptions {
         self.do_map(ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
     }
 
+    /// Maps a stack with an inaccessible guard page below it, and returns the mapping along with
+    /// the size of the guard page. Stacks that grow downward do not get a guard page, as Linux
+    /// keeps a gap below them instead, which an inaccessible page would prevent them from growing
+    /// into.
+    pub fn map_stack(mut self) -> Result<(Mmap, usize), Error> {
+        if self.file.is_some() || self.reservation.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "stacks must be anonymous",
+            )));
+        }
+
+        let guard = if self.flags.contains(MmapFlags::GROWS_DOWN) {
+            0
+        } else {
+            crate::page_size()
+        };
+
+        self.size = self.size.checked_add(guard).ok_or(Error::OutOfBounds(0..self.size))?;
+        self.flags |= MmapFlags::STACK;
+
+        let mapping = self.map_mut()?;
+
+        if guard != 0 {
+            mapping.protect_ranges(&[(0..guard, Protection::empty())])?;
+        }
+
+        Ok((mapping, guard))
+    }
+
+    #[cfg(feature = "jit")]
     pub fn map_exec_mut(self) -> Result<Mmap, Error> {
         if !self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
             return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
@@ -407,3 +2863,653 @@ impl MmapOptions {
         self.do_map(ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC)
     }
 }
+
+/// Maps `size` bytes of readable and writable anonymous memory, where the page at the offset
+/// `guard` is made inaccessible. Returns a null pointer on failure. This does not allocate on the
+/// heap, as it backs the guarded allocator.
+pub fn map_guarded(size: usize, guard: usize) -> *mut u8 {
+    let ptr = match unsafe {
+        mmap(
+            std::ptr::null_mut(),
+            size,
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+            MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    } {
+        Ok(ptr) => ptr as *mut u8,
+        _ => return std::ptr::null_mut(),
+    };
+
+    let status = unsafe {
+        mprotect(
+            ptr.add(guard) as *mut std::ffi::c_void,
+            crate::page_size(),
+            ProtFlags::PROT_NONE,
+        )
+    };
+
+    if status.is_err() {
+        let _ = unsafe { munmap(ptr as *mut std::ffi::c_void, size) };
+
+        return std::ptr::null_mut();
+    }
+
+    ptr
+}
+
+/// Replaces the pages with inaccessible pages, which releases the memory, while keeping the
+/// address range reserved, such that accessing the pages faults.
+pub fn retire_guarded(ptr: *mut u8, size: usize) {
+    let _ = reserve(ptr, size);
+}
+
+pub fn lock_all(flags: LockAllFlags) -> Result<(), Error> {
+    let mut lock_flags = MlockAllFlags::empty();
+
+    if flags.contains(LockAllFlags::CURRENT) {
+        lock_flags |= MlockAllFlags::MCL_CURRENT;
+    }
+
+    if flags.contains(LockAllFlags::FUTURE) {
+        lock_flags |= MlockAllFlags::MCL_FUTURE;
+    }
+
+    // MCL_ONFAULT is not exposed by nix.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    if flags.contains(LockAllFlags::ON_FAULT) {
+        lock_flags |= unsafe { MlockAllFlags::from_bits_unchecked(libc::MCL_ONFAULT) };
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    if flags.contains(LockAllFlags::ON_FAULT) {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "locking pages as they are faulted in is not supported on this platform",
+        )));
+    }
+
+    let result = mlockall(lock_flags);
+
+    trace_op!("lock_all", result, flags = flags);
+
+    result.map_err(|e| lock_error(e, 0))?;
+
+    Ok(())
+}
+
+pub fn unlock_all() -> Result<(), Error> {
+    munlockall()?;
+
+    Ok(())
+}
+
+/// Makes the pages read-only to track the writes to them, or readable and writable again.
+///
+/// # Safety
+///
+/// The pages must belong to a mapping that is readable and writable.
+pub unsafe fn write_protect(ptr: *mut u8, size: usize, protect: bool) -> Result<(), Error> {
+    let prot = if protect {
+        ProtFlags::PROT_READ
+    } else {
+        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE
+    };
+
+    mprotect(ptr as *mut std::ffi::c_void, size, prot)?;
+
+    Ok(())
+}
+
+/// Spawns the command as a child process that inherits the file, where the number of the file
+/// descriptor is passed through the given environment variable. The file descriptor is only made
+/// inheritable in the child, such that other processes spawned in the meantime do not inherit it.
+pub fn spawn_inheriting(
+    command: &mut std::process::Command,
+    file: &File,
+    variable: &str,
+) -> Result<std::process::Child, Error> {
+    use std::os::unix::process::CommandExt;
+
+    let fd = file.as_raw_fd();
+
+    command.env(variable, fd.to_string());
+
+    // Only async-signal-safe functions may be called between fork() and exec().
+    unsafe {
+        command.pre_exec(move || {
+            let flags = libc::fcntl(fd, libc::F_GETFD);
+
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        })
+    };
+
+    Ok(command.spawn()?)
+}
+
+/// Takes ownership of the file descriptor inherited from the parent process.
+pub unsafe fn inherited_file(handle: u64) -> Result<File, Error> {
+    use std::convert::TryFrom;
+
+    let fd = libc::c_int::try_from(handle)
+        .map_err(|_| Error::Io(std::io::Error::from(std::io::ErrorKind::InvalidInput)))?;
+
+    // Make sure the file descriptor is not inherited any further.
+    Errno::result(libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC))?;
+
+    Ok(File::from_raw_fd(fd))
+}
+
+/// The membarrier() commands to serialize the instruction stream of every core that runs a thread
+/// of the process, which are not exposed by libc.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+const MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE: libc::c_int = 1 << 5;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE: libc::c_int = 1 << 6;
+
+/// Makes every core that runs a thread of the process serialize its instruction stream using
+/// `membarrier()`, which requires Linux 4.16 or newer, and is not supported on every
+/// architecture. The process is registered for the command on first use.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn serialize_cores() -> Result<(), Error> {
+    static REGISTERED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+
+    let registered = *REGISTERED.get_or_init(|| {
+        Errno::result(unsafe {
+            libc::syscall(
+                libc::SYS_membarrier,
+                MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE,
+                0,
+            )
+        }).is_ok()
+    });
+
+    if !registered {
+        return flush_process_write_buffers();
+    }
+
+    Errno::result(unsafe {
+        libc::syscall(libc::SYS_membarrier, MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE, 0)
+    })?;
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+fn serialize_cores() -> Result<(), Error> {
+    flush_process_write_buffers()
+}
+
+/// Interrupts every core that runs a thread of the process by changing the protection of a dirty
+/// page, which makes the kernel shoot down the TLB entries on those cores, like
+/// `FlushProcessWriteBuffers()` on Microsoft Windows. Taking the interrupt serializes the
+/// instruction stream of the core.
+fn flush_process_write_buffers() -> Result<(), Error> {
+    static PAGE: std::sync::OnceLock<Result<std::sync::Mutex<usize>, Errno>> =
+        std::sync::OnceLock::new();
+
+    let page = PAGE.get_or_init(|| {
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                crate::page_size(),
+                ProtFlags::PROT_NONE,
+                MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        }?;
+
+        // Keep the page resident, such that it does not have to be faulted in every time.
+        let _ = unsafe { mlock(ptr, crate::page_size()) };
+
+        Ok(std::sync::Mutex::new(ptr as usize))
+    });
+
+    let page = page.as_ref().map_err(|e| *e)?;
+    let page = page.lock().unwrap_or_else(|e| e.into_inner());
+    let ptr = *page as *mut std::ffi::c_void;
+
+    unsafe {
+        mprotect(ptr, crate::page_size(), ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)?;
+
+        // The page has to be dirty for the kernel to flush the TLB entries.
+        std::ptr::write_volatile(ptr as *mut usize, 0);
+
+        mprotect(ptr, crate::page_size(), ProtFlags::PROT_NONE)?;
+    }
+
+    Ok(())
+}
+
+/// The signals that are raised when writing to a read-only page, where macOS raises `SIGBUS`
+/// rather than `SIGSEGV`.
+const WRITE_FAULT_SIGNALS: [libc::c_int; 2] = [libc::SIGSEGV, libc::SIGBUS];
+
+/// The code of a fault caused by accessing a page without the required access rights, which is
+/// the same on every Unix platform, but is not exposed by libc on all of them.
+const SEGV_ACCERR: libc::c_int = 2;
+
+/// The handlers that were installed before the write fault handler, to which faults outside of
+/// the tracked mappings are passed on.
+static PREVIOUS_HANDLERS: std::sync::OnceLock<[libc::sigaction; 2]> = std::sync::OnceLock::new();
+
+/// Installs the handler that records writes to tracked mappings, once.
+pub fn install_write_fault_handler() -> Result<(), Error> {
+    static INSTALLED: std::sync::OnceLock<Result<(), Errno>> = std::sync::OnceLock::new();
+
+    let result = *INSTALLED.get_or_init(|| unsafe {
+        // The previous handlers have to be known before the new handler can be invoked.
+        let mut previous: [libc::sigaction; 2] = std::mem::zeroed();
+
+        for (signal, previous) in WRITE_FAULT_SIGNALS.iter().zip(&mut previous) {
+            Errno::result(libc::sigaction(*signal, std::ptr::null(), previous))?;
+        }
+
+        let _ = PREVIOUS_HANDLERS.set(previous);
+
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_write_fault as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        for signal in WRITE_FAULT_SIGNALS {
+            Errno::result(libc::sigaction(signal, &action, std::ptr::null_mut()))?;
+        }
+
+        Ok(())
+    });
+
+    Ok(result?)
+}
+
+/// Records writes to tracked mappings and makes the page writable again, such that the write is
+/// retried once the handler returns. Other faults, such as `SIGBUS` raised by accessing pages past
+/// the end of a truncated file, are passed on to the previous handler, as making the page
+/// writable would not resolve them.
+extern "C" fn handle_write_fault(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut std::ffi::c_void,
+) {
+    let address = unsafe { (*info).si_addr() } as usize;
+
+    // macOS raises `SIGBUS` rather than `SIGSEGV` when writing to a read-only page, with a code
+    // that does not tell it apart from other bus errors.
+    let is_write_fault = match signal {
+        libc::SIGSEGV => (unsafe { (*info).si_code }) == SEGV_ACCERR,
+        _ => cfg!(any(target_os = "ios", target_os = "macos")),
+    };
+
+    if is_write_fault {
+        if let Some(page) = crate::dirty::record_write_fault(address) {
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let page = page as *mut std::ffi::c_void;
+
+            if unsafe { libc::mprotect(page, crate::page_size(), prot) } == 0 {
+                return;
+            }
+        }
+    }
+
+    let index = if signal == libc::SIGSEGV { 0 } else { 1 };
+    let previous = match PREVIOUS_HANDLERS.get() {
+        Some(previous) => &previous[index],
+        None => return,
+    };
+
+    unsafe {
+        if previous.sa_flags & libc::SA_SIGINFO != 0 {
+            let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut std::ffi::c_void) =
+                std::mem::transmute(previous.sa_sigaction);
+
+            handler(signal, info, context);
+        } else if previous.sa_sigaction == libc::SIG_DFL || previous.sa_sigaction == libc::SIG_IGN {
+            // Ignoring a fault would retry the faulting instruction forever, so reset to the
+            // default action and raise the signal again, which is delivered once the handler
+            // returns and terminates the process.
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = libc::SIG_DFL;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+            libc::raise(signal);
+        } else {
+            let handler: extern "C" fn(libc::c_int) = std::mem::transmute(previous.sa_sigaction);
+
+            handler(signal);
+        }
+    }
+}
+
+pub fn capabilities() -> Capabilities {
+    let (page_size, allocation_granularity) =
+        (crate::page_size(), crate::allocation_granularity());
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    let memfd = probe_memfd();
+
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    let memfd = false;
+
+    Capabilities {
+        page_size,
+        allocation_granularity,
+        huge_page_sizes: huge_page_sizes(),
+        fixed_noreplace: fixed_noreplace(),
+        memfd,
+        // Sealing was added to memfd_create() right away.
+        sealing: memfd,
+        jit: cfg!(any(target_os = "ios", target_os = "macos")),
+        write_watch: false,
+        numa_nodes: numa_nodes(),
+    }
+}
+
+/// Returns the sizes of the huge pages that the kernel supports, as listed in sysfs.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn huge_page_sizes() -> Vec<usize> {
+    let entries = match std::fs::read_dir("/sys/kernel/mm/hugepages") {
+        Ok(entries) => entries,
+        _ => return vec![],
+    };
+
+    let mut sizes: Vec<usize> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            entry.file_name()
+                .to_str()?
+                .strip_prefix("hugepages-")?
+                .strip_suffix("kB")?
+                .parse::<usize>()
+                .ok()
+        })
+        .map(|size| size << 10)
+        .collect();
+
+    sizes.sort_unstable();
+    sizes
+}
+
+/// Returns the sizes of the super pages that the kernel supports, excluding the base page size.
+#[cfg(target_os = "freebsd")]
+fn huge_page_sizes() -> Vec<usize> {
+    let mut sizes = vec![0; 8];
+
+    let count = unsafe {
+        libc::getpagesizes(sizes.as_mut_ptr(), sizes.len() as libc::c_int)
+    };
+
+    sizes.truncate(count.max(0) as usize);
+    sizes.retain(|&size| size > crate::page_size());
+    sizes.sort_unstable();
+    sizes
+}
+
+#[cfg(not(any(target_os = "android", target_os = "freebsd", target_os = "linux")))]
+fn huge_page_sizes() -> Vec<usize> {
+    vec![]
+}
+
+/// MAP_FIXED_NOREPLACE was added in Linux 4.17, older kernels treat it as a hint.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn fixed_noreplace() -> bool {
+    let utsname = match nix::sys::utsname::uname() {
+        Ok(utsname) => utsname,
+        _ => return false,
+    };
+
+    let release = utsname.release().to_string_lossy();
+    let mut version = release
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse::<u32>().unwrap_or(0));
+
+    let major = version.next().unwrap_or(0);
+    let minor = version.next().unwrap_or(0);
+
+    (major, minor) >= (4, 17)
+}
+
+/// FreeBSD supports MAP_EXCL in combination with MAP_FIXED.
+#[cfg(target_os = "freebsd")]
+fn fixed_noreplace() -> bool {
+    true
+}
+
+#[cfg(not(any(target_os = "android", target_os = "freebsd", target_os = "linux")))]
+fn fixed_noreplace() -> bool {
+    false
+}
+
+/// Probes for memfd_create(), which was added in Linux 3.17 and may be blocked by seccomp.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn probe_memfd() -> bool {
+    let fd = unsafe {
+        libc::memfd_create(b"mmap-rs\0".as_ptr() as *const libc::c_char, libc::MFD_CLOEXEC)
+    };
+
+    if fd < 0 {
+        return false;
+    }
+
+    let _ = close(fd);
+
+    true
+}
+
+/// Counts the NUMA nodes that are listed in sysfs.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn numa_nodes() -> usize {
+    let entries = match std::fs::read_dir("/sys/devices/system/node") {
+        Ok(entries) => entries,
+        _ => return 1,
+    };
+
+    let count = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("node"))
+                .is_some_and(|id| !id.is_empty() && id.bytes().all(|c| c.is_ascii_digit()))
+        })
+        .count();
+
+    count.max(1)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+fn numa_nodes() -> usize {
+    1
+}
+
+/// Flushes the pages in the given range of addresses to the file backing them. As flushing never
+/// changes the contents of the pages, this is safe to call even if the pages have been unmapped,
+/// in which case this merely fails.
+pub fn flush_range(address: usize, size: usize, _file: Option<&File>, sync: bool) -> Result<(), Error> {
+    let flags = if sync {
+        MsFlags::MS_SYNC
+    } else {
+        MsFlags::MS_ASYNC
+    };
+
+    let result = unsafe {
+        msync(address as *mut std::ffi::c_void, size, flags)
+    };
+
+    trace_op!("flush_range", result, address = address, size = size, sync = sync);
+
+    Ok(result?)
+}
+
+/// Returns for every page in the given range whether the page has been touched, i.e. whether the
+/// page is either resident or swapped out. This uses the page map of the current process.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub fn touched_pages(address: usize, size: usize) -> Result<Option<Vec<bool>>, Error> {
+    use std::convert::TryInto;
+
+    const PAGE_PRESENT: u64 = 1 << 63;
+    const PAGE_SWAPPED: u64 = 1 << 62;
+
+    let page_size = crate::page_size();
+    let start = address / page_size;
+    let end = (address + size).div_ceil(page_size);
+
+    let mut entries = vec![0u8; (end - start) * 8];
+    let file = File::open("/proc/self/pagemap")?;
+
+    read_exact_at(&file, &mut entries, (start * 8) as u64)?;
+
+    Ok(Some(entries
+        .chunks_exact(8)
+        .map(|entry| u64::from_ne_bytes(entry.try_into().unwrap()))
+        .map(|entry| entry & (PAGE_PRESENT | PAGE_SWAPPED) != 0)
+        .collect()))
+}
+
+/// The bitmap through which pages are marked as idle, and which reports whether the pages have
+/// been accessed since, indexed by the page frame number.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+const PAGE_IDLE_BITMAP: &str = "/sys/kernel/mm/page_idle/bitmap";
+
+/// Returns the page frame number of every page in the given range, or `None` for pages that are
+/// not resident. The page frame numbers are only reported to processes with `CAP_SYS_ADMIN`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn page_frames(address: usize, size: usize) -> Result<Vec<Option<u64>>, Error> {
+    use std::convert::TryInto;
+
+    const PAGE_PRESENT: u64 = 1 << 63;
+    const PAGE_FRAME_MASK: u64 = (1 << 55) - 1;
+
+    let page_size = crate::page_size();
+    let start = address / page_size;
+    let end = (address + size).div_ceil(page_size);
+
+    let mut entries = vec![0u8; (end - start) * 8];
+    let file = File::open("/proc/self/pagemap")?;
+
+    read_exact_at(&file, &mut entries, (start * 8) as u64)?;
+
+    entries
+        .chunks_exact(8)
+        .map(|entry| u64::from_ne_bytes(entry.try_into().unwrap()))
+        .map(|entry| match (entry & PAGE_PRESENT, entry & PAGE_FRAME_MASK) {
+            (0, _) => Ok(None),
+            (_, 0) => Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "the page frame numbers are hidden from processes without CAP_SYS_ADMIN",
+            ))),
+            (_, frame) => Ok(Some(frame)),
+        })
+        .collect()
+}
+
+/// Opens the bitmap of idle pages, which is only available if the kernel has been built with
+/// idle page tracking.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn open_page_idle_bitmap(write: bool) -> Result<File, Error> {
+    let result = std::fs::OpenOptions::new()
+        .read(!write)
+        .write(write)
+        .open(PAGE_IDLE_BITMAP);
+
+    match result {
+        Ok(file) => Ok(file),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "idle page tracking is not available (see CONFIG_IDLE_PAGE_TRACKING)",
+        ))),
+        Err(e) => Err(Error::Io(e)),
+    }
+}
+
+/// Marks the resident pages in the given range as idle, which clears the accessed bits of the
+/// page table entries mapping them. `/proc/self/clear_refs` clears the accessed bits as well, but
+/// they are not reported through the page map or `/proc/kpageflags` afterwards, whereas idle page
+/// tracking consults the accessed bits when the bitmap is read.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub fn clear_accessed(address: usize, size: usize) -> Result<(), Error> {
+    use std::collections::BTreeMap;
+    use std::os::unix::fs::FileExt;
+
+    let mut words: BTreeMap<u64, u64> = BTreeMap::new();
+
+    for frame in page_frames(address, size)?.into_iter().flatten() {
+        *words.entry(frame / 64).or_default() |= 1 << (frame % 64);
+    }
+
+    let file = open_page_idle_bitmap(true)?;
+
+    for (word, bits) in words {
+        file.write_all_at(&bits.to_ne_bytes(), word * 8)?;
+    }
+
+    Ok(())
+}
+
+/// Returns for every page in the given range whether the page has been accessed since the pages
+/// were marked as idle. Pages that are not resident have not been accessed, while pages that
+/// became resident since are not idle and therefore have been accessed.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub fn accessed_pages(address: usize, size: usize) -> Result<Vec<bool>, Error> {
+    let frames = page_frames(address, size)?;
+    let file = open_page_idle_bitmap(false)?;
+
+    // Neighbouring pages tend to share the same word of the bitmap.
+    let mut cached: Option<(u64, u64)> = None;
+
+    frames
+        .into_iter()
+        .map(|frame| {
+            let frame = match frame {
+                Some(frame) => frame,
+                None => return Ok(false),
+            };
+
+            let bits = match cached {
+                Some((word, bits)) if word == frame / 64 => bits,
+                _ => {
+                    let mut bytes = [0u8; 8];
+                    read_exact_at(&file, &mut bytes, frame / 64 * 8)?;
+
+                    let bits = u64::from_ne_bytes(bytes);
+                    cached = Some((frame / 64, bits));
+                    bits
+                }
+            };
+
+            Ok(bits & (1 << (frame % 64)) == 0)
+        })
+        .collect()
+}
+
+/// Marks the pages in the given range as idle. This is not supported on this platform.
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+pub fn clear_accessed(_address: usize, _size: usize) -> Result<(), Error> {
+    Err(Error::Io(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the accessed bits of pages cannot be sampled on this platform",
+    )))
+}
+
+/// Returns for every page in the given range whether the page has been accessed. This is not
+/// supported on this platform.
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+pub fn accessed_pages(_address: usize, _size: usize) -> Result<Vec<bool>, Error> {
+    Err(Error::Io(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the accessed bits of pages cannot be sampled on this platform",
+    )))
+}
+
+/// Returns for every page in the given range whether the page has been touched. This is not
+/// supported on this platform.
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+pub fn touched_pages(_address: usize, _size: usize) -> Result<Option<Vec<bool>>, Error> {
+    Ok(None)
+}
@@ -1,56 +1,228 @@
 use bitflags::bitflags;
-use crate::areas::{MemoryArea, Protection, ShareMode};
-use crate::mmap::{MmapFlags, PageSize, UnsafeMmapFlags};
-use crate::error::Error;
+use crate::protection::Protection;
+use crate::capabilities::Capabilities;
+use crate::lock::LockAllFlags;
+use crate::reservation::{Shared, Slot};
+use crate::mmap::{
+    allocation_granularity, check_address_range, Advice, DropBehavior, Eviction, FileLock,
+    MmapFlags, PageSize, UnsafeMmapFlags,
+};
+use crate::error::{Error, OutOfMemoryCause};
+use crate::stats::Record;
+
+pub use super::file::{lock_file_range, read_exact_at, unlock_file_range};
+use super::process::enable_lock_memory_privilege;
 use std::fs::File;
 use std::ops::Range;
+use std::sync::Arc;
 use std::os::windows::io::AsRawHandle;
-use std::path::PathBuf;
 use windows::core::PCWSTR;
-use windows::Win32::Foundation::{CloseHandle, HANDLE, MAX_PATH};
-#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-use windows::Win32::System::Diagnostics::Debug::FlushInstructionCache;
+use windows::Win32::Foundation::{
+    CloseHandle, DuplicateHandle, DUPLICATE_SAME_ACCESS, ERROR_ALREADY_EXISTS, ERROR_COMMITMENT_LIMIT, ERROR_COMMITMENT_MINIMUM, ERROR_DYNAMIC_CODE_BLOCKED,
+    ERROR_INVALID_ADDRESS, ERROR_INVALID_PARAMETER, ERROR_NOT_ENOUGH_MEMORY,
+    ERROR_NO_SYSTEM_RESOURCES, ERROR_PRIVILEGE_NOT_HELD, ERROR_WORKING_SET_QUOTA, EXCEPTION_ACCESS_VIOLATION, HANDLE, INVALID_HANDLE_VALUE,
+};
+use windows::Win32::Storage::FileSystem::{
+    LockFileEx, UnlockFileEx, FILE_EXECUTE, FILE_WRITE_DATA, LOCKFILE_EXCLUSIVE_LOCK,
+    LOCKFILE_FAIL_IMMEDIATELY, LOCK_FILE_FLAGS,
+};
+use windows::Win32::System::Diagnostics::Debug::{
+    AddVectoredExceptionHandler, FlushInstructionCache, EXCEPTION_POINTERS,
+};
+use windows::Win32::System::IO::OVERLAPPED;
 use windows::Win32::System::Memory::*;
-use windows::Win32::System::ProcessStatus::K32GetMappedFileNameW;
-use windows::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
-use windows::Win32::System::Threading::{PROCESS_ALL_ACCESS, GetCurrentProcess, OpenProcess};
+use windows::Win32::System::ProcessStatus::{
+    K32GetProcessMemoryInfo, K32QueryWorkingSetEx,
+    PROCESS_MEMORY_COUNTERS, PSAPI_WORKING_SET_EX_BLOCK, PSAPI_WORKING_SET_EX_INFORMATION,
+};
+use windows::Win32::System::SystemServices::{
+    QUOTA_LIMITS_HARDWS_MIN_DISABLE, QUOTA_LIMITS_HARDWS_MIN_ENABLE,
+};
+use windows::Win32::System::SystemInformation::{
+    GetSystemInfo, GlobalMemoryStatusEx, MEMORYSTATUSEX, SYSTEM_INFO,
+};
+use windows::Win32::System::Threading::{
+    FlushProcessWriteBuffers, GetCurrentProcess, GetNumaHighestNodeNumber, GetProcessWorkingSetSize,
+};
+use windows::Win32::System::WindowsProgramming::{
+    NtQueryObject, ObjectBasicInformation, PUBLIC_OBJECT_BASIC_INFORMATION,
+};
 
 bitflags! {
     struct Flags: u32 {
         const COPY_ON_WRITE = 1 << 0;
         const JIT           = 1 << 1;
+        const UNCACHED      = 1 << 2;
+        const WRITE_COMBINE = 1 << 3;
+        const LOCKED        = 1 << 4;
+        const ZERO_ON_DROP  = 1 << 5;
+        const CHECK_MEMORY_LIMIT = 1 << 6;
+    }
+}
+
+/// Combines the page protection with the caching attributes that have been requested, as
+/// `PAGE_NOCACHE` and `PAGE_WRITECOMBINE` are modifiers to the page protection. Note that these
+/// modifiers cannot be combined with `PAGE_NOACCESS`.
+fn with_cache_attributes(flags: Flags, protect: PAGE_PROTECTION_FLAGS) -> PAGE_PROTECTION_FLAGS {
+    if protect == PAGE_NOACCESS {
+        return protect;
+    }
+
+    if flags.contains(Flags::UNCACHED) {
+        protect | PAGE_NOCACHE
+    } else if flags.contains(Flags::WRITE_COMBINE) {
+        protect | PAGE_WRITECOMBINE
+    } else {
+        protect
+    }
+}
+
+/// Returns the access that a view of a file mapping needs to support the given protection.
+fn view_access(protect: PAGE_PROTECTION_FLAGS) -> FILE_MAP {
+    match protect {
+        PAGE_READWRITE => FILE_MAP_READ | FILE_MAP_WRITE,
+        PAGE_WRITECOPY => FILE_MAP_COPY,
+        PAGE_EXECUTE_READ => FILE_MAP_READ | FILE_MAP_EXECUTE,
+        PAGE_EXECUTE_READWRITE => FILE_MAP_READ | FILE_MAP_WRITE | FILE_MAP_EXECUTE,
+        PAGE_EXECUTE_WRITECOPY => FILE_MAP_COPY | FILE_MAP_EXECUTE,
+        _ => FILE_MAP_READ,
     }
 }
 
+/// The file mapping object (or section) backing a view, which is kept open such that the view can
+/// be mapped again when it grows or moves.
+struct Section {
+    handle: HANDLE,
+    offset: u64,
+    /// The access that the view has been mapped with, which covers all the access that the file
+    /// handle grants.
+    access: FILE_MAP,
+    /// The protection that the section has been created with, such that a larger section can be
+    /// created the same way when the view grows.
+    protection: PAGE_PROTECTION_FLAGS,
+}
+
 pub struct Mmap {
     file: Option<File>,
+    section: Option<Section>,
     ptr: *mut u8,
     size: usize,
+    /// The offset of the mapping within the view, as the offset within the file has been rounded
+    /// down to the allocation granularity. The pointer, the size and the offset of the section
+    /// cover the whole view.
+    skew: usize,
     flags: Flags,
+    drop_behavior: DropBehavior,
+    raw_protection: PAGE_PROTECTION_FLAGS,
+    file_locked: bool,
+    /// The range of the reservation that replaced a placeholder, if any.
+    slot: Option<Box<Slot>>,
+    record: Record,
 }
 
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
 impl Mmap {
     #[inline]
     pub fn file(&self) -> Option<&File> {
         self.file.as_ref()
     }
 
+    /// Returns the offset within the file at which the view starts.
+    #[inline]
+    pub fn file_offset(&self) -> u64 {
+        self.section.as_ref().map_or(0, |section| section.offset + self.skew as u64)
+    }
+
     #[inline]
     pub fn as_ptr(&self) -> *const u8 {
-        self.ptr
+        unsafe { self.ptr.add(self.skew) }
     }
 
     #[inline]
     pub fn as_mut_ptr(&mut self) -> *mut u8 {
-        self.ptr
+        unsafe { self.ptr.add(self.skew) }
     }
 
     #[inline]
     pub fn size(&self) -> usize {
-        self.size
+        self.size - self.skew
+    }
+
+    /// Translates the range of the mapping into the range of the pages that cover it, relative to
+    /// the start of the view.
+    fn page_range(&self, range: Range<usize>) -> Range<usize> {
+        let end = (range.end + self.skew).min(crate::align_up(self.size));
+
+        crate::align_down(range.start + self.skew)..end
+    }
+
+    /// The flags that carry over to an anonymous copy of the mapping.
+    pub fn anon_flags(&self) -> MmapFlags {
+        let mut flags = MmapFlags::empty();
+
+        if self.flags.contains(Flags::LOCKED) {
+            flags |= MmapFlags::LOCKED;
+        }
+
+        if self.flags.contains(Flags::ZERO_ON_DROP) {
+            flags |= MmapFlags::ZERO_ON_DROP;
+        }
+
+        if self.flags.contains(Flags::CHECK_MEMORY_LIMIT) {
+            flags |= MmapFlags::CHECK_MEMORY_LIMIT;
+        }
+
+        flags
+    }
+
+    /// Checks whether the whole mapping fits within the commit limit, if requested.
+    pub fn check_memory_limit(&self) -> Result<(), Error> {
+        if self.flags.contains(Flags::CHECK_MEMORY_LIMIT) {
+            check_memory_limit(self.size)?;
+        }
+
+        Ok(())
+    }
+
+    /// The protection is not tracked, so query the protection of the whole mapping instead.
+    pub fn protection(&self) -> Protection {
+        self.protection_of(0..self.size()).unwrap_or(Protection::empty())
+    }
+
+    pub fn protection_of(&self, range: Range<usize>) -> Result<Protection, Error> {
+        let mut address = self.as_ptr() as usize + range.start;
+        let end = self.as_ptr() as usize + range.end;
+        let mut protection = Protection::all();
+
+        while address < end {
+            let mut info = MEMORY_BASIC_INFORMATION::default();
+
+            let status = unsafe {
+                VirtualQuery(
+                    address as *const std::ffi::c_void,
+                    &mut info,
+                    std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+                )
+            };
+
+            if status == 0 {
+                return Err(std::io::Error::last_os_error())?;
+            }
+
+            protection &= to_protection(info.Protect);
+            address = info.BaseAddress as usize + info.RegionSize;
+        }
+
+        Ok(protection)
     }
 
     pub fn lock(&mut self) -> Result<(), Error> {
+        self.check_memory_limit()?;
+
         let status = unsafe {
             VirtualLock(
                 self.ptr as *const std::ffi::c_void,
@@ -58,9 +230,17 @@ impl Mmap {
             )
         }.as_bool();
 
-        if !status {
-            return Err(std::io::Error::last_os_error())?;
-        }
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("lock", result, address = self.ptr, size = self.size);
+
+        result.map_err(lock_error)?;
+        self.flags |= Flags::LOCKED;
+        self.record.lock();
 
         Ok(())
     }
@@ -73,21 +253,64 @@ impl Mmap {
             )
         }.as_bool();
 
-        if !status {
-            return Err(std::io::Error::last_os_error())?;
-        }
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("unlock", result, address = self.ptr, size = self.size);
+
+        result?;
+        self.flags.remove(Flags::LOCKED);
+        self.record.unlock();
 
         Ok(())
     }
 
+    /// Locks or unlocks the pages in the given range, without marking the mapping as locked, such
+    /// that large mappings can be locked one chunk at a time.
+    pub fn lock_pages(&self, range: Range<usize>, lock: bool) -> Result<(), Error> {
+        let range = self.page_range(range);
+        let address = unsafe { self.ptr.add(range.start) } as *const std::ffi::c_void;
+        let size = range.end - range.start;
+
+        let status = unsafe {
+            if lock {
+                VirtualLock(address, size)
+            } else {
+                VirtualUnlock(address, size)
+            }
+        }.as_bool();
+
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("lock_pages", result, address = self.ptr, range = range, lock = lock);
+
+        result.map_err(lock_error)
+    }
+
+    /// Marks the mapping as locked, after all of its pages have been locked using `lock_pages()`.
+    pub fn set_locked(&mut self) {
+        self.flags |= Flags::LOCKED;
+        self.record.lock();
+    }
+
     pub fn flush(&self, range: Range<usize>) -> Result<(), Error> {
-        self.flush_async(range)?;
+        self.flush_async(range.clone())?;
 
-        if let Some(ref file) = self.file {
-            file.sync_data()?;
-        }
+        let result = match self.file {
+            Some(ref file) => file.sync_data(),
+            _ => Ok(()),
+        };
 
-        Ok(())
+        trace_op!("flush", result, address = self.ptr, range = range);
+
+        Ok(result?)
     }
 
     pub fn flush_async(&self, range: Range<usize>) -> Result<(), Error> {
@@ -95,223 +318,2440 @@ impl Mmap {
             return Ok(());
         }
 
+        let range = self.page_range(range);
+
         let status = unsafe {
             FlushViewOfFile(
-                self.ptr.offset(range.start as isize) as *const std::ffi::c_void,
+                self.ptr.add(range.start) as *const std::ffi::c_void,
                 range.end - range.start,
             )
         }.as_bool();
 
-        if !status {
-            return Err(std::io::Error::last_os_error())?;
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("flush_async", result, address = self.ptr, range = range);
+
+        Ok(result?)
+    }
+
+    /// Flushes the sorted, page-aligned ranges of the view, and syncs the file once afterwards
+    /// when flushing synchronously, as `FlushViewOfFile()` does not wait for the pages to be
+    /// written to the disk.
+    pub fn flush_ranges(&self, ranges: &[Range<usize>], sync: bool) -> Result<(), Error> {
+        let mut result = ranges.iter().try_for_each(|range| {
+            let range = self.page_range(range.clone());
+
+            let status = unsafe {
+                FlushViewOfFile(
+                    self.ptr.add(range.start) as *const std::ffi::c_void,
+                    range.end - range.start,
+                )
+            }.as_bool();
+
+            if status {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+
+        if let (Ok(()), Some(file), true) = (&result, &self.file, sync) {
+            result = file.sync_data();
         }
 
+        trace_op!("flush_ranges", result, address = self.ptr, ranges = ranges.len(), sync = sync);
+
+        Ok(result?)
+    }
+
+    /// There is no advice for sequential access on Microsoft Windows, other than prefetching.
+    pub fn set_sequential(&self, _sequential: bool) -> Result<(), Error> {
         Ok(())
     }
 
-    pub fn do_make(&self, protect: PAGE_PROTECTION_FLAGS) -> Result<(), Error> {
-        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+    /// Advises the operating system that the pages in the given range will be accessed soon, such
+    /// that they can be read ahead.
+    pub fn read_ahead(&self, range: Range<usize>) -> Result<(), Error> {
+        let range = self.page_range(range);
+        let entry = WIN32_MEMORY_RANGE_ENTRY {
+            VirtualAddress: unsafe { self.ptr.add(range.start) } as *mut std::ffi::c_void,
+            NumberOfBytes: range.end - range.start,
+        };
 
         let status = unsafe {
-            VirtualProtect(
-                self.ptr as *mut std::ffi::c_void,
-                self.size,
-                protect,
-                &mut old_protect,
-            ).as_bool()
-        };
+            PrefetchVirtualMemory(GetCurrentProcess(), &[entry], 0)
+        }.as_bool();
 
-       if !status {
-           return Err(std::io::Error::last_os_error())?;
+        if !status {
+            return Err(std::io::Error::last_os_error())?;
         }
 
         Ok(())
     }
 
-    pub fn flush_icache(&self) -> Result<(), Error> {
-        // While the x86 and x86-64 architectures guarantee cache coherency between the L1
-        // instruction and the L1 data cache, other architectures such as arm and aarch64 do not.
-        // If the user modified the pages, then executing the code after marking the pages as
-        // executable may result in undefined behavior. Since we cannot efficiently track writes,
-        // we have to flush the instruction cache unconditionally.
-        #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    /// There are no transparent huge pages on Microsoft Windows.
+    pub fn advise_huge_pages(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Faults in the pages in the given range by touching them, as `PrefetchVirtualMemory()` only
+    /// reads the pages into memory without mapping them into the working set.
+    pub fn populate(&self, range: Range<usize>, write: bool) -> Result<(), Error> {
+        let range = self.page_range(range);
+
         unsafe {
-            FlushInstructionCache(
-                GetCurrentProcess(),
-                self.ptr as *const std::ffi::c_void,
-                self.size,
-            )
+            super::touch(self.ptr.add(range.start), range.end - range.start, write)
         };
 
         Ok(())
     }
 
-    pub fn make_none(&self) -> Result<(), Error> {
-        self.do_make(PAGE_NOACCESS)
-    }
+    /// Removes the pages in the given range from the working set of the process. Unlocking pages
+    /// that are not locked removes them from the working set without discarding their contents,
+    /// unlike `DiscardVirtualMemory()`. Locked mappings are left alone, as unlocking them would
+    /// actually unlock the pages.
+    pub fn drop_behind(&self, range: Range<usize>) -> Result<(), Error> {
+        if self.flags.contains(Flags::LOCKED) {
+            return Ok(());
+        }
 
-    pub fn make_read_only(&self) -> Result<(), Error> {
-        self.do_make(PAGE_READWRITE)
+        let range = self.page_range(range);
+
+        // This always fails with `ERROR_NOT_LOCKED` as the pages are not locked.
+        unsafe {
+            VirtualUnlock(
+                self.ptr.add(range.start) as *const std::ffi::c_void,
+                range.end - range.start,
+            )
+        };
+
+        Ok(())
     }
 
-    pub fn make_exec(&self) -> Result<(), Error> {
-        self.do_make(PAGE_EXECUTE_READ)
+    /// The pages are removed from the working set in the same way as for dropping them behind,
+    /// as there is no way to evict the pages from memory directly. The standby and modified lists
+    /// take care of reclaiming them. Trimming the working set as a whole with
+    /// `SetProcessWorkingSetSizeEx()` is avoided, as it affects every mapping of the process.
+    /// Windows does not expose the accessed bits of the pages. Instead, the pages are removed from
+    /// the working set, such that the pages that are accessed afterwards are faulted back in
+    /// from the standby or modified list, which is cheap and can be observed through the working
+    /// set. Locked pages never leave the working set.
+    pub fn clear_accessed(&self) -> Result<(), Error> {
+        if self.flags.contains(Flags::LOCKED) {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "the pages of locked mappings cannot be removed from the working set",
+            )));
+        }
+
+        self.drop_behind(0..self.size())
     }
 
-    pub fn make_mut(&self) -> Result<(), Error> {
-        let protect = if self.file.is_some() && self.flags.contains(Flags::COPY_ON_WRITE) {
-            PAGE_WRITECOPY
-        } else {
-            PAGE_READWRITE
+    /// Returns for every page from the page that the mapping starts in whether it is in the
+    /// working set, using `QueryWorkingSetEx()`.
+    pub fn accessed_pages(&self) -> Result<Vec<bool>, Error> {
+        let page_size = crate::page_size();
+        let start = crate::align_down(self.skew);
+
+        let mut entries: Vec<PSAPI_WORKING_SET_EX_INFORMATION> = (start..self.size)
+            .step_by(page_size)
+            .map(|offset| PSAPI_WORKING_SET_EX_INFORMATION {
+                VirtualAddress: unsafe { self.ptr.add(offset) } as *mut std::ffi::c_void,
+                VirtualAttributes: PSAPI_WORKING_SET_EX_BLOCK { Flags: 0 },
+            })
+            .collect();
+
+        let status = unsafe {
+            K32QueryWorkingSetEx(
+                GetCurrentProcess(),
+                entries.as_mut_ptr() as *mut std::ffi::c_void,
+                (entries.len() * std::mem::size_of::<PSAPI_WORKING_SET_EX_INFORMATION>()) as u32,
+            ).as_bool()
         };
 
-        self.do_make(protect)
+        if !status {
+            return Err(Error::Windows(windows::core::Error::from_win32()));
+        }
+
+        // The lowest bit of the attributes is set if the page is valid, i.e. in the working set.
+        Ok(entries
+            .iter()
+            .map(|entry| unsafe { entry.VirtualAttributes.Flags } & 1 != 0)
+            .collect())
     }
 
-    pub fn make_exec_mut(&self) -> Result<(), Error> {
-        if !self.flags.contains(Flags::JIT) {
-            return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
+    pub fn evict(&self, range: Range<usize>, _eviction: Eviction) -> Result<(), Error> {
+        self.drop_behind(range)
+    }
+
+    /// There is no way to tell how the pages will be accessed. Only private pages can be
+    /// discarded, so the pages of views are removed from the working set instead.
+    pub fn advise(&self, range: Range<usize>, advice: Advice) -> Result<(), Error> {
+        match advice {
+            Advice::WillNeed => self.read_ahead(range),
+            Advice::Sequential | Advice::Random => Ok(()),
+            Advice::DontNeed | Advice::Free if self.section.is_some() => self.drop_behind(range),
+            Advice::DontNeed | Advice::Free => {
+                let range = self.page_range(range);
+
+                let status = unsafe {
+                    DiscardVirtualMemory(std::slice::from_raw_parts_mut(
+                        self.ptr.add(range.start),
+                        range.end - range.start,
+                    ))
+                };
+
+                let result = match status {
+                    0 => Ok(()),
+                    error => Err(std::io::Error::from_raw_os_error(error as i32)),
+                };
+
+                trace_op!("advise", result, address = self.ptr, range = range, advice = advice);
+
+                Ok(result?)
+            }
         }
+    }
 
-        let protect = if self.flags.contains(Flags::COPY_ON_WRITE) {
-            PAGE_EXECUTE_WRITECOPY
-        } else {
-            PAGE_EXECUTE_READWRITE
-        };
+    /// Checks whether the view supports the given protection, as `VirtualProtect()` cannot extend
+    /// the protection beyond the access that the view has been mapped with. The view is never
+    /// mapped again with more access, as another thread could claim the address range while the
+    /// view is unmapped, and as the pages would no longer be locked afterwards. As views are
+    /// mapped with all the access that the file handle grants, mapping the view again would not
+    /// help anyway.
+    fn check_view_access(&self, section: &Section, protect: PAGE_PROTECTION_FLAGS) -> Result<(), Error> {
+        let required = view_access(protect);
 
-        self.do_make(protect)
+        if (section.access | FILE_MAP_READ).0 & required.0 == required.0 {
+            return Ok(());
+        }
+
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "the view of the file has not been mapped with the access the protection requires",
+        )))
     }
-}
 
-impl Drop for Mmap {
-    fn drop(&mut self) {
-        if self.file.is_some() {
-            let _ = unsafe {
-                UnmapViewOfFile(
-                    self.ptr as *mut _,
-                )
-            };
-        } else {
-            let _ = unsafe {
-                VirtualFree(
-                    self.ptr as *mut _,
-                    self.size,
-                    // FIXME: for some reason BitOr is not implemented for VIRTUAL_FREE_TYPE.
-                    VIRTUAL_FREE_TYPE(MEM_DECOMMIT.0 | MEM_RELEASE.0),
-                )
-            };
+    /// Changes the protection of the given ranges, after checking that the view of a file mapping
+    /// supports the protection of every range.
+    pub fn protect_ranges(&self, ranges: &[(Range<usize>, Protection)]) -> Result<(), Error> {
+        let copy_on_write = self.file.is_some() && self.flags.contains(Flags::COPY_ON_WRITE);
+
+        if let Some(section) = self.section.as_ref() {
+            let union = ranges
+                .iter()
+                .fold(Protection::empty(), |union, (_, protection)| union | *protection);
+
+            self.check_view_access(section, page_protection(union, copy_on_write))?;
         }
-    }
-}
 
-pub struct MmapOptions {
-    address: Option<usize>,
-    file: Option<(File, u64)>,
-    size: usize,
-    flags: MmapFlags,
-    unsafe_flags: UnsafeMmapFlags,
-    page_size: Option<PageSize>,
-}
+        for (range, protection) in ranges {
+            let protect = page_protection(*protection, copy_on_write);
 
-impl MmapOptions {
-    pub fn new(size: usize) -> Self {
-        Self {
-            address: None,
-            file: None,
-            size,
-            flags: MmapFlags::empty(),
-            unsafe_flags: UnsafeMmapFlags::empty(),
-            page_size: None,
+            // The caching attributes of file mappings are part of the section instead.
+            let protect = if self.file.is_none() {
+                with_cache_attributes(self.flags, protect)
+            } else {
+                protect
+            };
+
+            self.protect_range(self.page_range(range.clone()), protect | self.raw_protection)?;
         }
+
+        Ok(())
     }
 
-    pub fn page_size() -> (usize, usize) {
-        let mut system_info = SYSTEM_INFO::default();
+    /// Changes the protection of the given range.
+    fn protect_range(&self, range: Range<usize>, protect: PAGE_PROTECTION_FLAGS) -> Result<(), Error> {
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+        let size = range.end - range.start;
 
-        unsafe {
-            GetSystemInfo(&mut system_info)
+        let status = unsafe {
+            VirtualProtect(
+                self.ptr.add(range.start) as *mut std::ffi::c_void,
+                size,
+                protect,
+                &mut old_protect,
+            ).as_bool()
         };
 
-        (system_info.dwPageSize as usize, system_info.dwAllocationGranularity as usize)
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("protect", result, address = self.ptr, range = range, protect = protect);
+
+        result.map_err(|e| map_error(e, size))?;
+
+        Ok(())
     }
 
-    pub fn with_address(mut self, address: usize) -> Self {
-        self.address = Some(address);
-        self
+    /// Whether the pages are private to this process rather than shared with other mappings.
+    #[inline]
+    pub fn is_copy_on_write(&self) -> bool {
+        self.flags.contains(Flags::COPY_ON_WRITE)
     }
 
-    pub fn with_file(mut self, file: File, offset: u64) -> Self {
-        self.file = Some((file, offset));
-        self
+    #[cfg(feature = "jit")]
+    pub fn is_jit(&self) -> bool {
+        self.flags.contains(Flags::JIT)
     }
 
-    pub fn with_flags(mut self, flags: MmapFlags) -> Self {
-        self.flags = flags;
-        self
-    }
+    /// Unmaps the view or releases the allocation, after which the mapping must no longer be
+    /// used. The pointer is reset to indicate that the pages have been unmapped.
+    pub fn unmap(&mut self) -> Result<(), Error> {
+        match self.drop_behavior {
+            DropBehavior::Flush if self.file.is_some() => {
+                let _ = self.flush(0..self.size());
+            }
+            // Discarding only applies to private memory, which excludes views of file mappings.
+            DropBehavior::Discard if self.section.is_none() => {
+                let _ = unsafe {
+                    DiscardVirtualMemory(
+                        std::slice::from_raw_parts_mut(self.ptr, self.size),
+                    )
+                };
+            }
+            _ => (),
+        }
+
+        crate::flush::forget(self.ptr as usize..self.ptr as usize + crate::align_up(self.size));
+
+        if self.flags.contains(Flags::ZERO_ON_DROP) && self.make_mut().is_ok() {
+            unsafe {
+                super::scrub(self.ptr, self.size)
+            };
+        }
+
+        // Views of a reservation are turned back into placeholders, which requires the size of the
+        // whole allocation, even if the mapping has been truncated.
+        let status = if let Some(slot) = self.slot.as_ref() {
+            if self.file.is_some() {
+                unsafe {
+                    UnmapViewOfFile2(
+                        GetCurrentProcess(),
+                        self.ptr as *mut _,
+                        MEM_PRESERVE_PLACEHOLDER,
+                    )
+                }.as_bool()
+            } else {
+                unsafe {
+                    VirtualFree(
+                        self.ptr as *mut _,
+                        slot.size(),
+                        VIRTUAL_FREE_TYPE(MEM_RELEASE.0 | MEM_PRESERVE_PLACEHOLDER.0),
+                    )
+                }.as_bool()
+            }
+        } else if self.file.is_some() {
+            unsafe {
+                UnmapViewOfFile(
+                    self.ptr as *mut _,
+                )
+            }.as_bool()
+        } else {
+            // Releasing the allocation requires a size of zero, which also decommits the pages.
+            // This releases the whole allocation, even if the mapping has been truncated.
+            unsafe {
+                VirtualFree(
+                    self.ptr as *mut _,
+                    0,
+                    MEM_RELEASE,
+                )
+            }.as_bool()
+        };
+
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("unmap", result, address = self.ptr, size = self.size);
+
+        result?;
+        self.ptr = std::ptr::null_mut();
+
+        if let Some(section) = self.section.take() {
+            unsafe {
+                CloseHandle(section.handle)
+            };
+        }
+
+        if let (true, Some(file)) = (self.file_locked, self.file.as_ref()) {
+            let mut overlapped = OVERLAPPED::default();
+
+            let _ = unsafe {
+                UnlockFileEx(
+                    HANDLE(file.as_raw_handle() as isize),
+                    0,
+                    u32::MAX,
+                    u32::MAX,
+                    &mut overlapped,
+                )
+            };
+        }
+
+        Ok(())
+    }
+
+    pub fn truncate(&mut self, size: usize) -> Result<(), Error> {
+        // The size of the view includes the part of the view before the mapping.
+        let size = size + self.skew;
+        let start = crate::align_up(size);
+        let end = crate::align_up(self.size);
+
+        if start < end {
+            let result = self.release_tail(start..end);
+
+            trace_op!("unmap", result, address = self.ptr, size = end - start);
+
+            result?;
+        }
+
+        self.size = size;
+        self.record.resize(size);
+
+        Ok(())
+    }
+
+    /// Allocations and views can only be released as a whole, so mappings cannot be split.
+    pub fn split_off(&mut self, _at: usize) -> Result<Self, Error> {
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "mappings cannot be split on Microsoft Windows",
+        )))
+    }
+
+    /// Decommits the pages in the given range at the end of the mapping, as `VirtualFree()` can
+    /// only release the address space of an allocation as a whole. Views of file mappings can
+    /// only be unmapped as a whole, and mapping a smaller view at the same address would let
+    /// another thread claim the address range in the meantime, so they cannot be shrunk.
+    fn release_tail(&self, tail: Range<usize>) -> Result<(), Error> {
+        if self.section.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "views of file mappings cannot be shrunk on Microsoft Windows",
+            )));
+        }
+
+        let ptr = unsafe { self.ptr.add(tail.start) } as *mut std::ffi::c_void;
+        let len = tail.end - tail.start;
+
+        if self.flags.contains(Flags::ZERO_ON_DROP) {
+            let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+
+            let status = unsafe {
+                VirtualProtect(ptr, len, PAGE_READWRITE, &mut old_protect)
+            }.as_bool();
+
+            if status {
+                unsafe {
+                    super::scrub(ptr as *mut u8, len)
+                };
+            }
+        }
+
+        let status = unsafe {
+            VirtualFree(ptr, len, MEM_DECOMMIT)
+        }.as_bool();
+
+        if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())?
+        }
+    }
+
+    pub fn remap_to(&mut self, address: usize) -> Result<(), Error> {
+        if self.slot.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "views of a reservation cannot be moved",
+            )));
+        }
+
+        crate::flush::forget(self.ptr as usize..self.ptr as usize + crate::align_up(self.size));
+
+        let result = self.do_remap_to(address);
+
+        trace_op!("remap", result, address = self.ptr, size = self.size, new_address = address);
+
+        self.ptr = result? as *mut u8;
+        self.record.relocate(self.ptr as usize);
+
+        Ok(())
+    }
+
+    /// Grows the mapping to the given size, which may move the mapping. Shrinking the mapping is
+    /// left to `truncate()`.
+    pub fn resize(&mut self, size: usize) -> Result<(), Error> {
+        if self.slot.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "views of a reservation cannot grow",
+            )));
+        }
+
+        let size = size + self.skew;
+
+        crate::flush::forget(self.ptr as usize..self.ptr as usize + crate::align_up(self.size));
+
+        let result = self.do_resize(size);
+
+        trace_op!("remap", result, address = self.ptr, size = self.size, new_size = size);
+
+        let (ptr, handle) = result?;
+
+        // The view has been mapped from a larger section, which replaces the old one.
+        if let (Some(section), Some(handle)) = (self.section.as_mut(), handle) {
+            section.handle = handle;
+        }
+
+        self.ptr = ptr as *mut u8;
+        self.size = size;
+        self.record.relocate(self.ptr as usize);
+        self.record.resize(size);
+
+        Ok(())
+    }
+
+    /// Grows anonymous memory in place if the pages that are added have been decommitted by
+    /// truncating the mapping, or copies the contents to new memory otherwise. Views of file
+    /// mappings are mapped elsewhere from a new section of the file, as the size of a section is
+    /// fixed, in which case the new section is returned as well.
+    fn do_resize(&self, size: usize) -> Result<(*mut std::ffi::c_void, Option<HANDLE>), Error> {
+        // Query the current protection, as the new pages have to be protected the same way.
+        let mut info = MEMORY_BASIC_INFORMATION::default();
+
+        let status = unsafe {
+            VirtualQuery(
+                self.ptr as *const std::ffi::c_void,
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+
+        if status == 0 {
+            return Err(std::io::Error::last_os_error())?;
+        }
+
+        let (section, file) = match (self.section.as_ref(), self.file.as_ref()) {
+            (Some(section), Some(file)) => (section, file),
+            _ => return self.grow_allocation(size, &info).map(|ptr| (ptr, None)),
+        };
+
+        // Private pages of a copy-on-write view would be lost by mapping the view again.
+        if self.flags.contains(Flags::COPY_ON_WRITE) {
+            return Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied))?;
+        }
+
+        // The section covers the whole file, which has been extended to back the new size.
+        let handle = unsafe {
+            CreateFileMappingW(
+                HANDLE(file.as_raw_handle() as isize),
+                std::ptr::null_mut(),
+                section.protection,
+                0,
+                0,
+                PCWSTR::null(),
+            )
+        }?;
+
+        let ptr = unsafe {
+            MapViewOfFileEx(
+                handle,
+                section.access,
+                ((section.offset >> 32) & 0xffff_ffff) as u32,
+                (section.offset & 0xffff_ffff) as u32,
+                size,
+                std::ptr::null(),
+            )
+        };
+
+        if ptr.is_null() {
+            let e = std::io::Error::last_os_error();
+
+            unsafe {
+                CloseHandle(handle)
+            };
+
+            return Err(map_error(e, size));
+        }
+
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+
+        let status = unsafe {
+            VirtualProtect(ptr, size, info.Protect, &mut old_protect)
+        }.as_bool();
+
+        if !status {
+            let e = std::io::Error::last_os_error();
+
+            unsafe {
+                UnmapViewOfFile(ptr);
+                CloseHandle(handle);
+            }
+
+            return Err(e)?;
+        }
+
+        if self.flags.contains(Flags::LOCKED) {
+            let _ = unsafe { VirtualLock(ptr, size) };
+        }
+
+        unsafe {
+            UnmapViewOfFile(self.ptr as *mut _);
+            CloseHandle(section.handle);
+        }
+
+        Ok((ptr, Some(handle)))
+    }
+
+    /// Commits the pages that are added in place if they still belong to the allocation, which
+    /// is the case if the mapping has been truncated before, as the address space of an
+    /// allocation can only be released as a whole. Otherwise, new memory is allocated, to which
+    /// the contents are copied, and the old memory is released.
+    fn grow_allocation(
+        &self,
+        size: usize,
+        info: &MEMORY_BASIC_INFORMATION,
+    ) -> Result<*mut std::ffi::c_void, Error> {
+        let start = crate::align_up(self.size);
+        let end = crate::align_up(size);
+
+        if start == end {
+            return Ok(self.ptr as *mut std::ffi::c_void);
+        }
+
+        let tail = unsafe { self.ptr.add(start) } as *mut std::ffi::c_void;
+        let mut tail_info = MEMORY_BASIC_INFORMATION::default();
+
+        let status = unsafe {
+            VirtualQuery(
+                tail,
+                &mut tail_info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+
+        // Committing pages of another allocation would succeed as well, so check that the pages
+        // are merely reserved as part of this allocation first.
+        if status != 0 &&
+            tail_info.AllocationBase == info.AllocationBase &&
+            tail_info.State == MEM_RESERVE &&
+            tail_info.RegionSize >= end - start {
+            let ptr = unsafe {
+                VirtualAlloc(tail, end - start, MEM_COMMIT, info.Protect)
+            };
+
+            if !ptr.is_null() {
+                if self.flags.contains(Flags::LOCKED) {
+                    let _ = unsafe { VirtualLock(ptr, end - start) };
+                }
+
+                return Ok(self.ptr as *mut std::ffi::c_void);
+            }
+        }
+
+        let ptr = unsafe {
+            VirtualAlloc(
+                std::ptr::null(),
+                size,
+                MEM_COMMIT | MEM_RESERVE,
+                PAGE_READWRITE,
+            )
+        };
+
+        if ptr.is_null() {
+            return Err(map_error(std::io::Error::last_os_error(), size));
+        }
+
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+
+        let status = unsafe {
+            VirtualProtect(
+                self.ptr as *mut std::ffi::c_void,
+                self.size,
+                PAGE_READWRITE,
+                &mut old_protect,
+            )
+        }.as_bool();
+
+        if status {
+            unsafe {
+                std::ptr::copy_nonoverlapping(self.ptr, ptr as *mut u8, self.size);
+
+                if self.flags.contains(Flags::ZERO_ON_DROP) {
+                    super::scrub(self.ptr, self.size);
+                }
+            }
+        }
+
+        let status = status && unsafe {
+            VirtualProtect(ptr, size, info.Protect, &mut old_protect)
+        }.as_bool();
+
+        if !status {
+            let e = std::io::Error::last_os_error();
+
+            unsafe {
+                VirtualFree(ptr, 0, MEM_RELEASE);
+            }
+
+            let _ = self.do_make(info.Protect);
+
+            return Err(e)?;
+        }
+
+        if self.flags.contains(Flags::LOCKED) {
+            let _ = unsafe { VirtualLock(ptr, size) };
+        }
+
+        unsafe {
+            VirtualFree(self.ptr as *mut std::ffi::c_void, 0, MEM_RELEASE);
+        }
+
+        Ok(ptr)
+    }
+
+    /// Maps a new view of the file mapping at the new address for file mappings, or allocates new
+    /// memory at the new address otherwise, and then copies the contents of private pages and
+    /// releases the old mapping. As Microsoft Windows does not support replacing pages, the new
+    /// address range must be free.
+    fn do_remap_to(&self, address: usize) -> Result<*mut std::ffi::c_void, Error> {
+        // Query the current protection, as the new mapping has to be protected the same way.
+        let mut info = MEMORY_BASIC_INFORMATION::default();
+
+        let status = unsafe {
+            VirtualQuery(
+                self.ptr as *const std::ffi::c_void,
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+
+        if status == 0 {
+            return Err(std::io::Error::last_os_error())?;
+        }
+
+        let ptr = if let Some(section) = self.section.as_ref() {
+            unsafe {
+                MapViewOfFileEx(
+                    section.handle,
+                    section.access,
+                    ((section.offset >> 32) & 0xffff_ffff) as u32,
+                    (section.offset & 0xffff_ffff) as u32,
+                    self.size,
+                    address as *const std::ffi::c_void,
+                )
+            }
+        } else {
+            unsafe {
+                VirtualAlloc(
+                    address as *const std::ffi::c_void,
+                    self.size,
+                    MEM_COMMIT | MEM_RESERVE,
+                    PAGE_READWRITE,
+                )
+            }
+        };
+
+        if ptr.is_null() {
+            return Err(map_error(std::io::Error::last_os_error(), self.size));
+        }
+
+        let release = |ptr: *mut std::ffi::c_void| unsafe {
+            if self.section.is_some() {
+                UnmapViewOfFile(ptr);
+            } else {
+                VirtualFree(ptr, 0, MEM_RELEASE);
+            }
+        };
+
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+
+        // Private pages are not shared with the file mapping and have to be copied.
+        if self.section.is_none() || self.flags.contains(Flags::COPY_ON_WRITE) {
+            let writable = if self.section.is_some() { PAGE_WRITECOPY } else { PAGE_READWRITE };
+
+            let status = unsafe {
+                VirtualProtect(
+                    self.ptr as *mut std::ffi::c_void,
+                    self.size,
+                    PAGE_READONLY,
+                    &mut old_protect,
+                ).as_bool() &&
+                VirtualProtect(
+                    ptr,
+                    self.size,
+                    writable,
+                    &mut old_protect,
+                ).as_bool()
+            };
+
+            if !status {
+                let e = std::io::Error::last_os_error();
+
+                release(ptr);
+                let _ = self.do_make(info.Protect);
+
+                return Err(e)?;
+            }
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(self.ptr, ptr as *mut u8, self.size);
+            }
+        }
+
+        let status = unsafe {
+            VirtualProtect(
+                ptr,
+                self.size,
+                info.Protect,
+                &mut old_protect,
+            )
+        }.as_bool();
+
+        if !status {
+            let e = std::io::Error::last_os_error();
+
+            release(ptr);
+            let _ = self.do_make(info.Protect);
+
+            return Err(e)?;
+        }
+
+        if self.flags.contains(Flags::LOCKED) {
+            let _ = unsafe { VirtualLock(ptr, self.size) };
+        }
+
+        release(self.ptr as *mut std::ffi::c_void);
+
+        Ok(ptr)
+    }
+
+    pub fn do_make(&self, protect: PAGE_PROTECTION_FLAGS) -> Result<(), Error> {
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+
+        if let Some(section) = self.section.as_ref() {
+            self.check_view_access(section, protect)?;
+        }
+
+        // The caching attributes of file mappings are part of the section instead.
+        let protect = if self.file.is_none() {
+            with_cache_attributes(self.flags, protect)
+        } else {
+            protect
+        };
+
+        let protect = protect | self.raw_protection;
+
+        let status = unsafe {
+            VirtualProtect(
+                self.ptr as *mut std::ffi::c_void,
+                self.size,
+                protect,
+                &mut old_protect,
+            ).as_bool()
+        };
+
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("protect", result, address = self.ptr, size = self.size, protect = protect);
+
+        result.map_err(|e| map_error(e, self.size))
+    }
+
+    /// Flushes the instruction cache for the range, whatever the architecture, as the caller
+    /// asked for it explicitly, and then interrupts every core that runs a thread of the process
+    /// using `FlushProcessWriteBuffers()`, which serializes its instruction stream.
+    pub fn publish_code(&self, range: Range<usize>) -> Result<(), Error> {
+        let status = unsafe {
+            FlushInstructionCache(
+                GetCurrentProcess(),
+                self.as_ptr().add(range.start) as *const std::ffi::c_void,
+                range.end - range.start,
+            )
+        }.as_bool();
+
+        let result = if status {
+            unsafe {
+                FlushProcessWriteBuffers()
+            };
+
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!(
+            "publish_code",
+            result,
+            address = self.as_ptr() as usize + range.start,
+            size = range.end - range.start,
+        );
+
+        Ok(result?)
+    }
+
+    pub fn flush_icache(&self) -> Result<(), Error> {
+        self.flush_icache_range(0..self.size())
+    }
+
+    pub fn flush_icache_range(&self, range: Range<usize>) -> Result<(), Error> {
+        // While the x86 and x86-64 architectures guarantee cache coherency between the L1
+        // instruction and the L1 data cache, other architectures such as arm and aarch64 do not.
+        // If the user modified the pages, then executing the code after marking the pages as
+        // executable may result in undefined behavior. Since we cannot efficiently track writes,
+        // we have to flush the instruction cache unconditionally.
+        #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+        unsafe {
+            FlushInstructionCache(
+                GetCurrentProcess(),
+                self.as_ptr().add(range.start) as *const std::ffi::c_void,
+                range.end - range.start,
+            )
+        };
+
+        #[cfg(not(any(target_arch = "arm", target_arch = "aarch64")))]
+        let _ = range;
+
+        Ok(())
+    }
+
+    pub fn make_none(&self) -> Result<(), Error> {
+        self.do_make(PAGE_NOACCESS)
+    }
+
+    /// Microsoft Windows has no way to seal the protection of pages.
+    pub fn seal(&mut self) -> Result<(), Error> {
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "mappings cannot be sealed on Microsoft Windows",
+        )))
+    }
+
+    pub fn make_read_only(&self) -> Result<(), Error> {
+        self.do_make(PAGE_READWRITE)
+    }
+
+    #[cfg(feature = "exec")]
+    pub fn make_exec(&self) -> Result<(), Error> {
+        self.do_make(PAGE_EXECUTE_READ)
+    }
+
+    pub fn make_mut(&self) -> Result<(), Error> {
+        let protect = if self.file.is_some() && self.flags.contains(Flags::COPY_ON_WRITE) {
+            PAGE_WRITECOPY
+        } else {
+            PAGE_READWRITE
+        };
+
+        self.do_make(protect)
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn make_exec_mut(&self) -> Result<(), Error> {
+        if !self.flags.contains(Flags::JIT) {
+            return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
+        }
+
+        let protect = if self.flags.contains(Flags::COPY_ON_WRITE) {
+            PAGE_EXECUTE_WRITECOPY
+        } else {
+            PAGE_EXECUTE_READWRITE
+        };
+
+        self.do_make(protect)
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            let _ = self.unmap();
+        }
+    }
+}
+
+/// Spawns the command as a child process that inherits the file, where the value of the handle
+/// is passed through the given environment variable. Child processes are spawned with handle
+/// inheritance enabled, so an inheritable duplicate of the handle is created for the duration of
+/// the spawn, which other processes spawned in the meantime inherit as well.
+pub fn spawn_inheriting(
+    command: &mut std::process::Command,
+    file: &File,
+    variable: &str,
+) -> Result<std::process::Child, Error> {
+    let mut handle = HANDLE::default();
+
+    let status = unsafe {
+        DuplicateHandle(
+            GetCurrentProcess(),
+            HANDLE(file.as_raw_handle() as isize),
+            GetCurrentProcess(),
+            &mut handle,
+            0,
+            true,
+            DUPLICATE_SAME_ACCESS,
+        )
+    }.as_bool();
+
+    if !status {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    command.env(variable, (handle.0 as u64).to_string());
+
+    let result = command.spawn();
+
+    unsafe {
+        CloseHandle(handle)
+    };
+
+    Ok(result?)
+}
+
+/// Takes ownership of the handle inherited from the parent process.
+pub unsafe fn inherited_file(handle: u64) -> Result<File, Error> {
+    use std::os::windows::io::FromRawHandle;
+
+    Ok(File::from_raw_handle(handle as isize as std::os::windows::io::RawHandle))
+}
+
+/// Classifies the failure to map or protect memory of the given size, as Arbitrary Code Guard
+/// (ACG) denies dynamic code with `ERROR_DYNAMIC_CODE_BLOCKED` and the commit limit results in a
+/// generic out-of-memory error, which are easily misdiagnosed.
+fn map_error(error: std::io::Error, size: usize) -> Error {
+    let code = error.raw_os_error();
+
+    let out_of_memory = [ERROR_NOT_ENOUGH_MEMORY, ERROR_COMMITMENT_LIMIT, ERROR_COMMITMENT_MINIMUM]
+        .iter()
+        .any(|error| code == Some(error.0 as i32));
+
+    if out_of_memory {
+        return Error::OutOfMemory(probe_out_of_memory(size));
+    }
+
+    // The privilege may have been disabled by another thread since it was enabled.
+    if code == Some(ERROR_PRIVILEGE_NOT_HELD.0 as i32) {
+        return Error::HugePagesUnavailable(
+            "the SeLockMemoryPrivilege privilege is not enabled in the access token of the \
+            process, see mmap_rs::os::windows::enable_lock_memory_privilege()".to_string(),
+        );
+    }
+
+    if code != Some(ERROR_DYNAMIC_CODE_BLOCKED.0 as i32) {
+        return error.into();
+    }
+
+    Error::WxProtectionDenied(
+        "Arbitrary Code Guard (ACG) is enabled for the process, e.g. through the \
+        ProcessDynamicCodePolicy mitigation policy, which denies executable memory that is not \
+        backed by an image".to_string(),
+    )
+}
+
+/// Classifies the failure to map memory at the requested address. Rather than picking another
+/// address, Microsoft Windows fails with `ERROR_INVALID_ADDRESS` when the address range is in use,
+/// which is reported as [`std::io::ErrorKind::AddrInUse`].
+fn map_at_error(error: std::io::Error, address: Option<usize>, size: usize) -> Error {
+    match address {
+        Some(address) if error.raw_os_error() == Some(ERROR_INVALID_ADDRESS.0 as i32) => {
+            std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("the address range {:#x}..{:#x} is unavailable", address, address + size),
+            ).into()
+        }
+        _ => map_error(error, size),
+    }
+}
+
+/// Maps the error of mapping memory within an address range, where running out of memory usually
+/// means that the range has no free spot that is large enough.
+fn map_within_error(error: std::io::Error, range: &Range<usize>, size: usize) -> Error {
+    match error.raw_os_error() {
+        Some(code) if code == ERROR_NOT_ENOUGH_MEMORY.0 as i32 ||
+            code == ERROR_INVALID_ADDRESS.0 as i32 => no_address_within(range, size),
+        _ => map_error(error, size),
+    }
+}
+
+/// Returns the error for an address range that has no free spot of the given size.
+fn no_address_within(range: &Range<usize>, size: usize) -> Error {
+    Error::Io(std::io::Error::new(
+        std::io::ErrorKind::AddrNotAvailable,
+        format!(
+            "no free address range of {} bytes within {:#x}..{:#x}",
+            size,
+            range.start,
+            range.end,
+        ),
+    ))
+}
+
+/// Builds the extended parameter that confines an allocation or a view to the given address
+/// range. The parameter points to the requirements, which must outlive it.
+fn address_requirements(
+    range: &Range<usize>,
+    requirements: &mut MEM_ADDRESS_REQUIREMENTS,
+) -> MEM_EXTENDED_PARAMETER {
+    // The highest ending address is inclusive.
+    *requirements = MEM_ADDRESS_REQUIREMENTS {
+        LowestStartingAddress: range.start as *mut std::ffi::c_void,
+        HighestEndingAddress: range.end.saturating_sub(1) as *mut std::ffi::c_void,
+        Alignment: 0,
+    };
+
+    MEM_EXTENDED_PARAMETER {
+        Anonymous1: MEM_EXTENDED_PARAMETER_0 {
+            _bitfield: MemExtendedParameterAddressRequirements.0 as u64,
+        },
+        Anonymous2: MEM_EXTENDED_PARAMETER_1 {
+            Pointer: requirements as *mut MEM_ADDRESS_REQUIREMENTS as *mut std::ffi::c_void,
+        },
+    }
+}
+
+/// The attribute of `VirtualAlloc2()` that requests 1 GiB pages, which is not exposed by the
+/// windows crate.
+const MEM_EXTENDED_PARAMETER_NONPAGED_HUGE: u64 = 0x10;
+
+/// The size of the huge pages that are requested through [`PageSize::_1G`].
+const HUGE_PAGE_SIZE: usize = 1 << 30;
+
+/// Builds the extended parameter that requests the allocation to be backed by 1 GiB pages, which
+/// has to be combined with `MEM_LARGE_PAGES`.
+fn huge_page_attributes() -> MEM_EXTENDED_PARAMETER {
+    MEM_EXTENDED_PARAMETER {
+        Anonymous1: MEM_EXTENDED_PARAMETER_0 {
+            _bitfield: MemExtendedParameterAttributeFlags.0 as u64,
+        },
+        Anonymous2: MEM_EXTENDED_PARAMETER_1 {
+            ULong64: MEM_EXTENDED_PARAMETER_NONPAGED_HUGE,
+        },
+    }
+}
+
+/// Whether the processor supports 1 GiB pages, as reported by the `pdpe1gb` bit of `cpuid`.
+/// Microsoft Windows does not report whether it supports them itself, which requires Microsoft
+/// Windows 10, version 1803 or newer.
+#[cfg(target_arch = "x86_64")]
+fn supports_huge_pages() -> bool {
+    use std::arch::x86_64::__cpuid;
+
+    unsafe {
+        __cpuid(0x8000_0000).eax >= 0x8000_0001 && __cpuid(0x8000_0001).edx & (1 << 26) != 0
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn supports_huge_pages() -> bool {
+    false
+}
+
+/// Classifies the failure to allocate memory backed by 1 GiB pages, as the allocation fails with
+/// a generic error if the version of Microsoft Windows does not support them, or if there is not
+/// enough contiguous physical memory left, which is common once the system has been running for
+/// a while.
+fn huge_page_error(error: std::io::Error, size: usize) -> Error {
+    match error.raw_os_error() {
+        Some(code) if code == ERROR_INVALID_PARAMETER.0 as i32 => Error::HugePagesUnavailable(
+            "1 GiB pages are not supported, as they require Microsoft Windows 10, version 1803 \
+            or newer".to_string(),
+        ),
+        Some(code) if code == ERROR_NO_SYSTEM_RESOURCES.0 as i32 ||
+            code == ERROR_NOT_ENOUGH_MEMORY.0 as i32 => Error::HugePagesUnavailable(format!(
+            "not enough contiguous physical memory is available to back {} bytes with 1 GiB \
+            pages, which may be available right after booting or with PageSize::_2M instead",
+            size,
+        )),
+        _ => map_error(error, size),
+    }
+}
+
+/// Checks whether `size` more bytes can be committed without exceeding the commit limit of the
+/// system, i.e. the size of physical memory and the page files.
+fn check_memory_limit(size: usize) -> Result<(), Error> {
+    let mut status = MEMORYSTATUSEX {
+        dwLength: std::mem::size_of::<MEMORYSTATUSEX>() as u32,
+        ..Default::default()
+    };
+
+    if !unsafe { GlobalMemoryStatusEx(&mut status) }.as_bool() {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    if status.ullAvailPageFile < size as u64 {
+        return Err(Error::OutOfMemory(OutOfMemoryCause::CommitLimit(status.ullTotalPageFile)));
+    }
+
+    Ok(())
+}
+
+/// Probes whether the address space or the commit limit has been exhausted by a mapping of the
+/// given size.
+fn probe_out_of_memory(size: usize) -> OutOfMemoryCause {
+    let mut status = MEMORYSTATUSEX {
+        dwLength: std::mem::size_of::<MEMORYSTATUSEX>() as u32,
+        ..Default::default()
+    };
+
+    if !unsafe { GlobalMemoryStatusEx(&mut status) }.as_bool() {
+        return OutOfMemoryCause::Unknown;
+    }
+
+    if status.ullAvailVirtual < size as u64 {
+        return OutOfMemoryCause::AddressSpaceLimit(status.ullTotalVirtual);
+    }
+
+    if status.ullAvailPageFile < size as u64 {
+        return OutOfMemoryCause::CommitLimit(status.ullTotalPageFile);
+    }
+
+    OutOfMemoryCause::Unknown
+}
+
+/// Converts the failure to lock memory into an error, where exceeding the working set quota is
+/// reported along with the maximum working set size.
+fn lock_error(error: std::io::Error) -> Error {
+    if error.raw_os_error() != Some(ERROR_WORKING_SET_QUOTA.0 as i32) {
+        return error.into();
+    }
+
+    let mut minimum = 0;
+    let mut maximum = 0;
+
+    let status = unsafe {
+        GetProcessWorkingSetSize(
+            GetCurrentProcess(),
+            &mut minimum,
+            &mut maximum,
+        )
+    }.as_bool();
+
+    let cause = if status {
+        OutOfMemoryCause::LockedMemoryLimit(maximum as u64)
+    } else {
+        OutOfMemoryCause::Unknown
+    };
+
+    Error::OutOfMemory(cause)
+}
+
+/// A placeholder that reserves address space for views, which is split into smaller placeholders
+/// that are replaced by the views.
+pub struct Reservation {
+    ptr: *mut u8,
+    size: usize,
+}
+
+// The reservation merely holds on to the address space, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for Reservation {}
+unsafe impl Sync for Reservation {}
+
+/// Coalesces adjacent placeholders into a single placeholder. This is missing from the bindings.
+const MEM_COALESCE_PLACEHOLDERS: VIRTUAL_FREE_TYPE = VIRTUAL_FREE_TYPE(0x1);
+
+impl Reservation {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let ptr = unsafe {
+            VirtualAlloc2(
+                GetCurrentProcess(),
+                std::ptr::null(),
+                size,
+                MEM_RESERVE | MEM_RESERVE_PLACEHOLDER,
+                PAGE_NOACCESS.0,
+                &mut [],
+            )
+        };
+
+        let result = if ptr.is_null() {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(ptr)
+        };
+
+        trace_op!("reserve", result, address = ptr, size = size);
+
+        Ok(Self {
+            ptr: result? as *mut u8,
+            size,
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        // The views have all been dropped, so the reservation consists of placeholders only. The
+        // placeholders have to be coalesced to release them at once, which fails if there is only
+        // one.
+        let coalesce = VIRTUAL_FREE_TYPE(MEM_RELEASE.0 | MEM_COALESCE_PLACEHOLDERS.0);
+        let _ = free(self.ptr as usize, self.size, coalesce);
+
+        let result = free(self.ptr as usize, 0, MEM_RELEASE);
+
+        trace_op!("unreserve", result, address = self.ptr, size = self.size);
+    }
+}
+
+/// A reservation of address space, in which pages are committed on demand.
+pub struct ReservedMmap {
+    ptr: *mut u8,
+    size: usize,
+    /// Keeps the mapping registered for as long as it is alive.
+    _record: Record,
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for ReservedMmap {}
+unsafe impl Sync for ReservedMmap {}
+
+impl ReservedMmap {
+    fn new(address: Option<usize>, size: usize) -> Result<Self, Error> {
+        let ptr = unsafe {
+            VirtualAlloc(
+                address.unwrap_or(0) as *const std::ffi::c_void,
+                size,
+                MEM_RESERVE,
+                PAGE_NOACCESS,
+            )
+        };
+
+        let result = if ptr.is_null() {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(ptr)
+        };
+
+        trace_op!("reserve", result, address = ptr, size = size);
+
+        let ptr = result? as *mut u8;
+
+        Ok(Self {
+            ptr,
+            size,
+            _record: Record::new(ptr as usize, size, MmapFlags::NO_RESERVE, false),
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Commits the pages as readable and writable. Pages that are committed already keep their
+    /// contents.
+    pub fn commit(&self, range: Range<usize>) -> Result<(), Error> {
+        let ptr = unsafe { self.ptr.add(range.start) };
+        let size = range.end - range.start;
+
+        let committed = unsafe {
+            VirtualAlloc(ptr as *const std::ffi::c_void, size, MEM_COMMIT, PAGE_READWRITE)
+        };
+
+        let result = if committed.is_null() {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        };
+
+        trace_op!("commit", result, address = ptr, size = size);
+
+        Ok(result?)
+    }
+
+    /// Decommits the pages, which releases the memory while keeping the address space reserved.
+    pub fn uncommit(&self, range: Range<usize>) -> Result<(), Error> {
+        let ptr = unsafe { self.ptr.add(range.start) };
+        let size = range.end - range.start;
+
+        let result = free(ptr as usize, size, MEM_DECOMMIT);
+
+        trace_op!("uncommit", result, address = ptr, size = size);
+
+        result
+    }
+}
+
+impl Drop for ReservedMmap {
+    fn drop(&mut self) {
+        let result = free(self.ptr as usize, 0, MEM_RELEASE);
+
+        trace_op!("unreserve", result, address = self.ptr, size = self.size);
+    }
+}
+
+/// Splits the placeholders such that the given range is covered by a placeholder of its own.
+/// As views are turned back into placeholders when they are dropped, the range may span
+/// multiple placeholders, which are coalesced first.
+fn carve(address: usize, size: usize) -> Result<(), Error> {
+    let first = query(address)?;
+    let last = query(address + size - 1)?;
+
+    let start = first.AllocationBase as usize;
+    let end = last.BaseAddress as usize + last.RegionSize;
+    let coalesce = VIRTUAL_FREE_TYPE(MEM_RELEASE.0 | MEM_COALESCE_PLACEHOLDERS.0);
+    let split = VIRTUAL_FREE_TYPE(MEM_RELEASE.0 | MEM_PRESERVE_PLACEHOLDER.0);
+
+    if first.BaseAddress as usize + first.RegionSize < end {
+        free(start, end - start, coalesce)?;
+    }
+
+    if start < address {
+        free(start, address - start, split)?;
+    }
+
+    if address + size < end {
+        free(address, size, split)?;
+    }
+
+    Ok(())
+}
+
+/// Queries the region of pages with the same attributes that contains the given address.
+fn query(address: usize) -> Result<MEMORY_BASIC_INFORMATION, Error> {
+    let mut info = MEMORY_BASIC_INFORMATION::default();
+
+    let status = unsafe {
+        VirtualQuery(
+            address as *const std::ffi::c_void,
+            &mut info,
+            std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+        )
+    };
+
+    if status == 0 {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    Ok(info)
+}
+
+/// Calls `VirtualFree()` on the given range with the given flags.
+fn free(address: usize, size: usize, flags: VIRTUAL_FREE_TYPE) -> Result<(), Error> {
+    let status = unsafe {
+        VirtualFree(address as *mut std::ffi::c_void, size, flags)
+    }.as_bool();
+
+    if status {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())?
+    }
+}
+
+/// A mapping of the same section twice back-to-back.
+pub struct MirroredMmap {
+    ptr: *mut u8,
+    size: usize,
+    /// Keeps the mapping registered for as long as it is alive.
+    _record: Record,
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for MirroredMmap {}
+unsafe impl Sync for MirroredMmap {}
+
+impl MirroredMmap {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let result = Self::map(size);
+
+        trace_op!(
+            "map_mirrored",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            size = size,
+        );
+
+        result
+    }
+
+    /// Reserves a placeholder for both views first, which is split in two and then replaced by
+    /// the views, such that no other thread can map anything in between. Placeholders require
+    /// Microsoft Windows 10, version 1803 or newer.
+    fn map(size: usize) -> Result<Self, Error> {
+        let span = size.checked_mul(2).ok_or(Error::OutOfBounds(0..size))?;
+        let process = unsafe { GetCurrentProcess() };
+
+        let placeholder = unsafe {
+            VirtualAlloc2(
+                process,
+                std::ptr::null(),
+                span,
+                MEM_RESERVE | MEM_RESERVE_PLACEHOLDER,
+                PAGE_NOACCESS.0,
+                &mut [],
+            )
+        };
+
+        if placeholder.is_null() {
+            return Err(std::io::Error::last_os_error())?;
+        }
+
+        let upper = unsafe { (placeholder as *mut u8).add(size) } as *mut std::ffi::c_void;
+
+        // Split the placeholder in two, which can only fail if the arguments are invalid.
+        unsafe {
+            VirtualFree(
+                placeholder,
+                size,
+                VIRTUAL_FREE_TYPE(MEM_RELEASE.0 | MEM_PRESERVE_PLACEHOLDER.0),
+            )
+        };
+
+        let release = |views: &[*mut std::ffi::c_void]| unsafe {
+            for &view in views {
+                UnmapViewOfFile(view);
+            }
+
+            for half in [placeholder, upper] {
+                if !views.contains(&half) {
+                    VirtualFree(half, 0, MEM_RELEASE);
+                }
+            }
+        };
+
+        let section = unsafe {
+            CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                std::ptr::null(),
+                PAGE_READWRITE,
+                (match size.overflowing_shr(32) {
+                    (_, true) => 0,
+                    (size, false) => size,
+                } & 0xffff_ffff) as u32,
+                (size & 0xffff_ffff) as u32,
+                PCWSTR::null(),
+            )
+        };
+
+        let section = match section {
+            Ok(section) => section,
+            Err(e) => {
+                release(&[]);
+
+                return Err(Error::Windows(e));
+            }
+        };
+
+        let mut views = vec![];
+
+        for half in [placeholder, upper] {
+            let view = unsafe {
+                MapViewOfFile3(
+                    section,
+                    process,
+                    half,
+                    0,
+                    size,
+                    MEM_REPLACE_PLACEHOLDER,
+                    PAGE_READWRITE.0,
+                    &mut [],
+                )
+            };
+
+            if view.is_null() {
+                let error = std::io::Error::last_os_error();
+
+                release(&views);
+
+                unsafe {
+                    CloseHandle(section)
+                };
+
+                return Err(error)?;
+            }
+
+            views.push(view);
+        }
+
+        // The views keep the section alive, so the handle can be closed.
+        unsafe {
+            CloseHandle(section)
+        };
+
+        Ok(Self {
+            ptr: placeholder as *mut u8,
+            size,
+            _record: Record::new(placeholder as usize, span, MmapFlags::empty(), false),
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for MirroredMmap {
+    fn drop(&mut self) {
+        let status = unsafe {
+            UnmapViewOfFile(self.ptr as *const std::ffi::c_void).as_bool() &
+                UnmapViewOfFile(self.ptr.add(self.size) as *const std::ffi::c_void).as_bool()
+        };
+
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("unmap_mirrored", result, address = self.ptr, size = self.size);
+    }
+}
+
+/// Two views of the same section backed by the paging file, one readable and writable, and one
+/// readable and executable.
+#[cfg(feature = "exec")]
+pub struct DualMmap {
+    writable: *mut u8,
+    executable: *mut u8,
+    size: usize,
+    /// Keep the views registered for as long as they are alive.
+    _records: [Record; 2],
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+#[cfg(feature = "exec")]
+unsafe impl Send for DualMmap {}
+#[cfg(feature = "exec")]
+unsafe impl Sync for DualMmap {}
+
+#[cfg(feature = "exec")]
+impl DualMmap {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let result = Self::map(size);
+
+        trace_op!(
+            "map_dual",
+            result,
+            writable = result.as_ref().map(|mapping| mapping.writable).ok(),
+            executable = result.as_ref().map(|mapping| mapping.executable).ok(),
+            size = size,
+        );
+
+        result
+    }
+
+    /// Creates a section that allows both views, and maps the views using `MapViewOfFile()`. The
+    /// views keep the section alive, so the handle is closed once the views have been mapped.
+    fn map(size: usize) -> Result<Self, Error> {
+        let section = unsafe {
+            CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                std::ptr::null(),
+                PAGE_EXECUTE_READWRITE,
+                (match size.overflowing_shr(32) {
+                    (_, true) => 0,
+                    (size, false) => size,
+                } & 0xffff_ffff) as u32,
+                (size & 0xffff_ffff) as u32,
+                PCWSTR::null(),
+            )
+        }.map_err(|_| map_error(std::io::Error::last_os_error(), size))?;
+
+        let writable = unsafe {
+            MapViewOfFile(section, FILE_MAP_READ | FILE_MAP_WRITE, 0, 0, size)
+        };
+
+        let executable = if writable.is_null() {
+            std::ptr::null_mut()
+        } else {
+            unsafe {
+                MapViewOfFile(section, FILE_MAP_READ | FILE_MAP_EXECUTE, 0, 0, size)
+            }
+        };
+
+        let error = std::io::Error::last_os_error();
+
+        unsafe {
+            CloseHandle(section)
+        };
+
+        if executable.is_null() {
+            if !writable.is_null() {
+                unsafe {
+                    UnmapViewOfFile(writable)
+                };
+            }
+
+            return Err(map_error(error, size));
+        }
+
+        Ok(Self {
+            writable: writable as *mut u8,
+            executable: executable as *mut u8,
+            size,
+            _records: [
+                Record::new(writable as usize, size, MmapFlags::empty(), false),
+                Record::new(executable as usize, size, MmapFlags::empty(), false),
+            ],
+        })
+    }
+
+    #[inline]
+    pub fn writable_ptr(&self) -> *mut u8 {
+        self.writable
+    }
+
+    #[inline]
+    pub fn executable_ptr(&self) -> *const u8 {
+        self.executable
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Flushes the instruction cache for the range of the executable view, and then serializes
+    /// the instruction streams of the cores using `FlushProcessWriteBuffers()`.
+    pub fn publish_code(&self, range: Range<usize>) -> Result<(), Error> {
+        let status = unsafe {
+            FlushInstructionCache(
+                GetCurrentProcess(),
+                self.executable.add(range.start) as *const std::ffi::c_void,
+                range.end - range.start,
+            )
+        }.as_bool();
+
+        let result = if status {
+            unsafe {
+                FlushProcessWriteBuffers()
+            };
+
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!(
+            "publish_code",
+            result,
+            address = self.executable as usize + range.start,
+            size = range.end - range.start,
+        );
+
+        Ok(result?)
+    }
+}
+
+#[cfg(feature = "exec")]
+impl Drop for DualMmap {
+    fn drop(&mut self) {
+        for ptr in [self.writable, self.executable] {
+            let status = unsafe {
+                UnmapViewOfFile(ptr as *const std::ffi::c_void)
+            }.as_bool();
+
+            let result = if status {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            };
+
+            trace_op!("unmap_dual", result, address = ptr, size = self.size);
+        }
+    }
+}
+
+/// A view of a named section backed by the paging file, which other processes can open by its
+/// name.
+pub struct SharedMemory {
+    handle: HANDLE,
+    ptr: *mut u8,
+    size: usize,
+    /// Keeps the mapping registered for as long as it is alive.
+    _record: Record,
+}
+
+// The mapping exclusively owns the pages it points to, such that it can be safely sent to and
+// shared between threads.
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl SharedMemory {
+    pub fn create(name: &str, size: usize) -> Result<Self, Error> {
+        let result = Self::map(name, Some(size));
+
+        trace_op!(
+            "map_shared",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            name = name,
+            size = size,
+        );
+
+        result
+    }
+
+    pub fn open(name: &str) -> Result<Self, Error> {
+        let result = Self::map(name, None);
+
+        trace_op!(
+            "map_shared",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            name = name,
+        );
+
+        result
+    }
+
+    /// Creates the named section with the given size, or opens it if no size is given, and maps
+    /// a view of the whole section. The size of a section cannot be queried, so the size of an
+    /// opened section is the size of the view, which is rounded up to the page size.
+    fn map(name: &str, size: Option<usize>) -> Result<Self, Error> {
+        let name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+
+        let handle = match size {
+            Some(size) => {
+                let handle = unsafe {
+                    CreateFileMappingW(
+                        INVALID_HANDLE_VALUE,
+                        std::ptr::null(),
+                        PAGE_READWRITE,
+                        (match size.overflowing_shr(32) {
+                            (_, true) => 0,
+                            (size, false) => size,
+                        } & 0xffff_ffff) as u32,
+                        (size & 0xffff_ffff) as u32,
+                        PCWSTR(name.as_ptr()),
+                    )
+                }.map_err(|_| std::io::Error::last_os_error())?;
+
+                // An existing section is opened rather than created, whatever its size.
+                let error = std::io::Error::last_os_error();
+
+                if error.raw_os_error() == Some(ERROR_ALREADY_EXISTS.0 as i32) {
+                    unsafe {
+                        CloseHandle(handle)
+                    };
+
+                    return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists))?;
+                }
+
+                handle
+            }
+            _ => unsafe {
+                OpenFileMappingW((FILE_MAP_READ | FILE_MAP_WRITE).0, false, PCWSTR(name.as_ptr()))
+            }.map_err(|_| std::io::Error::last_os_error())?,
+        };
+
+        let ptr = unsafe {
+            MapViewOfFile(handle, FILE_MAP_READ | FILE_MAP_WRITE, 0, 0, size.unwrap_or(0))
+        };
+
+        if ptr.is_null() {
+            let error = std::io::Error::last_os_error();
+
+            unsafe {
+                CloseHandle(handle)
+            };
+
+            return Err(error)?;
+        }
+
+        let size = match size {
+            Some(size) => size,
+            _ => {
+                let mut info = MEMORY_BASIC_INFORMATION::default();
+
+                unsafe {
+                    VirtualQuery(
+                        ptr,
+                        &mut info,
+                        std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+                    )
+                };
+
+                info.RegionSize
+            }
+        };
+
+        Ok(Self {
+            handle,
+            ptr: ptr as *mut u8,
+            size,
+            _record: Record::new(ptr as usize, size, MmapFlags::empty(), false),
+        })
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let status = unsafe {
+            UnmapViewOfFile(self.ptr as *const std::ffi::c_void)
+        }.as_bool();
+
+        let result = if status {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        };
+
+        trace_op!("unmap_shared", result, address = self.ptr, size = self.size);
+
+        unsafe {
+            CloseHandle(self.handle)
+        };
+    }
+}
+
+/// Returns the size of the large pages that are used by [`MmapFlags::HUGE_PAGES`], if large pages
+/// are supported by the processor.
+pub fn huge_page_size() -> Option<usize> {
+    let minimum = unsafe {
+        GetLargePageMinimum()
+    };
+
+    (minimum != 0).then_some(minimum)
+}
+
+pub struct MmapOptions {
+    address: Option<usize>,
+    file: Option<(File, u64)>,
+    size: usize,
+    flags: MmapFlags,
+    unsafe_flags: UnsafeMmapFlags,
+    page_size: Option<PageSize>,
+    file_lock: Option<FileLock>,
+    drop_behavior: DropBehavior,
+    raw_protection: PAGE_PROTECTION_FLAGS,
+    reservation: Option<(Arc<Shared>, usize)>,
+    address_range: Option<Range<usize>>,
+    device: bool,
+}
+
+impl MmapOptions {
+    pub fn new(size: usize) -> Self {
+        Self {
+            address: None,
+            file: None,
+            size,
+            flags: MmapFlags::empty(),
+            unsafe_flags: UnsafeMmapFlags::empty(),
+            page_size: None,
+            file_lock: None,
+            drop_behavior: DropBehavior::Unmap,
+            raw_protection: PAGE_PROTECTION_FLAGS(0),
+            reservation: None,
+            address_range: None,
+            device: false,
+        }
+    }
+
+    pub fn page_size() -> (usize, usize) {
+        let mut system_info = SYSTEM_INFO::default();
+
+        unsafe {
+            GetSystemInfo(&mut system_info)
+        };
+
+        (system_info.dwPageSize as usize, system_info.dwAllocationGranularity as usize)
+    }
+
+    pub fn with_address(mut self, address: usize) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub fn with_reservation(mut self, reservation: Arc<Shared>, offset: usize) -> Self {
+        self.reservation = Some((reservation, offset));
+        self
+    }
+
+    pub fn with_address_range(mut self, range: Range<usize>) -> Self {
+        self.address_range = Some(range);
+        self
+    }
+
+    pub fn with_file(mut self, file: File, offset: u64) -> Self {
+        self.file = Some((file, offset));
+        self
+    }
+
+    pub fn with_device(mut self, file: File, offset: u64) -> Self {
+        self.file = Some((file, offset));
+        self.device = true;
+        self
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the options to map the given window of the file, relative to the offset of these
+    /// options, with the same flags as these options, but without the placement of the mapping,
+    /// as the windows are mapped one after the other.
+    pub fn window(&self, start: usize, size: usize) -> Result<Self, Error> {
+        let (file, offset) = match self.file {
+            Some((ref file, offset)) => (file, offset + start as u64),
+            _ => return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "only files can be mapped in chunks",
+            ))?,
+        };
+
+        Ok(Self {
+            address: None,
+            file: Some((file.try_clone()?, offset)),
+            size,
+            flags: self.flags,
+            unsafe_flags: self.unsafe_flags,
+            page_size: self.page_size,
+            file_lock: self.file_lock,
+            drop_behavior: self.drop_behavior,
+            raw_protection: self.raw_protection,
+            reservation: None,
+            address_range: None,
+            device: self.device,
+        })
+    }
+
+    pub fn with_flags(mut self, flags: MmapFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub unsafe fn with_unsafe_flags(mut self, flags: UnsafeMmapFlags) -> Self {
+        self.unsafe_flags = flags;
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: PageSize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Whether the mapping has to be backed by 1 GiB pages rather than large pages.
+    fn huge_pages(&self) -> bool {
+        self.page_size == Some(PageSize::_1G)
+    }
+
+    pub fn check_huge_pages(&self) -> Result<(), Error> {
+        if self.huge_pages() {
+            if self.file.is_some() {
+                return Err(Error::HugePagesUnavailable(
+                    "1 GiB pages are only supported for anonymous mappings".to_string(),
+                ));
+            }
+
+            if !supports_huge_pages() {
+                return Err(Error::HugePagesUnavailable(
+                    "the processor does not support 1 GiB pages".to_string(),
+                ));
+            }
+
+            if !self.size.is_multiple_of(HUGE_PAGE_SIZE) {
+                return Err(Error::Unaligned(self.size, HUGE_PAGE_SIZE));
+            }
+
+            return enable_lock_memory_privilege();
+        }
+
+        if !self.flags.contains(MmapFlags::HUGE_PAGES) {
+            return Ok(());
+        }
+
+        let minimum = unsafe {
+            GetLargePageMinimum()
+        };
+
+        if minimum == 0 {
+            return Err(Error::HugePagesUnavailable(
+                "the processor does not support large pages".to_string(),
+            ));
+        }
+
+        if !self.size.is_multiple_of(minimum) {
+            return Err(Error::Unaligned(self.size, minimum));
+        }
+
+        enable_lock_memory_privilege()
+    }
+
+    pub fn with_file_lock(mut self, lock: FileLock) -> Self {
+        self.file_lock = Some(lock);
+        self
+    }
+
+    pub fn with_drop_behavior(mut self, behavior: DropBehavior) -> Self {
+        self.drop_behavior = behavior;
+        self
+    }
+
+    pub unsafe fn with_raw_page_protection(mut self, protect: u32) -> Self {
+        self.raw_protection |= PAGE_PROTECTION_FLAGS(protect);
+        self
+    }
+
+    /// This is a helper function that acquires the advisory lock on the whole backing file, if
+    /// requested. Returns true if the file has been locked. The lock is exclusive if writes to the
+    /// mapping are written back to the file.
+    fn lock_file(&self, protection: PAGE_PROTECTION_FLAGS) -> Result<bool, Error> {
+        let (file, lock) = match (self.file.as_ref(), self.file_lock) {
+            (Some((file, _)), Some(lock)) => (file, lock),
+            _ => return Ok(false),
+        };
+
+        let mut lock_flags = LOCK_FILE_FLAGS(0);
+
+        if protection == PAGE_READWRITE || protection == PAGE_EXECUTE_READWRITE {
+            lock_flags |= LOCKFILE_EXCLUSIVE_LOCK;
+        }
+
+        if lock == FileLock::Try {
+            lock_flags |= LOCKFILE_FAIL_IMMEDIATELY;
+        }
+
+        let mut overlapped = OVERLAPPED::default();
+
+        let status = unsafe {
+            LockFileEx(
+                HANDLE(file.as_raw_handle() as isize),
+                lock_flags,
+                0,
+                u32::MAX,
+                u32::MAX,
+                &mut overlapped,
+            )
+        }.as_bool();
+
+        if !status {
+            return Err(std::io::Error::last_os_error())?;
+        }
+
+        Ok(true)
+    }
+
+    /// This is a helper function that queries the access rights that have been granted to the
+    /// file handle using [`NtQueryObject`], to figure out whether a file mapping can be created
+    /// with write and execute access. Returns whether write and execute access have been granted.
+    /// Returns false for both if there is no file or if the access rights could not be queried.
+    fn granted_access(&self) -> (bool, bool) {
+        // Grab a reference to the file, if there is one. Otherwise return false immediately.
+        let file = match self.file.as_ref() {
+            Some((file, _)) => file,
+            _ => return (false, false),
+        };
+
+        let mut info = PUBLIC_OBJECT_BASIC_INFORMATION::default();
+
+        let result = unsafe {
+            NtQueryObject(
+                HANDLE(file.as_raw_handle() as isize),
+                ObjectBasicInformation,
+                &mut info as *mut _ as *mut std::ffi::c_void,
+                std::mem::size_of::<PUBLIC_OBJECT_BASIC_INFORMATION>() as u32,
+                std::ptr::null_mut(),
+            )
+        };
+
+        if result.is_err() {
+            return (false, false);
+        }
+
+        // The generic access rights have already been mapped onto the specific access rights of
+        // the file when the handle was opened.
+        let write = info.GrantedAccess & FILE_WRITE_DATA.0 != 0;
+        let execute = info.GrantedAccess & FILE_EXECUTE.0 != 0;
+
+        (write, execute)
+    }
+
+    /// This is a helper function that sets up the desired memory mapping given the protection
+    /// flag, and records the outcome.
+    fn do_map(self, protection: PAGE_PROTECTION_FLAGS) -> Result<Mmap, Error> {
+        let hint = self.address;
+        let size = self.size;
+        let flags = self.flags;
+        let unsafe_flags = self.unsafe_flags;
+
+        let result = self.map_with(protection);
+
+        trace_op!(
+            "map",
+            result,
+            address = result.as_ref().map(|mapping| mapping.ptr).ok(),
+            hint = hint,
+            size = size,
+            flags = flags,
+            unsafe_flags = unsafe_flags,
+            protect = protection,
+        );
+
+        result
+    }
+
+    /// Rounds the offset within the file down to the allocation granularity, as views of a file
+    /// mapping have to start there, and grows the size to cover the bytes in between, of which
+    /// the number is returned.
+    fn align_file_offset(&mut self) -> Result<usize, Error> {
+        let granularity = allocation_granularity() as u64;
+
+        let skew = match self.file.as_mut() {
+            Some((_, offset)) => {
+                let skew = *offset % granularity;
+                *offset -= skew;
+                skew as usize
+            }
+            _ => return Ok(0),
+        };
+
+        self.size = self.size.checked_add(skew).ok_or(Error::OutOfBounds(0..self.size))?;
+
+        Ok(skew)
+    }
+
+    /// This is a helper function that goes through the process of setting up the desired memory
+    /// mapping given the protection flag.
+    fn map_with(mut self, protection: PAGE_PROTECTION_FLAGS) -> Result<Mmap, Error> {
+        // File mappings can only be created for files, not for device handles.
+        if self.device {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "devices cannot be mapped on Microsoft Windows",
+            )));
+        }
+
+        let skew = self.align_file_offset()?;
+
+        let mut flags = Flags::empty();
+
+        if self.flags.contains(MmapFlags::COPY_ON_WRITE) {
+            flags |= Flags::COPY_ON_WRITE;
+        }
+
+        #[cfg(feature = "jit")]
+        if self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
+            flags |= Flags::JIT;
+        }
+
+        if self.flags.contains(MmapFlags::UNCACHED) {
+            flags |= Flags::UNCACHED;
+        }
+
+        if self.flags.contains(MmapFlags::WRITE_COMBINE) {
+            flags |= Flags::WRITE_COMBINE;
+        }
+
+        if self.flags.contains(MmapFlags::ZERO_ON_DROP) {
+            flags |= Flags::ZERO_ON_DROP;
+        }
+
+        if self.flags.contains(MmapFlags::CHECK_MEMORY_LIMIT) {
+            flags |= Flags::CHECK_MEMORY_LIMIT;
+        }
+
+        // Uncached and write-combining are mutually exclusive.
+        if flags.contains(Flags::UNCACHED | Flags::WRITE_COMBINE) {
+            return Err(Error::UnsupportedFlags(MmapFlags::UNCACHED | MmapFlags::WRITE_COMBINE));
+        }
+
+        // Scrubbing a shared file mapping would overwrite the contents of the file.
+        if flags.contains(Flags::ZERO_ON_DROP) && self.file.is_some() &&
+            !flags.contains(Flags::COPY_ON_WRITE) {
+            return Err(Error::UnsupportedFlags(MmapFlags::ZERO_ON_DROP));
+        }
+
+        // Mappings cannot grow downward on Microsoft Windows.
+        if self.flags.contains(MmapFlags::GROWS_DOWN) {
+            return Err(Error::UnsupportedFlags(MmapFlags::GROWS_DOWN));
+        }
+
+        // Large pages cannot be allocated without the privilege, so try to enable it up front
+        // rather than failing with ERROR_PRIVILEGE_NOT_HELD.
+        if self.flags.contains(MmapFlags::HUGE_PAGES) {
+            enable_lock_memory_privilege()?;
+        }
+
+        // Views of file mappings must start at a multiple of the allocation granularity, rather
+        // than the address being rounded down as for anonymous memory.
+        if let (Some(address), Some(_)) = (self.address, self.file.as_ref()) {
+            let granularity = allocation_granularity();
+
+            if !address.is_multiple_of(granularity) {
+                return Err(Error::Unaligned(address, granularity));
+            }
+        }
+
+        // Large pages cannot replace placeholders.
+        if self.reservation.is_some() && self.flags.contains(MmapFlags::HUGE_PAGES) {
+            return Err(Error::UnsupportedFlags(MmapFlags::HUGE_PAGES));
+        }
 
-    pub unsafe fn with_unsafe_flags(mut self, flags: UnsafeMmapFlags) -> Self {
-        self.unsafe_flags = flags;
-        self
-    }
+        // Split off a placeholder for the range that the view will replace.
+        let slot = match self.reservation.as_ref() {
+            Some((reservation, offset)) => {
+                let slot = Slot::claim(reservation.clone(), *offset, self.size)?;
 
-    pub fn with_page_size(mut self, page_size: PageSize) -> Self {
-        self.page_size = Some(page_size);
-        self
-    }
+                carve(slot.address(), slot.size())?;
 
-    /// This is a helper function that simply calls [`CreateFileMappingW`] and then [`CloseHandle`]
-    /// to check if a file mapping can be created with the given protection. This is mostly needed
-    /// to figure out whether a file mapping can be created with read, write and execute access.
-    /// Returns true on success and false otherwise.
-    fn check_protection(&self, protection: PAGE_PROTECTION_FLAGS) -> bool {
-        // Grab a reference to the file, if there is one. Otherwise return false immediately.
-        let file = match self.file.as_ref() {
-            Some((file, _)) => file,
-            _ => return false,
+                Some(slot)
+            }
+            _ => None,
         };
 
-        // Try creating a file mapping with the given protection.
-        let file_mapping = unsafe {
-            CreateFileMappingW(
-                HANDLE(file.as_raw_handle() as isize),
-                std::ptr::null_mut(),
-                protection,
-                0,
-                0,
-                PCWSTR::null(),
-            )
-        };
+        // Views of a reservation are placed by the reservation instead.
+        let address_range = self.address_range.clone().filter(|_| slot.is_none());
+
+        if let Some(range) = address_range.as_ref() {
+            check_address_range(range)?;
+
+            // The address is not merely a hint, so it has to lie within the range.
+            if let Some(address) = self.address {
+                if address < range.start || address.saturating_add(self.size) > range.end {
+                    return Err(no_address_within(range, self.size));
+                }
+            }
+        }
 
-        let file_mapping = match file_mapping {
-            Ok(file_mapping) => file_mapping,
-            _ => return false,
+        // The address requirements cannot be combined with an address, which has been checked to
+        // lie within the range already.
+        let mut requirements = MEM_ADDRESS_REQUIREMENTS::default();
+        let mut parameters = match address_range.as_ref().filter(|_| self.address.is_none()) {
+            Some(range) => vec![address_requirements(range, &mut requirements)],
+            _ => vec![],
         };
 
-        // Return false if we could not create the mapping.
-        if file_mapping.is_invalid() {
-            return false;
+        // 1 GiB pages can only be requested through the extended parameters of VirtualAlloc2(),
+        // so check up front rather than failing with a generic error.
+        let huge_pages = self.huge_pages();
+
+        if huge_pages {
+            if slot.is_some() {
+                return Err(Error::HugePagesUnavailable(
+                    "1 GiB pages cannot back a view of a reservation".to_string(),
+                ));
+            }
+
+            self.check_huge_pages()?;
+            parameters.push(huge_page_attributes());
         }
 
-        // We could create the file mapping, now close the handle and return true.
-        unsafe {
-            CloseHandle(
-                file_mapping,
-            )
+        let file_locked = self.lock_file(protection)?;
+
+        let result = self.map_pages(
+            protection,
+            flags,
+            slot.as_ref(),
+            address_range.as_ref(),
+            parameters,
+            huge_pages,
+        );
+
+        let (ptr, section) = match result {
+            Ok(mapped) => mapped,
+            Err(e) => {
+                // The lock belongs to the file object, which may be shared with duplicates of the
+                // handle, such that closing the file may not release it.
+                if file_locked {
+                    self.unlock_file();
+                }
+
+                return Err(e);
+            }
         };
 
-        true
+        let size = self.size;
+        let file = self.file.take().map(|(file, _)| file);
+
+        let record = Record::new(ptr as usize, size, self.flags, file.is_some());
+
+        Ok(Mmap {
+            file,
+            section,
+            ptr: ptr as *mut u8,
+            size,
+            skew,
+            flags,
+            drop_behavior: self.drop_behavior,
+            raw_protection: self.raw_protection,
+            file_locked,
+            slot: slot.map(Box::new),
+            record,
+        })
     }
 
-    /// This is a helper function that goes through the process of setting up the desired memory
-    /// mapping given the protection flag.
-    fn do_map(mut self, protection: PAGE_PROTECTION_FLAGS) -> Result<Mmap, Error> {
+    /// Maps the view of the file, or allocates the anonymous memory, with the given protection.
+    /// Returns the address along with the file mapping object of a view.
+    fn map_pages(
+        &self,
+        protection: PAGE_PROTECTION_FLAGS,
+        flags: Flags,
+        slot: Option<&Slot>,
+        address_range: Option<&Range<usize>>,
+        mut parameters: Vec<MEM_EXTENDED_PARAMETER>,
+        huge_pages: bool,
+    ) -> Result<(*mut std::ffi::c_void, Option<Section>), Error> {
         // We have to check whether we can create the file mapping with write and execute
         // permissions. As Microsoft Windows won't let us set any access flags other than those
         // that have been set initially, we have to figure out the full set of access flags that
         // we can set, and then narrow down the access rights to what the user requested.
-        let write = self.check_protection(PAGE_READWRITE);
-        let execute = self.check_protection(PAGE_EXECUTE_READ);
+        let (write, execute) = self.granted_access();
 
         let mut map_access = FILE_MAP_READ;
         let mut map_protection = match (write, execute) {
@@ -331,98 +2771,212 @@ impl MmapOptions {
         };
 
         let size = self.size;
+        let mut section = None;
         let ptr = if let Some((file, offset)) = &self.file {
             if self.flags.contains(MmapFlags::HUGE_PAGES) {
                 map_access |= FILE_MAP_LARGE_PAGES;
                 map_protection |= SEC_LARGE_PAGES;
             }
 
+            if flags.contains(Flags::UNCACHED) {
+                map_protection |= SEC_NOCACHE;
+            } else if flags.contains(Flags::WRITE_COMBINE) {
+                map_protection |= SEC_WRITECOMBINE;
+            }
+
+            // The section has to cover the file up to the end of the view.
+            let end = *offset + size as u64;
+
             let file_mapping = unsafe {
                 CreateFileMappingW(
                     HANDLE(file.as_raw_handle() as isize),
                     std::ptr::null_mut(),
                     map_protection,
-                    (match size.overflowing_shr(32) {
-                        (_, true) => 0,
-                        (size, false) => size,
-                    } & 0xffff_ffff) as u32,
-                    (size & 0xffff_ffff) as u32,
+                    ((end >> 32) & 0xffff_ffff) as u32,
+                    (end & 0xffff_ffff) as u32,
                     PCWSTR::null(),
                 )
             }?;
 
-            let ptr = unsafe {
-                MapViewOfFileEx(
-                    file_mapping,
-                    map_access,
-                    ((offset >> 32) & 0xffff_ffff) as u32,
-                    (offset & 0xffff_ffff) as u32,
-                    size,
-                    std::ptr::null(),
-                )
-            };
+            // Copy-on-write views are mapped as private views using `FILE_MAP_COPY`, whatever
+            // the initial protection is, such that writes never reach the file, and such that the
+            // view never has to be remapped when the protection changes, as remapping the view
+            // would discard the private pages. This matches `MAP_PRIVATE` on Unix.
+            if flags.contains(Flags::COPY_ON_WRITE) {
+                map_access = FILE_MAP_COPY |
+                    FILE_MAP(map_access.0 & (FILE_MAP_EXECUTE.0 | FILE_MAP_LARGE_PAGES.0));
+            }
 
-            unsafe {
-                CloseHandle(file_mapping)
+            let ptr = if slot.is_some() || !parameters.is_empty() {
+                // Placeholders are replaced using page protection rather than access flags.
+                let view_protection = if map_access.0 & FILE_MAP_COPY.0 == FILE_MAP_COPY.0 {
+                    if execute { PAGE_EXECUTE_WRITECOPY } else { PAGE_WRITECOPY }
+                } else {
+                    PAGE_PROTECTION_FLAGS(map_protection.0 & 0xff)
+                };
+
+                let (address, allocation_type) = match slot {
+                    Some(slot) => (slot.address(), MEM_REPLACE_PLACEHOLDER),
+                    _ => (0, VIRTUAL_ALLOCATION_TYPE(0)),
+                };
+
+                unsafe {
+                    MapViewOfFile3(
+                        file_mapping,
+                        GetCurrentProcess(),
+                        address as *const std::ffi::c_void,
+                        *offset,
+                        size,
+                        allocation_type,
+                        view_protection.0,
+                        &mut parameters,
+                    )
+                }
+            } else {
+                unsafe {
+                    MapViewOfFileEx(
+                        file_mapping,
+                        map_access,
+                        ((offset >> 32) & 0xffff_ffff) as u32,
+                        (offset & 0xffff_ffff) as u32,
+                        size,
+                        self.address
+                            .map(|address| address as *const std::ffi::c_void)
+                            .unwrap_or(std::ptr::null()),
+                    )
+                }
             };
 
+            if ptr.is_null() {
+                let e = std::io::Error::last_os_error();
+
+                unsafe {
+                    CloseHandle(file_mapping)
+                };
+
+                return Err(match address_range {
+                    Some(range) => map_within_error(e, range, size),
+                    _ => map_at_error(e, self.address, size),
+                });
+            }
+
             let mut old_protect = PAGE_PROTECTION_FLAGS::default();
 
             let status = unsafe {
                 VirtualProtect(
                     ptr,
                     size,
-                    protection,
+                    protection | self.raw_protection,
                     &mut old_protect,
                 )
             }.as_bool();
 
             if !status {
-                return Err(std::io::Error::last_os_error())?;
+                let e = std::io::Error::last_os_error();
+
+                unsafe {
+                    if slot.is_some() {
+                        UnmapViewOfFile2(GetCurrentProcess(), ptr, MEM_PRESERVE_PLACEHOLDER);
+                    } else {
+                        UnmapViewOfFile(ptr);
+                    }
+
+                    CloseHandle(file_mapping);
+                };
+
+                return Err(map_error(e, size));
             }
 
+            section = Some(Section {
+                handle: file_mapping,
+                offset: *offset,
+                access: map_access,
+                protection: map_protection,
+            });
+
             ptr
         } else {
-            let mut flags = MEM_COMMIT | MEM_RESERVE;
+            let mut alloc_flags = MEM_COMMIT | MEM_RESERVE;
 
-            if self.flags.contains(MmapFlags::HUGE_PAGES) {
-                flags |= MEM_LARGE_PAGES;
+            if self.flags.contains(MmapFlags::HUGE_PAGES) || huge_pages {
+                alloc_flags |= MEM_LARGE_PAGES;
             }
 
-            unsafe {
-                VirtualAlloc(
-                    self.address
-                        .map(|address| address as *mut std::ffi::c_void)
-                        .unwrap_or(std::ptr::null_mut()),
-                    size,
-                    flags,
-                    protection,
-                )
+            if let Some(slot) = slot {
+                unsafe {
+                    VirtualAlloc2(
+                        GetCurrentProcess(),
+                        slot.address() as *const std::ffi::c_void,
+                        slot.size(),
+                        alloc_flags | MEM_REPLACE_PLACEHOLDER,
+                        (with_cache_attributes(flags, protection) | self.raw_protection).0,
+                        &mut [],
+                    )
+                }
+            } else if !parameters.is_empty() {
+                unsafe {
+                    VirtualAlloc2(
+                        GetCurrentProcess(),
+                        std::ptr::null(),
+                        size,
+                        alloc_flags,
+                        (with_cache_attributes(flags, protection) | self.raw_protection).0,
+                        &mut parameters,
+                    )
+                }
+            } else {
+                unsafe {
+                    VirtualAlloc(
+                        self.address
+                            .map(|address| address as *mut std::ffi::c_void)
+                            .unwrap_or(std::ptr::null_mut()),
+                        size,
+                        alloc_flags,
+                        with_cache_attributes(flags, protection) | self.raw_protection,
+                    )
+                }
             }
         };
 
         if ptr.is_null() {
-            return Err(std::io::Error::last_os_error())?;
+            let e = std::io::Error::last_os_error();
+
+            return Err(match address_range {
+                _ if huge_pages => huge_page_error(e, size),
+                Some(range) => map_within_error(e, range, size),
+                _ => map_at_error(e, self.address, size),
+            });
         }
 
-        let size = self.size;
-        let file = self.file.take().map(|(file, _)| file);
-        let mut flags = Flags::empty();
+        Ok((ptr, section))
+    }
 
-        if self.flags.contains(MmapFlags::COPY_ON_WRITE) {
-            flags |= Flags::COPY_ON_WRITE;
+    /// Releases the advisory lock on the backing file that has been acquired by `lock_file()`.
+    fn unlock_file(&self) {
+        if let Some((file, _)) = self.file.as_ref() {
+            let mut overlapped = OVERLAPPED::default();
+
+            let _ = unsafe {
+                UnlockFileEx(
+                    HANDLE(file.as_raw_handle() as isize),
+                    0,
+                    u32::MAX,
+                    u32::MAX,
+                    &mut overlapped,
+                )
+            };
         }
+    }
 
-        if self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
-            flags |= Flags::JIT;
+    pub fn reserve(self) -> Result<ReservedMmap, Error> {
+        if self.file.is_some() || self.reservation.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "reserved mappings must be anonymous",
+            )));
         }
 
-        Ok(Mmap {
-            file,
-            ptr: ptr as *mut u8,
-            size,
-            flags,
-        })
+        ReservedMmap::new(self.address, self.size)
     }
 
     pub fn map_none(self) -> Result<Mmap, Error> {
@@ -433,6 +2987,7 @@ impl MmapOptions {
         self.do_map(PAGE_READONLY)
     }
 
+    #[cfg(feature = "exec")]
     pub fn map_exec(self) -> Result<Mmap, Error> {
         self.do_map(PAGE_EXECUTE_READ)
     }
@@ -447,6 +3002,28 @@ impl MmapOptions {
         self.do_map(protect)
     }
 
+    /// Maps a stack with an inaccessible guard page below it, and returns the mapping along with
+    /// the size of the guard page.
+    pub fn map_stack(mut self) -> Result<(Mmap, usize), Error> {
+        if self.file.is_some() || self.reservation.is_some() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "stacks must be anonymous",
+            )));
+        }
+
+        let guard = crate::page_size();
+
+        self.size = self.size.checked_add(guard).ok_or(Error::OutOfBounds(0..self.size))?;
+
+        let mapping = self.map_mut()?;
+
+        mapping.protect_ranges(&[(0..guard, Protection::empty())])?;
+
+        Ok((mapping, guard))
+    }
+
+    #[cfg(feature = "jit")]
     pub fn map_exec_mut(self) -> Result<Mmap, Error> {
         if !self.unsafe_flags.contains(UnsafeMmapFlags::JIT) {
             return Err(Error::UnsafeFlagNeeded(UnsafeMmapFlags::JIT));
@@ -462,123 +3039,515 @@ impl MmapOptions {
     }
 }
 
+/// Allocates `size` bytes of readable and writable memory, where the page at the offset `guard`
+/// is made inaccessible. Returns a null pointer on failure. This does not allocate on the heap,
+/// as it backs the guarded allocator.
+pub fn map_guarded(size: usize, guard: usize) -> *mut u8 {
+    let ptr = unsafe {
+        VirtualAlloc(std::ptr::null(), size, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE)
+    } as *mut u8;
+
+    if ptr.is_null() {
+        return ptr;
+    }
+
+    let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+
+    let status = unsafe {
+        VirtualProtect(
+            ptr.add(guard) as *const std::ffi::c_void,
+            crate::page_size(),
+            PAGE_NOACCESS,
+            &mut old_protect,
+        )
+    }.as_bool();
+
+    if !status {
+        unsafe {
+            VirtualFree(ptr as *mut std::ffi::c_void, 0, MEM_RELEASE)
+        };
+
+        return std::ptr::null_mut();
+    }
+
+    ptr
+}
+
+/// Decommits the pages, which releases the memory, while keeping the address range reserved,
+/// such that accessing the pages faults.
+pub fn retire_guarded(ptr: *mut u8, size: usize) {
+    unsafe {
+        VirtualFree(ptr as *mut std::ffi::c_void, size, MEM_DECOMMIT)
+    };
+}
+
+/// There is no equivalent of `mlockall()`, so the minimum working set size is raised to the
+/// current working set size instead, and enforced as a hard limit.
+pub fn lock_all(flags: LockAllFlags) -> Result<(), Error> {
+    if flags.intersects(LockAllFlags::FUTURE | LockAllFlags::ON_FAULT) {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "only the pages that are currently resident can be locked on Microsoft Windows",
+        )));
+    }
+
+    if !flags.contains(LockAllFlags::CURRENT) {
+        return Ok(());
+    }
+
+    let process = unsafe { GetCurrentProcess() };
+    let mut counters = PROCESS_MEMORY_COUNTERS {
+        cb: std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        ..Default::default()
+    };
+
+    if !unsafe { K32GetProcessMemoryInfo(process, &mut counters, counters.cb) }.as_bool() {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    let mut minimum = 0;
+    let mut maximum = 0;
+
+    if !unsafe { GetProcessWorkingSetSize(process, &mut minimum, &mut maximum) }.as_bool() {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    let minimum = minimum.max(counters.WorkingSetSize);
+    let maximum = maximum.max(minimum);
+
+    let status = unsafe {
+        SetProcessWorkingSetSizeEx(process, minimum, maximum, QUOTA_LIMITS_HARDWS_MIN_ENABLE)
+    }.as_bool();
+
+    let result = if status {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    };
+
+    trace_op!("lock_all", result, flags = flags, minimum = minimum, maximum = maximum);
+
+    result.map_err(lock_error)?;
+
+    Ok(())
+}
+
+pub fn unlock_all() -> Result<(), Error> {
+    let process = unsafe { GetCurrentProcess() };
+    let mut minimum = 0;
+    let mut maximum = 0;
+
+    if !unsafe { GetProcessWorkingSetSize(process, &mut minimum, &mut maximum) }.as_bool() {
+        return Err(std::io::Error::last_os_error())?;
+    }
+
+    let status = unsafe {
+        SetProcessWorkingSetSizeEx(process, minimum, maximum, QUOTA_LIMITS_HARDWS_MIN_DISABLE)
+    }.as_bool();
+
+    let result = if status {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    };
+
+    trace_op!("unlock_all", result, minimum = minimum, maximum = maximum);
+
+    Ok(result?)
+}
+
+/// Makes the pages read-only to track the writes to them, or readable and writable again.
+///
+/// # Safety
+///
+/// The pages must belong to a mapping that is readable and writable.
+pub unsafe fn write_protect(ptr: *mut u8, size: usize, protect: bool) -> Result<(), Error> {
+    let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+    let protect = if protect { PAGE_READONLY } else { PAGE_READWRITE };
+
+    if !VirtualProtect(ptr as *mut std::ffi::c_void, size, protect, &mut old_protect).as_bool() {
+        return Err(Error::Windows(windows::core::Error::from_win32()));
+    }
+
+    Ok(())
+}
+
+/// Continues execution at the faulting instruction, as returned by vectored exception handlers.
+const EXCEPTION_CONTINUE_EXECUTION: i32 = -1;
+/// Passes the exception on to the next handler, as returned by vectored exception handlers.
+const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+
+/// Installs the handler that records writes to tracked mappings, once.
+pub fn install_write_fault_handler() -> Result<(), Error> {
+    static INSTALLED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+
+    let installed = *INSTALLED.get_or_init(|| unsafe {
+        !AddVectoredExceptionHandler(1, Some(handle_write_fault)).is_null()
+    });
+
+    if !installed {
+        return Err(Error::Io(std::io::Error::other(
+            "the vectored exception handler could not be installed",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Records writes to tracked mappings and makes the page writable again, such that the write is
+/// retried once the handler returns. Other exceptions are passed on to the next handler.
+unsafe extern "system" fn handle_write_fault(info: *mut EXCEPTION_POINTERS) -> i32 {
+    let record = &*(*info).ExceptionRecord;
+
+    // For access violations, the first parameter is one for writes and the second parameter is
+    // the address that was accessed.
+    if record.ExceptionCode != EXCEPTION_ACCESS_VIOLATION ||
+        record.NumberParameters < 2 ||
+        record.ExceptionInformation[0] != 1 {
+        return EXCEPTION_CONTINUE_SEARCH;
+    }
+
+    if let Some(page) = crate::dirty::record_write_fault(record.ExceptionInformation[1]) {
+        let mut old_protect = PAGE_PROTECTION_FLAGS::default();
+
+        if VirtualProtect(
+            page as *mut std::ffi::c_void,
+            crate::page_size(),
+            PAGE_READWRITE,
+            &mut old_protect,
+        ).as_bool() {
+            return EXCEPTION_CONTINUE_EXECUTION;
+        }
+    }
+
+    EXCEPTION_CONTINUE_SEARCH
+}
+
+pub fn capabilities() -> Capabilities {
+    let (page_size, allocation_granularity) = (crate::page_size(), allocation_granularity());
+
+    let minimum = unsafe {
+        GetLargePageMinimum()
+    };
+
+    let mut huge_page_sizes = if minimum == 0 {
+        vec![]
+    } else {
+        vec![minimum]
+    };
+
+    if minimum != 0 && supports_huge_pages() {
+        huge_page_sizes.push(HUGE_PAGE_SIZE);
+    }
+
+    let mut highest_node = 0;
+
+    let numa_nodes = if unsafe { GetNumaHighestNodeNumber(&mut highest_node) }.as_bool() {
+        highest_node as usize + 1
+    } else {
+        1
+    };
+
+    Capabilities {
+        page_size,
+        allocation_granularity,
+        huge_page_sizes,
+        // VirtualAlloc() and MapViewOfFileEx() never replace existing pages.
+        fixed_noreplace: true,
+        memfd: false,
+        sealing: false,
+        jit: false,
+        write_watch: true,
+        numa_nodes,
+    }
+}
+
+/// Flushes the pages in the given range of addresses to the file backing them. As flushing never
+/// changes the contents of the pages, this is safe to call even if the view has been unmapped, in
+/// which case this merely fails. `FlushViewOfFile()` does not wait for the pages to be written to
+/// the disk, so the file is flushed as well to flush synchronously.
+pub fn flush_range(address: usize, size: usize, file: Option<&File>, sync: bool) -> Result<(), Error> {
+    let status = unsafe {
+        FlushViewOfFile(address as *const std::ffi::c_void, size)
+    }.as_bool();
+
+    let result = if !status {
+        Err(std::io::Error::last_os_error())
+    } else {
+        match file {
+            Some(file) if sync => file.sync_data(),
+            _ => Ok(()),
+        }
+    };
+
+    trace_op!("flush_range", result, address = address, size = size, sync = sync);
+
+    Ok(result?)
+}
+
+/// Returns for every page in the given range whether the page has been touched. This is not
+/// supported on Microsoft Windows, as the working set does not tell whether pages that are not
+/// resident have been paged out or have never been touched.
+pub fn touched_pages(_address: usize, _size: usize) -> Result<Option<Vec<bool>>, Error> {
+    Ok(None)
+}
+
+#[cfg(feature = "areas")]
+use crate::areas::{MemoryArea, PathPattern, ShareMode};
+#[cfg(feature = "areas")]
 use std::io::{BufRead, BufReader};
+#[cfg(feature = "areas")]
 use std::marker::PhantomData;
+#[cfg(feature = "areas")]
+use std::path::PathBuf;
+#[cfg(feature = "areas")]
+use windows::Win32::Foundation::MAX_PATH;
+#[cfg(feature = "areas")]
+use windows::Win32::System::ProcessStatus::K32GetMappedFileNameW;
+#[cfg(feature = "areas")]
+use windows::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ};
 
+#[cfg(feature = "areas")]
 pub struct MemoryAreas<B> {
     handle: HANDLE,
+    owned: bool,
     address: usize,
+    name: Vec<u16>,
+    pending: Option<MemoryArea>,
+    pattern: Option<PathPattern>,
     marker: PhantomData<B>,
 }
 
+#[cfg(feature = "areas")]
 impl MemoryAreas<BufReader<File>> {
     pub fn open(pid: Option<u32>) -> Result<Self, Error> {
-        let handle = match pid {
-            Some(id) => unsafe { OpenProcess(
-                PROCESS_ALL_ACCESS,
+        // Only request the access needed to query the memory of the process, such that the
+        // process does not have to be suspended or fully opened.
+        let (handle, owned) = match pid {
+            Some(id) => (unsafe { OpenProcess(
+                PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
                 false,
                 id,
-            ) }?,
-            _ => unsafe { GetCurrentProcess() },
+            ) }?, true),
+            _ => (unsafe { GetCurrentProcess() }, false),
         };
 
         Ok(Self {
             handle,
+            owned,
             address: 0,
+            name: vec![0u16; MAX_PATH as usize],
+            pending: None,
+            pattern: None,
             marker: PhantomData,
         })
     }
-}
 
-impl<B: BufRead> Iterator for MemoryAreas<B> {
-    type Item = Result<MemoryArea, Error>;
+    pub fn query(pid: Option<u32>, address: usize) -> Result<Option<MemoryArea>, Error> {
+        let mut areas = Self::open(pid)?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut info = MEMORY_BASIC_INFORMATION::default();
+        Ok(areas.region(address).and_then(|(_, area)| area))
+    }
+}
 
-        loop {
-            let address = self.address;
+#[cfg(feature = "areas")]
+impl<B> MemoryAreas<B> {
+    pub fn filter_path(&mut self, pattern: PathPattern) {
+        self.pattern = Some(pattern);
+    }
+}
 
-            let size = unsafe {
-                VirtualQueryEx(
-                    self.handle,
-                    address as _,
-                    &mut info,
-                    std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
-                )
+#[cfg(feature = "areas")]
+impl<B> Drop for MemoryAreas<B> {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe {
+                CloseHandle(self.handle)
             };
+        }
+    }
+}
 
-            if size < std::mem::size_of::<MEMORY_BASIC_INFORMATION>() {
-                return None;
-            }
+#[cfg(feature = "areas")]
+impl<B> MemoryAreas<B> {
+    /// Queries the region of the process containing the given address using `VirtualQueryEx()`,
+    /// returning the end of the region, along with the region itself if it is committed.
+    fn region(&mut self, address: usize) -> Option<(usize, Option<MemoryArea>)> {
+        let mut info = MEMORY_BASIC_INFORMATION::default();
 
-            let size = info.RegionSize as usize;
-            let start = info.BaseAddress as usize;
-            let end = start + size;
-            let range = start..end;
+        let size = unsafe {
+            VirtualQueryEx(
+                self.handle,
+                address as _,
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
 
-            self.address += size;
+        if size < std::mem::size_of::<MEMORY_BASIC_INFORMATION>() {
+            return None;
+        }
 
-            if info.State & MEM_COMMIT == VIRTUAL_ALLOCATION_TYPE(0) {
-                continue;
-            }
+        let size = info.RegionSize as usize;
+        let start = info.BaseAddress as usize;
+        let end = start + size;
+        let range = start..end;
 
-            let copy_on_write =
-                info.Protect == PAGE_EXECUTE_WRITECOPY ||
-                info.Protect == PAGE_WRITECOPY;
+        if info.State & MEM_COMMIT == VIRTUAL_ALLOCATION_TYPE(0) {
+            return Some((end, None));
+        }
 
-            let share_mode = if info.Type & MEM_PRIVATE == MEM_PRIVATE {
-                ShareMode::Private
-            } else if copy_on_write {
-                ShareMode::CopyOnWrite
-            } else {
-                ShareMode::Shared
-            };
+        let copy_on_write =
+            info.Protect == PAGE_EXECUTE_WRITECOPY ||
+            info.Protect == PAGE_WRITECOPY;
 
-            let protection = match info.Protect {
-                PAGE_EXECUTE =>
-                    Protection::EXECUTE,
-                PAGE_EXECUTE_READ =>
-                    Protection::READ | Protection::EXECUTE,
-                PAGE_EXECUTE_READWRITE |
-                PAGE_EXECUTE_WRITECOPY =>
-                    Protection::READ | Protection::WRITE | Protection::EXECUTE,
-                PAGE_READONLY =>
-                    Protection::READ,
-                PAGE_READWRITE |
-                PAGE_WRITECOPY =>
-                    Protection::READ | Protection::WRITE,
-                _ =>
-                    Protection::empty(),
-            };
+        let private = info.Type & MEM_PRIVATE == MEM_PRIVATE;
 
-            let mut name = vec![0u16; MAX_PATH as usize];
+        let share_mode = if private {
+            ShareMode::Private
+        } else if copy_on_write {
+            ShareMode::CopyOnWrite
+        } else {
+            ShareMode::Shared
+        };
 
-            let name_size = unsafe {
+        let protection = to_protection(info.Protect);
+
+        // Private memory is never backed by a file, so skip looking up the file name.
+        let name_size = if private {
+            0
+        } else {
+            unsafe {
                 K32GetMappedFileNameW(
                     self.handle,
                     address as *const std::ffi::c_void,
-                    &mut name,
+                    &mut self.name,
                 )
-            };
+            }
+        };
 
-            let path = if name_size != 0 {
-                let path = widestring::U16CStr::from_slice_truncate(&name).unwrap();
-                let path = path.to_string_lossy();
+        let path = if name_size != 0 {
+            let path = widestring::U16CStr::from_slice_truncate(&self.name).unwrap();
+            let path = path.to_string_lossy();
 
-                let offset = (info.BaseAddress as u64) - (info.AllocationBase as u64);
+            let offset = (info.BaseAddress as u64) - (info.AllocationBase as u64);
 
-                Some((PathBuf::from(path), offset))
-            } else {
-                None
+            Some((PathBuf::from(path), offset))
+        } else {
+            None
+        };
+
+        Some((end, Some(MemoryArea {
+            range,
+            protection,
+            share_mode,
+            path,
+        })))
+    }
+
+    /// Queries the next committed region of the process.
+    fn next_region(&mut self) -> Option<MemoryArea> {
+        loop {
+            let (end, area) = self.region(self.address)?;
+
+            self.address = end;
+
+            if let Some(area) = area {
+                return Some(area);
+            }
+        }
+    }
+}
+
+/// Converts the [`Protection`] into the page protection as used by `VirtualProtect()`, where
+/// writable pages of copy-on-write mappings are protected as copy-on-write.
+fn page_protection(protection: Protection, copy_on_write: bool) -> PAGE_PROTECTION_FLAGS {
+    let read = protection.contains(Protection::READ);
+    let write = protection.contains(Protection::WRITE);
+    let execute = protection.contains(Protection::EXECUTE);
+
+    match (write, execute) {
+        (true, true) if copy_on_write => PAGE_EXECUTE_WRITECOPY,
+        (true, true) => PAGE_EXECUTE_READWRITE,
+        (true, false) if copy_on_write => PAGE_WRITECOPY,
+        (true, false) => PAGE_READWRITE,
+        (false, true) if read => PAGE_EXECUTE_READ,
+        (false, true) => PAGE_EXECUTE,
+        (false, false) if read => PAGE_READONLY,
+        (false, false) => PAGE_NOACCESS,
+    }
+}
+
+/// Converts the page protection as returned by `VirtualQuery()` into a [`Protection`], ignoring
+/// modifiers such as `PAGE_GUARD` and `PAGE_NOCACHE`.
+fn to_protection(protect: PAGE_PROTECTION_FLAGS) -> Protection {
+    match PAGE_PROTECTION_FLAGS(protect.0 & 0xff) {
+        PAGE_EXECUTE =>
+            Protection::EXECUTE,
+        PAGE_EXECUTE_READ =>
+            Protection::READ | Protection::EXECUTE,
+        PAGE_EXECUTE_READWRITE |
+        PAGE_EXECUTE_WRITECOPY =>
+            Protection::READ | Protection::WRITE | Protection::EXECUTE,
+        PAGE_READONLY =>
+            Protection::READ,
+        PAGE_READWRITE |
+        PAGE_WRITECOPY =>
+            Protection::READ | Protection::WRITE,
+        _ =>
+            Protection::empty(),
+    }
+}
+
+/// Returns true if the next memory area directly follows the memory area with the same
+/// attributes, such that both can be reported as a single memory area.
+#[cfg(feature = "areas")]
+fn is_contiguous(area: &MemoryArea, next: &MemoryArea) -> bool {
+    if area.range.end != next.range.start ||
+        area.protection != next.protection ||
+        area.share_mode != next.share_mode {
+        return false;
+    }
+
+    match (&area.path, &next.path) {
+        (None, None) => true,
+        (Some((path, offset)), Some((next_path, next_offset))) =>
+            path == next_path && offset + (area.range.end - area.range.start) as u64 == *next_offset,
+        _ => false,
+    }
+}
+
+#[cfg(feature = "areas")]
+impl<B: BufRead> Iterator for MemoryAreas<B> {
+    type Item = Result<MemoryArea, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut area = match self.pending.take() {
+                Some(area) => area,
+                _ => self.next_region()?,
             };
 
-            return Some(Ok(MemoryArea {
-                range,
-                protection,
-                share_mode,
-                path,
-            }));
+            // Coalesce adjacent regions with identical attributes, like VMMap does, as Microsoft
+            // Windows reports every allocation as a separate region.
+            while let Some(next) = self.next_region() {
+                if !is_contiguous(&area, &next) {
+                    self.pending = Some(next);
+                    break;
+                }
+
+                area.range.end = next.range.end;
+            }
+
+            if let Some(pattern) = &self.pattern {
+                let path = area.path.as_ref().map(|(path, _)| path.to_string_lossy());
+
+                if !matches!(path, Some(path) if pattern.matches(&path)) {
+                    continue;
+                }
+            }
+
+            return Some(Ok(area));
         }
     }
 }
@@ -0,0 +1,124 @@
+//! This module implements mapping the same range of a file twice, once as read-only and once as
+//! writable, such that readers cannot accidentally write to the file while a producer writes to
+//! it, without having to change the protection of the mapping back and forth.
+
+use crate::error::Error;
+use crate::mmap::MmapOptions;
+use crate::{Mmap, MmapMut};
+use std::fs::File;
+use std::ops::Range;
+
+/// Two views of the same range of a file, where the read-only view is handed out to consumers
+/// and the writable view is used by the producer. Writes through the writable view are visible
+/// through the read-only view right away, as both views map the same pages of the file. See
+/// [`PairedMmap::new()`].
+///
+/// Both views are owned by this object, such that they are unmapped together and such that the
+/// contents are flushed through the writable view, which covers the same pages as the read-only
+/// view.
+pub struct PairedMmap {
+    writable: MmapMut,
+    read_only: Mmap,
+}
+
+impl PairedMmap {
+    /// Maps `size` bytes of the file starting at `offset` twice, once as read-only and once as
+    /// writable. The file must have been opened for reading and writing.
+    ///
+    /// This is not supported with the `fake` feature, as the views of a fake mapping are copies
+    /// of the file rather than the same pages, in which case this fails with an error of the
+    /// kind [`std::io::ErrorKind::Unsupported`].
+    ///
+    /// # Safety
+    ///
+    /// See [`MmapOptions::with_file()`].
+    pub unsafe fn new(file: File, offset: u64, size: usize) -> Result<Self, Error> {
+        if cfg!(feature = "fake") {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "fake mappings cannot share pages between views",
+            )));
+        }
+
+        let read_only = MmapOptions::new(size)
+            .with_file(file.try_clone()?, offset)
+            .map()?;
+
+        let writable = MmapOptions::new(size)
+            .with_file(file, offset)
+            .map_mut()?;
+
+        Ok(Self {
+            writable,
+            read_only,
+        })
+    }
+
+    /// Returns the file backing the views.
+    #[inline]
+    pub fn file(&self) -> Option<&File> {
+        self.writable.file()
+    }
+
+    /// Returns the size of either view in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.writable.size()
+    }
+
+    /// Returns the read-only view, which is meant to be handed out to consumers. The writable
+    /// view cannot be borrowed while the read-only view is, such that the producer cannot change
+    /// the contents through this object in the meantime. Use [`PairedMmap::split()`] to borrow
+    /// both views at the same time.
+    #[inline]
+    pub fn read_only(&self) -> &Mmap {
+        &self.read_only
+    }
+
+    /// Returns the contents of the writable view, which is meant to be used by the producer. The
+    /// mapping itself is not handed out, as changing it, e.g. using [`MmapMut::truncate()`], would
+    /// no longer keep both views in sync.
+    #[inline]
+    pub fn writable(&mut self) -> &mut [u8] {
+        self.writable.as_mut_slice()
+    }
+
+    /// Returns both views at the same time, such that the read-only view can be handed out to
+    /// consumers, e.g. on other threads, while the producer writes to the writable view.
+    ///
+    /// The views are at different addresses, but cover the same pages, such that consumers may
+    /// observe writes that are in progress.
+    ///
+    /// # Safety
+    ///
+    /// The read-only view dereferences to a shared slice, which promises that its contents do not
+    /// change for as long as the slice is borrowed, while the producer may change them through
+    /// the writable slice. The caller must ensure that no reference into the read-only view,
+    /// e.g. a slice obtained by dereferencing the [`Mmap`], covers bytes that are written through
+    /// the writable slice while the reference is alive. Bytes that are read while they may be
+    /// written must only be accessed using volatile reads, e.g. [`Mmap::read_volatile_at()`], or
+    /// using atomics through [`Mmap::as_ptr()`], where the caller co-ordinates with the producer
+    /// to observe consistent contents.
+    #[inline]
+    pub unsafe fn split(&mut self) -> (&Mmap, &mut [u8]) {
+        (&self.read_only, self.writable.as_mut_slice())
+    }
+
+    /// Flushes the given range of the file synchronously, which covers the writes through the
+    /// writable view. See [`MmapMut::flush()`].
+    pub fn flush(&self, range: Range<usize>) -> Result<(), Error> {
+        self.writable.flush(range)
+    }
+
+    /// Flushes the given range of the file asynchronously, which covers the writes through the
+    /// writable view. See [`MmapMut::flush_async()`].
+    pub fn flush_async(&self, range: Range<usize>) -> Result<(), Error> {
+        self.writable.flush_async(range)
+    }
+
+    /// Unmaps the read-only view and returns the writable view. Consumers borrow the read-only
+    /// view from this object, such that they can no longer be using it at this point.
+    pub fn into_writable(self) -> MmapMut {
+        self.writable
+    }
+}
@@ -0,0 +1,130 @@
+//! This module implements writing back cache lines to memory, which is how stores to persistent
+//! memory mapped through DAX become durable without the overhead of `msync()`.
+
+/// Writes back the cache lines covering the given memory and waits for the write-back to
+/// complete, using `CLWB` or `CLFLUSHOPT` followed by `SFENCE` on x86 and x86-64, and `DC CVAP`
+/// followed by `DSB` on AArch64. Older CPUs fall back to `CLFLUSH` and `DC CVAC` respectively.
+/// Returns `false` if the architecture has no such instructions, in which case nothing is done.
+///
+/// # Safety
+///
+/// The memory must be readable.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) unsafe fn write_back(ptr: *const u8, size: usize) -> bool {
+    use std::arch::asm;
+
+    // Every x86 CPU has a cache line size of at least 64 bytes.
+    const LINE: usize = 64;
+
+    let start = ptr as usize & !(LINE - 1);
+    let end = ptr as usize + size;
+
+    match flush_instruction() {
+        FlushInstruction::Clwb => for line in (start..end).step_by(LINE) {
+            asm!("clwb [{}]", in(reg) line, options(nostack, preserves_flags));
+        },
+        FlushInstruction::ClflushOpt => for line in (start..end).step_by(LINE) {
+            asm!("clflushopt [{}]", in(reg) line, options(nostack, preserves_flags));
+        },
+        // CLFLUSH is ordered with respect to other stores, but the fence below does no harm.
+        FlushInstruction::Clflush => for line in (start..end).step_by(LINE) {
+            asm!("clflush [{}]", in(reg) line, options(nostack, preserves_flags));
+        },
+    }
+
+    asm!("sfence", options(nostack, preserves_flags));
+
+    true
+}
+
+/// The fastest instruction to write back a cache line that the CPU supports.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[derive(Clone, Copy)]
+enum FlushInstruction {
+    Clwb = 1,
+    ClflushOpt = 2,
+    Clflush = 3,
+}
+
+/// Detects the fastest instruction to write back a cache line once, as `CPUID` is expensive in
+/// virtual machines. The standard library cannot detect `CLWB` and `CLFLUSHOPT` on stable Rust.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn flush_instruction() -> FlushInstruction {
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::{__cpuid, __cpuid_count};
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::{__cpuid, __cpuid_count};
+
+    static INSTRUCTION: AtomicU8 = AtomicU8::new(0);
+
+    match INSTRUCTION.load(Ordering::Relaxed) {
+        1 => return FlushInstruction::Clwb,
+        2 => return FlushInstruction::ClflushOpt,
+        3 => return FlushInstruction::Clflush,
+        _ => (),
+    }
+
+    // The extended features are reported by leaf 7, where bit 23 of EBX is CLFLUSHOPT and bit 24
+    // is CLWB. Executing CPUID is only safe on recent versions of Rust.
+    #[allow(unused_unsafe)]
+    let features = unsafe {
+        if __cpuid(0).eax >= 7 { __cpuid_count(7, 0).ebx } else { 0 }
+    };
+
+    let instruction = if features & (1 << 24) != 0 {
+        FlushInstruction::Clwb
+    } else if features & (1 << 23) != 0 {
+        FlushInstruction::ClflushOpt
+    } else {
+        FlushInstruction::Clflush
+    };
+
+    INSTRUCTION.store(instruction as u8, Ordering::Relaxed);
+
+    instruction
+}
+
+/// See the x86 implementation.
+///
+/// # Safety
+///
+/// The memory must be readable.
+#[cfg(target_arch = "aarch64")]
+pub(crate) unsafe fn write_back(ptr: *const u8, size: usize) -> bool {
+    use std::arch::asm;
+
+    // The smallest data cache line size is encoded as the log2 of the number of words.
+    let ctr: u64;
+    asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack, preserves_flags));
+    let line = 4usize << ((ctr >> 16) & 0xf);
+
+    let start = ptr as usize & !(line - 1);
+    let end = ptr as usize + size;
+
+    if std::arch::is_aarch64_feature_detected!("dpb") {
+        // DC CVAP is encoded as a system instruction, as older assemblers do not know about it.
+        for line in (start..end).step_by(line) {
+            asm!("sys #3, c7, c12, #1, {}", in(reg) line, options(nostack, preserves_flags));
+        }
+    } else {
+        for line in (start..end).step_by(line) {
+            asm!("dc cvac, {}", in(reg) line, options(nostack, preserves_flags));
+        }
+    }
+
+    asm!("dsb sy", options(nostack, preserves_flags));
+
+    true
+}
+
+/// Other architectures have no instructions to write back cache lines from user space.
+///
+/// # Safety
+///
+/// The memory must be readable.
+#[cfg(not(any(target_arch = "aarch64", target_arch = "x86", target_arch = "x86_64")))]
+pub(crate) unsafe fn write_back(_ptr: *const u8, _size: usize) -> bool {
+    false
+}
@@ -0,0 +1,132 @@
+//! This module implements mappings that look like anonymous memory to the application, but that
+//! are backed by a file in a cache directory, such that their contents survive restarts of the
+//! process.
+
+use crate::error::Error;
+use crate::mmap::{DropBehavior, FileLock, MmapOptions};
+use crate::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+/// A zero-initialized mapping that is backed by a file in a cache directory, such that its
+/// contents are restored the next time the mapping is opened, e.g. to keep caches warm across
+/// restarts of the process. See [`PersistentMmap::open()`].
+///
+/// The contents are written back to the file by [`PersistentMmap::checkpoint()`] and when the
+/// mapping is dropped. As the mapping is a shared file mapping, the operating system may also
+/// write back modified pages at any earlier point, such that the file is not guaranteed to hold a
+/// consistent snapshot if the process crashes between checkpoints.
+pub struct PersistentMmap {
+    mapping: MmapMut,
+    path: PathBuf,
+    restored: bool,
+}
+
+impl PersistentMmap {
+    /// Opens the mapping of `size` bytes named `name` in the given directory, which is created if
+    /// it does not exist yet. If the file backing the mapping already exists, its contents are
+    /// restored, where the file is truncated or extended with zeros if the size differs.
+    /// Otherwise, the mapping is zero-initialized.
+    ///
+    /// An exclusive advisory lock is acquired on the file, such that opening the same mapping
+    /// again fails while the mapping is alive. See [`MmapOptions::with_file_lock()`].
+    ///
+    /// # Safety
+    ///
+    /// The file backing the mapping must not be modified or truncated by other means while the
+    /// mapping is alive, as the advisory lock only co-operates with processes that acquire the
+    /// lock as well. This includes opening the same mapping with a different size, as the file is
+    /// resized before the lock is acquired. See [`MmapOptions::with_file()`].
+    pub unsafe fn open<P: AsRef<Path>>(
+        directory: P,
+        name: &str,
+        size: usize,
+    ) -> Result<Self, Error> {
+        let directory = directory.as_ref();
+
+        std::fs::create_dir_all(directory)?;
+
+        let path = directory.join(name);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let len = file.metadata()?.len();
+
+        if len != size as u64 {
+            file.set_len(size as u64)?;
+        }
+
+        let mapping = MmapOptions::new(size)
+            .with_file(file, 0)
+            .with_file_lock(FileLock::Try)
+            .with_drop_behavior(DropBehavior::Flush)
+            .map_mut()?;
+
+        Ok(Self {
+            mapping,
+            path,
+            restored: len > 0,
+        })
+    }
+
+    /// Yields whether the contents were restored from an existing file when the mapping was
+    /// opened, rather than being zero-initialized.
+    #[inline]
+    pub fn restored(&self) -> bool {
+        self.restored
+    }
+
+    /// Returns the path of the file backing the mapping.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the file backing the mapping.
+    #[inline]
+    pub fn file(&self) -> Option<&File> {
+        self.mapping.file()
+    }
+
+    /// Returns the size of the mapping in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.mapping.size()
+    }
+
+    /// Synchronously writes the contents of the mapping back to the file, such that they are
+    /// restored the next time the mapping is opened, even if the process does not exit cleanly
+    /// afterwards.
+    pub fn checkpoint(&self) -> Result<(), Error> {
+        self.mapping.flush(0..self.mapping.size())
+    }
+
+    /// Returns the underlying mapping, which still writes its contents back to the file when it
+    /// is dropped.
+    #[inline]
+    pub fn into_inner(self) -> MmapMut {
+        self.mapping
+    }
+}
+
+impl Deref for PersistentMmap {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.mapping
+    }
+}
+
+impl DerefMut for PersistentMmap {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.mapping
+    }
+}
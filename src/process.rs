@@ -0,0 +1,39 @@
+//! This module implements reading the memory of the current process or of another process by
+//! address, where pages that cannot be read result in an error rather than a fault, such that
+//! tools can inspect the memory areas of a process without having to map them.
+
+use crate::error::Error;
+
+use crate::os_impl::process as platform;
+
+/// Reads the memory of the current process or of another process.
+///
+/// On Linux, this uses `process_vm_readv()`, which requires the same permissions as attaching a
+/// debugger through `ptrace()` to read the memory of another process. On Microsoft Windows, this
+/// uses `ReadProcessMemory()`, which requires the `PROCESS_VM_READ` access right. This is not
+/// supported on other platforms.
+pub struct ProcessMemory {
+    inner: platform::ProcessMemory,
+}
+
+impl ProcessMemory {
+    /// Opens the memory of the process with the given process ID for reading. If no process ID is
+    /// given, then the memory of the current process is opened.
+    pub fn open(pid: Option<u32>) -> Result<Self, Error> {
+        Ok(Self {
+            inner: platform::ProcessMemory::open(pid)?,
+        })
+    }
+
+    /// Reads the memory starting at the given address into the buffer, and returns the number of
+    /// bytes read, which is less than the size of the buffer if the memory past that point cannot
+    /// be read. Fails if none of the memory can be read, e.g. because it is not mapped or not
+    /// readable.
+    pub fn read_at(&self, address: usize, buffer: &mut [u8]) -> Result<usize, Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        self.inner.read(address, buffer)
+    }
+}
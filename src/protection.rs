@@ -0,0 +1,14 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// The protection of the memory area.
+    #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+    pub struct Protection: u32 {
+        /// The memory area is mapped with read permissions.
+        const READ          = 1 << 0;
+        /// The memory area is mapped with write permissions.
+        const WRITE         = 1 << 1;
+        /// The memory area is mapped with execute permissions.
+        const EXECUTE       = 1 << 3;
+    }
+}
@@ -0,0 +1,112 @@
+//! This module implements a quarantine for executable memory that is no longer in use, which
+//! mitigates the exploitation of dangling references to code that has been freed, e.g. by JIT
+//! engines.
+
+use crate::error::Error;
+use crate::{Mmap, MmapMut, MmapNone};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The instruction that traps when executed, which is used to overwrite code that has been freed.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const TRAP: &[u8] = &[0xcc]; // int3
+
+#[cfg(target_arch = "aarch64")]
+const TRAP: &[u8] = &[0x00, 0x00, 0x20, 0xd4]; // brk #0
+
+#[cfg(target_arch = "arm")]
+const TRAP: &[u8] = &[0xf0, 0x00, 0xf0, 0xe7]; // udf #0
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const TRAP: &[u8] = &[0x73, 0x00, 0x10, 0x00]; // ebreak
+
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "riscv32",
+    target_arch = "riscv64",
+)))]
+const TRAP: &[u8] = &[0x00];
+
+/// A quarantine for executable mappings that are no longer in use.
+///
+/// Retiring a mapping overwrites its contents with instructions that trap when executed and then
+/// makes the mapping inaccessible, such that jumping to the freed code crashes rather than
+/// executing code that an attacker may control. The mapping is then held in the quarantine for
+/// the configured period, before it can be reclaimed and reused for new code. This prevents the
+/// freed code from being replaced right away, which makes it harder to exploit stale function
+/// pointers.
+pub struct CodeQuarantine {
+    period: Duration,
+    entries: VecDeque<(Instant, MmapNone)>,
+}
+
+impl CodeQuarantine {
+    /// Creates an empty quarantine that holds mappings for the given period.
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Yields the number of mappings that are held in the quarantine.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Yields whether the quarantine holds no mappings.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Overwrites the contents of the mapping with trap instructions, makes the mapping
+    /// inaccessible and holds it in the quarantine.
+    ///
+    /// If the protection of the mapping cannot be changed, the mapping is unmapped instead, such
+    /// that the freed code cannot be executed either way.
+    pub fn retire(&mut self, mapping: Mmap) -> Result<(), Error> {
+        let mut mapping = mapping.make_mut().map_err(|(_, e)| e)?;
+
+        for chunk in mapping.as_mut_slice().chunks_mut(TRAP.len()) {
+            chunk.copy_from_slice(&TRAP[..chunk.len()]);
+        }
+
+        let mapping = mapping.make_none().map_err(|(_, e)| e)?;
+
+        self.entries.push_back((Instant::now(), mapping));
+
+        Ok(())
+    }
+
+    /// Reclaims the mapping that has been held in the quarantine the longest, if it has been held
+    /// for at least the configured period. The reclaimed mapping is mutable and is filled with
+    /// trap instructions, such that it can be reused for new code.
+    ///
+    /// If the protection of the mapping cannot be changed, the mapping is unmapped and the error
+    /// is returned.
+    pub fn reclaim(&mut self) -> Option<Result<MmapMut, Error>> {
+        match self.entries.front() {
+            Some((retired, _)) if retired.elapsed() >= self.period => (),
+            _ => return None,
+        }
+
+        let (_, mapping) = self.entries.pop_front()?;
+
+        Some(mapping.make_mut().map_err(|(_, e)| e))
+    }
+
+    /// Unmaps the mappings that have been held in the quarantine for at least the configured
+    /// period, rather than reclaiming them for reuse.
+    pub fn purge(&mut self) {
+        while let Some((retired, _)) = self.entries.front() {
+            if retired.elapsed() < self.period {
+                break;
+            }
+
+            self.entries.pop_front();
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! This module implements byte-range locks on the file backing a shared file mapping, such that
+//! processes that edit different regions of the same file can coordinate through the mapping,
+//! rather than having to open the file a second time to lock it.
+
+use crate::error::Error;
+use std::fs::File;
+use std::ops::Range;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// A byte-range lock on the file backing a mapping, which is released when dropped. See
+/// `lock_range()` on the mappings.
+pub struct RangeLock<'a> {
+    file: &'a File,
+    offset: u64,
+    len: u64,
+    range: Range<usize>,
+}
+
+impl<'a> RangeLock<'a> {
+    pub(crate) fn acquire(
+        file: &'a File,
+        offset: u64,
+        range: Range<usize>,
+        exclusive: bool,
+        wait: bool,
+    ) -> Result<Self, Error> {
+        let offset = offset + range.start as u64;
+        let len = (range.end - range.start) as u64;
+
+        platform::lock_file_range(file, offset, len, exclusive, wait)?;
+
+        Ok(Self {
+            file,
+            offset,
+            len,
+            range,
+        })
+    }
+
+    /// Returns the range of the mapping that is locked.
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Releases the lock, reporting any error that occurs, which is ignored when the lock is
+    /// dropped instead.
+    pub fn unlock(self) -> Result<(), Error> {
+        let result = platform::unlock_file_range(self.file, self.offset, self.len);
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl Drop for RangeLock<'_> {
+    fn drop(&mut self) {
+        let _ = platform::unlock_file_range(self.file, self.offset, self.len);
+    }
+}
@@ -0,0 +1,169 @@
+//! This module implements reservations of address space, in which views of files or anonymous
+//! memory can be mapped at chosen offsets, such that emulators and sandboxes can control the
+//! layout of the address space of a guest.
+
+use crate::error::Error;
+use crate::mmap::{align_up, allocation_granularity, check_range};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// Represents a reservation of address space, in which views can be mapped at chosen offsets
+/// using [`crate::MmapOptions::with_reservation()`]. The pages of the reservation that are not
+/// covered by a view are inaccessible, and no other mappings can be placed within the
+/// reservation.
+///
+/// On Unix, the reservation is an inaccessible anonymous mapping, over which the views are mapped
+/// using `MAP_FIXED`. On Microsoft Windows, the reservation is a placeholder, which is split and
+/// replaced by the views using `VirtualAlloc2()` and `MapViewOfFile3()`, which requires Microsoft
+/// Windows 10, version 1803 or newer. When a view is dropped, the pages are returned to the
+/// reservation rather than being unmapped. The address space is released once the reservation and
+/// all of its views have been dropped.
+///
+/// With the `fake` feature, the views are allocated on the heap rather than within the
+/// reservation, but the offsets are still validated.
+pub struct Reservation {
+    shared: Arc<Shared>,
+}
+
+/// The state of a reservation that is shared with its views.
+pub(crate) struct Shared {
+    inner: platform::Reservation,
+    /// The ranges of the reservation that are occupied by views.
+    occupied: Mutex<Vec<Range<usize>>>,
+}
+
+impl Reservation {
+    /// Reserves `size` bytes of address space, where `size` must be a multiple of the allocation
+    /// granularity. See [`allocation_granularity()`].
+    ///
+    /// Returns [`Error::Unaligned`] if the size is not a multiple of the allocation granularity.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        let granularity = allocation_granularity();
+
+        if !size.is_multiple_of(granularity) {
+            return Err(Error::Unaligned(size, granularity));
+        }
+
+        Ok(Self {
+            shared: Arc::new(Shared {
+                inner: platform::Reservation::new(size)?,
+                occupied: Mutex::new(vec![]),
+            }),
+        })
+    }
+
+    /// Returns a raw pointer to the start of the reservation.
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.shared.inner.as_ptr()
+    }
+
+    /// Returns the size of the reservation in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.shared.inner.size()
+    }
+
+    pub(crate) fn shared(&self) -> Arc<Shared> {
+        self.shared.clone()
+    }
+}
+
+/// Claims a range of a reservation for a view, which is released when the slot is dropped. The
+/// slot keeps the reservation alive, such that the address space is only released once all the
+/// views have been dropped.
+pub(crate) struct Slot {
+    shared: Arc<Shared>,
+    range: Range<usize>,
+}
+
+impl Slot {
+    /// Claims the pages spanning `size` bytes at the given offset within the reservation.
+    ///
+    /// Returns [`Error::Unaligned`] if the offset is not a multiple of the allocation granularity,
+    /// [`Error::OutOfBounds`] if the range does not fit in the reservation, or an error of the
+    /// kind [`std::io::ErrorKind::AddrInUse`] if the range overlaps with another view.
+    pub fn claim(shared: Arc<Shared>, offset: usize, size: usize) -> Result<Self, Error> {
+        let granularity = allocation_granularity();
+
+        if !offset.is_multiple_of(granularity) {
+            return Err(Error::Unaligned(offset, granularity));
+        }
+
+        let end = offset.checked_add(align_up(size)).ok_or(Error::OutOfBounds(offset..usize::MAX))?;
+        let range = offset..end;
+
+        check_range(&range, shared.inner.size())?;
+
+        let mut occupied = lock_occupied(&shared);
+
+        if occupied.iter().any(|other| other.start < range.end && range.start < other.end) {
+            let address = shared.inner.as_ptr() as usize;
+
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!(
+                    "the address range {:#x}..{:#x} overlaps with another view",
+                    address + range.start,
+                    address + range.end,
+                ),
+            )));
+        }
+
+        occupied.push(range.clone());
+        drop(occupied);
+
+        Ok(Self {
+            shared,
+            range,
+        })
+    }
+
+    /// Returns the address at which the view has to be mapped.
+    #[cfg(not(feature = "fake"))]
+    #[inline]
+    pub fn address(&self) -> usize {
+        self.shared.inner.as_ptr() as usize + self.range.start
+    }
+
+    /// Returns the size of the claimed range in bytes.
+    #[cfg(all(windows, not(feature = "fake")))]
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.range.end - self.range.start
+    }
+
+    /// Shrinks the claimed range to the pages spanning `size` bytes, after the view has been
+    /// truncated.
+    pub fn truncate(&mut self, size: usize) {
+        let end = self.range.start + align_up(size);
+        let mut occupied = lock_occupied(&self.shared);
+
+        if let Some(range) = occupied.iter_mut().find(|range| **range == self.range) {
+            range.end = end;
+        }
+
+        self.range.end = end;
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        lock_occupied(&self.shared).retain(|range| *range != self.range);
+    }
+}
+
+/// Locks the ranges that are occupied by views. A thread panicking while holding the lock cannot
+/// leave the ranges in an inconsistent state, so poisoning is ignored.
+fn lock_occupied(shared: &Shared) -> std::sync::MutexGuard<'_, Vec<Range<usize>>> {
+    shared.occupied.lock().unwrap_or_else(|e| e.into_inner())
+}
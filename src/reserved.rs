@@ -0,0 +1,185 @@
+//! This module implements reserving a large range of address space up front and committing the
+//! pages lazily, such that arena allocators can grow in place without having to move their
+//! contents.
+
+use crate::error::Error;
+use crate::mmap::{check_range, page_size};
+use std::ops::Range;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// Represents a range of address space that has been reserved, in which pages are committed on
+/// demand using [`ReservedMmap::commit()`] and released again using [`ReservedMmap::uncommit()`].
+/// Pages that are not committed are inaccessible and do not use any memory. See
+/// [`crate::MmapOptions::reserve()`].
+///
+/// On Unix, the reservation is an inaccessible anonymous mapping that does not reserve swap
+/// space, where committing makes the pages readable and writable using `mprotect()`, and
+/// uncommitting replaces the pages with fresh inaccessible pages. On Microsoft Windows, the pages
+/// are reserved, committed and decommitted using `VirtualAlloc()` with `MEM_RESERVE` and
+/// `MEM_COMMIT`, and `VirtualFree()` with `MEM_DECOMMIT`. Committed pages are zero-initialized on
+/// either platform.
+pub struct ReservedMmap {
+    inner: platform::ReservedMmap,
+    /// The ranges that are committed, which are sorted and merged.
+    committed: Vec<Range<usize>>,
+}
+
+impl ReservedMmap {
+    pub(crate) fn new(inner: platform::ReservedMmap) -> Self {
+        Self {
+            inner,
+            committed: vec![],
+        }
+    }
+
+    /// Returns a raw pointer to the start of the reservation.
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.inner.as_ptr()
+    }
+
+    /// Returns a raw mutable pointer to the start of the reservation.
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.inner.as_mut_ptr()
+    }
+
+    /// Returns the size of the reservation in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Returns the ranges that are committed, which are sorted and do not overlap or touch.
+    #[inline]
+    pub fn committed(&self) -> &[Range<usize>] {
+        &self.committed
+    }
+
+    /// Yields whether the given range is committed as a whole.
+    pub fn is_committed(&self, range: Range<usize>) -> bool {
+        range.is_empty() || self.committed
+            .iter()
+            .any(|committed| committed.start <= range.start && range.end <= committed.end)
+    }
+
+    /// Commits the pages in the given range, such that they become readable and writable. Newly
+    /// committed pages are zero-initialized, while pages that are committed already keep their
+    /// contents.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is out of bounds, or [`Error::Unaligned`] if
+    /// the range is not aligned to the page size.
+    pub fn commit(&mut self, range: Range<usize>) -> Result<(), Error> {
+        self.check_pages(&range)?;
+
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        self.inner.commit(range.clone())?;
+
+        let mut merged = Vec::with_capacity(self.committed.len() + 1);
+        let mut range = range;
+
+        for committed in self.committed.drain(..) {
+            if committed.end < range.start || range.end < committed.start {
+                merged.push(committed);
+            } else {
+                range = committed.start.min(range.start)..committed.end.max(range.end);
+            }
+        }
+
+        merged.push(range);
+        merged.sort_by_key(|range| range.start);
+        self.committed = merged;
+
+        Ok(())
+    }
+
+    /// Uncommits the pages in the given range, such that they become inaccessible and their
+    /// memory is released. The contents of the pages are lost.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is out of bounds, or [`Error::Unaligned`] if
+    /// the range is not aligned to the page size.
+    pub fn uncommit(&mut self, range: Range<usize>) -> Result<(), Error> {
+        self.check_pages(&range)?;
+
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        self.inner.uncommit(range.clone())?;
+
+        let mut remaining = Vec::with_capacity(self.committed.len() + 1);
+
+        for committed in self.committed.drain(..) {
+            if committed.start < range.start {
+                remaining.push(committed.start..committed.end.min(range.start));
+            }
+
+            if range.end < committed.end {
+                remaining.push(committed.start.max(range.end)..committed.end);
+            }
+        }
+
+        self.committed = remaining;
+
+        Ok(())
+    }
+
+    /// Extracts a slice of the given range, which must be committed.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is out of bounds or not committed as a whole.
+    pub fn slice(&self, range: Range<usize>) -> Result<&[u8], Error> {
+        check_range(&range, self.size())?;
+
+        if !self.is_committed(range.clone()) {
+            return Err(Error::OutOfBounds(range));
+        }
+
+        Ok(unsafe {
+            std::slice::from_raw_parts(self.as_ptr().add(range.start), range.end - range.start)
+        })
+    }
+
+    /// Extracts a mutable slice of the given range, which must be committed.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is out of bounds or not committed as a whole.
+    pub fn slice_mut(&mut self, range: Range<usize>) -> Result<&mut [u8], Error> {
+        check_range(&range, self.size())?;
+
+        if !self.is_committed(range.clone()) {
+            return Err(Error::OutOfBounds(range));
+        }
+
+        Ok(unsafe {
+            std::slice::from_raw_parts_mut(self.as_mut_ptr().add(range.start), range.end - range.start)
+        })
+    }
+
+    /// Checks whether the range is in bounds and aligned to the page size, where the end may also
+    /// be the end of the reservation.
+    fn check_pages(&self, range: &Range<usize>) -> Result<(), Error> {
+        check_range(range, self.size())?;
+
+        let page_size = page_size();
+
+        if !range.start.is_multiple_of(page_size) {
+            return Err(Error::Unaligned(range.start, page_size));
+        }
+
+        if !range.end.is_multiple_of(page_size) && range.end != self.size() {
+            return Err(Error::Unaligned(range.end, page_size));
+        }
+
+        Ok(())
+    }
+}
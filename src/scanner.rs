@@ -0,0 +1,202 @@
+//! This module implements searching the memory areas of the current or another process for byte
+//! patterns with wildcards, e.g. for debugging, malware analysis and cheat detection tools.
+
+use crate::areas::{MemoryArea, MemoryAreas};
+use crate::error::Error;
+use crate::process::ProcessMemory;
+use crate::protection::Protection;
+
+/// The default number of bytes that are read from the process at once.
+const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// A byte pattern to search for, where a mask selects the bits of every byte that have to match.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    mask: Vec<u8>,
+}
+
+impl Pattern {
+    /// Creates a pattern from the bytes and the mask, where only the bits that are set in the mask
+    /// have to match, e.g. a mask byte of `0x00` matches any byte.
+    ///
+    /// Returns an error of the kind [`std::io::ErrorKind::InvalidInput`] if the pattern is empty
+    /// or if the bytes and the mask differ in length.
+    pub fn new(bytes: &[u8], mask: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() || bytes.len() != mask.len() {
+            return Err(invalid_pattern(
+                "the bytes and the mask must be of the same, non-zero length",
+            ));
+        }
+
+        Ok(Self {
+            bytes: bytes.iter().zip(mask).map(|(byte, mask)| byte & mask).collect(),
+            mask: mask.to_vec(),
+        })
+    }
+
+    /// Creates a pattern that matches the bytes exactly.
+    ///
+    /// Returns an error of the kind [`std::io::ErrorKind::InvalidInput`] if the pattern is empty.
+    pub fn exact(bytes: &[u8]) -> Result<Self, Error> {
+        Self::new(bytes, &vec![0xff; bytes.len()])
+    }
+
+    /// Parses a pattern of hexadecimal bytes separated by whitespace, where `?` or `??` matches
+    /// any byte, e.g. `48 8b 05 ?? ?? ?? ??`.
+    ///
+    /// Returns an error of the kind [`std::io::ErrorKind::InvalidInput`] if the pattern is empty
+    /// or cannot be parsed.
+    pub fn parse(pattern: &str) -> Result<Self, Error> {
+        let mut bytes = vec![];
+        let mut mask = vec![];
+
+        for token in pattern.split_whitespace() {
+            let (byte, bits) = match token {
+                "?" | "??" => (0x00, 0x00),
+                _ if token.len() == 2 => match u8::from_str_radix(token, 16) {
+                    Ok(byte) => (byte, 0xff),
+                    Err(_) => return Err(invalid_pattern(&format!("invalid byte {:?}", token))),
+                },
+                _ => return Err(invalid_pattern(&format!("invalid byte {:?}", token))),
+            };
+
+            bytes.push(byte);
+            mask.push(bits);
+        }
+
+        Self::new(&bytes, &mask)
+    }
+
+    /// Returns the length of the pattern in bytes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns `false`, as patterns are never empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Checks whether the pattern matches the start of the bytes.
+    fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() >= self.len() &&
+            bytes.iter()
+                .zip(&self.bytes)
+                .zip(&self.mask)
+                .all(|((byte, expected), mask)| byte & mask == *expected)
+    }
+}
+
+fn invalid_pattern(message: &str) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, message.to_string()))
+}
+
+/// Searches the readable memory areas of the current or another process for a [`Pattern`].
+///
+/// The memory areas are enumerated using [`MemoryAreas`] and read in chunks using
+/// [`ProcessMemory`], so this has the same requirements and platform support. Consecutive chunks
+/// overlap by the length of the pattern minus one byte, such that matches that straddle chunks
+/// are found as well. If reading a memory area fails part way through, e.g. as the process
+/// unmapped it in the meantime, the rest of the memory area is skipped.
+pub struct MemoryScanner {
+    pid: Option<u32>,
+    memory: ProcessMemory,
+    filter: Box<dyn FnMut(&MemoryArea) -> bool>,
+    chunk_size: usize,
+}
+
+impl MemoryScanner {
+    /// Creates a scanner for the memory areas of the process with the given process ID. If no
+    /// process ID is given, then the memory areas of the current process are scanned.
+    pub fn new(pid: Option<u32>) -> Result<Self, Error> {
+        Ok(Self {
+            pid,
+            memory: ProcessMemory::open(pid)?,
+            filter: Box::new(|_| true),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Only scans the readable memory areas that pass the filter, e.g. to only scan the memory
+    /// areas that are executable, or that are backed by a specific file.
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: FnMut(&MemoryArea) -> bool + 'static,
+    {
+        self.filter = Box::new(filter);
+        self
+    }
+
+    /// Sets the number of bytes that are read from the process at once, which is 1 MiB by
+    /// default. Chunks smaller than the pattern are grown to the length of the pattern.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Returns the addresses at which the pattern has been found, in ascending order.
+    pub fn scan(&mut self, pattern: &Pattern) -> Result<Vec<usize>, Error> {
+        let mut addresses = vec![];
+
+        self.scan_with(pattern, |address| {
+            addresses.push(address);
+            true
+        })?;
+
+        Ok(addresses)
+    }
+
+    /// Calls the function with the address of every match in ascending order, until the function
+    /// returns `false`.
+    pub fn scan_with<F>(&mut self, pattern: &Pattern, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut areas = MemoryAreas::open(self.pid)?
+            .filter(|area| {
+                area.as_ref().map_or(true, |area| area.protection().contains(Protection::READ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        areas.retain(|area| (self.filter)(area));
+
+        let chunk_size = self.chunk_size.max(pattern.len());
+        let overlap = pattern.len() - 1;
+        let mut buffer = vec![0u8; chunk_size + overlap];
+
+        for area in &areas {
+            let mut address = area.start();
+
+            while address < area.end() {
+                let size = (chunk_size + overlap).min(area.end() - address);
+
+                let count = match self.memory.read_at(address, &mut buffer[..size]) {
+                    Ok(count) => count,
+                    Err(_) => break,
+                };
+
+                let bytes = &buffer[..count];
+
+                // Matches that start in the overlap are found by the next chunk.
+                let starts = count.saturating_sub(overlap).min(chunk_size);
+
+                for start in 0..starts {
+                    if pattern.matches(&bytes[start..]) && !f(address + start) {
+                        return Ok(());
+                    }
+                }
+
+                if count < size {
+                    break;
+                }
+
+                address += chunk_size;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+//! This module implements named shared memory, which unrelated processes can map by its name, such
+//! that they can communicate through shared memory without having to pass a file or handle to
+//! each other.
+
+use crate::error::Error;
+use std::ops::{Deref, DerefMut};
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// Represents a mapping of shared memory with a name, by which other processes can map the same
+/// memory. One process creates the shared memory using [`SharedMemory::create()`], after which
+/// other processes open it using [`SharedMemory::open()`]. The memory is readable and writable
+/// by every process that maps it.
+///
+/// On Unix, the memory is a POSIX shared memory object created using `shm_open()`, where a slash
+/// is prepended to the name if it does not start with one. Names are limited to 31 bytes on
+/// macOS. The name is unlinked when the shared memory that created it is dropped, after which the
+/// memory can no longer be opened, whereas the processes that opened it already keep their
+/// mappings. On Microsoft Windows, the memory is a named section backed by the paging file,
+/// created using `CreateFileMappingW()`, where the name may be prefixed with `Global\` or
+/// `Local\` to select the namespace. The name can be opened for as long as any process keeps the
+/// section mapped. This is not supported with the `fake` feature.
+pub struct SharedMemory {
+    inner: platform::SharedMemory,
+    name: String,
+}
+
+impl SharedMemory {
+    /// Creates zero-initialized shared memory of `size` bytes with the given name and maps it.
+    /// Fails with an error of the kind [`std::io::ErrorKind::AlreadyExists`] if shared memory
+    /// with the name exists already.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the size is zero.
+    ///
+    /// # Safety
+    ///
+    /// The memory is shared with the processes that open it, which may modify it at any time, so
+    /// the caller must co-ordinate the accesses with them, e.g. by using atomics through
+    /// [`SharedMemory::as_mut_ptr()`]. See [`crate::MmapOptions::with_file()`].
+    pub unsafe fn create(name: &str, size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::OutOfBounds(0..size));
+        }
+
+        Ok(Self {
+            inner: platform::SharedMemory::create(name, size)?,
+            name: name.to_string(),
+        })
+    }
+
+    /// Opens the shared memory with the given name, which another process has created, and maps
+    /// it. Fails with an error of the kind [`std::io::ErrorKind::NotFound`] if there is no shared
+    /// memory with the name.
+    ///
+    /// On Microsoft Windows, the size of a section cannot be queried, so the size of the mapping
+    /// is rounded up to the page size.
+    ///
+    /// # Safety
+    ///
+    /// See [`SharedMemory::create()`].
+    pub unsafe fn open(name: &str) -> Result<Self, Error> {
+        Ok(Self {
+            inner: platform::SharedMemory::open(name)?,
+            name: name.to_string(),
+        })
+    }
+
+    /// Returns the name of the shared memory, as it has been created or opened with.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the size of the mapping in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Returns a raw pointer to the start of the mapping.
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.inner.as_ptr()
+    }
+
+    /// Returns a raw mutable pointer to the start of the mapping.
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.inner.as_mut_ptr()
+    }
+}
+
+impl Deref for SharedMemory {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(self.as_ptr(), self.size())
+        }
+    }
+}
+
+impl DerefMut for SharedMemory {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.size())
+        }
+    }
+}
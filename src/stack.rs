@@ -0,0 +1,94 @@
+//! This module implements stacks for threads and coroutines, which are mapped with an inaccessible
+//! guard page below them, such that a stack overflow faults rather than silently corrupting the
+//! memory below the stack.
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// The alignment of the initial stack pointer, which satisfies the ABIs of the common 64-bit and
+/// 32-bit architectures.
+const STACK_ALIGNMENT: usize = 16;
+
+/// Represents a stack that has been mapped using [`crate::MmapOptions::map_stack()`], which grows
+/// downward from [`StackMmap::top()`] towards [`StackMmap::as_ptr()`], below which an
+/// inaccessible guard page has been mapped.
+///
+/// On Unix, the stack is mapped as private memory using `MAP_STACK` where available, which is
+/// required for stacks on OpenBSD. With [`crate::MmapFlags::GROWS_DOWN`] on Linux, the kernel
+/// extends the stack downward as it is used and keeps a gap below the stack rather than a guard
+/// page, such that no guard page is mapped. On Microsoft Windows, the guard page is mapped with
+/// `PAGE_NOACCESS`, as `PAGE_GUARD` is only meaningful to the stacks that the system manages.
+/// With the `fake` feature, the guard page is not enforced.
+pub struct StackMmap {
+    inner: platform::Mmap,
+    guard_size: usize,
+}
+
+impl StackMmap {
+    pub(crate) fn new(inner: platform::Mmap, guard_size: usize) -> Self {
+        Self {
+            inner,
+            guard_size,
+        }
+    }
+
+    /// Returns the usable size of the stack in bytes, which excludes the guard page.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.size() - self.guard_size
+    }
+
+    /// Returns the size of the guard page below the stack in bytes, which is zero for stacks that
+    /// grow downward.
+    #[inline]
+    pub fn guard_size(&self) -> usize {
+        self.guard_size
+    }
+
+    /// Returns a raw pointer to the lowest usable address of the stack, right above the guard
+    /// page.
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        unsafe { self.inner.as_ptr().add(self.guard_size) }
+    }
+
+    /// Returns a raw mutable pointer to the lowest usable address of the stack, right above the
+    /// guard page.
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        unsafe { self.inner.as_mut_ptr().add(self.guard_size) }
+    }
+
+    /// Returns the initial stack pointer, i.e. the end of the stack rounded down to an alignment
+    /// of 16 bytes, which the ABIs of the common architectures require. The stack pointer is
+    /// decremented before anything is pushed, so this address itself is never written to.
+    #[inline]
+    pub fn top(&self) -> *mut u8 {
+        let end = self.as_ptr() as usize + self.size();
+
+        (end & !(STACK_ALIGNMENT - 1)) as *mut u8
+    }
+
+    /// Extracts a slice of the usable part of the stack, e.g. to determine how much of the stack
+    /// has been used after filling it with a known pattern up front.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(self.as_ptr(), self.size())
+        }
+    }
+
+    /// Extracts a mutable slice of the usable part of the stack.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.size())
+        }
+    }
+}
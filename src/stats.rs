@@ -0,0 +1,350 @@
+//! This module implements an opt-in global registry of the memory mappings that are alive, such
+//! that services can export statistics about their memory mappings, e.g. as Prometheus metrics.
+//! The registry is only maintained when the `stats` feature is enabled, and is updated using
+//! relaxed atomic operations.
+//!
+//! For debugging, the `registry` feature additionally keeps track of every individual memory
+//! mapping together with the backtrace of where it has been created, such that leaked memory
+//! mappings can be traced back to their origin.
+
+use crate::MmapFlags;
+
+#[cfg(feature = "registry")]
+use std::backtrace::Backtrace;
+#[cfg(feature = "registry")]
+use std::collections::BTreeMap;
+#[cfg(feature = "registry")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "registry")]
+use std::sync::atomic::AtomicU64;
+#[cfg(any(feature = "registry", feature = "stats"))]
+use std::sync::atomic::Ordering;
+#[cfg(feature = "stats")]
+use std::sync::atomic::AtomicUsize;
+
+/// The number of memory mappings and the total number of bytes they span.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MappingStats {
+    /// The number of memory mappings.
+    pub count: usize,
+    /// The total number of bytes spanned by the memory mappings.
+    pub bytes: usize,
+}
+
+/// A snapshot of the statistics of the memory mappings that are alive in the current process and
+/// that have been created through this crate. See [`stats()`].
+#[cfg(feature = "stats")]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MmapStats {
+    /// All the memory mappings that are alive.
+    pub total: MappingStats,
+    /// The memory mappings that are backed by a file.
+    pub file_backed: MappingStats,
+    /// The number of bytes that are locked into physical memory, either by mapping them with
+    /// [`MmapFlags::LOCKED`] or by locking them afterwards.
+    pub locked_bytes: usize,
+    /// The breakdown of the memory mappings per flag they have been created with, for every flag
+    /// in [`MmapFlags`].
+    pub flags: Vec<(MmapFlags, MappingStats)>,
+}
+
+#[cfg(feature = "stats")]
+struct Counter {
+    count: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+#[cfg(feature = "stats")]
+impl Counter {
+    const fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(&self, size: usize) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+    }
+
+    fn sub(&self, size: usize) {
+        self.count.fetch_sub(1, Ordering::Relaxed);
+        self.bytes.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    fn load(&self) -> MappingStats {
+        MappingStats {
+            count: self.count.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+const FLAG_COUNT: usize = u32::BITS as usize;
+
+#[cfg(feature = "stats")]
+static TOTAL: Counter = Counter::new();
+
+#[cfg(feature = "stats")]
+static FILE_BACKED: Counter = Counter::new();
+
+#[cfg(feature = "stats")]
+static LOCKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "stats")]
+#[allow(clippy::declare_interior_mutable_const)]
+static FLAGS: [Counter; FLAG_COUNT] = {
+    const COUNTER: Counter = Counter::new();
+    [COUNTER; FLAG_COUNT]
+};
+
+/// Returns a snapshot of the statistics of the memory mappings that are alive. As the counters
+/// are updated independently, the snapshot may be slightly inconsistent while other threads are
+/// creating or destroying memory mappings.
+#[cfg(feature = "stats")]
+pub fn stats() -> MmapStats {
+    let flags = (0..FLAG_COUNT)
+        .filter_map(|bit| MmapFlags::from_bits(1 << bit).map(|flag| (flag, FLAGS[bit].load())))
+        .collect();
+
+    MmapStats {
+        total: TOTAL.load(),
+        file_backed: FILE_BACKED.load(),
+        locked_bytes: LOCKED_BYTES.load(Ordering::Relaxed),
+        flags,
+    }
+}
+
+/// A memory mapping that is alive, as recorded by the `registry` feature. See [`live_mappings()`].
+#[cfg(feature = "registry")]
+#[derive(Clone, Debug)]
+pub struct LiveMapping {
+    /// The address of the memory mapping.
+    pub address: usize,
+    /// The size of the memory mapping in bytes.
+    pub size: usize,
+    /// The flags the memory mapping has been created with.
+    pub flags: MmapFlags,
+    /// Whether the memory mapping is backed by a file.
+    pub file_backed: bool,
+    /// The backtrace of where the memory mapping has been created. This is resolved lazily when
+    /// it is formatted.
+    pub backtrace: Arc<Backtrace>,
+}
+
+#[cfg(feature = "registry")]
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "registry")]
+static LIVE_MAPPINGS: Mutex<BTreeMap<u64, LiveMapping>> = Mutex::new(BTreeMap::new());
+
+/// Returns the memory mappings that are alive in the current process and that have been created
+/// through this crate, ordered by when they have been created. This is meant for diagnosing leaks
+/// of address space in long-running processes, e.g. by periodically logging the memory mappings
+/// that have been alive for longer than expected.
+///
+/// As a backtrace is captured for every memory mapping that is created, regardless of the
+/// `RUST_BACKTRACE` and `RUST_LIB_BACKTRACE` environment variables, the `registry` feature adds
+/// considerable overhead to creating memory mappings and should only be enabled for debugging.
+#[cfg(feature = "registry")]
+pub fn live_mappings() -> Vec<LiveMapping> {
+    lock_live_mappings().values().cloned().collect()
+}
+
+/// Locks the memory mappings that are alive. A thread panicking while holding the lock cannot
+/// leave the map in an inconsistent state, so poisoning is ignored.
+#[cfg(feature = "registry")]
+fn lock_live_mappings() -> std::sync::MutexGuard<'static, BTreeMap<u64, LiveMapping>> {
+    LIVE_MAPPINGS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Adds the memory mapping to the statistics.
+#[cfg(feature = "stats")]
+fn add_stats(size: usize, flags: MmapFlags, file_backed: bool) {
+    TOTAL.add(size);
+
+    if file_backed {
+        FILE_BACKED.add(size);
+    }
+
+    for (bit, counter) in FLAGS.iter().enumerate() {
+        if flags.bits() & (1 << bit) != 0 {
+            counter.add(size);
+        }
+    }
+}
+
+/// Removes the memory mapping from the statistics.
+#[cfg(feature = "stats")]
+fn sub_stats(size: usize, flags: MmapFlags, file_backed: bool) {
+    TOTAL.sub(size);
+
+    if file_backed {
+        FILE_BACKED.sub(size);
+    }
+
+    for (bit, counter) in FLAGS.iter().enumerate() {
+        if flags.bits() & (1 << bit) != 0 {
+            counter.sub(size);
+        }
+    }
+}
+
+/// Keeps a memory mapping registered for as long as it is alive. Without the `stats` and the
+/// `registry` features, this is a zero-sized type that does nothing.
+pub(crate) struct Record {
+    #[cfg(feature = "stats")]
+    size: usize,
+    #[cfg(feature = "stats")]
+    flags: MmapFlags,
+    #[cfg(feature = "stats")]
+    file_backed: bool,
+    #[cfg(feature = "stats")]
+    locked: bool,
+    #[cfg(feature = "registry")]
+    id: u64,
+}
+
+impl Record {
+    /// Registers a memory mapping of the given size at the given address that has been created
+    /// with the given flags.
+    #[cfg_attr(not(feature = "registry"), allow(unused_variables))]
+    #[inline]
+    pub fn new(address: usize, size: usize, flags: MmapFlags, file_backed: bool) -> Self {
+        #[cfg(feature = "stats")]
+        add_stats(size, flags, file_backed);
+
+        #[cfg(feature = "stats")]
+        let locked = flags.contains(MmapFlags::LOCKED);
+
+        #[cfg(feature = "stats")]
+        if locked {
+            LOCKED_BYTES.fetch_add(size, Ordering::Relaxed);
+        }
+
+        #[cfg(feature = "registry")]
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "registry")]
+        lock_live_mappings().insert(id, LiveMapping {
+            address,
+            size,
+            flags,
+            file_backed,
+            backtrace: Arc::new(Backtrace::force_capture()),
+        });
+
+        Self {
+            #[cfg(feature = "stats")]
+            size,
+            #[cfg(feature = "stats")]
+            flags,
+            #[cfg(feature = "stats")]
+            file_backed,
+            #[cfg(feature = "stats")]
+            locked,
+            #[cfg(feature = "registry")]
+            id,
+        }
+    }
+
+    /// Marks the memory mapping as locked into physical memory.
+    #[inline]
+    pub fn lock(&mut self) {
+        #[cfg(feature = "stats")]
+        if !std::mem::replace(&mut self.locked, true) {
+            LOCKED_BYTES.fetch_add(self.size, Ordering::Relaxed);
+        }
+    }
+
+    /// Updates the address of the memory mapping, e.g. after the mapping has been moved.
+    #[cfg_attr(not(feature = "registry"), allow(unused_variables))]
+    #[inline]
+    pub fn relocate(&mut self, address: usize) {
+        #[cfg(feature = "registry")]
+        if let Some(mapping) = lock_live_mappings().get_mut(&self.id) {
+            mapping.address = address;
+        }
+    }
+
+    /// Updates the size of the memory mapping, e.g. after the mapping has been truncated.
+    #[cfg_attr(not(any(feature = "registry", feature = "stats")), allow(unused_variables))]
+    #[inline]
+    pub fn resize(&mut self, size: usize) {
+        #[cfg(feature = "stats")]
+        {
+            let locked = self.locked;
+
+            self.unlock();
+            sub_stats(self.size, self.flags, self.file_backed);
+            add_stats(size, self.flags, self.file_backed);
+            self.size = size;
+
+            if locked {
+                self.lock();
+            }
+        }
+
+        #[cfg(feature = "registry")]
+        if let Some(mapping) = lock_live_mappings().get_mut(&self.id) {
+            mapping.size = size;
+        }
+    }
+
+    /// Splits the memory mapping at the given offset, where this record keeps the part before the
+    /// offset and the returned record covers the remainder, which has been mapped at the given
+    /// address.
+    #[cfg_attr(not(any(feature = "registry", feature = "stats")), allow(unused_variables))]
+    pub fn split_off(&mut self, address: usize, at: usize) -> Self {
+        #[cfg(feature = "stats")]
+        let (size, flags, file_backed, locked) =
+            (self.size, self.flags, self.file_backed, self.locked);
+
+        #[cfg(all(feature = "registry", not(feature = "stats")))]
+        let (size, flags, file_backed, locked) = lock_live_mappings()
+            .get(&self.id)
+            .map(|mapping| (mapping.size, mapping.flags, mapping.file_backed, false))
+            .unwrap_or((at, MmapFlags::empty(), false, false));
+
+        #[cfg(not(any(feature = "registry", feature = "stats")))]
+        let (size, flags, file_backed, locked) = (at, MmapFlags::empty(), false, false);
+
+        self.resize(at);
+
+        let mut record = Self::new(address, size - at, flags, file_backed);
+
+        if locked {
+            record.lock();
+        } else {
+            record.unlock();
+        }
+
+        record
+    }
+
+    /// Marks the memory mapping as no longer locked into physical memory.
+    #[inline]
+    pub fn unlock(&mut self) {
+        #[cfg(feature = "stats")]
+        if std::mem::replace(&mut self.locked, false) {
+            LOCKED_BYTES.fetch_sub(self.size, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(any(feature = "registry", feature = "stats"))]
+impl Drop for Record {
+    fn drop(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.unlock();
+            sub_stats(self.size, self.flags, self.file_backed);
+        }
+
+        #[cfg(feature = "registry")]
+        lock_live_mappings().remove(&self.id);
+    }
+}
@@ -0,0 +1,37 @@
+//! This module implements the instrumentation of the operations on memory mappings, such that
+//! they can be audited using [`tracing`] when the `tracing` feature is enabled.
+//!
+//! [`tracing`]: https://docs.rs/tracing
+
+/// Emits an event for the operation with the given fields, where the fields are recorded using
+/// their [`Debug`] implementation. Successful operations are emitted at the debug level, whereas
+/// failed operations are emitted at the warn level together with the error.
+#[cfg(feature = "tracing")]
+macro_rules! trace_op {
+    ($op:literal, $result:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        match &$result {
+            Ok(_) => tracing::debug!(
+                target: "mmap_rs",
+                op = $op,
+                $($name = ?$value,)+
+                "memory mapping operation succeeded"
+            ),
+            Err(e) => tracing::warn!(
+                target: "mmap_rs",
+                op = $op,
+                $($name = ?$value,)+
+                error = %e,
+                "memory mapping operation failed"
+            ),
+        }
+    }
+}
+
+/// Without the `tracing` feature, the result and the fields are only borrowed, such that there
+/// is no overhead.
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_op {
+    ($op:literal, $result:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        let _ = (&$result, $(&$value),+);
+    }
+}
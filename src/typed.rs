@@ -0,0 +1,97 @@
+//! This module implements typed zero-copy views of memory mappings using either [`bytemuck`] or
+//! [`zerocopy`], such that binary formats can be read directly from the mapping.
+
+use crate::error::Error;
+use crate::{Mmap, MmapMut};
+use std::mem::{align_of, size_of};
+
+/// Checks whether a value of type `T` fits at the start of the given bytes and whether the bytes
+/// are properly aligned for `T`.
+#[cfg(feature = "zerocopy")]
+fn check_prefix<T>(bytes: &[u8]) -> Result<(), Error> {
+    if bytes.len() < size_of::<T>() {
+        return Err(Error::OutOfBounds(0..size_of::<T>()));
+    }
+
+    if !(bytes.as_ptr() as usize).is_multiple_of(align_of::<T>()) {
+        return Err(Error::Unaligned(bytes.as_ptr() as usize, align_of::<T>()));
+    }
+
+    Ok(())
+}
+
+/// Converts the error returned by [`bytemuck`] into the error type of this crate.
+#[cfg(feature = "bytemuck")]
+fn pod_cast_error<T>(address: usize, size: usize, e: bytemuck::PodCastError) -> Error {
+    use bytemuck::PodCastError;
+
+    match e {
+        PodCastError::TargetAlignmentGreaterAndInputNotAligned |
+        PodCastError::AlignmentMismatch =>
+            Error::Unaligned(address, align_of::<T>()),
+        // The bytes past the last whole `T` cannot be interpreted.
+        _ =>
+            Error::OutOfBounds(size - size % size_of::<T>().max(1)..size),
+    }
+}
+
+macro_rules! typed_impl {
+    ($t:ident) => {
+        impl $t {
+            /// Interprets the entire mapping as a slice of `T`.
+            ///
+            /// Returns [`Error::Unaligned`] if the start of the mapping is not aligned to the
+            /// alignment of `T`, or [`Error::OutOfBounds`] with the trailing bytes if the size of
+            /// the mapping is not a multiple of the size of `T`.
+            #[cfg(feature = "bytemuck")]
+            pub fn as_slice_of<T: bytemuck::Pod>(&self) -> Result<&[T], Error> {
+                bytemuck::try_cast_slice(self.as_slice())
+                    .map_err(|e| pod_cast_error::<T>(self.as_ptr() as usize, self.size(), e))
+            }
+
+            /// Interprets the start of the mapping as a reference to `T`.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the mapping is too small to hold `T`, or
+            /// [`Error::Unaligned`] if the start of the mapping is not aligned to the alignment of
+            /// `T`.
+            #[cfg(feature = "zerocopy")]
+            pub fn as_ref_of<T: zerocopy::FromBytes>(&self) -> Result<&T, Error> {
+                let bytes = self.as_slice();
+
+                check_prefix::<T>(bytes)?;
+
+                Ok(T::ref_from_prefix(bytes).unwrap())
+            }
+        }
+    }
+}
+
+typed_impl!(Mmap);
+typed_impl!(MmapMut);
+
+impl MmapMut {
+    /// Interprets the entire mapping as a mutable slice of `T`.
+    ///
+    /// Returns [`Error::Unaligned`] if the start of the mapping is not aligned to the alignment of
+    /// `T`, or [`Error::OutOfBounds`] with the trailing bytes if the size of the mapping is not a
+    /// multiple of the size of `T`.
+    #[cfg(feature = "bytemuck")]
+    pub fn as_mut_slice_of<T: bytemuck::Pod>(&mut self) -> Result<&mut [T], Error> {
+        let address = self.as_ptr() as usize;
+        let size = self.size();
+
+        bytemuck::try_cast_slice_mut(self.as_mut_slice())
+            .map_err(|e| pod_cast_error::<T>(address, size, e))
+    }
+
+    /// Interprets the start of the mapping as a mutable reference to `T`.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the mapping is too small to hold `T`, or
+    /// [`Error::Unaligned`] if the start of the mapping is not aligned to the alignment of `T`.
+    #[cfg(feature = "zerocopy")]
+    pub fn as_mut_of<T: zerocopy::FromBytes + zerocopy::AsBytes>(&mut self) -> Result<&mut T, Error> {
+        check_prefix::<T>(self.as_slice())?;
+
+        Ok(T::mut_from_prefix(self.as_mut_slice()).unwrap())
+    }
+}
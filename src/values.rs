@@ -0,0 +1,107 @@
+//! This module implements checked reads and writes of primitive values at offsets within memory
+//! mappings, including reads and writes in an explicit byte order, such that binary formats can
+//! be parsed and written without pointer casts or slicing bytes by hand.
+
+use crate::error::Error;
+use crate::volatile::{check_bytes, check_value};
+use crate::{Mmap, MmapMut, VolatileValue};
+use std::mem::size_of;
+
+/// Converts between the native byte order and little endian.
+#[inline]
+fn le<T: VolatileValue>(value: T) -> T {
+    if cfg!(target_endian = "little") {
+        value
+    } else {
+        value.swap_bytes()
+    }
+}
+
+/// Converts between the native byte order and big endian.
+#[inline]
+fn be<T: VolatileValue>(value: T) -> T {
+    if cfg!(target_endian = "big") {
+        value
+    } else {
+        value.swap_bytes()
+    }
+}
+
+macro_rules! value_read_impl {
+    ($t:ident) => {
+        impl $t {
+            /// Reads a value of type `T` in the native byte order at the given offset.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping, or
+            /// [`Error::Unaligned`] if the address is not aligned to the alignment of `T`.
+            pub fn read_at<T: VolatileValue>(&self, offset: usize) -> Result<T, Error> {
+                check_value::<T>(self.as_ptr() as usize, self.size(), offset)?;
+
+                Ok(unsafe { std::ptr::read(self.as_ptr().add(offset) as *const T) })
+            }
+
+            /// Reads a little-endian value of type `T` at the given offset, which does not have
+            /// to be aligned, as fields of binary formats often are not.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping.
+            pub fn read_le_at<T: VolatileValue>(&self, offset: usize) -> Result<T, Error> {
+                Ok(le(self.read_unaligned_at::<T>(offset)?))
+            }
+
+            /// Reads a big-endian value of type `T` at the given offset, which does not have to
+            /// be aligned, as fields of binary formats often are not.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping.
+            pub fn read_be_at<T: VolatileValue>(&self, offset: usize) -> Result<T, Error> {
+                Ok(be(self.read_unaligned_at::<T>(offset)?))
+            }
+
+            fn read_unaligned_at<T: VolatileValue>(&self, offset: usize) -> Result<T, Error> {
+                check_bytes(self.size(), offset, size_of::<T>())?;
+
+                Ok(unsafe { std::ptr::read_unaligned(self.as_ptr().add(offset) as *const T) })
+            }
+        }
+    }
+}
+
+value_read_impl!(Mmap);
+value_read_impl!(MmapMut);
+
+impl MmapMut {
+    /// Writes a value of type `T` in the native byte order at the given offset.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping, or
+    /// [`Error::Unaligned`] if the address is not aligned to the alignment of `T`.
+    pub fn write_at<T: VolatileValue>(&mut self, offset: usize, value: T) -> Result<(), Error> {
+        check_value::<T>(self.as_ptr() as usize, self.size(), offset)?;
+
+        unsafe { std::ptr::write(self.as_mut_ptr().add(offset) as *mut T, value) };
+
+        Ok(())
+    }
+
+    /// Writes a value of type `T` as little endian at the given offset, which does not have to
+    /// be aligned.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping.
+    pub fn write_le_at<T: VolatileValue>(&mut self, offset: usize, value: T) -> Result<(), Error> {
+        self.write_unaligned_at(offset, le(value))
+    }
+
+    /// Writes a value of type `T` as big endian at the given offset, which does not have to be
+    /// aligned.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping.
+    pub fn write_be_at<T: VolatileValue>(&mut self, offset: usize, value: T) -> Result<(), Error> {
+        self.write_unaligned_at(offset, be(value))
+    }
+
+    fn write_unaligned_at<T: VolatileValue>(&mut self, offset: usize, value: T) -> Result<(), Error> {
+        check_bytes(self.size(), offset, size_of::<T>())?;
+
+        unsafe { std::ptr::write_unaligned(self.as_mut_ptr().add(offset) as *mut T, value) };
+
+        Ok(())
+    }
+}
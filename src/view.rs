@@ -0,0 +1,160 @@
+//! This module implements views of a page-aligned range of a mapping, such that dynamic linkers
+//! and emulators can manage the protection, flushing, locking and advice of the many regions
+//! within a single mapping without having to compute the offsets of every region by hand.
+
+use crate::error::Error;
+use crate::mmap::{check_protection, check_range, page_size, range_to_page_bounds, Advice, Eviction};
+use crate::protection::Protection;
+use std::ops::Range;
+
+#[cfg(all(unix, not(feature = "fake")))]
+use crate::os_impl::unix as platform;
+
+#[cfg(all(windows, not(feature = "fake")))]
+use crate::os_impl::windows as platform;
+
+#[cfg(feature = "fake")]
+use crate::os_impl::fake as platform;
+
+/// A view of a page-aligned range of a mapping, which borrows the mapping mutably, such that the
+/// protection of the range can be changed. Every operation of the view is scoped to its range,
+/// and every offset is relative to the start of the view. See [`crate::MmapMut::as_view()`].
+///
+/// The contents are not handed out as slices, as the protection of the range may differ from the
+/// protection of the mapping. Use [`MmapView::as_ptr()`] to access the contents instead.
+pub struct MmapView<'a> {
+    inner: &'a mut platform::Mmap,
+    range: Range<usize>,
+}
+
+impl<'a> MmapView<'a> {
+    /// Creates a view of the range, which must be non-empty and must start and end at a multiple
+    /// of the page size, except that it may end at the end of the mapping.
+    pub(crate) fn new(inner: &'a mut platform::Mmap, range: Range<usize>) -> Result<Self, Error> {
+        check_range(&range, inner.size())?;
+
+        if range.is_empty() {
+            return Err(Error::OutOfBounds(range));
+        }
+
+        let page_size = page_size();
+        let address = inner.as_ptr() as usize;
+
+        if !(address + range.start).is_multiple_of(page_size) {
+            return Err(Error::Unaligned(range.start, page_size));
+        }
+
+        if !(address + range.end).is_multiple_of(page_size) && range.end != inner.size() {
+            return Err(Error::Unaligned(range.end, page_size));
+        }
+
+        Ok(Self {
+            inner,
+            range,
+        })
+    }
+
+    /// Returns the range of the mapping that this view covers.
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Returns the offset of the view within the mapping.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.range.start
+    }
+
+    /// Returns the size of the view in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.range.end - self.range.start
+    }
+
+    /// Returns a raw pointer to the start of the view.
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        unsafe { self.inner.as_ptr().add(self.range.start) }
+    }
+
+    /// Queries the effective protection of the pages of the view. See
+    /// [`crate::MmapMut::protection_of()`].
+    pub fn protection(&self) -> Result<Protection, Error> {
+        self.inner.protection_of(self.range())
+    }
+
+    /// Changes the protection of the pages of the view. The instruction cache is flushed for the
+    /// view if the protection is executable, like [`crate::MmapMut::make_exec()`].
+    ///
+    /// Returns [`Error::UnsafeFlagNeeded`] if the protection is both writable and executable
+    /// without [`crate::UnsafeMmapFlags::JIT`] having been set.
+    ///
+    /// # Safety
+    ///
+    /// See [`crate::MmapMut::protect_ranges()`].
+    pub unsafe fn protect(&mut self, protection: Protection) -> Result<(), Error> {
+        check_protection(self.inner, protection)?;
+
+        self.inner.protect_ranges(&[(self.range(), protection)])?;
+
+        #[cfg(feature = "exec")]
+        if protection.contains(Protection::EXECUTE) {
+            self.inner.flush_icache_range(self.range())?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the view synchronously. See [`crate::MmapMut::flush()`].
+    pub fn flush(&self) -> Result<(), Error> {
+        self.inner.flush(self.range())
+    }
+
+    /// Flushes the view asynchronously. See [`crate::MmapMut::flush_async()`].
+    pub fn flush_async(&self) -> Result<(), Error> {
+        self.inner.flush_async(self.range())
+    }
+
+    /// Locks the physical pages of the view in memory, without marking the mapping as locked.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        self.inner.lock_pages(self.range(), true)
+    }
+
+    /// Unlocks the physical pages of the view, which also unlocks them if the whole mapping has
+    /// been locked.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        self.inner.lock_pages(self.range(), false)
+    }
+
+    /// Advises the operating system how the pages of the view will be accessed, or that their
+    /// contents are no longer needed. See [`crate::MmapMut::advise()`].
+    pub fn advise(&mut self, advice: Advice) -> Result<(), Error> {
+        let range = match advice {
+            Advice::DontNeed | Advice::Free => self.range(),
+            _ => range_to_page_bounds(self.range()),
+        };
+
+        self.inner.advise(range, advice)
+    }
+
+    /// Evicts the pages of the view from memory without discarding their contents. See
+    /// [`crate::MmapMut::evict()`].
+    pub fn evict(&self, eviction: Eviction) -> Result<(), Error> {
+        self.inner.evict(range_to_page_bounds(self.range()), eviction)
+    }
+
+    /// Borrows a page-aligned range of this view as a view of its own, where the range is relative
+    /// to the start of this view.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the range is empty or out of bounds, or
+    /// [`Error::Unaligned`] if the range is not aligned to the page size, where the offsets of
+    /// the errors are relative to the start of the mapping.
+    pub fn view(&mut self, range: Range<usize>) -> Result<MmapView<'_>, Error> {
+        check_range(&range, self.size())?;
+
+        let offset = self.range.start;
+
+        MmapView::new(self.inner, offset + range.start..offset + range.end)
+    }
+}
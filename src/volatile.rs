@@ -0,0 +1,154 @@
+//! This module implements volatile accesses to memory mappings, which are meant for mappings that
+//! are shared with other processes or devices. As the contents of such mappings may change at any
+//! time, the accesses go through raw pointers rather than through references to the contents.
+
+use crate::error::Error;
+use crate::mmap::check_range;
+use crate::{Mmap, MmapMut};
+use std::mem::{align_of, size_of};
+
+mod sealed {
+    pub trait Sealed {
+        /// Reverses the byte order of the value.
+        fn swap_bytes(self) -> Self;
+    }
+}
+
+/// The primitive types that can be accessed with volatile reads and writes, for which every bit
+/// pattern is a valid value. This trait is sealed and cannot be implemented outside of this crate.
+pub trait VolatileValue: Copy + sealed::Sealed {}
+
+macro_rules! volatile_value_impl {
+    ($($t:ty),+) => {
+        $(
+            impl sealed::Sealed for $t {
+                #[inline]
+                fn swap_bytes(self) -> Self {
+                    <$t>::swap_bytes(self)
+                }
+            }
+
+            impl VolatileValue for $t {}
+        )+
+    }
+}
+
+macro_rules! volatile_float_impl {
+    ($($t:ty),+) => {
+        $(
+            impl sealed::Sealed for $t {
+                #[inline]
+                fn swap_bytes(self) -> Self {
+                    <$t>::from_bits(self.to_bits().swap_bytes())
+                }
+            }
+
+            impl VolatileValue for $t {}
+        )+
+    }
+}
+
+volatile_value_impl!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+volatile_float_impl!(f32, f64);
+
+/// Checks whether a value of type `T` at the given offset is within the bounds of a mapping of the
+/// given size and whether the address is properly aligned for `T`.
+pub(crate) fn check_value<T>(address: usize, size: usize, offset: usize) -> Result<(), Error> {
+    let end = offset
+        .checked_add(size_of::<T>())
+        .ok_or(Error::OutOfBounds(offset..usize::MAX))?;
+
+    check_range(&(offset..end), size)?;
+
+    if !(address + offset).is_multiple_of(align_of::<T>()) {
+        return Err(Error::Unaligned(address + offset, align_of::<T>()));
+    }
+
+    Ok(())
+}
+
+/// Checks whether the range starting at the given offset is within the bounds of a mapping of the
+/// given size.
+pub(crate) fn check_bytes(size: usize, offset: usize, len: usize) -> Result<(), Error> {
+    let end = offset
+        .checked_add(len)
+        .ok_or(Error::OutOfBounds(offset..usize::MAX))?;
+
+    check_range(&(offset..end), size)
+}
+
+macro_rules! volatile_read_impl {
+    ($t:ident) => {
+        impl $t {
+            /// Reads a value of type `T` at the given offset using a volatile read, such that the
+            /// read is neither elided nor merged with other reads.
+            ///
+            /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping, or
+            /// [`Error::Unaligned`] if the address is not aligned to the alignment of `T`.
+            pub fn read_volatile_at<T: VolatileValue>(&self, offset: usize) -> Result<T, Error> {
+                check_value::<T>(self.as_ptr() as usize, self.size(), offset)?;
+
+                Ok(unsafe {
+                    std::ptr::read_volatile(self.as_ptr().add(offset) as *const T)
+                })
+            }
+
+            /// Copies the bytes starting at the given offset into the buffer using volatile reads
+            /// of a byte each.
+            ///
+            /// Returns [`Error::OutOfBounds`] without reading anything if the range is out of
+            /// bounds.
+            pub fn read_volatile_into(&self, offset: usize, buffer: &mut [u8]) -> Result<(), Error> {
+                check_bytes(self.size(), offset, buffer.len())?;
+
+                let ptr = unsafe { self.as_ptr().add(offset) };
+
+                for (index, byte) in buffer.iter_mut().enumerate() {
+                    *byte = unsafe {
+                        std::ptr::read_volatile(ptr.add(index))
+                    };
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+volatile_read_impl!(Mmap);
+volatile_read_impl!(MmapMut);
+
+impl MmapMut {
+    /// Writes a value of type `T` at the given offset using a volatile write, such that the write
+    /// is neither elided nor merged with other writes.
+    ///
+    /// Returns [`Error::OutOfBounds`] if the value does not fit within the mapping, or
+    /// [`Error::Unaligned`] if the address is not aligned to the alignment of `T`.
+    pub fn write_volatile_at<T: VolatileValue>(&mut self, offset: usize, value: T) -> Result<(), Error> {
+        check_value::<T>(self.as_ptr() as usize, self.size(), offset)?;
+
+        unsafe {
+            std::ptr::write_volatile(self.as_mut_ptr().add(offset) as *mut T, value)
+        };
+
+        Ok(())
+    }
+
+    /// Copies the bytes from the buffer into the mapping starting at the given offset using
+    /// volatile writes of a byte each.
+    ///
+    /// Returns [`Error::OutOfBounds`] without writing anything if the range is out of bounds.
+    pub fn write_volatile_from(&mut self, offset: usize, buffer: &[u8]) -> Result<(), Error> {
+        check_bytes(self.size(), offset, buffer.len())?;
+
+        let ptr = unsafe { self.as_mut_ptr().add(offset) };
+
+        for (index, byte) in buffer.iter().enumerate() {
+            unsafe {
+                std::ptr::write_volatile(ptr.add(index), *byte)
+            };
+        }
+
+        Ok(())
+    }
+}
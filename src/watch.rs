@@ -0,0 +1,266 @@
+//! This module implements watching the file backing a mapping for changes made by other
+//! processes, and remapping the file when it has been resized or replaced, e.g. for configuration
+//! or asset files that are reloaded while the process keeps running.
+
+use crate::error::Error;
+use crate::{Mmap, MmapOptions};
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the metadata of the file is polled on platforms without inotify.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the watcher thread waits at most before checking whether it should stop.
+const STOP_LATENCY: Duration = Duration::from_millis(100);
+
+/// A change to a watched file. See [`FileWatcher`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileEvent {
+    /// The contents of the file have been modified, without changing its length.
+    Modified,
+    /// The length of the file has changed to the given number of bytes.
+    Resized(u64),
+    /// The file has been replaced by another file, e.g. by renaming a new file over it, or has
+    /// been recreated after being removed.
+    Replaced,
+    /// The file has been removed.
+    Removed,
+}
+
+/// The metadata of a file that is compared to tell what changed.
+#[derive(Clone, PartialEq)]
+struct Snapshot {
+    identity: (u64, u64),
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Snapshot {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+
+        Some(Self {
+            identity: identity(&metadata),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Identifies the file by its device and inode number.
+#[cfg(unix)]
+fn identity(metadata: &std::fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.dev(), metadata.ino())
+}
+
+/// Identifies the file by its creation time, as the file index is not exposed on stable Rust.
+/// A file that is renamed over the watched file keeps its own creation time.
+#[cfg(windows)]
+fn identity(metadata: &std::fs::Metadata) -> (u64, u64) {
+    let created = metadata
+        .created()
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    (created.as_secs(), created.subsec_nanos() as u64)
+}
+
+/// Tells what changed between two snapshots of the file. `notified` is set if the operating
+/// system reported a change, in which case an unchanged snapshot still counts as a modification,
+/// as the modification time may not have advanced.
+fn diff(old: &Option<Snapshot>, new: &Option<Snapshot>, notified: bool) -> Option<FileEvent> {
+    match (old, new) {
+        (Some(_), None) => Some(FileEvent::Removed),
+        (None, Some(_)) => Some(FileEvent::Replaced),
+        (Some(old), Some(new)) if old.identity != new.identity => Some(FileEvent::Replaced),
+        (Some(old), Some(new)) if old.len != new.len => Some(FileEvent::Resized(new.len)),
+        (Some(old), Some(new)) if old.modified != new.modified || notified => {
+            Some(FileEvent::Modified)
+        }
+        _ => None,
+    }
+}
+
+/// Watches a file for changes made by other processes from a background thread, and calls the
+/// given function for every change. The function is called from the background thread, so it
+/// should not block for long.
+///
+/// On Linux, the directory containing the file is watched using inotify, such that the file
+/// being replaced through `rename()` is noticed as well. On other platforms, the metadata of the
+/// file is polled twice per second. Changes that happen in quick succession may be reported as
+/// a single event. The thread is stopped when the watcher is dropped.
+pub struct FileWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FileWatcher {
+    /// Starts watching the file at the given path, which does not have to exist yet.
+    pub fn new<P, F>(path: P, mut callback: F) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(FileEvent) + Send + 'static,
+    {
+        let path = path.as_ref().to_path_buf();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut wait = waiter(&path)?;
+
+        let thread = {
+            let stop = stop.clone();
+
+            std::thread::Builder::new()
+                .name("mmap-rs-watch".into())
+                .spawn(move || {
+                    let mut snapshot = Snapshot::of(&path);
+
+                    while !stop.load(Ordering::Relaxed) {
+                        let notified = match wait(&stop) {
+                            Ok(notified) => notified,
+                            // The watch is lost, e.g. because the directory has been removed.
+                            Err(_) => {
+                                wait = polling();
+                                false
+                            }
+                        };
+
+                        let current = Snapshot::of(&path);
+
+                        if let Some(event) = diff(&snapshot, &current, notified) {
+                            callback(event);
+                        }
+
+                        snapshot = current;
+                    }
+                })?
+        };
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Waits until the operating system reports a change or until it is time to poll again, and
+/// returns whether a change has been reported.
+type Wait = Box<dyn FnMut(&AtomicBool) -> Result<bool, Error> + Send>;
+
+#[cfg(target_os = "linux")]
+fn waiter(path: &Path) -> Result<Wait, Error> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let name = path.file_name().unwrap_or_default().to_os_string();
+    let inotify = crate::os_impl::linux::watch_directory(directory)?;
+
+    Ok(Box::new(move |_| {
+        crate::os_impl::linux::wait_for_changes(&inotify, &name, STOP_LATENCY.as_millis() as _)
+    }))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn waiter(_path: &Path) -> Result<Wait, Error> {
+    Ok(polling())
+}
+
+/// Sleeps until the next poll is due, waking up regularly to check whether to stop.
+fn polling() -> Wait {
+    Box::new(|stop| {
+        let deadline = Instant::now() + POLL_INTERVAL;
+
+        while !stop.load(Ordering::Relaxed) && Instant::now() < deadline {
+            std::thread::sleep(STOP_LATENCY);
+        }
+
+        Ok(false)
+    })
+}
+
+/// A read-only mapping of a file that is remapped when the file is resized or replaced by
+/// another process, such that readers always see the latest version of the file, e.g. for
+/// configuration files that are reloaded without restarting the process.
+///
+/// Readers take a snapshot of the current mapping using [`ReloadingMmap::load()`], which keeps
+/// that version of the file mapped for as long as the snapshot is held, even after the file has
+/// been replaced. Modifications that do not change the length of the file are visible through the
+/// existing mapping, as the file is mapped as shared. If the file cannot be remapped, e.g. because
+/// it has been removed or has become empty, the previous mapping is kept.
+pub struct ReloadingMmap {
+    current: Arc<Mutex<Arc<Mmap>>>,
+    generation: Arc<AtomicU64>,
+    _watcher: FileWatcher,
+}
+
+impl ReloadingMmap {
+    /// Maps the file at the given path and starts watching it. See [`FileWatcher`].
+    ///
+    /// # Safety
+    ///
+    /// See [`MmapOptions::with_file()`]. In particular, the file must be replaced rather than
+    /// truncated in place, e.g. by writing a new file and renaming it over the old one, as
+    /// accessing the pages of a snapshot past the end of a truncated file raises `SIGBUS` on
+    /// Unix. The file must not be empty.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let current = Arc::new(Mutex::new(Arc::new(map_file(&path)?)));
+        let generation = Arc::new(AtomicU64::new(0));
+
+        let watcher = {
+            let current = current.clone();
+            let generation = generation.clone();
+
+            FileWatcher::new(path.clone(), move |event| {
+                if let FileEvent::Resized(_) | FileEvent::Replaced = event {
+                    if let Ok(mapping) = map_file(&path) {
+                        *current.lock().unwrap_or_else(|e| e.into_inner()) = Arc::new(mapping);
+                        generation.fetch_add(1, Ordering::Release);
+                    }
+                }
+            })?
+        };
+
+        Ok(Self {
+            current,
+            generation,
+            _watcher: watcher,
+        })
+    }
+
+    /// Returns a snapshot of the current mapping of the file.
+    pub fn load(&self) -> Arc<Mmap> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Returns the number of times the file has been remapped, such that readers can tell
+    /// whether their snapshot is outdated.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+}
+
+/// Maps the whole file at the given path as read-only.
+fn map_file(path: &Path) -> Result<Mmap, Error> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    unsafe { MmapOptions::new(len as usize).with_file(file, 0) }.map()
+}
@@ -0,0 +1,49 @@
+use mmap_rs::{Error, MmapOptions};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[test]
+fn atomic_slice_aliases_mapping() {
+    let mut mapping = MmapOptions::new(64).map_mut().unwrap();
+
+    let atomics = mapping.as_atomic_slice::<AtomicU32>().unwrap();
+    assert_eq!(atomics.len(), 16);
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..1000 {
+                    atomics[1].fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    assert_eq!(mapping[4..8], 4000u32.to_ne_bytes());
+}
+
+#[cfg(all(unix, not(feature = "fake")))]
+#[test]
+fn atomic_slice_is_shared_after_fork() {
+    let mut mapping = MmapOptions::new(64).map_mut().unwrap();
+    let atomics = mapping.as_atomic_slice::<AtomicU32>().unwrap();
+
+    match unsafe { libc::fork() } {
+        0 => {
+            atomics[0].store(42, Ordering::SeqCst);
+            unsafe { libc::_exit(0) };
+        }
+        pid => {
+            assert!(pid > 0);
+            assert_eq!(unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) }, pid);
+        }
+    }
+
+    assert_eq!(atomics[0].load(Ordering::SeqCst), 42);
+}
+
+#[test]
+fn atomic_slice_rejects_partial_atomics() {
+    let mut mapping = MmapOptions::new(6).map_mut().unwrap();
+
+    assert!(matches!(mapping.as_atomic_slice::<AtomicU32>(), Err(Error::Unaligned(6, 4))));
+}
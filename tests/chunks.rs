@@ -0,0 +1,65 @@
+#![cfg(not(feature = "fake"))]
+
+use mmap_rs::{allocation_granularity, Error, MmapOptions};
+use std::fs::File;
+use std::io::Write;
+
+fn temp_file(name: &str, contents: &[u8]) -> File {
+    let path = std::env::temp_dir().join(format!("mmap-rs-chunks-{}-{}", name, std::process::id()));
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(contents).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    file
+}
+
+#[test]
+fn map_chunks_covers_file() {
+    let granularity = allocation_granularity();
+    let size = 2 * granularity + 100;
+    let contents: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+    let file = temp_file("covers", &contents);
+
+    let chunks = unsafe { MmapOptions::new(size).with_file(file, 0) }
+        .map_chunks(granularity)
+        .unwrap();
+
+    let mut output = vec![];
+    let mut sizes = vec![];
+
+    for chunk in chunks {
+        let chunk = chunk.unwrap();
+
+        sizes.push(chunk.size());
+        output.extend_from_slice(&chunk);
+    }
+
+    assert_eq!(sizes, [granularity, granularity, 100]);
+    assert_eq!(output, contents);
+}
+
+#[test]
+fn map_chunks_rejects_unaligned_chunk_size() {
+    let granularity = allocation_granularity();
+    let file = temp_file("unaligned", &vec![0; granularity]);
+    let options = unsafe { MmapOptions::new(granularity).with_file(file, 0) };
+
+    assert!(matches!(options.map_chunks(granularity + 1), Err(Error::Unaligned(..))));
+}
+
+#[test]
+fn map_chunks_requires_file() {
+    let granularity = allocation_granularity();
+
+    match MmapOptions::new(granularity).map_chunks(granularity) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+}
@@ -0,0 +1,76 @@
+#![cfg(not(feature = "fake"))]
+
+use mmap_rs::compat::{MmapMut, MmapOptions};
+use mmap_rs::page_size;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+
+fn temp_file(name: &str, contents: &[u8]) -> File {
+    let path = std::env::temp_dir().join(format!("mmap-rs-compat-{}-{}", name, std::process::id()));
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(contents).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    file
+}
+
+#[test]
+fn map_unaligned_offset() {
+    let contents: Vec<u8> = (0..2 * page_size()).map(|i| i as u8).collect();
+    let file = temp_file("unaligned", &contents);
+
+    let mapping = unsafe { MmapOptions::new().offset(5).len(100).map(&file) }.unwrap();
+    assert_eq!(&mapping[..], &contents[5..105]);
+
+    let mapping = unsafe { MmapOptions::new().offset(5).map(&file) }.unwrap();
+    assert_eq!(&mapping[..], &contents[5..]);
+}
+
+#[test]
+fn map_mut_unaligned_offset_flushes() {
+    let file = temp_file("flush", &vec![0; 2 * page_size()]);
+    let offset = page_size() + 7;
+
+    let mut mapping = unsafe {
+        MmapOptions::new()
+            .offset(offset as u64)
+            .len(10)
+            .map_mut(&file)
+    }
+    .unwrap();
+    mapping[3] = 42;
+    mapping.flush_range(3, 1).unwrap();
+
+    let mapping = unsafe { MmapOptions::new().map(&file) }.unwrap();
+    assert_eq!(mapping[offset + 3], 42);
+}
+
+#[test]
+fn map_past_end_of_file() {
+    let file = temp_file("past-end", &vec![0; page_size()]);
+
+    let result = unsafe { MmapOptions::new().offset(1).len(page_size()).map(&file) };
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    let result = unsafe { MmapOptions::new().offset(page_size() as u64 + 1).map(&file) };
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn map_anon() {
+    let mut mapping = MmapMut::map_anon(100).unwrap();
+    assert_eq!(mapping.len(), 100);
+
+    mapping[99] = 42;
+    assert_eq!(mapping[99], 42);
+
+    let mapping = MmapMut::map_anon(0).unwrap();
+    assert!(mapping.is_empty());
+}
@@ -0,0 +1,101 @@
+#![cfg(all(unix, not(feature = "fake")))]
+
+use mmap_rs::{page_size, DirtyTracker, MmapOptions};
+use std::fs::File;
+
+/// Creates an unlinked temporary file of the given size.
+fn temp_file(name: &str, size: usize) -> File {
+    let path = std::env::temp_dir().join(format!("mmap-rs-{}-{}", name, std::process::id()));
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.set_len(size as u64).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    file
+}
+
+/// Runs the function in a child process and returns the signal that terminated the child, if
+/// any. The child is killed by `SIGALRM` if it does not finish within a few seconds.
+fn run_in_child<F: FnOnce()>(f: F) -> Option<libc::c_int> {
+    match unsafe { libc::fork() } {
+        0 => {
+            unsafe { libc::alarm(5) };
+            f();
+            unsafe { libc::_exit(0) };
+        }
+        pid => {
+            assert!(pid > 0);
+
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+
+            if libc::WIFSIGNALED(status) {
+                Some(libc::WTERMSIG(status))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[test]
+fn track_unaligned_file_mapping() {
+    let page_size = page_size();
+    let file = temp_file("dirty", 3 * page_size);
+
+    let mapping = unsafe { MmapOptions::new(2 * page_size).with_file(file, 100) }
+        .map_mut()
+        .unwrap();
+    let mut tracker = DirtyTracker::new(mapping).map_err(|(_, e)| e).unwrap();
+
+    tracker[0] = 1;
+    tracker[page_size] = 1;
+
+    assert_eq!(tracker.take_dirty().unwrap(), vec![0..2 * page_size - 100]);
+    assert_eq!(tracker.take_dirty().unwrap(), vec![]);
+
+    tracker[2 * page_size - 1] = 1;
+
+    assert_eq!(tracker.take_dirty().unwrap(), vec![2 * page_size - 100..2 * page_size]);
+}
+
+#[test]
+fn bus_error_in_tracked_mapping_is_passed_on() {
+    let page_size = page_size();
+    let file = temp_file("dirty-truncated", 2 * page_size);
+    let truncate = file.try_clone().unwrap();
+
+    let mapping = unsafe { MmapOptions::new(2 * page_size).with_file(file, 0) }
+        .map_mut()
+        .unwrap();
+    let mut tracker = DirtyTracker::new(mapping).map_err(|(_, e)| e).unwrap();
+
+    // Making the page writable does not resolve the fault, so the child must not retry forever.
+    let signal = run_in_child(|| {
+        truncate.set_len(0).unwrap();
+        tracker[page_size] = 1;
+    });
+
+    assert_eq!(signal, Some(libc::SIGBUS));
+}
+
+#[test]
+fn fault_outside_tracked_mapping_is_passed_on() {
+    let page_size = page_size();
+    let mapping = MmapOptions::new(page_size).map_mut().unwrap();
+    let _tracker = DirtyTracker::new(mapping).map_err(|(_, e)| e).unwrap();
+
+    let read_only = MmapOptions::new(page_size).map().unwrap();
+
+    let signal = run_in_child(|| unsafe {
+        (read_only.as_ptr() as *mut u8).write_volatile(1);
+    });
+
+    assert_eq!(signal, Some(libc::SIGSEGV));
+}
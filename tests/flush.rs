@@ -0,0 +1,62 @@
+use mmap_rs::{page_size, FlushScheduler, MmapOptions};
+use std::fs::File;
+use std::time::Duration;
+
+/// Creates an unlinked temporary file of the given size.
+fn temp_file(name: &str, size: usize) -> File {
+    let path = std::env::temp_dir().join(format!("mmap-rs-{}-{}", name, std::process::id()));
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.set_len(size as u64).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    file
+}
+
+#[test]
+fn barrier_flushes_dirty_ranges() {
+    let page_size = page_size();
+    let file = temp_file("flush", 2 * page_size);
+
+    let mut mapping = unsafe { MmapOptions::new(2 * page_size).with_file(file, 0) }
+        .map_mut()
+        .unwrap();
+    let scheduler = FlushScheduler::new(Duration::from_secs(60)).unwrap();
+
+    mapping[page_size + 3] = 42;
+    scheduler.mark_dirty(&mapping, page_size + 3..page_size + 4).unwrap();
+    scheduler.barrier().unwrap();
+
+    // The in-memory backend only writes the contents back when the mapping is flushed.
+    #[cfg(not(feature = "fake"))]
+    {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = mapping.file().unwrap();
+        let mut byte = [0u8];
+
+        file.seek(SeekFrom::Start(page_size as u64 + 3)).unwrap();
+        file.read_exact(&mut byte).unwrap();
+
+        assert_eq!(byte, [42]);
+    }
+}
+
+#[test]
+fn mark_dirty_rejects_out_of_bounds() {
+    let page_size = page_size();
+    let file = temp_file("flush-bounds", page_size);
+
+    let mapping = unsafe { MmapOptions::new(page_size).with_file(file, 0) }
+        .map_mut()
+        .unwrap();
+    let scheduler = FlushScheduler::new(Duration::from_secs(60)).unwrap();
+
+    assert!(scheduler.mark_dirty(&mapping, 0..page_size + 1).is_err());
+}
@@ -0,0 +1,49 @@
+#![cfg(all(target_os = "linux", not(feature = "fake")))]
+
+use mmap_rs::os::linux::{seals, MmapOptionsExt, Seals};
+use mmap_rs::{page_size, Error, MmapOptions, Protection};
+use std::io::ErrorKind;
+
+#[test]
+fn freeze_makes_read_only() {
+    let mut mapping = MmapOptions::new(page_size()).map_mut().unwrap();
+    mapping[5] = 42;
+
+    let frozen = mapping.freeze(false).map_err(|(_, e)| e).unwrap();
+
+    assert_eq!(frozen[5], 42);
+    assert_eq!(frozen.protection(), Protection::READ);
+}
+
+#[test]
+fn seal_seals_memfd() {
+    let mapping = unsafe { MmapOptions::new(page_size()).map_memfd("freeze", Seals::empty()) }
+        .unwrap();
+
+    let frozen = match mapping.freeze(true) {
+        Ok(frozen) => frozen,
+        // Sealing mappings requires Linux 6.10 or newer.
+        Err((_, Error::Io(e))) if e.kind() == ErrorKind::Unsupported => return,
+        Err((_, e)) => panic!("{}", e),
+    };
+
+    let seals = seals(frozen.file().unwrap()).unwrap();
+
+    assert!(seals.contains(Seals::FUTURE_WRITE | Seals::SEAL));
+}
+
+#[test]
+fn seal_rejects_file_sealed_against_seals() {
+    let mapping = unsafe { MmapOptions::new(page_size()).map_memfd("freeze", Seals::SEAL) }
+        .unwrap();
+
+    let (mut mapping, _) = match mapping.freeze(true) {
+        Ok(_) => panic!("the file cannot be sealed"),
+        Err(e) => e,
+    };
+
+    // Nothing has been sealed, such that the mapping is writable again.
+    assert_eq!(mapping.protection(), Protection::READ | Protection::WRITE);
+    mapping[5] = 42;
+    assert_eq!(seals(mapping.file().unwrap()).unwrap(), Seals::SEAL);
+}
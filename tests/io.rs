@@ -0,0 +1,89 @@
+#![cfg(all(unix, not(feature = "fake")))]
+#![allow(clippy::single_range_in_vec_init)]
+
+use mmap_rs::{page_size, MmapFlags, MmapOptions};
+use std::io::{Cursor, Read, Write};
+
+#[test]
+fn write_touched_shared_after_fork() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(2 * page_size).map_mut().unwrap();
+
+    // The page is only touched by the child, such that it is absent from the page map of the
+    // parent.
+    match unsafe { libc::fork() } {
+        0 => {
+            mapping[page_size + 3] = 0xab;
+            unsafe { libc::_exit(0) };
+        }
+        pid => {
+            assert!(pid > 0);
+            assert_eq!(unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) }, pid);
+        }
+    }
+
+    let mut output = Cursor::new(vec![]);
+    mapping.write_touched_to(&mut output, &[0..2 * page_size]).unwrap();
+
+    let output = output.into_inner();
+    assert_eq!(output.len(), 2 * page_size);
+    assert_eq!(output[page_size + 3], 0xab);
+}
+
+#[test]
+fn write_touched_private_zeroes_existing_output() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(3 * page_size)
+        .with_flags(MmapFlags::COPY_ON_WRITE)
+        .map_mut()
+        .unwrap();
+
+    mapping[5] = 42;
+
+    let mut output = Cursor::new(vec![0xff; 2 * page_size]);
+    mapping.write_touched_to(&mut output, &[0..3 * page_size]).unwrap();
+
+    let output = output.into_inner();
+    assert_eq!(output.len(), 3 * page_size);
+    assert_eq!(output[5], 42);
+    assert!(output[page_size..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn drop_behind_reader_reads_file() {
+    let page_size = page_size();
+    let contents: Vec<u8> = (0..4 * page_size + 10).map(|i| (i % 251) as u8).collect();
+    let path = std::env::temp_dir().join(format!("mmap-rs-drop-behind-{}", std::process::id()));
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(&contents).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mapping = unsafe { MmapOptions::new(contents.len()).with_file(file, 0) }
+        .map()
+        .unwrap();
+
+    let mut reader = mapping.drop_behind_reader().with_window(page_size);
+    let mut output = vec![];
+    let mut buf = [0; 1000];
+
+    loop {
+        match reader.read(&mut buf).unwrap() {
+            0 => break,
+            size => output.extend_from_slice(&buf[..size]),
+        }
+    }
+
+    assert_eq!(reader.position(), contents.len());
+    drop(reader);
+
+    // The pages behind the reader are read back from the file.
+    assert_eq!(output, contents);
+    assert_eq!(&mapping[..], &contents[..]);
+}
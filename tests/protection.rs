@@ -0,0 +1,32 @@
+#![cfg(all(unix, feature = "areas", not(feature = "fake")))]
+
+use mmap_rs::{page_size, MmapOptions, Protection};
+
+#[test]
+fn protection_guard_restores_on_drop() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(page_size).map_mut().unwrap();
+
+    {
+        let mut guard = mapping.push_protection(Protection::READ).unwrap();
+
+        assert!(guard.as_slice().is_some());
+        assert!(guard.as_mut_slice().is_none());
+    }
+
+    assert_eq!(mapping.protection_of(0..page_size).unwrap(), Protection::READ | Protection::WRITE);
+    mapping[5] = 42;
+}
+
+#[test]
+fn protection_guard_pops() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(page_size).map().unwrap();
+
+    let guard = mapping.push_protection(Protection::empty()).unwrap();
+    assert!(guard.as_slice().is_none());
+    guard.pop_protection().unwrap();
+
+    assert_eq!(mapping.protection_of(0..page_size).unwrap(), Protection::READ);
+    assert_eq!(mapping[5], 0);
+}
@@ -0,0 +1,81 @@
+#![cfg(all(unix, not(feature = "fake")))]
+
+use mmap_rs::{page_size, Error, MmapFlags, MmapMut, MmapOptions, Protection};
+use std::io::ErrorKind;
+
+/// Maps a single page, of which the next page is occupied by another mapping, such that growing
+/// the mapping has to relocate it.
+fn map_blocked(flags: MmapFlags) -> (MmapMut, MmapMut) {
+    let page_size = page_size();
+
+    let mut mapping = MmapOptions::new(2 * page_size).with_flags(flags).map_mut().unwrap();
+    let blocker = mapping.split_off(page_size).unwrap();
+
+    (mapping, blocker)
+}
+
+#[test]
+fn relocation_preserves_protection() {
+    let page_size = page_size();
+    let (mut mapping, _blocker) = map_blocked(MmapFlags::COPY_ON_WRITE);
+
+    mapping[5] = 42;
+    mapping.resize(2 * page_size).unwrap();
+
+    assert_eq!(mapping[5], 42);
+    assert_eq!(mapping.protection(), Protection::READ | Protection::WRITE);
+}
+
+#[test]
+fn relocated_mapping_grows_writable() {
+    let page_size = page_size();
+    let (mut mapping, _blocker) = map_blocked(MmapFlags::COPY_ON_WRITE);
+
+    mapping.resize(2 * page_size).unwrap();
+    mapping.resize(3 * page_size).unwrap();
+    mapping[2 * page_size + 5] = 42;
+
+    assert_eq!(mapping[2 * page_size + 5], 42);
+    assert_eq!(mapping.protection(), Protection::READ | Protection::WRITE);
+}
+
+#[test]
+fn shared_anonymous_memory_is_not_relocated() {
+    let page_size = page_size();
+    let (mut mapping, _blocker) = map_blocked(MmapFlags::empty());
+
+    mapping[5] = 42;
+
+    match mapping.resize(2 * page_size) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::Unsupported),
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+
+    assert_eq!(mapping.size(), page_size);
+    assert_eq!(mapping[5], 42);
+}
+
+#[test]
+fn read_only_mapping_does_not_extend_file() {
+    let page_size = page_size();
+    let path = std::env::temp_dir().join(format!("mmap-rs-resize-{}", std::process::id()));
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.set_len(page_size as u64).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut mapping = unsafe { MmapOptions::new(page_size).with_file(file, 0) }.map().unwrap();
+
+    match mapping.resize(2 * page_size) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::PermissionDenied),
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    assert_eq!(mapping.file().unwrap().metadata().unwrap().len(), page_size as u64);
+}
@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+use mmap_rs::{page_size, Error, MmapOptions};
+
+#[test]
+fn split_off_keeps_contents() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(3 * page_size).map_mut().unwrap();
+
+    mapping[5] = 1;
+    mapping[page_size + 5] = 2;
+
+    let mut tail = mapping.split_off(page_size).unwrap();
+
+    assert_eq!(mapping.size(), page_size);
+    assert_eq!(tail.size(), 2 * page_size);
+    assert_eq!(mapping[5], 1);
+    assert_eq!(tail[5], 2);
+
+    // Both halves remain writable after the other one has been dropped.
+    drop(mapping);
+    tail[2 * page_size - 1] = 3;
+    assert_eq!(tail[2 * page_size - 1], 3);
+}
+
+#[test]
+fn split_to_keeps_contents() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(2 * page_size).map_mut().unwrap();
+
+    mapping[5] = 1;
+    mapping[page_size + 5] = 2;
+
+    let head = mapping.split_to(page_size).unwrap();
+
+    assert_eq!(head.size(), page_size);
+    assert_eq!(mapping.size(), page_size);
+    assert_eq!(head[5], 1);
+    assert_eq!(mapping[5], 2);
+}
+
+#[test]
+fn split_off_rejects_invalid_offsets() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(2 * page_size).map_mut().unwrap();
+
+    assert!(matches!(mapping.split_off(0), Err(Error::OutOfBounds(_))));
+    assert!(matches!(mapping.split_off(2 * page_size), Err(Error::OutOfBounds(_))));
+    assert!(matches!(mapping.split_off(1), Err(Error::Unaligned(..))));
+    assert_eq!(mapping.size(), 2 * page_size);
+}
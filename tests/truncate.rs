@@ -0,0 +1,39 @@
+#![cfg(unix)]
+
+use mmap_rs::{page_size, Error, MmapOptions};
+
+#[test]
+fn truncate_keeps_contents() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(3 * page_size).map_mut().unwrap();
+
+    mapping[page_size - 1] = 42;
+    mapping.truncate(page_size).unwrap();
+
+    assert_eq!(mapping.size(), page_size);
+    assert_eq!(mapping[page_size - 1], 42);
+}
+
+#[cfg(not(feature = "fake"))]
+#[test]
+fn truncate_releases_tail() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(3 * page_size).map_mut().unwrap();
+
+    mapping.truncate(page_size).unwrap();
+
+    // msync() fails with ENOMEM for pages that are not mapped.
+    let tail = unsafe { mapping.as_ptr().add(page_size) } as *mut libc::c_void;
+    assert_eq!(unsafe { libc::msync(tail, 2 * page_size, libc::MS_ASYNC) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENOMEM));
+}
+
+#[test]
+fn truncate_rejects_invalid_sizes() {
+    let page_size = page_size();
+    let mut mapping = MmapOptions::new(page_size).map_mut().unwrap();
+
+    assert!(matches!(mapping.truncate(0), Err(Error::OutOfBounds(_))));
+    assert!(matches!(mapping.truncate(2 * page_size), Err(Error::OutOfBounds(_))));
+    assert_eq!(mapping.size(), page_size);
+}
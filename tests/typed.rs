@@ -0,0 +1,38 @@
+#![cfg(any(feature = "bytemuck", feature = "zerocopy"))]
+
+use mmap_rs::{Error, MmapOptions};
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn slice_of_pod() {
+    let mut mapping = MmapOptions::new(16).map_mut().unwrap();
+
+    mapping.as_mut_slice_of::<u32>().unwrap()[2] = 42;
+
+    assert_eq!(mapping.as_slice_of::<u32>().unwrap(), [0, 0, 42, 0]);
+    assert_eq!(mapping[8..12], 42u32.to_ne_bytes());
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn slice_of_pod_rejects_trailing_bytes() {
+    let mapping = MmapOptions::new(10).map_mut().unwrap();
+
+    let result = mapping.as_slice_of::<u32>();
+
+    assert!(matches!(result, Err(Error::OutOfBounds(range)) if range == (8..10)));
+}
+
+#[cfg(feature = "zerocopy")]
+#[test]
+fn ref_of_prefix() {
+    let mut mapping = MmapOptions::new(16).map_mut().unwrap();
+
+    *mapping.as_mut_of::<[u32; 2]>().unwrap() = [1, 2];
+
+    assert_eq!(*mapping.as_ref_of::<[u32; 2]>().unwrap(), [1, 2]);
+
+    let result = mapping.as_ref_of::<[u64; 4]>();
+
+    assert!(matches!(result, Err(Error::OutOfBounds(range)) if range == (0..32)));
+}
@@ -0,0 +1,24 @@
+use mmap_rs::{Error, MmapMut, MmapOptions};
+use std::convert::TryFrom;
+
+#[test]
+fn mapping_from_slice() {
+    let bytes: Vec<u8> = (0..100).collect();
+    let mapping = MmapMut::try_from(bytes.as_slice()).unwrap();
+
+    assert_eq!(&mapping[..], &bytes[..]);
+    assert_eq!(mapping.to_vec(10..20).unwrap(), &bytes[10..20]);
+}
+
+#[test]
+fn mapping_from_empty_slice() {
+    assert!(matches!(MmapMut::try_from(Vec::new()), Err(Error::OutOfBounds(_))));
+}
+
+#[test]
+fn map_mut_from_slice_rejects_oversized_input() {
+    let bytes = vec![42; 200];
+    let result = MmapOptions::new(100).map_mut_from_slice(&bytes);
+
+    assert!(matches!(result, Err(Error::OutOfBounds(range)) if range == (0..200)));
+}